@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+
+use vm::{
+    diagnostic::{Diagnostic, Span},
+    suggest::{closest, DidYouMean},
+    FnKind, OpCode, OpCodeMetadata, Symbol, Value, VirtualMachine,
+};
+
+use crate::compiler::Visible;
+
+/// Walks the bytecode of a program and of the functions it defines
+fn walk(ops: &[OpCodeMetadata], constants: &[Value], f: &mut impl FnMut(&OpCodeMetadata)) {
+    ops.iter().for_each(&mut *f);
+
+    for constant in constants {
+        match constant {
+            Value::Fn(fun) => match &*fun.body {
+                FnKind::Bytecode(ops) | FnKind::Generator(ops) => walk(ops, &[], f),
+                FnKind::Native(_) => (),
+            },
+            Value::Module(module) => {
+                let fields = module.fields.values().collect::<Vec<_>>();
+                walk(&[], &fields, f);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Finds the globals that are loaded but never defined, neither by the program, by the files it
+/// imports, whose globals are `imported`, nor by the prelude, suggesting the close names among the
+/// globals and the locals `visible` where they are loaded
+pub(crate) fn undefined_globals(
+    bytecode: &[OpCodeMetadata],
+    constants: &[Value],
+    visible: &Visible,
+    imported: HashSet<Symbol>,
+) -> Vec<Diagnostic> {
+    let prelude = VirtualMachine::default();
+
+    let mut defined = imported;
+    walk(bytecode, constants, &mut |op| {
+        if let OpCode::Savg(name) = op.opcode {
+            defined.insert(name);
+        }
+    });
+
+    // the names a misspelled variable could be meant to be
+    let mut names = defined.iter().copied().collect::<Vec<_>>();
+    names.extend(prelude.globals().keys());
+
+    let mut errors = vec![];
+    walk(bytecode, constants, &mut |op| match op.opcode {
+        OpCode::Loag(name) if !defined.contains(&name) && prelude.get_global(name).is_none() => {
+            let locals = visible.get(&(op.line, op.column)).into_iter().flatten();
+            let candidates = names.iter().chain(locals).map(Symbol::as_str);
+            let close = closest(name.as_str(), candidates);
+            let message = format!(
+                "Undefined variable '{}'{}",
+                name.as_str(),
+                DidYouMean(close)
+            );
+            errors.push(
+                Diagnostic::error(message)
+                    .at(op.line, op.column)
+                    .with_code("NameError"),
+            );
+        }
+        _ => (),
+    });
+
+    errors.sort_by_key(|err| err.span.map(|Span { line, column }| (line, column)));
+    errors
+}
+
+#[test]
+fn check_undefined_globals() {
+    let errors = crate::check("let x = foo 1\ndef f y = f (y + x)\nlet z = println bar");
+    let names = errors
+        .iter()
+        .map(|err| err.message.as_str())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        names,
+        ["Undefined variable 'foo'", "Undefined variable 'bar'"]
+    );
+
+    let errors = crate::check("let x = pritnln 1");
+    assert_eq!(
+        errors[0].message,
+        "Undefined variable 'pritnln', did you mean 'println' or 'eprintln'?"
+    );
+
+    let errors = crate::check("def f length = lenght + 1");
+    assert_eq!(
+        errors[0].message,
+        "Undefined variable 'lenght', did you mean 'length'?"
+    );
+}
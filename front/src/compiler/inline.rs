@@ -0,0 +1,254 @@
+//! Inlines calls to small functions known at compile time
+//!
+//! A call is inlined when its callee is a lambda pushed right before it, or a global defined by a
+//! top-level `def` of one, and the callee is small and calls nothing. The body of a function that
+//! calls nothing can't recurse, and its locals can be moved past the ones of the caller without
+//! being clobbered by the frames of other calls.
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use vm::{gc::GcRef, Bytecode, Fn, FnKind, OpCode, OpCodeMetadata, Operand, Symbol, Value};
+
+/// The number of opcodes of the largest function that's inlined by default
+pub(crate) const DEFAULT_THRESHOLD: usize = 16;
+
+static THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_THRESHOLD);
+
+/// Sets the number of opcodes of the largest function that's inlined, `0` disables inlining
+pub(crate) fn set_threshold(threshold: usize) {
+    THRESHOLD.store(threshold, Ordering::Relaxed);
+}
+
+/// The current inline threshold
+pub(crate) fn threshold() -> usize {
+    THRESHOLD.load(Ordering::Relaxed)
+}
+
+/// The opcodes that can run, in order, with the jumps pointing to the new indexes and the jumps
+/// out of the bytecode pointing to its end
+fn reachable(ops: &[OpCodeMetadata]) -> Bytecode {
+    let mut seen = vec![false; ops.len()];
+    let mut work = vec![0];
+
+    while let Some(ip) = work.pop() {
+        if ip >= ops.len() || seen[ip] {
+            continue;
+        }
+        seen[ip] = true;
+
+        match ops[ip].opcode {
+            OpCode::Jmp(to) => work.push(to),
+            OpCode::Jmf(to) | OpCode::Try(to) => work.extend([to, ip + 1]),
+            _ => work.push(ip + 1),
+        }
+    }
+
+    let mut index = Vec::with_capacity(ops.len());
+    let mut len = 0;
+    for &seen in &seen {
+        index.push(len);
+        len += usize::from(seen);
+    }
+    let target = |to: usize| index.get(to).copied().unwrap_or(len);
+
+    ops.iter()
+        .zip(seen)
+        .filter(|(_, seen)| *seen)
+        .map(|(op, _)| {
+            let opcode = match op.opcode {
+                OpCode::Jmp(to) => OpCode::Jmp(target(to)),
+                OpCode::Jmf(to) => OpCode::Jmf(target(to)),
+                op => op,
+            };
+            OpCodeMetadata { opcode, ..*op }
+        })
+        .collect()
+}
+
+/// The body a function is inlined as, if it's small enough and calls nothing
+fn inlinable(value: &Value, arity: usize, threshold: usize) -> Option<Bytecode> {
+    let Value::Fn(fun) = value else {
+        return None;
+    };
+    let FnKind::Bytecode(body) = &*fun.body else {
+        return None;
+    };
+    if fun.arity != arity || !fun.args.is_empty() {
+        return None;
+    }
+
+    let body = reachable(body);
+    let simple = body.iter().all(|op| {
+        !matches!(
+            op.opcode,
+            OpCode::Call(_)
+                | OpCode::TCall(_)
+                | OpCode::Try(_)
+                | OpCode::EndTry
+                | OpCode::Yield
+                | OpCode::Recur
+                | OpCode::Loac(_)
+        )
+    });
+
+    (simple && body.len() <= threshold).then_some(body)
+}
+
+/// The number of local slots used by some bytecode
+fn slots(ops: &[OpCodeMetadata]) -> usize {
+    let local = |operand| match operand {
+        Operand::Local(index) => index as usize + 1,
+        _ => 0,
+    };
+
+    ops.iter()
+        .map(|op| match op.opcode {
+            OpCode::Save(index) | OpCode::Load(index) | OpCode::Drop(index) => index + 1,
+            OpCode::Bin(_, left, right) => local(left).max(local(right)),
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Moves the locals of an inlined body by `shift` slots and its jumps by `start` opcodes
+fn relocate(op: OpCode, shift: usize, start: usize) -> OpCode {
+    let local = |operand| match operand {
+        Operand::Local(index) => {
+            Operand::Local(index + u32::try_from(shift).expect("too many locals"))
+        }
+        operand => operand,
+    };
+
+    match op {
+        OpCode::Save(index) => OpCode::Save(index + shift),
+        OpCode::Load(index) => OpCode::Load(index + shift),
+        OpCode::Bin(operator, left, right) => OpCode::Bin(operator, local(left), local(right)),
+        OpCode::Jmp(to) => OpCode::Jmp(to + start),
+        OpCode::Jmf(to) => OpCode::Jmf(to + start),
+        op => op,
+    }
+}
+
+/// Inlines the calls of some bytecode, given the constant index of the functions each global was
+/// defined as
+fn inline_calls(
+    ops: &mut Bytecode,
+    constants: &[Value],
+    globals: &HashMap<Symbol, usize>,
+    threshold: usize,
+) {
+    let mut site = 0;
+
+    while site + 1 < ops.len() {
+        let callee = match ops[site].opcode {
+            OpCode::Push(index) => Some(index),
+            OpCode::Loag(name) => globals.get(&name).copied(),
+            _ => None,
+        };
+        let body = match (callee, ops[site + 1].opcode) {
+            (Some(callee), OpCode::Call(arity) | OpCode::TCall(arity)) => {
+                inlinable(&constants[callee], arity, threshold)
+            }
+            _ => None,
+        };
+
+        // a jump to the call itself would skip the callee, which is no longer pushed
+        let targeted = ops.iter().any(|op| {
+            matches!(op.opcode, OpCode::Jmp(to) | OpCode::Jmf(to) | OpCode::Try(to) if to == site + 1)
+        });
+        let Some(body) = body.filter(|_| !targeted) else {
+            site += 1;
+            continue;
+        };
+
+        let len = body.len();
+        let shift = slots(ops);
+        let moved = |to: usize| if to > site { to + len - 2 } else { to };
+        for op in ops.iter_mut() {
+            op.opcode = match op.opcode {
+                OpCode::Jmp(to) => OpCode::Jmp(moved(to)),
+                OpCode::Jmf(to) => OpCode::Jmf(moved(to)),
+                OpCode::Try(to) => OpCode::Try(moved(to)),
+                op => op,
+            };
+        }
+
+        let body = body.into_iter().map(|op| OpCodeMetadata {
+            opcode: relocate(op.opcode, shift, site),
+            ..op
+        });
+        ops.splice(site..site + 2, body);
+        site += len;
+    }
+}
+
+/// Inlines the calls of a program and of the functions it defines, the functions that are
+/// inlined stay in the constants, they can still be called from other places
+pub(crate) fn run(bytecode: &mut Bytecode, constants: &mut [Value], threshold: usize) {
+    if threshold == 0 {
+        return;
+    }
+
+    // globals can't be reassigned, so a global defined as a lambda is always that lambda
+    let globals = bytecode
+        .windows(2)
+        .filter_map(|ops| match (ops[0].opcode, ops[1].opcode) {
+            (OpCode::Push(index), OpCode::Savg(name)) => Some((name, index)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    inline_calls(bytecode, constants, &globals, threshold);
+
+    for index in 0..constants.len() {
+        let Value::Fn(fun) = &constants[index] else {
+            continue;
+        };
+        let FnKind::Bytecode(body) = &*fun.body else {
+            continue;
+        };
+
+        let mut body = body.clone();
+        inline_calls(&mut body, constants, &globals, threshold);
+
+        constants[index] = Value::Fn(GcRef::new(Fn {
+            body: GcRef::new(FnKind::Bytecode(body)),
+            ..(**fun).clone()
+        }));
+    }
+}
+
+#[test]
+fn inline_small_functions() {
+    let source = "def double x = x * 2\ndef f y = double (y |> fn z -> z + 1)\ndef g n = g (n - 1)";
+    let (_, constants) = crate::parse(source).unwrap();
+
+    let bodies = constants
+        .iter()
+        .filter_map(|it| match it {
+            Value::Fn(f) => match &*f.body {
+                FnKind::Bytecode(body) => Some(body.iter().map(|op| op.opcode).collect::<Vec<_>>()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // `f` is the function that uses the locals of both of the inlined ones
+    let f = bodies
+        .iter()
+        .find(|ops| ops.contains(&OpCode::Save(2)))
+        .unwrap();
+    assert!(!f[..f.len() - 4]
+        .iter()
+        .any(|op| matches!(op, OpCode::Call(_))));
+    assert!(!f.contains(&OpCode::Loag("double".into())));
+
+    // `g` is recursive, so it calls itself
+    assert!(bodies
+        .iter()
+        .any(|ops| ops.contains(&OpCode::Loag("g".into()))));
+}
@@ -1,42 +1,153 @@
-use std::collections::HashMap;
+pub(crate) mod inline;
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{Mutex, PoisonError},
+};
 
 use vm::{
-    gc::GcRef, stackvec, Bytecode, EnvTable, Fn, FnKind, List, OpCode, OpCodeMetadata, Symbol,
-    Value, YexModule,
+    gc::GcRef, stackvec, Bytecode, EnvTable, Fn, FnKind, List, OpCode, OpCodeMetadata, Operand,
+    Operator, Symbol, Value, YexModule,
 };
 
-use crate::parser::ast::{
-    BinOp, Bind, Def, Expr, ExprKind, Literal, Location, MatchArm, Pattern, Stmt, StmtKind, VarDecl,
+use crate::{
+    import::Unit,
+    parser::{
+        ast::{
+            BinOp, Bind, Def, Expr, ExprKind, Literal, Location, MatchArm, Pattern, Stmt, StmtKind,
+            VarDecl,
+        },
+        code,
+    },
+    BENCH_PREFIX, TEST_PREFIX,
 };
 
+/// The flags defined for `@when`, for the programs compiled after they are
+static DEFINED: Mutex<Vec<Symbol>> = Mutex::new(Vec::new());
+
+/// Defines a flag, compiling the `@when` expressions that check it
+pub(crate) fn define(flag: Symbol) {
+    let mut defined = DEFINED.lock().unwrap_or_else(PoisonError::into_inner);
+    if !defined.contains(&flag) {
+        defined.push(flag);
+    }
+}
+
 #[derive(Default)]
 struct Scope {
     opcodes: Vec<OpCodeMetadata>,
+    /// the slot of each local variable in scope
     locals: HashMap<Symbol, usize>,
+    /// the slots of the variables hidden by newer ones with the same name
+    shadowed: HashMap<Symbol, Vec<usize>>,
+    /// the slots of the variables that went out of scope, which are reused
+    free: BTreeSet<usize>,
+    /// the number of slots used by the function
+    slots: usize,
+    /// if the scope contains a `yield`, making it a generator
+    generator: bool,
+    /// the locals of the enclosing functions the function uses, by the index `Loac` reads them at
+    captures: Vec<Symbol>,
+}
+
+/// Where a variable is read from by the function being compiled
+#[derive(Clone, Copy)]
+enum Resolved {
+    Local(usize),
+    Captured(usize),
 }
 
 impl Scope {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Gives a slot to a new variable, hiding the variable with the same name until it goes out of
+    /// scope
+    fn declare(&mut self, name: Symbol) -> usize {
+        // taking the lowest free slot keeps every slot below it in use, so a frame never saves
+        // past the slots it already has
+        let slot = self.free.pop_first().unwrap_or_else(|| {
+            self.slots += 1;
+            self.slots - 1
+        });
+
+        if let Some(hidden) = self.locals.insert(name, slot) {
+            self.shadowed.entry(name).or_default().push(hidden);
+        }
+        slot
+    }
+
+    /// Ends the scope of a variable, freeing its slot and showing the variable it hid, if any
+    fn release(&mut self, name: Symbol) {
+        let Some(slot) = self.locals.remove(&name) else {
+            return;
+        };
+        self.free.insert(slot);
+
+        if let Some(hidden) = self.shadowed.get_mut(&name).and_then(Vec::pop) {
+            self.locals.insert(name, hidden);
+        }
+    }
 }
 
+/// The names of the locals in scope, by the line and column where they are
+pub(crate) type Visible = HashMap<(usize, usize), Vec<Symbol>>;
+
 #[derive(Default)]
 pub struct Compiler {
     scope_stack: Vec<Scope>,
     constants: Vec<Value>,
     unique_counter: usize,
+    /// the number of opcodes of the largest function that's inlined
+    inline_threshold: usize,
+    /// the flags `@when` expressions are compiled for
+    defined: Vec<Symbol>,
+    /// the locals in scope where each global is loaded, by line and column, suggested by
+    /// `yex check` if the global turns out to be undefined
+    visible: Visible,
+    /// the globals some names refer to in the file being compiled, see [`Unit::aliases`]
+    aliases: HashMap<Symbol, Symbol>,
+    /// the namespaces of the modules the file being compiled imports
+    modules: HashSet<Symbol>,
 }
 
 impl Compiler {
     pub fn new() -> Self {
-        Compiler::default()
+        Compiler {
+            inline_threshold: inline::threshold(),
+            defined: DEFINED
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+            ..Compiler::default()
+        }
+    }
+
+    /// Finishes the compilation, inlining the calls to small functions
+    fn finish(mut self) -> (Bytecode, Vec<Value>) {
+        let mut bytecode = self.scope_stack.pop().unwrap().opcodes;
+        inline::run(&mut bytecode, &mut self.constants, self.inline_threshold);
+        (bytecode, self.constants)
+    }
+
+    /// Compiles an expression into a function of one ignored argument, which evaluates it
+    pub fn compile_fn(mut self, expr: &Expr) -> (Value, Vec<Value>) {
+        self.scope_stack.push(Scope::new());
+
+        let arg = Pattern::Id(Symbol::from("_"));
+        // there are no locals around it to capture
+        let (fun, _) = self.lambda_expr(&[arg], expr, &expr.location);
+        let idx = self.emit_const(Value::Fn(fun), &expr.location);
+
+        let (_, constants) = self.finish();
+        (constants[idx].clone(), constants)
     }
 
     pub fn compile_expr(mut self, expr: &Expr) -> (Bytecode, Vec<Value>) {
         self.scope_stack.push(Scope::new());
         self.expr(expr);
-        (self.scope_stack.pop().unwrap().opcodes, self.constants)
+        self.finish()
     }
 
     fn scope_mut(&mut self) -> &mut Scope {
@@ -61,15 +172,34 @@ impl Compiler {
         }
     }
 
-    fn emit_lit(&mut self, lit: &Literal, node: &Location) {
+    fn lit_index(&mut self, lit: &Literal) -> usize {
         if let Some(idx) = self.constants.iter().position(|c| lit == c) {
-            self.emit_op(OpCode::Push(idx), node);
+            idx
         } else {
             self.constants.push(lit.clone().into());
-            self.emit_op(OpCode::Push(self.constants.len() - 1), node);
+            self.constants.len() - 1
         }
     }
 
+    fn emit_lit(&mut self, lit: &Literal, node: &Location) {
+        let idx = self.lit_index(lit);
+        self.emit_op(OpCode::Push(idx), node);
+    }
+
+    /// Where an expression can be read from without compiling it, if it's a local or a literal
+    fn operand(&mut self, expr: &Expr) -> Option<Operand> {
+        let index = match &expr.kind {
+            ExprKind::Var(name) => {
+                let index = *self.scope().locals.get(name)?;
+                return u32::try_from(index).ok().map(Operand::Local);
+            }
+            ExprKind::Lit(lit) => self.lit_index(lit),
+            _ => return None,
+        };
+
+        u32::try_from(index).ok().map(Operand::Const)
+    }
+
     fn emit_const(&mut self, const_: Value, node: &Location) -> usize {
         if !matches!(const_, Value::Module(_)) {
             if let Some(idx) = self.constants.iter().position(|c| c == &const_) {
@@ -84,13 +214,22 @@ impl Compiler {
         pos
     }
 
+    /// Saves the value on the top of the stack to a new variable, which is in scope until it's
+    /// released
     fn emit_save(&mut self, bind: VarDecl, node: &Location) {
-        let len = self.scope().locals.len();
-
-        let index = *self.scope_mut().locals.entry(bind).or_insert(len);
+        let index = self.scope_mut().declare(bind);
         self.emit_op(OpCode::Save(index), node);
     }
 
+    /// Calls `res.close ()` for a variable, dropping what it returns
+    fn emit_close(&mut self, bind: &VarDecl, loc: &Location) {
+        self.emit_op(OpCode::Tup(0), loc);
+        self.emit_load(bind, loc);
+        self.emit_op(OpCode::Ref(Symbol::from("close")), loc);
+        self.emit_op(OpCode::Call(1), loc);
+        self.emit_op(OpCode::Pop, loc);
+    }
+
     fn emit_unique(&mut self, loc: &Location) -> Symbol {
         self.unique_counter += 1;
 
@@ -101,10 +240,63 @@ impl Compiler {
     }
 
     fn emit_load(&mut self, bind: &VarDecl, loc: &Location) {
-        if let Some(offset) = self.scope().locals.get(bind) {
-            self.emit_op(OpCode::Load(*offset), loc);
-        } else {
-            self.emit_op(OpCode::Loag(*bind), loc);
+        match self.resolve(self.scope_stack.len() - 1, *bind) {
+            Some(Resolved::Local(offset)) => self.emit_op(OpCode::Load(offset), loc),
+            Some(Resolved::Captured(index)) => self.emit_op(OpCode::Loac(index), loc),
+            None => self.emit_op(OpCode::Loag(self.global(*bind)), loc),
+        }
+    }
+
+    /// Finds a variable in the function at `depth` of the scope stack. If it's a local of an
+    /// enclosing function, it's captured by every function from that one to `depth`
+    fn resolve(&mut self, depth: usize, name: Symbol) -> Option<Resolved> {
+        let scope = &self.scope_stack[depth];
+        if let Some(offset) = scope.locals.get(&name) {
+            return Some(Resolved::Local(*offset));
+        }
+        if let Some(index) = scope.captures.iter().position(|it| *it == name) {
+            return Some(Resolved::Captured(index));
+        }
+
+        if depth == 0 || self.resolve(depth - 1, name).is_none() {
+            return None;
+        }
+        let captures = &mut self.scope_stack[depth].captures;
+        captures.push(name);
+        Some(Resolved::Captured(captures.len() - 1))
+    }
+
+    /// Pushes a function, as a closure of the values of the locals it captured from the function
+    /// being compiled, if any
+    fn emit_closure(&mut self, fun: Value, captures: &[Symbol], loc: &Location) {
+        for name in captures {
+            self.emit_load(name, loc);
+        }
+        self.emit_const(fun, loc);
+        if !captures.is_empty() {
+            self.emit_op(OpCode::Closure(captures.len()), loc);
+        }
+    }
+
+    /// The global a name refers to, which is under the namespace of the module it's from if it's
+    /// imported
+    fn global(&self, name: Symbol) -> Symbol {
+        self.aliases.get(&name).copied().unwrap_or(name)
+    }
+
+    /// The global `module.name`, if `expr` is the namespace of an imported module
+    fn module_global(&self, expr: &Expr, name: Symbol) -> Option<Symbol> {
+        match expr.kind {
+            ExprKind::Var(module)
+                if self.modules.contains(&module)
+                    && !self
+                        .scope_stack
+                        .iter()
+                        .any(|scope| scope.locals.contains_key(&module)) =>
+            {
+                Some(format!("{module}.{name}").into())
+            }
+            _ => None,
         }
     }
 
@@ -133,6 +325,16 @@ impl Compiler {
     }
 
     fn match_arm(&mut self, arm: &MatchArm, loc: &Location) -> usize {
+        self.match_arm_with(arm, loc, |this| this.expr(&arm.body))
+    }
+
+    /// Compiles the pattern and the guard of an arm, using `body` to compile its body
+    fn match_arm_with(
+        &mut self,
+        arm: &MatchArm,
+        loc: &Location,
+        body: impl FnOnce(&mut Self),
+    ) -> usize {
         // creates a stack of jmp indexes to be fixed later
         let (declarations, fix_stack) = self.match_pattern(&arm.cond, false, loc);
 
@@ -146,11 +348,11 @@ impl Compiler {
             None
         };
 
-        self.expr(&arm.body);
+        body(self);
 
         // remove all the declarations from the local scope
-        for decl in declarations {
-            self.scope_mut().locals.remove(&decl);
+        for decl in declarations.into_iter().rev() {
+            self.scope_mut().release(decl);
         }
 
         // emit a new jump, since we need to jump to the end of the when if the condition was
@@ -191,7 +393,7 @@ impl Compiler {
 
             Pattern::Id(id) if id.as_str() != "_" => {
                 if global {
-                    self.emit_op(OpCode::Savg(*id), loc);
+                    self.emit_op(OpCode::Savg(self.global(*id)), loc);
                 } else {
                     self.emit_save(*id, loc);
                 }
@@ -203,6 +405,12 @@ impl Compiler {
             }
 
             Pattern::Variant(path, args) => {
+                // the tags don't have the namespace of the module the type is from
+                let path = match path.split_first() {
+                    Some((module, rest)) if rest.len() > 1 && self.modules.contains(module) => rest,
+                    _ => &path[..],
+                };
+
                 // gets the tag of the value
                 let name = path
                     .iter()
@@ -232,7 +440,10 @@ impl Compiler {
 
                 // checks if the two "tuples" have the same length
                 self.emit_op(OpCode::Len, loc);
-                self.emit_lit(&Literal::Num(args.len() as f64), loc);
+                self.emit_lit(
+                    &Literal::Int(i64::try_from(args.len()).unwrap_or(i64::MAX)),
+                    loc,
+                );
                 self.emit_op(OpCode::Eq, loc);
 
                 // emit the jump place-holder
@@ -249,6 +460,7 @@ impl Compiler {
                     declarations.extend(decls);
                 }
 
+                self.scope_mut().release(local);
                 (declarations, labels)
             }
 
@@ -275,6 +487,7 @@ impl Compiler {
                     declarations.extend(decls);
                 }
 
+                self.scope_mut().release(local);
                 (declarations, labels)
             }
 
@@ -303,6 +516,7 @@ impl Compiler {
                 labels.extend(offsets);
                 declarations.extend(ids);
 
+                self.scope_mut().release(local);
                 (declarations, labels)
             }
 
@@ -316,6 +530,130 @@ impl Compiler {
 
                 (vec![], vec![offset])
             }
+
+            Pattern::Range(start, end) => {
+                let local = self.emit_unique(loc);
+                let mut labels = vec![];
+
+                // only numbers can be compared, anything else just doesn't match
+                self.emit_load(&local, loc);
+                self.emit_op(OpCode::Loag("Num".into()), loc);
+                self.emit_op(OpCode::IsA, loc);
+                labels.push(self.scope().opcodes.len());
+                self.emit_op(OpCode::Jmf(0), loc);
+
+                // start <= value
+                self.emit_lit(&Literal::Num(*start), loc);
+                self.emit_load(&local, loc);
+                self.emit_op(OpCode::LessEq, loc);
+                labels.push(self.scope().opcodes.len());
+                self.emit_op(OpCode::Jmf(0), loc);
+
+                // value <= end
+                self.emit_load(&local, loc);
+                self.emit_lit(&Literal::Num(*end), loc);
+                self.emit_op(OpCode::LessEq, loc);
+                labels.push(self.scope().opcodes.len());
+                self.emit_op(OpCode::Jmf(0), loc);
+
+                self.scope_mut().release(local);
+                (vec![], labels)
+            }
+
+            Pattern::Prefix(affix, pat) | Pattern::Suffix(pat, affix) => {
+                let method = if matches!(pattern, Pattern::Prefix(..)) {
+                    "stripPrefix"
+                } else {
+                    "stripSuffix"
+                };
+                let local = self.emit_unique(loc);
+
+                // `Str.stripPrefix` only takes strings, anything else just doesn't match
+                self.emit_load(&local, loc);
+                self.emit_op(OpCode::Loag("Str".into()), loc);
+                self.emit_op(OpCode::IsA, loc);
+                let mut labels = vec![self.scope().opcodes.len()];
+                self.emit_op(OpCode::Jmf(0), loc);
+
+                // the rest of the string, or `nil` if it doesn't have the affix
+                self.emit_lit(&Literal::Str(affix.clone()), loc);
+                self.emit_load(&local, loc);
+                self.emit_op(OpCode::RevN(2), loc);
+                self.emit_op(OpCode::Loag("Str".into()), loc);
+                self.emit_op(OpCode::Ref(method.into()), loc);
+                self.emit_op(OpCode::Call(2), loc);
+                self.scope_mut().release(local);
+
+                let rest = self.emit_unique(loc);
+                self.emit_load(&rest, loc);
+                self.emit_lit(&Literal::Unit, loc);
+                self.emit_op(OpCode::Eq, loc);
+                self.emit_op(OpCode::Not, loc);
+                labels.push(self.scope().opcodes.len());
+                self.emit_op(OpCode::Jmf(0), loc);
+
+                self.emit_load(&rest, loc);
+                let (declarations, offsets) = self.match_pattern(pat, global, loc);
+                labels.extend(offsets);
+
+                self.scope_mut().release(rest);
+                (declarations, labels)
+            }
+
+            Pattern::Typed(pat, path) => {
+                let local = self.emit_unique(loc);
+
+                // checks if the value is of the type at the path
+                self.emit_load(&local, loc);
+                let path = match path.split_first() {
+                    Some((module, rest)) if !rest.is_empty() && self.modules.contains(module) => {
+                        self.emit_op(OpCode::Loag(format!("{module}.{}", rest[0]).into()), loc);
+                        &rest[1..]
+                    }
+                    _ => {
+                        self.emit_op(OpCode::Loag(self.global(path[0])), loc);
+                        &path[1..]
+                    }
+                };
+                for name in path {
+                    self.emit_op(OpCode::Ref(*name), loc);
+                }
+                self.emit_op(OpCode::IsA, loc);
+
+                let mut labels = vec![self.scope().opcodes.len()];
+                self.emit_op(OpCode::Jmf(0), loc);
+
+                self.emit_load(&local, loc);
+                let (declarations, offsets) = self.match_pattern(pat, global, loc);
+                labels.extend(offsets);
+
+                self.scope_mut().release(local);
+                (declarations, labels)
+            }
+
+            Pattern::Fields(fields) => {
+                let local = self.emit_unique(loc);
+                let mut labels = vec![];
+                let mut declarations = vec![];
+
+                for (name, pat) in fields {
+                    // values without the field, or that aren't instances at all, don't match
+                    self.emit_load(&local, loc);
+                    self.emit_op(OpCode::HasField(*name), loc);
+                    labels.push(self.scope().opcodes.len());
+                    self.emit_op(OpCode::Jmf(0), loc);
+
+                    self.emit_load(&local, loc);
+                    self.emit_op(OpCode::Ref(*name), loc);
+
+                    let (decls, offsets) = self.match_pattern(pat, global, loc);
+                    labels.extend(offsets);
+                    declarations.extend(decls);
+                }
+
+                self.scope_mut().release(local);
+                (declarations, labels)
+            }
         }
     }
 
@@ -323,20 +661,24 @@ impl Compiler {
         // compiles the condition
         self.expr(cond);
 
+        self.match_arms(arms, loc);
+    }
+
+    /// Matches the value on the top of the stack against the arms
+    fn match_arms(&mut self, arms: &[MatchArm], loc: &Location) {
         let local = self.emit_unique(loc);
 
         // keep track of all the jump offsets
         let mut jmps = vec![];
 
-        self.emit_const("Starting match".to_string().into(), loc);
-        self.emit_op(OpCode::Pop, loc);
-
         for arm in arms {
             self.emit_load(&local, loc);
             let jmp = self.match_arm(arm, &arm.location);
             jmps.push(jmp);
         }
 
+        self.scope_mut().release(local);
+
         // this section is ignored by any arm, since they jump to the next section in the end,
         // thus, if anything here is executed, it's a sign that the match couldn't match anything,
         // which is probably a bug, so it will just raise a new exception
@@ -352,7 +694,57 @@ impl Compiler {
         }
     }
 
-    fn lambda_expr(&mut self, args: &[Pattern], body: &Expr, loc: &Location) -> GcRef<Fn> {
+    fn receive_expr(&mut self, arms: &[MatchArm], loc: &Location) {
+        // compiles the patterns and guards of the arms into a predicate, that's used by
+        // `Process.receive` to select the first message in the mailbox that matches any arm
+        self.scope_stack.push(Scope::new());
+
+        let local = self.emit_unique(loc);
+        let mut jmps = vec![];
+
+        for arm in arms {
+            self.emit_load(&local, loc);
+            let jmp = self.match_arm_with(arm, &arm.location, |this| {
+                this.emit_const(true.into(), loc);
+            });
+            jmps.push(jmp);
+        }
+
+        self.emit_const(false.into(), loc);
+
+        let ip = self.scope().opcodes.len();
+        for jmp in jmps {
+            self.scope_mut().opcodes[jmp].opcode = OpCode::Jmp(ip);
+        }
+
+        let Scope {
+            opcodes, captures, ..
+        } = self.scope_stack.pop().unwrap();
+        let predicate = Fn {
+            body: GcRef::new(FnKind::Bytecode(opcodes)),
+            arity: 1,
+            args: stackvec![],
+            signature: None,
+            captures: Box::new([]),
+        };
+
+        // takes the selected message out of the mailbox, the guards may use the locals around it
+        self.emit_closure(Value::Fn(GcRef::new(predicate)), &captures, loc);
+        self.emit_op(OpCode::Loag("Process".into()), loc);
+        self.emit_op(OpCode::Ref("receive".into()), loc);
+        self.emit_op(OpCode::Call(1), loc);
+
+        // and matches it against the arms
+        self.match_arms(arms, loc);
+    }
+
+    /// Compiles a function, also returning the locals around it that it captures
+    fn lambda_expr(
+        &mut self,
+        args: &[Pattern],
+        body: &Expr,
+        loc: &Location,
+    ) -> (GcRef<Fn>, Vec<Symbol>) {
         // creates the lambda scope
         self.scope_stack.push(Scope::new());
 
@@ -387,17 +779,30 @@ impl Compiler {
         self.scope_mut().opcodes[jmp_label].opcode = OpCode::Jmp(self.scope().opcodes.len());
 
         // pops the lambda scope
-        let Scope { opcodes, .. } = self.scope_stack.pop().unwrap();
+        let Scope {
+            opcodes,
+            generator,
+            captures,
+            ..
+        } = self.scope_stack.pop().unwrap();
+
+        // functions that yield are generators, calling them doesn't run the body right away
+        let body = if generator {
+            FnKind::Generator(opcodes)
+        } else {
+            FnKind::Bytecode(opcodes)
+        };
 
         // convert it to a `Fn` struct
         let func = Fn {
-            body: GcRef::new(FnKind::Bytecode(opcodes)),
+            body: GcRef::new(body),
             arity: args.len(),
             args: stackvec![],
+            signature: None,
+            captures: Box::new([]),
         };
 
-        // push the function onto the stack
-        GcRef::new(func)
+        (GcRef::new(func), captures)
     }
 
     fn expr(&mut self, node: &Expr) {
@@ -409,8 +814,8 @@ impl Compiler {
 
             // compiles a lambda expression
             ExprKind::Lambda { args, body } => {
-                let func = self.lambda_expr(args, body, loc);
-                self.emit_const(Value::Fn(func), loc);
+                let (func, captures) = self.lambda_expr(args, body, loc);
+                self.emit_closure(Value::Fn(func), &captures, loc);
             }
 
             ExprKind::App { callee, args, tail } => {
@@ -435,17 +840,103 @@ impl Compiler {
                 }
             }
 
-            ExprKind::Var(name) => {
-                // get the local index
-                let pred = self.scope().locals.get(name).copied();
+            ExprKind::Quote(quoted) => {
+                let (code, holes) = code::to_code(quoted);
+                self.emit_const(code, loc);
+
+                // the values of the `unquote`s, spliced into the holes they left in the code
+                if !holes.is_empty() {
+                    for hole in &holes {
+                        self.expr(hole);
+                    }
+                    self.emit_const(Value::List(List::new()), loc);
+                    for _ in &holes {
+                        self.emit_op(OpCode::Prep, loc);
+                    }
+
+                    self.emit_op(OpCode::RevN(2), loc);
+                    self.emit_op(OpCode::Loag(Symbol::from("Code")), loc);
+                    self.emit_op(OpCode::Ref(Symbol::from("splice")), loc);
+                    self.emit_op(OpCode::Call(2), loc);
+                }
+            }
+
+            ExprKind::Unquote(_) => unreachable!("'unquote' is only parsed in a 'quote'"),
+
+            ExprKind::Interp { pieces, exprs } => {
+                // the pieces and the values, as strings, added up from the first one
+                self.emit_const(pieces[0].clone().into(), loc);
+                for (expr, piece) in exprs.iter().zip(&pieces[1..]) {
+                    self.expr(expr);
+                    self.emit_op(OpCode::Loag(Symbol::from("to_str")), loc);
+                    self.emit_op(OpCode::Call(1), loc);
+                    self.emit_op(OpCode::Add, loc);
+                    if !piece.is_empty() {
+                        self.emit_const(piece.clone().into(), loc);
+                        self.emit_op(OpCode::Add, loc);
+                    }
+                }
+            }
+
+            ExprKind::Dbg { source, value } => {
+                let label = format!("[{}:{}] {source} =", loc.line, loc.column);
+
+                self.expr(value);
+                self.emit_const(label.into(), loc);
+                self.emit_op(OpCode::RevN(2), loc);
+                self.emit_op(OpCode::Loag(Symbol::from("dbg")), loc);
+                self.emit_op(OpCode::Call(2), loc);
+            }
 
-                if let Some(idx) = pred {
-                    // if the variable is in the current scope
-                    // emit the `Load` opcode, which loads a local
-                    self.emit_op(OpCode::Load(idx), loc);
+            ExprKind::When { flag, body } => {
+                if self.defined.contains(flag) {
+                    self.expr(body);
                 } else {
-                    // otherwise emit the `Loag` opcode, which loads a global
-                    self.emit_op(OpCode::Loag(*name), loc);
+                    self.emit_lit(&Literal::Unit, loc);
+                }
+            }
+
+            ExprKind::TryPipe { arg, callee } => {
+                self.expr(arg);
+
+                // `nil` and failed results are left on the stack, skipping the call
+                let mut skip_labels = vec![];
+                self.emit_op(OpCode::Dup, loc);
+                self.emit_lit(&Literal::Unit, loc);
+                self.emit_op(OpCode::Eq, loc);
+                self.emit_op(OpCode::Not, loc);
+                skip_labels.push(self.scope().opcodes.len());
+                self.emit_op(OpCode::Jmf(0), loc);
+
+                self.emit_op(OpCode::Dup, loc);
+                self.emit_op(OpCode::TagOf, loc);
+                self.emit_const(Symbol::from("Result.fail").into(), loc);
+                self.emit_op(OpCode::Eq, loc);
+                self.emit_op(OpCode::Not, loc);
+                skip_labels.push(self.scope().opcodes.len());
+                self.emit_op(OpCode::Jmf(0), loc);
+
+                self.expr(callee);
+                self.emit_op(OpCode::Call(1), loc);
+
+                for label in skip_labels {
+                    self.scope_mut().opcodes[label].opcode =
+                        OpCode::Jmf(self.scope().opcodes.len());
+                }
+            }
+
+            ExprKind::Var(name) => {
+                // a local of the function or of the ones around it, captured by the closure
+                self.emit_load(name, loc);
+
+                // otherwise it's a global
+                if let Some(OpCode::Loag(_)) = self.scope().opcodes.last().map(|op| op.opcode) {
+                    let locals = self
+                        .scope_stack
+                        .iter()
+                        .flat_map(|scope| scope.locals.keys().copied())
+                        .collect();
+                    self.visible.insert((loc.line, loc.column), locals);
                 }
             }
 
@@ -453,6 +944,17 @@ impl Compiler {
 
             ExprKind::Match { expr, arms } => self.match_expr(expr, arms, loc),
 
+            ExprKind::Receive { arms } => self.receive_expr(arms, loc),
+
+            // the running function, which the VM keeps in the frame
+            ExprKind::Recur => self.emit_op(OpCode::Recur, loc),
+
+            ExprKind::Yield(value) => {
+                self.expr(value);
+                self.emit_op(OpCode::Yield, loc);
+                self.scope_mut().generator = true;
+            }
+
             ExprKind::Let { bind, value, body } => {
                 // compiles the value and pushes it on the stack
                 self.expr(value);
@@ -463,8 +965,8 @@ impl Compiler {
                 self.expr(body);
 
                 // remove all the declarations from the local scope
-                for decl in declarations {
-                    self.scope_mut().locals.remove(&decl);
+                for decl in declarations.into_iter().rev() {
+                    self.scope_mut().release(decl);
                 }
 
                 // emit a jump to ignore the
@@ -498,6 +1000,7 @@ impl Compiler {
 
                 // emits a `nil` value, since everything should return something
                 self.expr(body);
+                self.scope_mut().release(*bind);
             }
 
             ExprKind::Binary { left, op, right } if op == &BinOp::And => {
@@ -544,9 +1047,24 @@ impl Compiler {
             }
 
             ExprKind::Binary { left, op, right } => {
-                self.expr(left);
-                self.expr(right);
-                self.emit_ops((*op).into(), loc);
+                let ops: &[OpCode] = (*op).into();
+
+                // locals and literals are read by the operator itself, instead of being pushed,
+                // only the left operand can be on the stack, since it's evaluated first
+                if let (Ok(operator), Some(right)) =
+                    (Operator::try_from(ops[0]), self.operand(right))
+                {
+                    let left = self.operand(left).unwrap_or_else(|| {
+                        self.expr(left);
+                        Operand::Stack
+                    });
+                    self.emit_op(OpCode::Bin(operator, left, right), loc);
+                    self.emit_ops(&ops[1..], loc);
+                } else {
+                    self.expr(left);
+                    self.expr(right);
+                    self.emit_ops(ops, loc);
+                }
             }
 
             ExprKind::List(xs) => {
@@ -579,8 +1097,50 @@ impl Compiler {
 
             // compiles a method reference access
             ExprKind::MethodRef { ty, method } => {
-                self.expr(ty);
-                self.emit_op(OpCode::Ref(*method), loc);
+                if let Some(global) = self.module_global(ty, *method) {
+                    self.emit_op(OpCode::Loag(global), loc);
+                } else {
+                    self.expr(ty);
+                    self.emit_op(OpCode::Ref(*method), loc);
+                }
+            }
+
+            ExprKind::With { bind, value, body } => {
+                self.expr(value);
+                self.emit_save(*bind, loc);
+
+                let try_label = self.scope().opcodes.len();
+                self.emit_op(OpCode::Try(0), loc);
+                self.expr(body);
+                self.emit_op(OpCode::EndTry, loc);
+                self.emit_close(bind, loc);
+
+                let end_label = self.scope().opcodes.len();
+                self.emit_op(OpCode::Jmp(0), loc);
+
+                // like in `try`, the instruction the error jumps to is skipped
+                self.scope_mut().opcodes[try_label].opcode =
+                    OpCode::Try(self.scope().opcodes.len());
+                self.emit_op(OpCode::Nop, loc);
+
+                // the error is only closed over, and raised again with its message
+                self.emit_op(OpCode::Pop, loc);
+                self.emit_close(bind, loc);
+                self.emit_op(OpCode::Rethrow, loc);
+
+                self.scope_mut().opcodes[end_label].opcode =
+                    OpCode::Jmp(self.scope().opcodes.len());
+                self.scope_mut().release(*bind);
+            }
+
+            ExprKind::SetField {
+                instance,
+                field,
+                value,
+            } => {
+                self.expr(instance);
+                self.expr(value);
+                self.emit_op(OpCode::SetField(*field), loc);
             }
 
             ExprKind::Try { body, bind, rescue } => {
@@ -605,15 +1165,33 @@ impl Compiler {
                 // pop the return from the try block
                 self.emit_op(OpCode::Pop, loc);
 
-                // saves the exception to the bind
-                self.emit_save(*bind, loc);
+                // keeps a copy of the exception, to raise it again if it doesn't match
+                self.emit_op(OpCode::Dup, loc);
+                let (declarations, fix_stack) = self.match_pattern(bind, false, loc);
+                self.emit_op(OpCode::Pop, loc);
 
                 // compiles the rescue block
                 self.expr(rescue);
+                for decl in declarations.into_iter().rev() {
+                    self.scope_mut().release(decl);
+                }
+
+                let rescued_label = self.scope().opcodes.len();
+                self.emit_op(OpCode::Jmp(0), loc);
+
+                for offset in fix_stack {
+                    self.scope_mut().opcodes[offset].opcode =
+                        OpCode::Jmf(self.scope().opcodes.len());
+                }
+
+                // raises the exception again, as it was
+                self.emit_op(OpCode::Pop, loc);
+                self.emit_op(OpCode::Rethrow, loc);
 
                 // fix the end of the rescue block
-                self.scope_mut().opcodes[end_label].opcode =
-                    OpCode::Jmp(self.scope().opcodes.len());
+                let end = self.scope().opcodes.len();
+                self.scope_mut().opcodes[end_label].opcode = OpCode::Jmp(end);
+                self.scope_mut().opcodes[rescued_label].opcode = OpCode::Jmp(end);
             }
 
             ExprKind::Tuple(xs) => {
@@ -633,7 +1211,7 @@ impl Compiler {
             // compiles a `def` statement into a `Savg` instruction
             StmtKind::Def(Def { bind, value, .. }) => {
                 self.expr(value);
-                self.emit_op(OpCode::Savg(*bind), &node.location);
+                self.emit_op(OpCode::Savg(self.global(*bind)), &node.location);
             }
 
             // compiles a `let` statement into a `Savg` instruction
@@ -664,6 +1242,13 @@ impl Compiler {
                     OpCode::Jmp(self.scope().opcodes.len());
             }
 
+            // compiles a `const` statement into a `Savg` instruction, the parser already made sure
+            // nothing else defines the name
+            StmtKind::Const { name, value } => {
+                self.expr(value);
+                self.emit_op(OpCode::Savg(self.global(*name)), loc);
+            }
+
             // compiles a `module` declaration into an YexModule and save the module to a global name
             StmtKind::Type {
                 name,
@@ -672,11 +1257,36 @@ impl Compiler {
             } => {
                 self.type_(name, variants, members, &node.location);
             }
+
+            // compiles a `test` declaration into a nullary function saved to a mangled global name
+            StmtKind::Test { name, body } => {
+                self.nullary_global(format!("{TEST_PREFIX}{name}").into(), body, loc);
+            }
+
+            // compiles a `bench` declaration the same way as a `test` one
+            StmtKind::Bench { name, body } => {
+                self.nullary_global(format!("{BENCH_PREFIX}{name}").into(), body, loc);
+            }
+
+            // the imports are loaded before compiling, see [`crate::import`]
+            StmtKind::Module(_) | StmtKind::Import { .. } => {}
+
+            // compiles an expression statement, dropping its value
+            StmtKind::Expr(expr) => {
+                self.expr(expr);
+                self.emit_op(OpCode::Pop, loc);
+            }
         };
 
         self.unique_counter = 0;
     }
 
+    fn nullary_global(&mut self, name: Symbol, body: &Expr, loc: &Location) {
+        let (func, captures) = self.lambda_expr(&[], body, loc);
+        self.emit_closure(Value::Fn(func), &captures, loc);
+        self.emit_op(OpCode::Savg(name), loc);
+    }
+
     fn type_(
         &mut self,
         decl: &VarDecl,
@@ -687,7 +1297,8 @@ impl Compiler {
         let mut table = EnvTable::new();
         for m in members {
             let func = match &m.value.kind {
-                ExprKind::Lambda { args, body } => Value::Fn(self.lambda_expr(args, body, loc)),
+                // types are declared at the top level, so there are no locals to capture
+                ExprKind::Lambda { args, body } => Value::Fn(self.lambda_expr(args, body, loc).0),
                 _ => unreachable!(),
             };
 
@@ -718,6 +1329,8 @@ impl Compiler {
                 body: GcRef::new(FnKind::Bytecode(opcodes)),
                 arity: args.len(),
                 args: stackvec![],
+                signature: None,
+                captures: Box::new([]),
             };
 
             table.insert(
@@ -726,7 +1339,9 @@ impl Compiler {
             );
         }
 
-        let mut type_ = GcRef::new(YexModule::new(*decl, table));
+        let mut type_ = YexModule::new(*decl, table);
+        type_.variants = variants.iter().cloned().collect();
+        let mut type_ = GcRef::new(type_);
         for (path, entry) in patch_list {
             unsafe {
                 let clone = type_.clone();
@@ -739,14 +1354,217 @@ impl Compiler {
 
         self.constants[index] = Value::Module(type_);
         self.emit_op(OpCode::Push(index), loc);
-        self.emit_op(OpCode::Savg(*decl), loc);
+        self.emit_op(OpCode::Savg(self.global(*decl)), loc);
     }
 
-    pub fn compile_stmts(mut self, stmts: &[Stmt]) -> (Vec<OpCodeMetadata>, Vec<Value>) {
+    pub fn compile_repl(mut self, stmts: &[Stmt], expr: Option<&Expr>) -> (Bytecode, Vec<Value>) {
         self.scope_stack.push(Scope::new());
         for stmt in stmts {
             self.stmt(stmt);
         }
-        (self.scope_stack.pop().unwrap().opcodes, self.constants)
+
+        // the value of the line is left on the stack
+        match expr {
+            Some(expr) => self.expr(expr),
+            None => {
+                self.emit_const(Value::Nil, &Location::default());
+            }
+        }
+
+        self.finish()
+    }
+
+    /// Compiles a program after the files it imports, in the order [`crate::import::load`]
+    /// returns them
+    pub fn compile_units(mut self, imported: Vec<Unit>, program: Unit) -> (Bytecode, Vec<Value>) {
+        self.scope_stack.push(Scope::new());
+        for unit in imported {
+            self.aliases = unit.aliases;
+            self.modules = unit.modules;
+            for stmt in &unit.stmts {
+                self.stmt(stmt);
+            }
+        }
+        self.aliases = program.aliases;
+        self.modules = program.modules;
+
+        // the value of a trailing expression of the program is its result
+        match program.stmts.split_last() {
+            Some((
+                Stmt {
+                    kind: StmtKind::Expr(expr),
+                    ..
+                },
+                rest,
+            )) => {
+                for stmt in rest {
+                    self.stmt(stmt);
+                }
+                self.expr(expr);
+            }
+            _ => {
+                for stmt in &program.stmts {
+                    self.stmt(stmt);
+                }
+            }
+        }
+        self.finish()
+    }
+
+    /// Compiles a top-level statement on its own, adding its constants to the ones of the
+    /// statements compiled before it, without inlining. The trailing expression of a program
+    /// (`value`) leaves its value on the stack
+    pub(crate) fn compile_chunk(
+        mut self,
+        constants: Vec<Value>,
+        stmt: &Stmt,
+        value: bool,
+    ) -> (Bytecode, Vec<Value>) {
+        self.constants = constants;
+        self.scope_stack.push(Scope::new());
+        match &stmt.kind {
+            StmtKind::Expr(expr) if value => self.expr(expr),
+            _ => self.stmt(stmt),
+        }
+
+        let Scope { opcodes, .. } = self.scope_stack.pop().unwrap();
+        (opcodes, self.constants)
+    }
+
+    /// Joins the chunks made by [`Compiler::compile_chunk`] into a program, pointing their jumps
+    /// to the new indexes, and inlines its calls like [`Compiler::compile_units`]
+    pub(crate) fn link_chunks<'a>(
+        mut self,
+        chunks: impl IntoIterator<Item = &'a [OpCodeMetadata]>,
+        constants: Vec<Value>,
+    ) -> (Bytecode, Vec<Value>) {
+        let mut opcodes = vec![];
+        for chunk in chunks {
+            let start = opcodes.len();
+            opcodes.extend(chunk.iter().map(|op| {
+                let opcode = match op.opcode {
+                    OpCode::Jmp(to) => OpCode::Jmp(to + start),
+                    OpCode::Jmf(to) => OpCode::Jmf(to + start),
+                    OpCode::Try(to) => OpCode::Try(to + start),
+                    opcode => opcode,
+                };
+                OpCodeMetadata { opcode, ..*op }
+            }));
+        }
+
+        self.constants = constants;
+        self.scope_stack.push(Scope {
+            opcodes,
+            ..Scope::new()
+        });
+        self.finish()
+    }
+
+    /// The flags `@when` expressions are compiled for
+    pub(crate) fn defined(&self) -> &[Symbol] {
+        &self.defined
+    }
+
+    /// Compiles the statements, also returning the locals in scope where each global is loaded
+    pub(crate) fn compile_checked(mut self, program: Unit) -> (Bytecode, Vec<Value>, Visible) {
+        self.aliases = program.aliases;
+        self.modules = program.modules;
+        self.scope_stack.push(Scope::new());
+        for stmt in &program.stmts {
+            self.stmt(stmt);
+        }
+
+        let visible = std::mem::take(&mut self.visible);
+        let (bytecode, constants) = self.finish();
+        (bytecode, constants, visible)
     }
 }
+
+#[test]
+fn compile_register_operands() {
+    let (bytecode, constants) = crate::parse("def f x = (x * 2) + x > 1").unwrap();
+    let Some(Value::Fn(f)) = constants.iter().find(|it| matches!(it, Value::Fn(_))) else {
+        panic!("expected a function constant");
+    };
+    let FnKind::Bytecode(body) = &*f.body else {
+        unreachable!()
+    };
+    let ops = body.iter().map(|it| it.opcode).collect::<Vec<_>>();
+
+    assert!(matches!(bytecode[0].opcode, OpCode::Push(_)));
+    assert!(matches!(
+        ops[..4],
+        [
+            OpCode::Save(0),
+            OpCode::Bin(Operator::Mul, Operand::Local(0), Operand::Const(_)),
+            OpCode::Bin(Operator::Add, Operand::Stack, Operand::Local(0)),
+            OpCode::Bin(Operator::LessEq, Operand::Stack, Operand::Const(_)),
+        ]
+    ));
+    assert_eq!(ops[4], OpCode::Not);
+}
+
+#[test]
+fn capture_enclosing_locals() {
+    let (bytecode, constants) =
+        crate::parse_expr("let a = 1 in let b = 2 in fn x -> fn y -> a + y").unwrap();
+    let ops = |value: &Value| match value {
+        Value::Fn(f) => match &*f.body {
+            FnKind::Bytecode(body) => body.iter().map(|it| it.opcode).collect::<Vec<_>>(),
+            _ => unreachable!(),
+        },
+        _ => vec![],
+    };
+
+    // only `a` is captured, by both functions, and the outer one builds the inner closure
+    assert!(matches!(
+        bytecode[4..7]
+            .iter()
+            .map(|it| it.opcode)
+            .collect::<Vec<_>>()[..],
+        [OpCode::Load(0), OpCode::Push(_), OpCode::Closure(1)]
+    ));
+    let outer = constants
+        .iter()
+        .map(ops)
+        .find(|ops| ops.contains(&OpCode::Closure(1)))
+        .unwrap();
+    assert!(outer.contains(&OpCode::Loac(0)));
+    assert!(constants
+        .iter()
+        .map(ops)
+        .any(|ops| !ops.contains(&OpCode::Closure(1)) && ops.contains(&OpCode::Loac(0))));
+}
+
+#[test]
+fn reuse_local_slots() {
+    let (bytecode, constants) =
+        crate::parse_expr("let a = 1 in let b = 2 in (let a = 3 in a) + (let c = 4 in a + b)")
+            .unwrap();
+    let saves = bytecode
+        .iter()
+        .filter_map(|op| match op.opcode {
+            OpCode::Save(slot) => Some(slot),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // the inner `a` and `c` take the same slot, after `a` and `b`
+    assert_eq!(saves, [0, 1, 2, 2]);
+
+    let mut vm = vm::VirtualMachine::default();
+    vm.set_consts(constants);
+    vm.run(&bytecode).unwrap();
+    assert_eq!(vm.pop_last(), &Value::Num(6.0));
+}
+
+#[test]
+fn compile_when_defined() {
+    let source = "println (@when(compile_when_test) \"on\")";
+    let (_, constants) = crate::parse(source).unwrap();
+    assert!(!constants.contains(&Value::from(String::from("on"))));
+
+    crate::define("compile_when_test");
+    let (_, constants) = crate::parse(source).unwrap();
+    assert!(constants.contains(&Value::from(String::from("on"))));
+}
@@ -0,0 +1,171 @@
+use crate::parser::ast::{Def, ExprKind, Literal, Pattern, Stmt, StmtKind};
+
+/// A declaration with its doc comment, the `///` lines right above it
+#[derive(Debug)]
+pub struct DocItem {
+    /// `def` or `type`
+    pub kind: &'static str,
+    /// The name it was declared with, members include the type name, like `Option.map`
+    pub name: String,
+    /// How it's declared, like `add x y`
+    pub signature: String,
+    /// The text of the doc comment, without the slashes
+    pub doc: String,
+    /// The documented methods, for types
+    pub members: Vec<DocItem>,
+}
+
+/// Gets the doc comment right above `line` (counting from 1)
+fn doc_comment(lines: &[&str], line: usize) -> String {
+    let mut doc = lines[..line.saturating_sub(1).min(lines.len())]
+        .iter()
+        .rev()
+        .map_while(|it| it.trim().strip_prefix("///"))
+        .map(|it| it.strip_prefix(' ').unwrap_or(it))
+        .collect::<Vec<_>>();
+
+    doc.reverse();
+    doc.join("\n")
+}
+
+fn pattern(pat: &Pattern) -> String {
+    match pat {
+        Pattern::Id(name) => name.to_string(),
+        Pattern::Lit(Literal::Str(s)) => format!("{s:?}"),
+        Pattern::Lit(Literal::Sym(s)) => format!(":{s}"),
+        Pattern::Lit(Literal::Int(n)) => n.to_string(),
+        Pattern::Lit(Literal::Num(n)) => n.to_string(),
+        Pattern::Lit(Literal::Bool(b)) => b.to_string(),
+        Pattern::Lit(Literal::Unit) => String::from("()"),
+        Pattern::Variant(path, args) => {
+            let path = path.iter().map(ToString::to_string).collect::<Vec<_>>();
+            let args = args.iter().map(pattern);
+            let parts = std::iter::once(path.join(".")).chain(args);
+            format!("({})", parts.collect::<Vec<_>>().join(" "))
+        }
+        Pattern::Tuple(items) => {
+            let items = items.iter().map(pattern).collect::<Vec<_>>();
+            format!("({})", items.join(", "))
+        }
+        Pattern::List(head, tail) => format!("({} :: {})", pattern(head), pattern(tail)),
+        Pattern::EmptyList => String::from("[]"),
+        Pattern::Range(start, end) => format!("{start}..{end}"),
+        Pattern::Prefix(prefix, rest) => format!("({prefix:?} <> {})", pattern(rest)),
+        Pattern::Suffix(start, suffix) => format!("({} <> {suffix:?})", pattern(start)),
+        Pattern::Typed(pat, ty) => {
+            let ty = ty.iter().map(ToString::to_string).collect::<Vec<_>>();
+            format!("({}: {})", pattern(pat), ty.join("."))
+        }
+        Pattern::Fields(fields) => {
+            let fields = fields
+                .iter()
+                .map(|(name, pat)| format!("{name}: {}", pattern(pat)))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", fields.join(", "))
+        }
+    }
+}
+
+fn def(lines: &[&str], def: &Def, line: usize, prefix: &str) -> DocItem {
+    let name = format!("{prefix}{}", def.bind);
+
+    let mut signature = vec![name.clone()];
+    if let ExprKind::Lambda { args, .. } = &def.value.kind {
+        signature.extend(args.iter().map(pattern));
+    }
+
+    DocItem {
+        kind: "def",
+        name,
+        signature: signature.join(" "),
+        doc: doc_comment(lines, line),
+        members: vec![],
+    }
+}
+
+/// Collects the documented `def` and `type` declarations of a program
+pub(crate) fn collect(source: &str, ast: &[Stmt]) -> Vec<DocItem> {
+    let lines = source.lines().collect::<Vec<_>>();
+    let mut items = vec![];
+
+    for stmt in ast {
+        let line = stmt.location.line;
+
+        match &stmt.kind {
+            StmtKind::Def(it) => {
+                let item = def(&lines, it, line, "");
+                if !item.doc.is_empty() {
+                    items.push(item);
+                }
+            }
+            StmtKind::Type {
+                name,
+                variants,
+                members,
+            } => {
+                let prefix = format!("{name}.");
+                let members = members
+                    .iter()
+                    .map(|it| def(&lines, it, it.value.location.line, &prefix))
+                    .filter(|it| !it.doc.is_empty())
+                    .collect::<Vec<_>>();
+
+                let doc = doc_comment(&lines, line);
+                if doc.is_empty() && members.is_empty() {
+                    continue;
+                }
+
+                let variants = variants
+                    .iter()
+                    .map(|(name, fields)| {
+                        let fields = fields.iter().map(ToString::to_string);
+                        // variants are saved with the type name, like `Option.some`
+                        let name = name.to_string();
+                        let name = name.strip_prefix(&prefix).unwrap_or(&name).to_string();
+                        std::iter::once(name)
+                            .chain(fields)
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .collect::<Vec<_>>();
+
+                items.push(DocItem {
+                    kind: "type",
+                    name: name.to_string(),
+                    signature: format!("{name} = {}", variants.join(" | ")),
+                    doc,
+                    members,
+                });
+            }
+            _ => (),
+        }
+    }
+
+    items
+}
+
+#[test]
+fn collect_doc_comments() {
+    let source = "\
+/// Adds two numbers
+/// together
+def add x y = x + y
+
+def undocumented x = x
+
+/// An optional value
+type Option = some x | none with
+  /// Gets the value
+  def get (Option.some x) = x
+end";
+
+    let items = crate::docs(source).unwrap();
+    assert_eq!(items.len(), 2);
+
+    assert_eq!(items[0].signature, "add x y");
+    assert_eq!(items[0].doc, "Adds two numbers\ntogether");
+
+    assert_eq!(items[1].signature, "Option = some x | none");
+    assert_eq!(items[1].members[0].signature, "Option.get (Option.some x)");
+    assert_eq!(items[1].members[0].doc, "Gets the value");
+}
@@ -1,3 +1,5 @@
+use vm::diagnostic::{Diagnostic, SYNTAX_ERROR};
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 /// Parse errors
 pub struct ParseError {
@@ -15,11 +17,41 @@ impl std::fmt::Display for ParseError {
 }
 
 impl ParseError {
-    pub(crate) fn throw<T>(line: usize, column: usize, message: String) -> Result<T, Self> {
-        Err(Self {
+    /// The line the error was found at
+    #[must_use]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The column the error was found at
+    #[must_use]
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// What went wrong
+    #[must_use]
+    pub fn message(&self) -> &str {
+        self.message
+    }
+
+    pub(crate) fn new(line: usize, column: usize, message: String) -> Self {
+        Self {
             line,
             column,
             message: Box::leak(message.into_boxed_str()),
-        })
+        }
+    }
+
+    pub(crate) fn throw<T>(line: usize, column: usize, message: String) -> Result<T, Self> {
+        Err(Self::new(line, column, message))
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(err: ParseError) -> Self {
+        Self::error(err.message)
+            .at(err.line, err.column)
+            .with_code(SYNTAX_ERROR)
     }
 }
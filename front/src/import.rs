@@ -0,0 +1,296 @@
+//! Loading the files a program imports with `import "path"` and `open "path"`. Each file is parsed
+//! and compiled once, before the files importing it, and its globals are saved under the namespace
+//! of its `module` declaration, like `Math.sqrt`. A bare name, like `import "json"`, imports the
+//! package fetched to `yex_modules` by `yex pkg`
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use vm::Symbol;
+
+use crate::{
+    error::{ParseError, ParseResult},
+    lexer::Lexer,
+    parser::{
+        ast::{Pattern, Stmt, StmtKind},
+        Parser,
+    },
+};
+
+/// The directory the packages are fetched to, in the root of a project
+pub const MODULES: &str = "yex_modules";
+
+/// The file of a package that is loaded when it's imported
+pub const PACKAGE_ENTRY: &str = "lib.yex";
+
+/// A file of the program, with the globals its names refer to
+pub(crate) struct Unit {
+    pub stmts: Vec<Stmt>,
+    /// The globals some of the names used in the file refer to, the names of its own top-level
+    /// declarations if it's imported, which are under its namespace, and the globals of the
+    /// modules it opens
+    pub aliases: HashMap<Symbol, Symbol>,
+    /// The namespaces of the modules the file imports
+    pub modules: HashSet<Symbol>,
+}
+
+/// The namespace of a file that's loaded, and the names of the globals under it
+#[derive(Clone)]
+struct Module {
+    namespace: Symbol,
+    globals: Vec<Symbol>,
+}
+
+#[derive(Default)]
+struct Loader {
+    units: Vec<Unit>,
+    loaded: HashMap<PathBuf, Module>,
+    /// The files being loaded, each one imported by the one before it
+    loading: Vec<PathBuf>,
+}
+
+/// Loads the files a program imports, whose paths are relative to `dir`, in the order they are
+/// compiled, along with the program itself
+pub(crate) fn load(stmts: Vec<Stmt>, dir: &Path) -> ParseResult<(Vec<Unit>, Unit)> {
+    let mut loader = Loader::default();
+    let program = loader.unit(stmts, dir, None)?;
+    Ok((loader.units, program))
+}
+
+/// Checks if `name` can be the name of a package, which is a directory right inside
+/// [`MODULES`]
+#[must_use]
+pub fn is_package_name(name: &str) -> bool {
+    !matches!(name, "" | "." | "..") && !name.contains(['/', '\\', ':'])
+}
+
+/// The entry of the package an import refers to, if the path is a package name rather than a
+/// file, in the closest directory above `dir` with a [`MODULES`] directory that has it
+fn package(dir: &Path, path: &str) -> Option<Result<PathBuf, String>> {
+    if !is_package_name(path) || Path::new(path).extension().is_some_and(|it| it == "yex") {
+        return None;
+    }
+
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let entry = dir
+        .ancestors()
+        .map(|it| it.join(MODULES).join(path))
+        .find(|it| it.is_dir())
+        .map(|it| it.join(PACKAGE_ENTRY))
+        .ok_or_else(|| {
+            format!("Can't find the package '{path}' in {MODULES}, try running `yex pkg install`")
+        });
+    Some(entry)
+}
+
+/// The name of a global under a namespace
+fn qualified(namespace: Symbol, name: Symbol) -> Symbol {
+    format!("{namespace}.{name}").into()
+}
+
+/// The names of the globals the top-level declarations define
+fn globals(stmts: &[Stmt]) -> Vec<Symbol> {
+    fn bound(pattern: &Pattern, names: &mut Vec<Symbol>) {
+        match pattern {
+            Pattern::Id(id) if id.as_str() != "_" => names.push(*id),
+            Pattern::Variant(_, patterns) | Pattern::Tuple(patterns) => {
+                for pattern in patterns {
+                    bound(pattern, names);
+                }
+            }
+            Pattern::List(head, tail) => {
+                bound(head, names);
+                bound(tail, names);
+            }
+            Pattern::Typed(pattern, _)
+            | Pattern::Prefix(_, pattern)
+            | Pattern::Suffix(pattern, _) => {
+                bound(pattern, names);
+            }
+            Pattern::Fields(fields) => fields.iter().for_each(|(_, it)| bound(it, names)),
+            _ => {}
+        }
+    }
+
+    let mut names = vec![];
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Def(def) => names.push(def.bind),
+            StmtKind::Let { bind, .. } => bound(bind, &mut names),
+            StmtKind::Const { name, .. } | StmtKind::Type { name, .. } => names.push(*name),
+            _ => {}
+        }
+    }
+    names
+}
+
+impl Loader {
+    /// Loads the imports of a file, `namespace` is the one of the file if another file imports it
+    fn unit(
+        &mut self,
+        stmts: Vec<Stmt>,
+        dir: &Path,
+        namespace: Option<Symbol>,
+    ) -> ParseResult<Unit> {
+        let own = globals(&stmts);
+        let mut aliases = HashMap::new();
+        let mut modules = HashSet::new();
+
+        for stmt in &stmts {
+            let StmtKind::Import { path, open } = &stmt.kind else {
+                continue;
+            };
+
+            let module = match package(dir, path) {
+                Some(Ok(entry)) => self.file(&entry, Some(path), stmt)?,
+                Some(Err(message)) => {
+                    let loc = &stmt.location;
+                    return Err(ParseError::new(loc.line, loc.column, message));
+                }
+                None => self.file(&dir.join(path), None, stmt)?,
+            };
+            modules.insert(module.namespace);
+            if *open {
+                // the globals of the file hide the ones of the modules it opens
+                for name in module.globals.iter().filter(|it| !own.contains(it)) {
+                    aliases.insert(*name, qualified(module.namespace, *name));
+                }
+            }
+        }
+
+        let mut stmts = stmts;
+        if let Some(namespace) = namespace {
+            aliases.extend(own.iter().map(|it| (*it, qualified(namespace, *it))));
+            // only the tests and the benchmarks of the program are run
+            stmts.retain(|it| !matches!(it.kind, StmtKind::Test { .. } | StmtKind::Bench { .. }));
+        }
+
+        Ok(Unit {
+            stmts,
+            aliases,
+            modules,
+        })
+    }
+
+    /// Loads a file imported by the `import` statement, unless it's already loaded, `package` is
+    /// the name of the package it's the entry of
+    fn file(&mut self, path: &Path, package: Option<&str>, import: &Stmt) -> ParseResult<Module> {
+        let loc = &import.location;
+        let error = |message: String| ParseError::new(loc.line, loc.column, message);
+
+        let path = fs::canonicalize(path)
+            .map_err(|err| error(format!("Can't import '{}': {err}", path.display())))?;
+        if let Some(module) = self.loaded.get(&path) {
+            return Ok(module.clone());
+        }
+        if self.loading.contains(&path) {
+            return Err(error(format!("Circular import of '{}'", path.display())));
+        }
+
+        let source = fs::read_to_string(&path)
+            .map_err(|err| error(format!("Can't import '{}': {err}", path.display())))?;
+        let in_file = |err: ParseError| error(format!("In '{}': {err}", path.display()));
+        let stmts = Parser::new(Lexer::new(source))
+            .and_then(Parser::parse)
+            .map_err(in_file)?;
+
+        // the namespace is the one of the `module` declaration, or else the name of the package
+        // or of the file
+        let namespace = stmts
+            .iter()
+            .find_map(|it| match it.kind {
+                StmtKind::Module(name) => Some(name),
+                _ => None,
+            })
+            .unwrap_or_else(|| {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let mut chars = package.unwrap_or(&stem).chars();
+                let first = chars.next().map(|it| it.to_uppercase().collect::<String>());
+                format!("{}{}", first.unwrap_or_default(), chars.as_str()).into()
+            });
+        if let Some((other, _)) = self.loaded.iter().find(|(_, it)| it.namespace == namespace) {
+            return Err(error(format!(
+                "'{}' and '{}' are both the module '{namespace}'",
+                other.display(),
+                path.display()
+            )));
+        }
+
+        let module = Module {
+            namespace,
+            globals: globals(&stmts),
+        };
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        self.loading.push(path.clone());
+        let unit = self.unit(stmts, &dir, Some(namespace)).map_err(in_file);
+        self.loading.pop();
+
+        self.units.push(unit?);
+        self.loaded.insert(path, module.clone());
+        Ok(module)
+    }
+}
+
+#[test]
+fn import_files() {
+    let dir = std::env::temp_dir().join("yex_import");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(
+        dir.join("lib/geometry.yex"),
+        "module Geo\nopen \"square.yex\"\ntype Point = p x y with end\ndef norm2 pt = square pt.x + square pt.y\ntest \"skipped\" = false",
+    )
+    .unwrap();
+    fs::write(dir.join("lib/square.yex"), "def square x = x * x").unwrap();
+    fs::write(dir.join("cycle.yex"), "import \"cycle.yex\"").unwrap();
+
+    let source = "import \"lib/geometry.yex\"\nimport \"lib/square.yex\"\nmatch Geo.Point.p 3 4 with\n| Geo.Point.p x y -> Geo.norm2 (Geo.Point.p x y) + Square.square 2";
+    let (bytecode, constants) = crate::parse_in(source, &dir).unwrap();
+    let mut vm = vm::VirtualMachine::default();
+    vm.set_consts(constants);
+    vm.run(&bytecode).unwrap();
+    assert_eq!(vm.pop_last(), &vm::Value::Num(29.0));
+    assert!(vm.get_global("Geo.norm2").is_some());
+    assert!(vm.get_global("norm2").is_none());
+    assert!(vm.get_global("test skipped").is_none());
+
+    let (imported, _) = load(
+        crate::Parser::new(Lexer::new(source))
+            .unwrap()
+            .parse()
+            .unwrap(),
+        &dir,
+    )
+    .unwrap();
+    // the square file is compiled once, before the geometry one
+    assert_eq!(imported.len(), 2);
+
+    assert!(crate::parse_in("import \"cycle.yex\"", &dir).is_err());
+    assert!(crate::parse_in("import \"missing.yex\"", &dir).is_err());
+}
+
+#[test]
+fn import_packages() {
+    let dir = std::env::temp_dir().join("yex_import_packages");
+    fs::create_dir_all(dir.join(MODULES).join("json")).unwrap();
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join(MODULES).join("json").join(PACKAGE_ENTRY),
+        "open \"num.yex\"\ndef parse s = digit s",
+    )
+    .unwrap();
+    fs::write(dir.join(MODULES).join("json/num.yex"), "def digit s = 7").unwrap();
+
+    // the package is found above the importing file, and named after the package
+    let (bytecode, constants) =
+        crate::parse_in("import \"json\"\nJson.parse \"7\"", &dir.join("src")).unwrap();
+    let mut vm = vm::VirtualMachine::default();
+    vm.set_consts(constants);
+    vm.run(&bytecode).unwrap();
+    assert_eq!(vm.pop_last(), &vm::Value::Int(7));
+
+    assert!(crate::parse_in("import \"missing\"", &dir).is_err());
+    assert!(!is_package_name(".."));
+    assert!(!is_package_name("a/b"));
+}
@@ -17,8 +17,17 @@ type Tk = Result<Token, ParseError>;
 
 impl Lexer {
     pub fn new<T: Into<String>>(t: T) -> Self {
+        let mut tokens = t.into().chars().collect::<Vec<_>>();
+
+        // a `#!/usr/bin/env yex` line is blanked out, so the positions of the rest stay the same
+        if tokens.starts_with(&['#', '!']) {
+            for c in tokens.iter_mut().take_while(|c| **c != '\n') {
+                *c = ' ';
+            }
+        }
+
         Self {
-            tokens: t.into().chars().collect(),
+            tokens,
             line: 1,
             column: 1,
             idx: 0,
@@ -35,6 +44,13 @@ impl Lexer {
         self.idx = idx;
     }
 
+    /// The source between two character indexes
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        self.tokens[start..end.min(self.tokens.len())]
+            .iter()
+            .collect()
+    }
+
     fn throw<A, T: Into<String>>(&self, str: T) -> Result<A, ParseError> {
         ParseError::throw(self.line, self.column, str.into())
     }
@@ -88,9 +104,14 @@ impl Lexer {
             self.next();
         }
         self.back();
-        let unicode = u32::from_str_radix(&unicode, 16).unwrap();
-        let unicode = char::from_u32(unicode).unwrap();
-        Ok(unicode)
+        // surrogates and codes past the last one aren't characters
+        match u32::from_str_radix(&unicode, 16)
+            .ok()
+            .and_then(char::from_u32)
+        {
+            Some(unicode) => Ok(unicode),
+            None => self.throw("malformed Unicode character escape sequence"),
+        }
     }
 
     fn escape_char(&mut self) -> Result<String, ParseError> {
@@ -103,6 +124,7 @@ impl Lexer {
             '0' => EOF,
             '\\' => '\\',
             '"' => '"',
+            '#' => '#',
             'r' => '\r',
             other => self.throw(format!("Unknow escape char '{}'", other))?,
         };
@@ -133,11 +155,100 @@ impl Lexer {
         Ok(item)
     }
 
+    /// A string that may have `#{expr}` in it, which is split into the pieces around the
+    /// expressions and their sources
+    fn take_interp(&mut self) -> Result<TokenType, ParseError> {
+        let mut pieces = vec![String::new()];
+        let mut exprs = vec![];
+
+        while self.current() != '"' {
+            match self.current() {
+                '\\' => {
+                    self.next();
+                    let chr = self.escape_char()?;
+                    pieces.last_mut().unwrap().push_str(&chr);
+                }
+                '#' if self.peek_at(1) == '{' => {
+                    self.next();
+                    self.next();
+                    let (line, column) = (self.line, self.column);
+                    exprs.push((self.take_code()?, line, column));
+                    pieces.push(String::new());
+                }
+                EOF => self.throw("Unclosed delimiter opened here")?,
+                other => {
+                    pieces.last_mut().unwrap().push(other);
+                    self.next();
+                }
+            }
+        }
+        self.back();
+
+        Ok(match pieces.pop() {
+            Some(piece) if exprs.is_empty() => TokenType::Str(piece),
+            last => {
+                pieces.extend(last);
+                TokenType::Interp(pieces, exprs)
+            }
+        })
+    }
+
+    /// The source of an expression in a string, up to the `}` that closes it
+    fn take_code(&mut self) -> Result<String, ParseError> {
+        let mut code = String::new();
+        let mut depth = 0;
+
+        loop {
+            match self.current() {
+                EOF => self.throw("Unclosed '#{' in the string")?,
+                '}' if depth == 0 => {
+                    self.next();
+                    return Ok(code);
+                }
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                // the strings in the expression may have braces too
+                '"' => {
+                    code.push('"');
+                    self.next();
+                    while !matches!(self.current(), '"' | EOF) {
+                        if self.current() == '\\' {
+                            code.push('\\');
+                            self.next();
+                        }
+                        code.push(self.current());
+                        self.next();
+                    }
+                }
+                _ => (),
+            }
+            code.push(self.current());
+            self.next();
+        }
+    }
+
     fn peek_at(&self, n: usize) -> char {
         *self.tokens.get(self.idx + n).unwrap_or(&EOF)
     }
 
+    /// The indentation of the current line, if there's nothing but it before the current char
+    fn indent(&self) -> Option<usize> {
+        let before = &self.tokens[..self.idx.min(self.tokens.len())];
+        let start = before
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map_or(0, |it| it + 1);
+        let indent = &before[start..];
+        indent
+            .iter()
+            .all(|c| c.is_whitespace())
+            .then_some(indent.len())
+    }
+
     fn get(&mut self) -> Tk {
+        let indent = self.indent();
+        let start = self.idx;
+
         let tk = match self.current() {
             // comments
             '/' if self.peek_at(1) == '/' => {
@@ -156,6 +267,15 @@ impl Lexer {
 
             '/' => TokenType::Div,
             '*' => TokenType::Mul,
+            '%' if self.peek_at(1) == '%' => {
+                self.next();
+                TokenType::Mod
+            }
+            '%' if self.peek_at(1) == '/' && self.peek_at(2) == '%' => {
+                self.next();
+                self.next();
+                TokenType::FloorDiv
+            }
             '%' => TokenType::Rem,
             '=' if self.peek_at(1) == '=' => {
                 self.next();
@@ -183,10 +303,23 @@ impl Lexer {
             ':' if self.peek_at(1).is_alphanumeric() => {
                 self.next();
 
-                let sym = self.take_while(|c| c.is_alphanumeric() || c == '_')?;
+                let mut sym = self.take_while(|c| c.is_alphanumeric() || c == '_')?;
+                // the same suffixes as names, so `:empty?` names the `empty?` method
+                while matches!(self.peek_at(1), '?' | '!' | '\'') {
+                    self.next();
+                    sym.push(self.current());
+                }
 
                 TokenType::Sym(Symbol::new(sym))
             }
+            // symbols that aren't names are quoted, like `:"two words"`
+            ':' if self.peek_at(1) == '"' => {
+                self.next();
+                self.next();
+                let sym = self.take_str()?;
+                self.next();
+                TokenType::Sym(Symbol::new(sym))
+            }
             ':' => TokenType::Colon,
 
             '=' => TokenType::Assign,
@@ -196,15 +329,40 @@ impl Lexer {
             }
             '"' => {
                 self.next();
-                let a = TokenType::Str(self.take_str()?);
+                let a = self.take_interp()?;
                 self.next();
                 a
             }
             c if c.is_numeric() => {
-                let n = self.take_while(|c| c.is_numeric() || c == '.')?;
-                match n.parse::<f64>() {
-                    Ok(n) => TokenType::Num(n),
-                    Err(_) => self.throw(format!("Can't parse number {}", n))?,
+                let mut n = self.take_while(char::is_numeric)?;
+                let digits = n.len();
+                // only a dot followed by a digit starts the decimals, `1..9` is a range
+                if self.peek_at(1) == '.' && self.peek_at(2).is_numeric() {
+                    self.next();
+                    n.push('.');
+                    self.next();
+                    n.push_str(&self.take_while(char::is_numeric)?);
+                }
+                // `1e300` and `1.5e-8`, the way the numbers too big or too small are written
+                let sign = usize::from(matches!(self.peek_at(2), '+' | '-'));
+                if matches!(self.peek_at(1), 'e' | 'E') && self.peek_at(2 + sign).is_numeric() {
+                    for _ in 0..=sign {
+                        n.push(self.next());
+                    }
+                    self.next();
+                    n.push_str(&self.take_while(char::is_numeric)?);
+                }
+                // the numbers without decimals nor an exponent are integers
+                if n.len() == digits {
+                    match n.parse::<i64>() {
+                        Ok(n) => TokenType::Int(n),
+                        Err(_) => self.throw(format!("Can't parse number {n}, it's too large"))?,
+                    }
+                } else {
+                    match n.parse::<f64>() {
+                        Ok(n) => TokenType::Num(n),
+                        Err(_) => self.throw(format!("Can't parse number {}", n))?,
+                    }
                 }
             }
             c if c.is_alphabetic() || c == '_' => {
@@ -232,6 +390,12 @@ impl Lexer {
                 self.next();
                 TokenType::BitOr
             }
+            '>' if self.peek_at(1) == '>' && self.peek_at(2) == '>' && self.peek_at(3) == '>' => {
+                self.next();
+                self.next();
+                self.next();
+                TokenType::UShr
+            }
             '>' if self.peek_at(1) == '>' && self.peek_at(2) == '>' => {
                 self.next();
                 self.next();
@@ -253,18 +417,31 @@ impl Lexer {
                 self.next();
                 TokenType::BitXor
             }
+            '~' if self.peek_at(1) == '~' && self.peek_at(2) == '~' => {
+                self.next();
+                self.next();
+                TokenType::BitNot
+            }
             ',' => TokenType::Comma,
             ';' => TokenType::Semicolon,
             '<' if self.peek_at(1) == '=' => {
                 self.next();
                 TokenType::LessEq
             }
+            '<' if self.peek_at(1) == '>' => {
+                self.next();
+                TokenType::Concat
+            }
             '<' => TokenType::Less,
             '>' if self.peek_at(1) == '=' => {
                 self.next();
                 TokenType::GreaterEq
             }
             '>' => TokenType::Greater,
+            '.' if self.peek_at(1) == '.' => {
+                self.next();
+                TokenType::DotDot
+            }
             '.' => TokenType::Dot,
 
             // Logical
@@ -272,12 +449,19 @@ impl Lexer {
                 self.next();
                 TokenType::And
             }
+            '&' => TokenType::Amp,
+            '@' => TokenType::At,
             '|' if self.peek_at(1) == '|' => {
                 self.next();
                 TokenType::Or
             }
             '!' => TokenType::Not,
 
+            '|' if self.peek_at(1) == '>' && self.peek_at(2) == '?' => {
+                self.next();
+                self.next();
+                TokenType::TryPipe
+            }
             '|' if self.peek_at(1) == '>' => {
                 self.next();
                 TokenType::Pipe
@@ -298,6 +482,9 @@ impl Lexer {
             line: self.line,
             column: self.column,
             token: tk,
+            indent,
+            start,
+            end: self.idx + 1,
         })
     }
 }
@@ -324,7 +511,10 @@ fn string_delimiter() {
 #[test]
 fn parse_number() {
     let token = Lexer::new("100").get().unwrap();
-    assert_eq!(token.token, TokenType::Num(100.0))
+    assert_eq!(token.token, TokenType::Int(100));
+    let token = Lexer::new("100.0").get().unwrap();
+    assert_eq!(token.token, TokenType::Num(100.0));
+    assert!(Lexer::new("9223372036854775808").get().is_err());
 }
 
 #[test]
@@ -332,3 +522,83 @@ fn parse_string() {
     let string = Lexer::new(r#""Hello, world!""#).get().unwrap();
     assert_eq!(string.token, TokenType::Str(String::from("Hello, world!")))
 }
+
+#[test]
+fn skip_shebang() {
+    let token = Lexer::new("#!/usr/bin/env yex\n100").get().unwrap();
+    assert_eq!(token.token, TokenType::Int(100));
+    assert_eq!(token.line, 2);
+}
+
+#[test]
+fn parse_exponents() {
+    let tokens = Lexer::new("1e300 1.5e-8 2E+3 4e")
+        .take(5)
+        .map(|token| token.unwrap().token)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tokens,
+        [
+            TokenType::Num(1e300),
+            TokenType::Num(1.5e-8),
+            TokenType::Num(2e3),
+            TokenType::Int(4),
+            TokenType::Name(Symbol::new("e")),
+        ]
+    );
+}
+
+#[test]
+fn parse_range() {
+    let tokens = Lexer::new("1..9.5")
+        .take(3)
+        .map(|token| token.unwrap().token)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tokens,
+        [TokenType::Int(1), TokenType::DotDot, TokenType::Num(9.5)]
+    );
+}
+
+#[test]
+fn parse_interpolation() {
+    let token = Lexer::new(r#""a#{ "}" + b }c#{1}""#).get().unwrap();
+    assert_eq!(
+        token.token,
+        TokenType::Interp(
+            vec!["a".into(), "c".into(), String::new()],
+            vec![(r#" "}" + b "#.into(), 1, 5), ("1".into(), 1, 18)]
+        )
+    );
+    assert!(Lexer::new(r#""a#{b""#).get().is_err());
+    let token = Lexer::new(r#""\#{b}""#).get().unwrap();
+    assert_eq!(token.token, TokenType::Str(String::from("#{b}")));
+}
+
+#[test]
+fn malformed_unicode_escapes() {
+    // surrogates and codes past the last character
+    for source in [r#""\ud800""#, r#""\UFFFFFFFF""#] {
+        assert!(Lexer::new(source).get().is_err());
+    }
+    let token = Lexer::new(r#""é""#).get().unwrap();
+    assert_eq!(token.token, TokenType::Str(String::from("é")));
+}
+
+#[test]
+fn parse_floor_division_and_modulo() {
+    let tokens = Lexer::new("div %/% mod %% %")
+        .take(5)
+        .map(|token| token.unwrap().token)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tokens,
+        [
+            TokenType::Name(Symbol::new("div")),
+            TokenType::FloorDiv,
+            TokenType::Name(Symbol::new("mod")),
+            TokenType::Mod,
+            TokenType::Rem,
+        ]
+    );
+}
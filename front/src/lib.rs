@@ -9,28 +9,199 @@
 )]
 
 //! Compiler for the yex language
+mod check;
 mod compiler;
+mod doc;
 mod error;
+mod import;
 mod lexer;
 mod parser;
+mod session;
 mod tokens;
+mod value;
 
 use compiler::Compiler;
+pub use doc::DocItem;
 pub use error::ParseError;
+pub use import::{is_package_name, MODULES, PACKAGE_ENTRY};
+pub use session::Session;
+pub use tokens::KEYWORDS;
 
 use error::ParseResult;
 use lexer::Lexer;
 use parser::Parser;
-use vm::{Bytecode, Value};
+use std::path::Path;
+use vm::{diagnostic::Diagnostic, Bytecode, Value, VirtualMachine};
 
-/// Parses a given string into an AST
+/// Prefix of the global names `test` declarations are saved under, a `test "name" = expr`
+/// declaration is compiled to a nullary function stored in the `"test name"` global
+pub const TEST_PREFIX: &str = "test ";
+
+/// Prefix of the global names `bench` declarations are saved under, works like [`TEST_PREFIX`]
+pub const BENCH_PREFIX: &str = "bench ";
+
+/// The number of opcodes of the largest function whose calls are inlined, unless changed with
+/// [`set_inline_threshold`]
+pub const DEFAULT_INLINE_THRESHOLD: usize = compiler::inline::DEFAULT_THRESHOLD;
+
+/// Sets the number of opcodes of the largest function whose calls are inlined by the programs
+/// compiled after it, `0` disables inlining. Only small functions that call nothing are inlined,
+/// which include the lambdas called right away, like the ones in pipes, and top-level `def`s
+pub fn set_inline_threshold(threshold: usize) {
+    compiler::inline::set_threshold(threshold);
+}
+
+/// Defines a flag for the programs compiled after it, which compile the expressions under
+/// `@when(flag)` instead of replacing them by `nil`
+pub fn define(flag: &str) {
+    compiler::define(flag.into());
+}
+
+/// Compiles the nodes of quoted code into a function of one ignored argument that evaluates
+/// them, this is the compiler of the `compile` native, set with [`vm::literal::code::set_compiler`]
+pub fn compile_fn(code: &Value) -> Result<(Value, Vec<Value>), String> {
+    let ast = parser::code::from_code(code)?;
+    Ok(Compiler::new().compile_fn(&ast))
+}
+
+/// Reads a value from the code its `Display` writes, which is made only of literals, lists,
+/// tuples and the variants of the types defined in `vm`, this is the parser of [`Value::parse`],
+/// set with [`vm::literal::set_parser`]
+pub fn parse_value(vm: &VirtualMachine, source: &str) -> Result<Value, String> {
+    let lexer = Lexer::new(source);
+    let expr = Parser::new(lexer)
+        .and_then(Parser::parse_only_expr)
+        .map_err(|err| err.to_string())?;
+
+    value::eval(vm, &expr)
+}
+
+/// Lets the virtual machines created from now on load the part of their prelude written in yex,
+/// compiling it the first time each thread creates one
+pub fn init() {
+    vm::set_prelude_loader(|_| parse(vm::PRELUDE_SOURCE).ok());
+}
+
+/// Parses a given string into an AST, the files it imports are relative to the current directory
 pub fn parse<T: Into<String>>(str: T) -> ParseResult<(Bytecode, Vec<Value>)> {
+    parse_in(str, Path::new("."))
+}
+
+/// Parses a program like [`parse`], with the files it imports relative to `dir`, which is the
+/// directory of the program's file. Each file imported is compiled once, before the program, and
+/// its globals are under its namespace, like `Math.sqrt`
+pub fn parse_in<T: Into<String>>(str: T, dir: &Path) -> ParseResult<(Bytecode, Vec<Value>)> {
     let lexer = Lexer::new(str);
     let parser = Parser::new(lexer)?;
     let ast = parser.parse()?;
 
+    let (imported, program) = import::load(ast, dir)?;
+    let compiler = Compiler::new();
+    Ok(compiler.compile_units(imported, program))
+}
+
+/// Parses any bytes like [`parse`], reading them as UTF-8 with the invalid sequences replaced,
+/// and checks the bytecode with [`vm::encoding::verify`]. It never panics, a bug of the parser or
+/// of the compiler is returned as a [`ParseError`] too, so it's the one to use with fuzzers and
+/// with code from untrusted sources
+pub fn parse_untrusted(source: &[u8]) -> ParseResult<(Bytecode, Vec<Value>)> {
+    let source = String::from_utf8_lossy(source).into_owned();
+    let parsed = std::panic::catch_unwind(|| parse(source)).map_err(|panic| {
+        let reason = panic
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        ParseError::new(0, 0, format!("The compiler panicked: {reason}"))
+    })?;
+
+    let (bytecode, constants) = parsed?;
+    if let Err(err) = vm::encoding::verify(&bytecode, &constants) {
+        return ParseError::throw(
+            err.line,
+            err.column,
+            format!("Invalid bytecode: {}", err.msg),
+        );
+    }
+    Ok((bytecode, constants))
+}
+
+/// Parses a given string like [`parse`], packing the bytecode with [`vm::encoding::encode`]
+pub fn parse_packed<T: Into<String>>(str: T) -> ParseResult<(Vec<u8>, Vec<Value>)> {
+    let (bytecode, constants) = parse(str)?;
+    Ok((vm::encoding::encode(&bytecode), constants))
+}
+
+/// Parses a program and dumps its AST as JSON, with the line and column of every node, indented
+/// if `pretty` is set
+pub fn ast_json<T: Into<String>>(str: T, pretty: bool) -> ParseResult<String> {
+    use parser::json::{Json, ToJson};
+
+    let lexer = Lexer::new(str);
+    let parser = Parser::new(lexer)?;
+    let ast = parser.parse()?;
+
+    let json = Json::Arr(ast.iter().map(ToJson::to_json).collect());
+    Ok(if pretty {
+        format!("{json:#}")
+    } else {
+        json.to_string()
+    })
+}
+
+/// Parses a program and collects its documented declarations, the `def` and `type` declarations
+/// with `///` comments right above them
+pub fn docs(source: &str) -> ParseResult<Vec<DocItem>> {
+    let lexer = Lexer::new(source);
+    let parser = Parser::new(lexer)?;
+    let ast = parser.parse()?;
+
+    Ok(doc::collect(source, &ast))
+}
+
+/// Checks a program without running it, returning every syntax error and the uses of undefined
+/// globals, which are only looked for if the program has no syntax errors. The files it imports
+/// are relative to the current directory
+#[must_use]
+pub fn check<T: Into<String>>(str: T) -> Vec<Diagnostic> {
+    check_in(str, Path::new("."))
+}
+
+/// Checks a program like [`check`], with the files it imports relative to `dir`, like
+/// [`parse_in`]. Only the program itself is checked, the globals of the files it imports are
+/// taken as defined
+#[must_use]
+pub fn check_in<T: Into<String>>(str: T, dir: &Path) -> Vec<Diagnostic> {
+    let lexer = Lexer::new(str);
+    let parser = match Parser::new(lexer) {
+        Ok(parser) => parser,
+        Err(err) => return vec![err.into()],
+    };
+
+    let (ast, errors) = parser.parse_all();
+    if !errors.is_empty() {
+        return errors.into_iter().map(Diagnostic::from).collect();
+    }
+
+    let (imported, program) = match import::load(ast, dir) {
+        Ok(units) => units,
+        Err(err) => return vec![err.into()],
+    };
+    let imported = imported.iter().flat_map(|it| it.aliases.values().copied());
+
+    let (bytecode, constants, visible) = Compiler::new().compile_checked(program);
+    check::undefined_globals(&bytecode, &constants, &visible, imported.collect())
+}
+
+/// Parses the input of a REPL line, any mix of statements followed by an optional expression,
+/// running the code leaves the value of the expression (or `nil`) on the stack
+pub fn parse_repl<T: Into<String>>(str: T) -> ParseResult<(Bytecode, Vec<Value>)> {
+    let lexer = Lexer::new(str);
+    let parser = Parser::new(lexer)?;
+    let (stmts, expr) = parser.parse_repl()?;
+
     let compiler = Compiler::new();
-    Ok(compiler.compile_stmts(&ast))
+    Ok(compiler.compile_repl(&stmts, expr.as_ref()))
 }
 
 /// Parses the given string in a single expression
@@ -20,6 +20,17 @@ pub enum Pattern {
     Tuple(Vec<Pattern>),
     List(Box<Self>, Box<Self>),
     EmptyList,
+    /// The numbers from the first to the last, both included
+    Range(f64, f64),
+    /// A value of the type at the path, matching the pattern
+    Typed(Box<Self>, Path),
+    /// A string starting with the prefix, the rest of it matches the pattern
+    Prefix(String, Box<Self>),
+    /// A string ending with the suffix, the start of it matches the pattern
+    Suffix(Box<Self>, String),
+    /// `{name: pattern, ...}`, an instance whose variant has the fields, each one matching its
+    /// pattern, the other fields are ignored
+    Fields(Vec<(Symbol, Pattern)>),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -33,10 +44,13 @@ pub enum BinOp {
     Mul,
     Div,
     Rem,
+    FloorDiv,
+    Mod,
     BitAnd,
     BitOr,
     BitXor,
     Shr,
+    UShr,
     Shl,
     Eq,
     Ne,
@@ -57,14 +71,17 @@ impl<'a> From<BinOp> for &'a [OpCode] {
             BinOp::Mul => &[OpCode::Mul],
             BinOp::Div => &[OpCode::Div],
             BinOp::Rem => &[OpCode::Rem],
+            BinOp::FloorDiv => &[OpCode::FloorDiv],
+            BinOp::Mod => &[OpCode::Mod],
             BinOp::BitAnd => &[OpCode::BitAnd],
             BinOp::BitOr => &[OpCode::BitOr],
             BinOp::BitXor => &[OpCode::Xor],
             BinOp::Shr => &[OpCode::Shr],
+            BinOp::UShr => &[OpCode::UShr],
             BinOp::Shl => &[OpCode::Shl],
             BinOp::Eq => &[OpCode::Eq],
             BinOp::Ne => &[OpCode::Eq, OpCode::Not],
-            BinOp::Is => &[OpCode::Rev, OpCode::Type, OpCode::Eq],
+            BinOp::Is => &[OpCode::IsA],
             BinOp::And | BinOp::Or => unreachable!(),
         }
     }
@@ -84,10 +101,13 @@ impl TryFrom<TokenType> for BinOp {
             TokenType::Mul => Ok(BinOp::Mul),
             TokenType::Div => Ok(BinOp::Div),
             TokenType::Rem => Ok(BinOp::Rem),
+            TokenType::FloorDiv => Ok(BinOp::FloorDiv),
+            TokenType::Mod => Ok(BinOp::Mod),
             TokenType::BitAnd => Ok(BinOp::BitAnd),
             TokenType::BitOr => Ok(BinOp::BitOr),
             TokenType::BitXor => Ok(BinOp::BitXor),
             TokenType::Shr => Ok(BinOp::Shr),
+            TokenType::UShr => Ok(BinOp::UShr),
             TokenType::Shl => Ok(BinOp::Shl),
             TokenType::Eq => Ok(BinOp::Eq),
             TokenType::Ne => Ok(BinOp::Ne),
@@ -102,6 +122,7 @@ impl TryFrom<TokenType> for BinOp {
 pub enum UnOp {
     Not,
     Neg,
+    BitNot,
 }
 
 impl TryFrom<TokenType> for UnOp {
@@ -111,6 +132,7 @@ impl TryFrom<TokenType> for UnOp {
         match t {
             TokenType::Not => Ok(UnOp::Not),
             TokenType::Sub => Ok(UnOp::Neg),
+            TokenType::BitNot => Ok(UnOp::BitNot),
             _ => Err(()),
         }
     }
@@ -121,6 +143,7 @@ impl<'a> From<UnOp> for &'a [OpCode] {
         match op {
             UnOp::Not => &[OpCode::Not],
             UnOp::Neg => &[OpCode::Neg],
+            UnOp::BitNot => &[OpCode::BitNot],
         }
     }
 }
@@ -184,6 +207,15 @@ pub enum ExprKind {
         arms: Vec<MatchArm>,
     },
 
+    Receive {
+        arms: Vec<MatchArm>,
+    },
+
+    Yield(Box<Expr>),
+
+    /// `recur`, the function it's in, so anonymous functions can call themselves
+    Recur,
+
     Lambda {
         args: Vec<Pattern>, // specifies the arguments name and types
         body: Box<Expr>,    // the function body
@@ -195,11 +227,58 @@ pub enum ExprKind {
         tail: bool,
     },
 
+    /// `arg |>? callee`, which is `arg` itself when it's `nil` or a failed `Result`
+    TryPipe {
+        arg: Box<Expr>,
+        callee: Box<Expr>,
+    },
+
+    /// `quote (1 + unquote x)`, the nodes of the expression as a value, with the values of its
+    /// `unquote`s spliced into them when it's evaluated
+    Quote(Box<Expr>),
+
+    /// `unquote x`, which splices the value of `x` into the innermost `quote`
+    Unquote(Box<Expr>),
+
+    /// `"x = #{x}"`, the pieces of the string, with the values of the expressions between them
+    /// converted to strings like `print` does
+    Interp {
+        pieces: Vec<String>,
+        exprs: Vec<Expr>,
+    },
+
+    /// `dbg value`, which prints the source of `value`, where it is and what it evaluates to,
+    /// then evaluates to it
+    Dbg {
+        source: String,
+        value: Box<Expr>,
+    },
+
+    /// `@when(flag) body`, which is `nil` unless the flag was defined when compiling it
+    When {
+        flag: Symbol,
+        body: Box<Expr>,
+    },
+
     MethodRef {
         ty: Box<Expr>,
         method: VarDecl,
     },
 
+    /// `with bind = value do body end`, which calls `bind.close` once the body ends
+    With {
+        bind: VarDecl,
+        value: Box<Expr>,
+        body: Box<Expr>,
+    },
+
+    /// A copy of `instance` with `field` set to `value`, from `let self.field = value`
+    SetField {
+        instance: Box<Expr>,
+        field: Symbol,
+        value: Box<Expr>,
+    },
+
     Var(Symbol),
     Lit(Literal),
     List(Vec<Expr>),
@@ -218,7 +297,7 @@ pub enum ExprKind {
 
     Try {
         body: Box<Expr>,
-        bind: VarDecl,
+        bind: Pattern,
         rescue: Box<Expr>,
     },
 
@@ -227,6 +306,7 @@ pub enum ExprKind {
 
 #[derive(Debug, Clone)]
 pub enum Literal {
+    Int(i64),
     Num(f64),
     Str(String),
     Bool(bool),
@@ -237,6 +317,7 @@ pub enum Literal {
 impl PartialEq<Value> for Literal {
     fn eq(&self, other: &Value) -> bool {
         match (self, other) {
+            (Literal::Int(a), Value::Int(b)) => a == b,
             (Literal::Num(a), Value::Num(b)) => a == b,
             (Literal::Str(a), Value::Str(b)) => a == &**b,
             (Literal::Bool(a), Value::Bool(b)) => a == b,
@@ -250,6 +331,7 @@ impl PartialEq<Value> for Literal {
 impl From<Literal> for Value {
     fn from(lit: Literal) -> Value {
         match lit {
+            Literal::Int(n) => Value::Int(n),
             Literal::Num(n) => Value::Num(n),
             Literal::Str(s) => Value::Str(GcRef::new(s)),
             Literal::Bool(b) => Value::Bool(b),
@@ -319,9 +401,31 @@ pub enum StmtKind {
         bind: Pattern,
         value: Expr,
     },
+    /// A global that can't be defined again
+    Const {
+        name: VarDecl,
+        value: Expr,
+    },
     Type {
         name: VarDecl,
         variants: Vec<(VarDecl, Vec<VarDecl>)>,
         members: Vec<Def>,
     },
+    Test {
+        name: String,
+        body: Expr,
+    },
+    Bench {
+        name: String,
+        body: Expr,
+    },
+    /// `module Name`, the namespace the globals of the file are under in the files importing it
+    Module(VarDecl),
+    /// `import "path"`, or `open "path"`, which also lets the globals of the file be used without
+    /// their namespace
+    Import {
+        path: String,
+        open: bool,
+    },
+    Expr(Expr),
 }
@@ -0,0 +1,560 @@
+//! Converts expressions to the nodes of quoted code and back. The nodes are the variants of the
+//! `Code` type, whose fields are the ones of the AST dumped as JSON
+use vm::{
+    literal::code::{kind_of, node, NODES},
+    Symbol, Tuple, Value,
+};
+
+use super::ast::{BinOp, Bind, Expr, ExprKind, Literal, Location, MatchArm, Pattern, UnOp};
+
+type CodeResult<T> = Result<T, String>;
+
+const BIN_OPS: [BinOp; 22] = [
+    BinOp::Less,
+    BinOp::LessEq,
+    BinOp::Greater,
+    BinOp::GreaterEq,
+    BinOp::Add,
+    BinOp::Sub,
+    BinOp::Mul,
+    BinOp::Div,
+    BinOp::Rem,
+    BinOp::FloorDiv,
+    BinOp::Mod,
+    BinOp::BitAnd,
+    BinOp::BitOr,
+    BinOp::BitXor,
+    BinOp::Shr,
+    BinOp::UShr,
+    BinOp::Shl,
+    BinOp::Eq,
+    BinOp::Ne,
+    BinOp::And,
+    BinOp::Or,
+    BinOp::Is,
+];
+
+const UN_OPS: [UnOp; 3] = [UnOp::Not, UnOp::Neg, UnOp::BitNot];
+
+/// The nodes of a quoted expression. The `unquote`s of the quote hold the index of their value
+/// in the expressions returned with the nodes, the ones of the quotes in it are left as they are
+pub(crate) fn to_code(expr: &Expr) -> (Value, Vec<&Expr>) {
+    let mut quoter = Quoter {
+        holes: vec![],
+        nested: false,
+    };
+    let code = quoter.expr(expr);
+    (code, quoter.holes)
+}
+
+/// Reads the expression the nodes of quoted code stand for
+pub(crate) fn from_code(code: &Value) -> CodeResult<Expr> {
+    Reader { quoting: false }.expr(code)
+}
+
+fn sym(name: Symbol) -> Value {
+    Value::Sym(name.into())
+}
+
+fn path(path: &[Symbol]) -> Value {
+    Value::List(path.iter().copied().map(sym).collect())
+}
+
+fn location(location: Location) -> [Value; 2] {
+    let number = |n: usize| Value::Int(i64::try_from(n).unwrap_or(i64::MAX));
+    [number(location.line), number(location.column)]
+}
+
+fn pattern(pat: &Pattern) -> Value {
+    let all = |pats: &[Pattern]| Value::List(pats.iter().map(pattern).collect());
+
+    let (kind, fields) = match pat {
+        Pattern::Id(name) => ("PatternId", vec![sym(*name)]),
+        Pattern::Lit(lit) => ("PatternLit", vec![lit.clone().into()]),
+        Pattern::Variant(ty, args) => ("PatternVariant", vec![path(ty), all(args)]),
+        Pattern::Tuple(items) => ("PatternTuple", vec![all(items)]),
+        Pattern::List(head, tail) => ("PatternCons", vec![pattern(head), pattern(tail)]),
+        Pattern::EmptyList => ("PatternEmptyList", vec![]),
+        Pattern::Range(start, end) => ("PatternRange", vec![Value::Num(*start), Value::Num(*end)]),
+        Pattern::Prefix(prefix, rest) => {
+            ("PatternPrefix", vec![prefix.clone().into(), pattern(rest)])
+        }
+        Pattern::Suffix(start, suffix) => {
+            ("PatternSuffix", vec![pattern(start), suffix.clone().into()])
+        }
+        Pattern::Typed(pat, ty) => ("PatternTyped", vec![pattern(pat), path(ty)]),
+        Pattern::Fields(fields) => {
+            let fields = fields
+                .iter()
+                .map(|(name, pat)| Value::Tuple(Tuple::from(vec![sym(*name), pattern(pat)])))
+                .collect();
+            ("PatternFields", vec![Value::List(fields)])
+        }
+    };
+
+    node(kind, fields)
+}
+
+/// Converts a quoted expression to its nodes
+struct Quoter<'a> {
+    /// The values of the `unquote`s of the quote
+    holes: Vec<&'a Expr>,
+    /// If the expression being converted is in another quote, whose `unquote`s are its own
+    nested: bool,
+}
+
+impl<'a> Quoter<'a> {
+    fn all(&mut self, exprs: &'a [Expr]) -> Value {
+        Value::List(exprs.iter().map(|expr| self.expr(expr)).collect())
+    }
+
+    fn arms(&mut self, arms: &'a [MatchArm]) -> Value {
+        let arms = arms.iter().map(|arm| {
+            let guard = arm.guard.as_ref().map_or(Value::Nil, |it| self.expr(it));
+            let [line, column] = location(arm.location);
+            node(
+                "Arm",
+                vec![
+                    line,
+                    column,
+                    pattern(&arm.cond),
+                    guard,
+                    self.expr(&arm.body),
+                ],
+            )
+        });
+        Value::List(arms.collect())
+    }
+
+    fn expr(&mut self, expr: &'a Expr) -> Value {
+        let (kind, fields) = match &expr.kind {
+            ExprKind::If { cond, then, else_ } => (
+                "If",
+                vec![self.expr(cond), self.expr(then), self.expr(else_)],
+            ),
+            ExprKind::Let { bind, value, body } => (
+                "Let",
+                vec![pattern(bind), self.expr(value), self.expr(body)],
+            ),
+            ExprKind::Def { bind, body } => {
+                let [line, column] = location(bind.location);
+                let bind = node(
+                    "Bind",
+                    vec![line, column, sym(bind.bind), self.expr(&bind.value)],
+                );
+                ("Def", vec![bind, self.expr(body)])
+            }
+            ExprKind::Match { expr, arms } => ("Match", vec![self.expr(expr), self.arms(arms)]),
+            ExprKind::Receive { arms } => ("Receive", vec![self.arms(arms)]),
+            ExprKind::Yield(value) => ("Yield", vec![self.expr(value)]),
+            ExprKind::Recur => ("Recur", vec![]),
+            ExprKind::Lambda { args, body } => {
+                let args = Value::List(args.iter().map(pattern).collect());
+                ("Lambda", vec![args, self.expr(body)])
+            }
+            ExprKind::App { callee, args, tail } => (
+                "App",
+                vec![self.expr(callee), self.all(args), Value::Bool(*tail)],
+            ),
+            ExprKind::TryPipe { arg, callee } => {
+                ("TryPipe", vec![self.expr(arg), self.expr(callee)])
+            }
+            ExprKind::Quote(quoted) => {
+                let outer = std::mem::replace(&mut self.nested, true);
+                let quoted = self.expr(quoted);
+                self.nested = outer;
+                ("Quote", vec![quoted])
+            }
+            ExprKind::Unquote(value) if self.nested => ("Unquote", vec![self.expr(value)]),
+            ExprKind::Unquote(value) => {
+                self.holes.push(value);
+                let idx = i64::try_from(self.holes.len() - 1).unwrap_or(i64::MAX);
+                ("Unquote", vec![Value::Int(idx)])
+            }
+            ExprKind::Interp { pieces, exprs } => {
+                let pieces = pieces.iter().map(|it| it.clone().into()).collect();
+                ("Interp", vec![Value::List(pieces), self.all(exprs)])
+            }
+            ExprKind::Dbg { source, value } => {
+                ("Dbg", vec![source.clone().into(), self.expr(value)])
+            }
+            ExprKind::When { flag, body } => ("When", vec![sym(*flag), self.expr(body)]),
+            ExprKind::MethodRef { ty, method } => ("MethodRef", vec![self.expr(ty), sym(*method)]),
+            ExprKind::With { bind, value, body } => {
+                ("With", vec![sym(*bind), self.expr(value), self.expr(body)])
+            }
+            ExprKind::SetField {
+                instance,
+                field,
+                value,
+            } => (
+                "SetField",
+                vec![self.expr(instance), sym(*field), self.expr(value)],
+            ),
+            ExprKind::Var(name) => ("Var", vec![sym(*name)]),
+            ExprKind::Lit(lit) => ("Lit", vec![lit.clone().into()]),
+            ExprKind::List(items) => ("List", vec![self.all(items)]),
+            ExprKind::Binary { left, op, right } => (
+                "Binary",
+                vec![
+                    sym(Symbol::new(format!("{op:?}"))),
+                    self.expr(left),
+                    self.expr(right),
+                ],
+            ),
+            ExprKind::Cons { head, tail } => ("Cons", vec![self.expr(head), self.expr(tail)]),
+            ExprKind::UnOp(op, value) => (
+                "UnOp",
+                vec![sym(Symbol::new(format!("{op:?}"))), self.expr(value)],
+            ),
+            ExprKind::Try { body, bind, rescue } => (
+                "Try",
+                vec![self.expr(body), pattern(bind), self.expr(rescue)],
+            ),
+            ExprKind::Tuple(items) => ("Tuple", vec![self.all(items)]),
+        };
+
+        let mut node_fields = location(expr.location).to_vec();
+        node_fields.extend(fields);
+        node(kind, node_fields)
+    }
+}
+
+/// Checks that a value is a node of quoted code with the fields of its kind
+fn fields_of(value: &Value) -> CodeResult<(&str, &[Value])> {
+    let Some((kind, fields)) = kind_of(value) else {
+        return Err(format!("Expected a node of quoted code, found '{value}'"));
+    };
+
+    match NODES.iter().find(|(name, _)| *name == kind) {
+        Some((_, names)) if names.len() == fields.len() => Ok((kind, fields)),
+        Some((_, names)) => Err(format!(
+            "The node 'Code.{kind}' has {} fields, found {}",
+            names.len(),
+            fields.len()
+        )),
+        None => Err(format!("Unknown node 'Code.{kind}'")),
+    }
+}
+
+fn number(value: &Value) -> CodeResult<usize> {
+    match value {
+        Value::Int(n) => {
+            usize::try_from(*n).map_err(|_| format!("Expected a position, found '{n}'"))
+        }
+        other => Err(format!("Expected a position, found '{other}'")),
+    }
+}
+
+fn name(value: &Value) -> CodeResult<Symbol> {
+    match value {
+        Value::Sym(name) => Ok(name.0),
+        other => Err(format!("Expected a symbol, found '{other}'")),
+    }
+}
+
+fn string(value: &Value) -> CodeResult<String> {
+    match value {
+        Value::Str(s) => Ok(s.to_string()),
+        other => Err(format!("Expected a string, found '{other}'")),
+    }
+}
+
+fn list(value: &Value) -> CodeResult<Vec<Value>> {
+    match value {
+        Value::List(items) => Ok(items.to_vec()),
+        other => Err(format!("Expected a list, found '{other}'")),
+    }
+}
+
+fn names(value: &Value) -> CodeResult<Vec<Symbol>> {
+    list(value)?.iter().map(name).collect()
+}
+
+fn literal(value: &Value) -> CodeResult<Literal> {
+    Ok(match value {
+        Value::Int(n) => Literal::Int(*n),
+        Value::Num(n) => Literal::Num(*n),
+        Value::Str(s) => Literal::Str(s.to_string()),
+        Value::Bool(b) => Literal::Bool(*b),
+        Value::Sym(s) => Literal::Sym(s.0),
+        Value::Nil => Literal::Unit,
+        other => return Err(format!("Expected a literal, found '{other}'")),
+    })
+}
+
+/// Finds the operator whose name is the symbol
+fn operator<T: Copy + std::fmt::Debug>(ops: &[T], value: &Value) -> CodeResult<T> {
+    let op = name(value)?;
+    ops.iter()
+        .copied()
+        .find(|it| format!("{it:?}") == op.as_str())
+        .ok_or(format!("Unknown operator '{op}'"))
+}
+
+fn read_pattern(value: &Value) -> CodeResult<Pattern> {
+    let all =
+        |value: &Value| -> CodeResult<Vec<_>> { list(value)?.iter().map(read_pattern).collect() };
+    let boxed = |value: &Value| read_pattern(value).map(Box::new);
+
+    Ok(match fields_of(value)? {
+        ("PatternId", [name_]) => Pattern::Id(name(name_)?),
+        ("PatternLit", [value]) => Pattern::Lit(literal(value)?),
+        ("PatternVariant", [ty, args]) => Pattern::Variant(names(ty)?, all(args)?),
+        ("PatternTuple", [items]) => Pattern::Tuple(all(items)?),
+        ("PatternCons", [head, tail]) => Pattern::List(boxed(head)?, boxed(tail)?),
+        ("PatternEmptyList", []) => Pattern::EmptyList,
+        ("PatternRange", [start, end]) => match (start.as_num(), end.as_num()) {
+            (Some(start), Some(end)) => Pattern::Range(start, end),
+            _ => return Err(format!("Expected a range of numbers, found '{value}'")),
+        },
+        ("PatternPrefix", [prefix, rest]) => Pattern::Prefix(string(prefix)?, boxed(rest)?),
+        ("PatternSuffix", [start, suffix]) => Pattern::Suffix(boxed(start)?, string(suffix)?),
+        ("PatternTyped", [pat, ty]) => Pattern::Typed(boxed(pat)?, names(ty)?),
+        ("PatternFields", [fields]) => {
+            let fields = list(fields)?
+                .iter()
+                .map(|field| match field {
+                    Value::Tuple(t) if t.len() == 2 => Ok((name(&t.0[0])?, read_pattern(&t.0[1])?)),
+                    other => Err(format!("Expected a field and its pattern, found '{other}'")),
+                })
+                .collect::<CodeResult<_>>()?;
+            Pattern::Fields(fields)
+        }
+        _ => return Err(format!("Expected a pattern, found '{value}'")),
+    })
+}
+
+/// Converts the nodes of quoted code back to an expression
+struct Reader {
+    /// If the node being read is in a quote, outside of its `unquote`s
+    quoting: bool,
+}
+
+impl Reader {
+    fn boxed(&mut self, value: &Value) -> CodeResult<Box<Expr>> {
+        self.expr(value).map(Box::new)
+    }
+
+    fn all(&mut self, value: &Value) -> CodeResult<Vec<Expr>> {
+        list(value)?.iter().map(|it| self.expr(it)).collect()
+    }
+
+    fn arms(&mut self, value: &Value) -> CodeResult<Vec<MatchArm>> {
+        list(value)?
+            .iter()
+            .map(|arm| match fields_of(arm)? {
+                ("Arm", [line, column, pat, guard, body]) => {
+                    let guard = match guard {
+                        Value::Nil => None,
+                        guard => Some(self.expr(guard)?),
+                    };
+                    Ok(MatchArm::new(
+                        read_pattern(pat)?,
+                        self.expr(body)?,
+                        guard,
+                        number(line)?,
+                        number(column)?,
+                    ))
+                }
+                _ => Err(format!("Expected an arm, found '{arm}'")),
+            })
+            .collect()
+    }
+
+    fn expr(&mut self, value: &Value) -> CodeResult<Expr> {
+        let (kind, fields) = fields_of(value)?;
+        let (line, column, fields) = match fields {
+            [line, column, fields @ ..] if !kind.starts_with("Pattern") => {
+                (number(line)?, number(column)?, fields)
+            }
+            _ => return Err(format!("Expected an expression, found '{value}'")),
+        };
+
+        let kind = match (kind, fields) {
+            ("If", [cond, then, else_]) => ExprKind::If {
+                cond: self.boxed(cond)?,
+                then: self.boxed(then)?,
+                else_: self.boxed(else_)?,
+            },
+            ("Let", [pat, value, body]) => ExprKind::Let {
+                bind: read_pattern(pat)?,
+                value: self.boxed(value)?,
+                body: self.boxed(body)?,
+            },
+            ("Def", [bind, body]) => {
+                let bind = match fields_of(bind)? {
+                    ("Bind", [line, column, name_, value]) => Bind::new(
+                        name(name_)?,
+                        self.boxed(value)?,
+                        number(line)?,
+                        number(column)?,
+                    ),
+                    _ => return Err(format!("Expected a binding, found '{bind}'")),
+                };
+                ExprKind::Def {
+                    bind,
+                    body: self.boxed(body)?,
+                }
+            }
+            ("Match", [expr, arms]) => ExprKind::Match {
+                expr: self.boxed(expr)?,
+                arms: self.arms(arms)?,
+            },
+            ("Receive", [arms]) => ExprKind::Receive {
+                arms: self.arms(arms)?,
+            },
+            ("Yield", [value]) => ExprKind::Yield(self.boxed(value)?),
+            ("Recur", []) => ExprKind::Recur,
+            ("Lambda", [args, body]) => ExprKind::Lambda {
+                args: list(args)?
+                    .iter()
+                    .map(read_pattern)
+                    .collect::<CodeResult<_>>()?,
+                body: self.boxed(body)?,
+            },
+            ("App", [callee, args, Value::Bool(tail)]) => ExprKind::App {
+                callee: self.boxed(callee)?,
+                args: self.all(args)?,
+                tail: *tail,
+            },
+            ("TryPipe", [arg, callee]) => ExprKind::TryPipe {
+                arg: self.boxed(arg)?,
+                callee: self.boxed(callee)?,
+            },
+            ("Quote", [quoted]) => {
+                let outer = std::mem::replace(&mut self.quoting, true);
+                let quoted = self.boxed(quoted);
+                self.quoting = outer;
+                ExprKind::Quote(quoted?)
+            }
+            ("Unquote", [value]) => {
+                if !self.quoting {
+                    return Err(format!("'unquote' outside of a 'quote' in '{value}'"));
+                }
+                self.quoting = false;
+                let value = self.boxed(value);
+                self.quoting = true;
+                ExprKind::Unquote(value?)
+            }
+            ("Interp", [pieces, exprs]) => {
+                let pieces = list(pieces)?
+                    .iter()
+                    .map(string)
+                    .collect::<CodeResult<Vec<_>>>()?;
+                let exprs = self.all(exprs)?;
+                // the pieces go around the expressions
+                if pieces.len() != exprs.len() + 1 {
+                    return Err(format!(
+                        "Expected {} pieces of string around the expressions, found {}",
+                        exprs.len() + 1,
+                        pieces.len()
+                    ));
+                }
+                ExprKind::Interp { pieces, exprs }
+            }
+            ("Dbg", [source, value]) => ExprKind::Dbg {
+                source: string(source)?,
+                value: self.boxed(value)?,
+            },
+            ("When", [flag, body]) => ExprKind::When {
+                flag: name(flag)?,
+                body: self.boxed(body)?,
+            },
+            ("MethodRef", [ty, method]) => ExprKind::MethodRef {
+                ty: self.boxed(ty)?,
+                method: name(method)?,
+            },
+            ("With", [bind, value, body]) => ExprKind::With {
+                bind: name(bind)?,
+                value: self.boxed(value)?,
+                body: self.boxed(body)?,
+            },
+            ("SetField", [instance, field, value]) => ExprKind::SetField {
+                instance: self.boxed(instance)?,
+                field: name(field)?,
+                value: self.boxed(value)?,
+            },
+            ("Var", [name_]) => ExprKind::Var(name(name_)?),
+            ("Lit", [value]) => ExprKind::Lit(literal(value)?),
+            ("List", [items]) => ExprKind::List(self.all(items)?),
+            ("Binary", [op, left, right]) => ExprKind::Binary {
+                left: self.boxed(left)?,
+                op: operator(&BIN_OPS, op)?,
+                right: self.boxed(right)?,
+            },
+            ("Cons", [head, tail]) => ExprKind::Cons {
+                head: self.boxed(head)?,
+                tail: self.boxed(tail)?,
+            },
+            ("UnOp", [op, value]) => ExprKind::UnOp(operator(&UN_OPS, op)?, self.boxed(value)?),
+            ("Try", [body, bind, rescue]) => ExprKind::Try {
+                body: self.boxed(body)?,
+                bind: read_pattern(bind)?,
+                rescue: self.boxed(rescue)?,
+            },
+            ("Tuple", [items]) => ExprKind::Tuple(self.all(items)?),
+            _ => return Err(format!("Expected an expression, found '{value}'")),
+        };
+
+        Ok(Expr::new(kind, line, column))
+    }
+}
+
+#[cfg(test)]
+fn parse(source: &str) -> Expr {
+    use crate::{lexer::Lexer, parser::Parser};
+
+    Parser::new(Lexer::new(source))
+        .and_then(Parser::parse_expr)
+        .unwrap()
+}
+
+#[test]
+fn quote_expressions_and_read_them_back() {
+    // every node has the fields of its kind
+    fn check(value: &Value) {
+        match value {
+            Value::Tagged(..) => {
+                let (_, fields) = fields_of(value).unwrap();
+                fields.iter().for_each(check);
+            }
+            Value::List(items) => items.iter().for_each(check),
+            Value::Tuple(items) => items.0.iter().for_each(check),
+            _ => (),
+        }
+    }
+
+    let expr = parse(
+        "let (x, _) = (1, 2) in match [x] with | y :: _ if y > 0 -> fn z -> z + y | _ -> nil",
+    );
+    let (code, holes) = to_code(&expr);
+    assert!(holes.is_empty());
+    check(&code);
+
+    let read = from_code(&code).unwrap();
+    assert_eq!(format!("{read:?}"), format!("{expr:?}"));
+
+    let expr = parse("quote (1 + unquote (quote (unquote x)))");
+    let ExprKind::Quote(quoted) = &expr.kind else {
+        panic!("expected a quote, found {expr:?}");
+    };
+    let (code, holes) = to_code(quoted);
+    assert_eq!(holes.len(), 1);
+    assert!(matches!(
+        kind_of(&code),
+        Some(("Binary", [_, _, _, _, right])) if matches!(kind_of(right), Some(("Unquote", [_, _, Value::Int(0)])))
+    ));
+
+    assert!(from_code(&code).is_err());
+    assert!(from_code(&Value::Int(1)).is_err());
+    assert!(from_code(&node("Var", vec![Value::Int(1), Value::Int(1)])).is_err());
+
+    let expr = parse("quote (unquote x)");
+    let (code, holes) = to_code(&expr);
+    assert!(holes.is_empty());
+    assert_eq!(
+        format!("{:?}", from_code(&code).unwrap()),
+        format!("{expr:?}")
+    );
+}
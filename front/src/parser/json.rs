@@ -0,0 +1,420 @@
+use std::fmt::{self, Write};
+
+use super::ast::{Bind, Def, Expr, ExprKind, Literal, Location, MatchArm, Pattern, Stmt, StmtKind};
+
+/// A JSON document, only used to dump the AST for external tools
+pub enum Json {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(&'static str, Json)>),
+}
+
+fn escape(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl Json {
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        // `{:#}` indents the output, otherwise it's written in a single line
+        let (newline, pad, colon) = if f.alternate() {
+            ("\n", "  ", ": ")
+        } else {
+            ("", "", ":")
+        };
+
+        match self {
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Num(n) if n.is_finite() => write!(f, "{n}"),
+            Self::Null | Self::Num(_) => f.write_str("null"),
+            Self::Str(s) => escape(f, s),
+            Self::Arr(items) if items.is_empty() => f.write_str("[]"),
+            Self::Arr(items) => {
+                f.write_char('[')?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{newline}{}", pad.repeat(indent + 1))?;
+                    item.write(f, indent + 1)?;
+                }
+                write!(f, "{newline}{}]", pad.repeat(indent))
+            }
+            Self::Obj(fields) => {
+                f.write_char('{')?;
+                for (idx, (key, value)) in fields.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{newline}{}", pad.repeat(indent + 1))?;
+                    escape(f, key)?;
+                    f.write_str(colon)?;
+                    value.write(f, indent + 1)?;
+                }
+                write!(f, "{newline}{}}}", pad.repeat(indent))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// Converts a node of the AST to JSON
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> Json {
+        Json::Arr(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Box<T> {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().map_or(Json::Null, ToJson::to_json)
+    }
+}
+
+impl ToJson for vm::Symbol {
+    fn to_json(&self) -> Json {
+        Json::Str(self.to_string())
+    }
+}
+
+/// Creates a node object, with its kind and location first
+fn node(kind: &str, location: Location, fields: Vec<(&'static str, Json)>) -> Json {
+    let mut node = vec![
+        ("kind", Json::Str(kind.to_string())),
+        ("line", Json::Num(location.line as f64)),
+        ("column", Json::Num(location.column as f64)),
+    ];
+    node.extend(fields);
+    Json::Obj(node)
+}
+
+impl ToJson for Literal {
+    fn to_json(&self) -> Json {
+        match self {
+            Literal::Int(n) => Json::Num(*n as f64),
+            Literal::Num(n) => Json::Num(*n),
+            Literal::Str(s) => Json::Str(s.clone()),
+            Literal::Bool(b) => Json::Bool(*b),
+            Literal::Sym(s) => Json::Str(format!(":{s}")),
+            Literal::Unit => Json::Null,
+        }
+    }
+}
+
+impl ToJson for Pattern {
+    fn to_json(&self) -> Json {
+        let (kind, fields) = match self {
+            Pattern::Id(name) => ("Id", vec![("name", name.to_json())]),
+            Pattern::Lit(lit) => ("Lit", vec![("value", lit.to_json())]),
+            Pattern::Variant(path, args) => (
+                "Variant",
+                vec![("path", path.to_json()), ("args", args.to_json())],
+            ),
+            Pattern::Tuple(items) => ("Tuple", vec![("items", items.to_json())]),
+            Pattern::List(head, tail) => (
+                "Cons",
+                vec![("head", head.to_json()), ("tail", tail.to_json())],
+            ),
+            Pattern::EmptyList => ("EmptyList", vec![]),
+            Pattern::Range(start, end) => (
+                "Range",
+                vec![("start", Json::Num(*start)), ("end", Json::Num(*end))],
+            ),
+            Pattern::Prefix(prefix, rest) => (
+                "Prefix",
+                vec![
+                    ("prefix", Json::Str(prefix.clone())),
+                    ("rest", rest.to_json()),
+                ],
+            ),
+            Pattern::Suffix(start, suffix) => (
+                "Suffix",
+                vec![
+                    ("start", start.to_json()),
+                    ("suffix", Json::Str(suffix.clone())),
+                ],
+            ),
+            Pattern::Typed(pat, ty) => (
+                "Typed",
+                vec![("pattern", pat.to_json()), ("type", ty.to_json())],
+            ),
+            Pattern::Fields(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, pat)| {
+                        Json::Obj(vec![("name", name.to_json()), ("pattern", pat.to_json())])
+                    })
+                    .collect();
+                ("Fields", vec![("fields", Json::Arr(fields))])
+            }
+        };
+
+        let mut pattern = vec![("kind", Json::Str(kind.to_string()))];
+        pattern.extend(fields);
+        Json::Obj(pattern)
+    }
+}
+
+impl ToJson for MatchArm {
+    fn to_json(&self) -> Json {
+        node(
+            "Arm",
+            self.location,
+            vec![
+                ("pattern", self.cond.to_json()),
+                ("guard", self.guard.to_json()),
+                ("body", self.body.to_json()),
+            ],
+        )
+    }
+}
+
+impl ToJson for Bind {
+    fn to_json(&self) -> Json {
+        node(
+            "Bind",
+            self.location,
+            vec![
+                ("name", self.bind.to_json()),
+                ("value", self.value.to_json()),
+            ],
+        )
+    }
+}
+
+impl ToJson for Def {
+    fn to_json(&self) -> Json {
+        node(
+            "Def",
+            self.value.location,
+            vec![
+                ("name", self.bind.to_json()),
+                ("value", self.value.to_json()),
+            ],
+        )
+    }
+}
+
+impl ToJson for Expr {
+    fn to_json(&self) -> Json {
+        let (kind, fields) = match &self.kind {
+            ExprKind::If { cond, then, else_ } => (
+                "If",
+                vec![
+                    ("cond", cond.to_json()),
+                    ("then", then.to_json()),
+                    ("else", else_.to_json()),
+                ],
+            ),
+            ExprKind::Let { bind, value, body } => (
+                "Let",
+                vec![
+                    ("pattern", bind.to_json()),
+                    ("value", value.to_json()),
+                    ("body", body.to_json()),
+                ],
+            ),
+            ExprKind::Def { bind, body } => (
+                "Def",
+                vec![("bind", bind.to_json()), ("body", body.to_json())],
+            ),
+            ExprKind::Match { expr, arms } => (
+                "Match",
+                vec![("expr", expr.to_json()), ("arms", arms.to_json())],
+            ),
+            ExprKind::Receive { arms } => ("Receive", vec![("arms", arms.to_json())]),
+            ExprKind::Yield(value) => ("Yield", vec![("value", value.to_json())]),
+            ExprKind::Recur => ("Recur", vec![]),
+            ExprKind::Lambda { args, body } => (
+                "Lambda",
+                vec![("args", args.to_json()), ("body", body.to_json())],
+            ),
+            ExprKind::App { callee, args, tail } => (
+                "App",
+                vec![
+                    ("callee", callee.to_json()),
+                    ("args", args.to_json()),
+                    ("tail", Json::Bool(*tail)),
+                ],
+            ),
+            ExprKind::TryPipe { arg, callee } => (
+                "TryPipe",
+                vec![("arg", arg.to_json()), ("callee", callee.to_json())],
+            ),
+            ExprKind::Quote(expr) => ("Quote", vec![("expr", expr.to_json())]),
+            ExprKind::Unquote(value) => ("Unquote", vec![("value", value.to_json())]),
+            ExprKind::Interp { pieces, exprs } => (
+                "Interp",
+                vec![
+                    (
+                        "pieces",
+                        Json::Arr(pieces.iter().cloned().map(Json::Str).collect()),
+                    ),
+                    ("exprs", exprs.to_json()),
+                ],
+            ),
+            ExprKind::Dbg { source, value } => (
+                "Dbg",
+                vec![
+                    ("source", Json::Str(source.clone())),
+                    ("value", value.to_json()),
+                ],
+            ),
+            ExprKind::When { flag, body } => (
+                "When",
+                vec![("flag", flag.to_json()), ("body", body.to_json())],
+            ),
+            ExprKind::MethodRef { ty, method } => (
+                "MethodRef",
+                vec![("type", ty.to_json()), ("method", method.to_json())],
+            ),
+            ExprKind::With { bind, value, body } => (
+                "With",
+                vec![
+                    ("bind", bind.to_json()),
+                    ("value", value.to_json()),
+                    ("body", body.to_json()),
+                ],
+            ),
+            ExprKind::SetField {
+                instance,
+                field,
+                value,
+            } => (
+                "SetField",
+                vec![
+                    ("instance", instance.to_json()),
+                    ("field", field.to_json()),
+                    ("value", value.to_json()),
+                ],
+            ),
+            ExprKind::Var(name) => ("Var", vec![("name", name.to_json())]),
+            ExprKind::Lit(lit) => ("Lit", vec![("value", lit.to_json())]),
+            ExprKind::List(items) => ("List", vec![("items", items.to_json())]),
+            ExprKind::Binary { left, op, right } => (
+                "Binary",
+                vec![
+                    ("op", Json::Str(format!("{op:?}"))),
+                    ("left", left.to_json()),
+                    ("right", right.to_json()),
+                ],
+            ),
+            ExprKind::Cons { head, tail } => (
+                "Cons",
+                vec![("head", head.to_json()), ("tail", tail.to_json())],
+            ),
+            ExprKind::UnOp(op, value) => (
+                "UnOp",
+                vec![
+                    ("op", Json::Str(format!("{op:?}"))),
+                    ("value", value.to_json()),
+                ],
+            ),
+            ExprKind::Try { body, bind, rescue } => (
+                "Try",
+                vec![
+                    ("body", body.to_json()),
+                    ("bind", bind.to_json()),
+                    ("rescue", rescue.to_json()),
+                ],
+            ),
+            ExprKind::Tuple(items) => ("Tuple", vec![("items", items.to_json())]),
+        };
+
+        node(kind, self.location, fields)
+    }
+}
+
+impl ToJson for Stmt {
+    fn to_json(&self) -> Json {
+        let (kind, fields) = match &self.kind {
+            StmtKind::Def(def) => (
+                "DefStmt",
+                vec![("name", def.bind.to_json()), ("value", def.value.to_json())],
+            ),
+            StmtKind::Let { bind, value } => (
+                "LetStmt",
+                vec![("pattern", bind.to_json()), ("value", value.to_json())],
+            ),
+            StmtKind::Const { name, value } => (
+                "ConstStmt",
+                vec![("name", name.to_json()), ("value", value.to_json())],
+            ),
+            StmtKind::Type {
+                name,
+                variants,
+                members,
+            } => {
+                let variants = variants
+                    .iter()
+                    .map(|(name, fields)| {
+                        Json::Obj(vec![("name", name.to_json()), ("fields", fields.to_json())])
+                    })
+                    .collect();
+
+                (
+                    "Type",
+                    vec![
+                        ("name", name.to_json()),
+                        ("variants", Json::Arr(variants)),
+                        ("members", members.to_json()),
+                    ],
+                )
+            }
+            StmtKind::Test { name, body } => (
+                "Test",
+                vec![("name", Json::Str(name.clone())), ("body", body.to_json())],
+            ),
+            StmtKind::Bench { name, body } => (
+                "Bench",
+                vec![("name", Json::Str(name.clone())), ("body", body.to_json())],
+            ),
+            StmtKind::Module(name) => ("Module", vec![("name", name.to_json())]),
+            StmtKind::Import { path, open } => (
+                "Import",
+                vec![
+                    ("path", Json::Str(path.clone())),
+                    ("open", Json::Bool(*open)),
+                ],
+            ),
+            StmtKind::Expr(expr) => ("ExprStmt", vec![("expr", expr.to_json())]),
+        };
+
+        node(kind, self.location, fields)
+    }
+}
+
+#[test]
+fn json_escapes_strings() {
+    let json = Json::Obj(vec![("s", Json::Str(String::from("a\"b\n")))]);
+    assert_eq!(json.to_string(), r#"{"s":"a\"b\n"}"#);
+}
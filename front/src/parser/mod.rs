@@ -8,14 +8,41 @@ use crate::{
     tokens::{Token, TokenType as Tkt},
 };
 
-use self::ast::{Bind, Def, Expr, ExprKind, Literal, MatchArm, Pattern, Stmt, StmtKind, VarDecl};
+use self::ast::{
+    Bind, Def, Expr, ExprKind, Literal, Location, MatchArm, Pattern, Stmt, StmtKind, VarDecl,
+};
 
 pub mod ast;
+pub mod code;
+pub mod json;
+
+/// How deeply expressions and patterns can be nested, so the parser and the compiler passes
+/// after it, which recurse on the tree, don't overflow the stack
+const MAX_DEPTH: usize = 256;
 
 pub struct Parser {
     lexer: Lexer,
     current: Token,
+    /// The line of the token before `current`
+    last_line: usize,
+    /// The end of the token before `current`
+    last_end: usize,
+    /// The indentation of the statements of the innermost block, a line indented up to it starts
+    /// a new statement instead of passing more arguments to a call
+    indent: usize,
     locals: HashSet<Symbol>,
+    /// The globals declared with `const`, which can't be defined again
+    constants: HashSet<Symbol>,
+    /// If `current` is in a `quote`, outside of its `unquote`s
+    quoting: bool,
+    /// If a `do` ends the expression being parsed instead of starting a block, like the value of
+    /// `with x = value do ... end`
+    stop_at_do: bool,
+    /// If a `;` ends the expression being parsed, in the statements of a `do` block
+    in_block: bool,
+    /// How many expressions and patterns are open around `current`, each operator of a chain like
+    /// `a + b + c` opening one more
+    depth: usize,
 }
 
 impl Parser {
@@ -23,7 +50,15 @@ impl Parser {
         let mut this = Parser {
             lexer,
             current: Token::default(),
+            last_line: 0,
+            last_end: 0,
+            indent: 0,
             locals: HashSet::new(),
+            constants: HashSet::new(),
+            quoting: false,
+            stop_at_do: false,
+            in_block: false,
+            depth: 0,
         };
         this.next()?;
         Ok(this)
@@ -32,19 +67,157 @@ impl Parser {
     pub fn parse(mut self) -> ParseResult<Vec<Stmt>> {
         let mut stmts = Vec::new();
         while self.current.token != Tkt::Eof {
+            stmts.push(self.stmt()?);
+        }
+
+        Ok(stmts)
+    }
+
+    /// Parses the whole program, skipping to the next top-level statement after an error, so
+    /// every statement gets checked
+    /// Parses a program like [`Parser::parse`], along with the source of each statement and where
+    /// it starts
+    pub fn parse_sources(mut self) -> ParseResult<Vec<(Stmt, Location, String)>> {
+        let mut stmts = Vec::new();
+        while self.current.token != Tkt::Eof {
+            let (start, line, column) =
+                (self.current.start, self.current.line, self.current.column);
+            let stmt = self.stmt()?;
+            let source = self.lexer.slice(start, self.last_end);
+            stmts.push((stmt, Location { line, column }, source));
+        }
+
+        Ok(stmts)
+    }
+
+    pub fn parse_all(mut self) -> (Vec<Stmt>, Vec<ParseError>) {
+        let mut stmts = Vec::new();
+        let mut errors = Vec::new();
+
+        while self.current.token != Tkt::Eof {
+            let start = (self.current.line, self.current.column);
+
+            match self.stmt() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(err) => {
+                    errors.push(err);
+                    self.locals.clear();
+                    if let Err(err) = self.recover(start) {
+                        errors.push(err);
+                        break;
+                    }
+                }
+            }
+        }
+
+        (stmts, errors)
+    }
+
+    /// Skips tokens until a statement keyword that starts a line, other than the one at `start`
+    fn recover(&mut self, start: (usize, usize)) -> ParseResult<()> {
+        loop {
+            let moved = (self.current.line, self.current.column) != start;
+            let starts_line = self.current.line != self.last_line;
+
             match self.current.token {
-                Tkt::Type => {
-                    stmts.push(self.type_()?);
+                Tkt::Eof => return Ok(()),
+                Tkt::Type | Tkt::Def | Tkt::Let | Tkt::Const | Tkt::Test | Tkt::Bench
+                    if moved && starts_line =>
+                {
+                    return Ok(())
                 }
+                _ => self.next()?,
+            }
+        }
+    }
+
+    fn stmt(&mut self) -> ParseResult<Stmt> {
+        if let Some(stmt) = self.declaration()? {
+            return Ok(stmt);
+        }
+
+        // anything else is an expression, evaluated for its effects
+        let line = self.current.line;
+        let column = self.current.column;
+
+        self.locals = HashSet::new();
+        let expr = self.expr()?;
+
+        Ok(Stmt::new(StmtKind::Expr(expr), line, column))
+    }
 
-                Tkt::Def => stmts.push(self.def_global()?),
-                Tkt::Let => stmts.push(self.let_global()?),
+    /// Parses a `type`, `def`, `let`, `const`, `test`, `bench`, `module`, `import` or `open`
+    /// declaration, if the next tokens are one
+    fn declaration(&mut self) -> ParseResult<Option<Stmt>> {
+        let state = self.state();
 
-                ref other => self.throw(format!("Unexpected token '{other}'"))?,
+        let stmt = match self.current.token {
+            Tkt::Type => self.type_()?,
+            Tkt::Def => self.def_global()?,
+            Tkt::Let => self.let_global()?,
+            Tkt::Const => self.const_global()?,
+            Tkt::Test | Tkt::Bench => self.test_()?,
+            Tkt::Name(name) if matches!(name.as_str(), "module" | "import" | "open") => {
+                if let Some(stmt) = self.module_stmt(name)? {
+                    stmt
+                } else {
+                    self.set_state(state);
+                    return Ok(None);
+                }
             }
+            _ => return Ok(None),
+        };
+
+        // `let x = 1 in x` isn't a declaration, it's an expression
+        if self.current.token == Tkt::In {
+            self.set_state(state);
+            return Ok(None);
         }
 
-        Ok(stmts)
+        Ok(Some(stmt))
+    }
+
+    /// Parses `module Name`, `import "path"` or `open "path"`. They aren't keywords, so the names
+    /// can still be used for anything else, like calling a function named `open`, which is why the
+    /// statement has to end the line
+    fn module_stmt(&mut self, keyword: Symbol) -> ParseResult<Option<Stmt>> {
+        let line = self.current.line;
+        let column = self.current.column;
+        self.next()?;
+
+        let kind = match (keyword.as_str(), &self.current.token) {
+            ("module", Tkt::Name(name)) => StmtKind::Module(*name),
+            ("import" | "open", Tkt::Str(path)) => StmtKind::Import {
+                path: path.clone(),
+                open: keyword.as_str() == "open",
+            },
+            _ => return Ok(None),
+        };
+        self.next()?;
+
+        if self.current.token != Tkt::Eof && self.current.line == self.last_line {
+            return Ok(None);
+        }
+        Ok(Some(Stmt::new(kind, line, column)))
+    }
+
+    /// Parses any mix of statements, optionally followed by a trailing expression
+    pub fn parse_repl(mut self) -> ParseResult<(Vec<Stmt>, Option<Expr>)> {
+        let mut stmts = Vec::new();
+        while self.current.token != Tkt::Eof {
+            if let Some(stmt) = self.declaration()? {
+                stmts.push(stmt);
+                continue;
+            }
+
+            self.locals = HashSet::new();
+            let expr = self.expr()?;
+            self.assert(&Tkt::Eof)?;
+
+            return Ok((stmts, Some(expr)));
+        }
+
+        Ok((stmts, None))
     }
 
     pub fn let_global(&mut self) -> ParseResult<Stmt> {
@@ -56,6 +229,7 @@ impl Parser {
         self.locals = HashSet::new();
 
         let (_, bind) = self.pattern()?;
+        self.check_const(&bind, line, column)?;
 
         self.expect(&Tkt::Assign)?;
 
@@ -64,16 +238,69 @@ impl Parser {
         Ok(Stmt::new(StmtKind::Let { bind, value }, line, column))
     }
 
+    fn const_global(&mut self) -> ParseResult<Stmt> {
+        let line = self.current.line;
+        let column = self.current.column;
+
+        self.expect(&Tkt::Const)?;
+
+        let name = self.var_decl()?;
+        self.check_const(&Pattern::Id(name), line, column)?;
+
+        self.expect(&Tkt::Assign)?;
+
+        self.locals = HashSet::new();
+        let value = self.expr()?;
+
+        self.constants.insert(name);
+        Ok(Stmt::new(StmtKind::Const { name, value }, line, column))
+    }
+
+    fn test_(&mut self) -> ParseResult<Stmt> {
+        let line = self.current.line;
+        let column = self.current.column;
+
+        let keyword = self.current.token.clone();
+        self.next()?;
+
+        let name = match self.current.token {
+            Tkt::Str(ref name) => name.clone(),
+            ref other => self.throw(format!("Expected {keyword} name, found '{other}'"))?,
+        };
+        self.next()?;
+
+        self.expect(&Tkt::Assign)?;
+
+        self.locals = HashSet::new();
+
+        let body = self.expr()?;
+
+        let kind = match keyword {
+            Tkt::Test => StmtKind::Test { name, body },
+            _ => StmtKind::Bench { name, body },
+        };
+
+        Ok(Stmt::new(kind, line, column))
+    }
+
     pub fn parse_expr(mut self) -> ParseResult<Expr> {
         self.expr()
     }
 
+    /// Parses an expression that must be the whole input
+    pub fn parse_only_expr(mut self) -> ParseResult<Expr> {
+        let expr = self.expr()?;
+        self.assert(&Tkt::Eof)?;
+        Ok(expr)
+    }
+
     fn type_(&mut self) -> ParseResult<Stmt> {
         self.expect(&Tkt::Type)?;
         let line = self.current.line;
         let column = self.current.column;
 
         let name = self.var_decl()?;
+        self.check_const(&Pattern::Id(name), line, column)?;
 
         self.expect(&Tkt::Assign)?;
 
@@ -129,12 +356,15 @@ impl Parser {
         self.expect(&Tkt::Def)?;
 
         let bind = self.var_decl()?;
+        self.check_const(&Pattern::Id(bind), line, column)?;
         let value = self.function(false)?;
 
         Ok(Stmt::new(StmtKind::Def(Def { value, bind }), line, column))
     }
 
     fn next(&mut self) -> ParseResult<()> {
+        self.last_line = self.current.line;
+        self.last_end = self.current.end;
         self.current = self.lexer.next().unwrap()?;
         Ok(())
     }
@@ -165,6 +395,31 @@ impl Parser {
         Ok(())
     }
 
+    /// Raises if the pattern binds a name declared with `const`
+    fn check_const(&self, bind: &Pattern, line: usize, column: usize) -> ParseResult<()> {
+        match bind {
+            Pattern::Id(name) if self.constants.contains(name) => ParseError::throw(
+                line,
+                column,
+                format!("Can't redefine the constant '{}'", name.as_str()),
+            ),
+            Pattern::Variant(_, pats) | Pattern::Tuple(pats) => pats
+                .iter()
+                .try_for_each(|pat| self.check_const(pat, line, column)),
+            Pattern::List(head, tail) => {
+                self.check_const(head, line, column)?;
+                self.check_const(tail, line, column)
+            }
+            Pattern::Typed(pat, _) | Pattern::Prefix(_, pat) | Pattern::Suffix(pat, _) => {
+                self.check_const(pat, line, column)
+            }
+            Pattern::Fields(fields) => fields
+                .iter()
+                .try_for_each(|(_, pat)| self.check_const(pat, line, column)),
+            _ => Ok(()),
+        }
+    }
+
     fn assert(&mut self, expected: &Tkt) -> ParseResult<()> {
         if &self.current.token == expected {
             Ok(())
@@ -188,17 +443,42 @@ impl Parser {
         self.skip(tokens)
     }
 
-    fn state(&self) -> (Token, (usize, usize, usize)) {
-        (self.current.clone(), self.lexer.state())
+    fn state(&self) -> (Token, (usize, usize), (usize, usize, usize)) {
+        let last = (self.last_line, self.last_end);
+        (self.current.clone(), last, self.lexer.state())
     }
 
-    fn set_state(&mut self, (current, state): (Token, (usize, usize, usize))) {
+    fn set_state(
+        &mut self,
+        (current, last, state): (Token, (usize, usize), (usize, usize, usize)),
+    ) {
         self.current = current;
+        (self.last_line, self.last_end) = last;
         self.lexer.set_state(state);
     }
 
     fn expr(&mut self) -> ParseResult<Expr> {
-        self.pipe()
+        self.nested(Self::pipe)
+    }
+
+    /// Parses an expression or pattern inside of another one, raising an error instead of
+    /// overflowing the stack when they're nested too deeply
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        let depth = self.depth;
+        self.deepen()?;
+        let parsed = parse(self);
+        self.depth = depth;
+        parsed
+    }
+
+    /// Goes one level deeper, like each operator of `a + b + c` nests the expression before it
+    fn deepen(&mut self) -> ParseResult<()> {
+        if self.depth == MAX_DEPTH {
+            return self.throw("Expression nested too deeply");
+        }
+
+        self.depth += 1;
+        Ok(())
     }
 
     fn condition(&mut self) -> ParseResult<Expr> {
@@ -280,6 +560,27 @@ impl Parser {
 
         self.expect(&Tkt::With)?;
 
+        let arms = self.match_arms()?;
+
+        Ok(Expr::new(ExprKind::Match { expr, arms }, line, column))
+    }
+
+    fn receive(&mut self) -> ParseResult<Expr> {
+        self.expect(&Tkt::Receive)?;
+
+        let line = self.current.line;
+        let column = self.current.column;
+
+        let arms = self.match_arms()?;
+
+        if arms.is_empty() {
+            self.throw("Expected at least one arm after 'receive'")?;
+        }
+
+        Ok(Expr::new(ExprKind::Receive { arms }, line, column))
+    }
+
+    fn match_arms(&mut self) -> ParseResult<Vec<MatchArm>> {
         let mut arms = vec![];
 
         let mut last_state = self.state();
@@ -294,7 +595,7 @@ impl Parser {
             self.match_arm()?;
         }
 
-        Ok(Expr::new(ExprKind::Match { expr, arms }, line, column))
+        Ok(arms)
     }
 
     fn match_arm(&mut self) -> ParseResult<MatchArm> {
@@ -322,6 +623,27 @@ impl Parser {
         Ok(MatchArm::new(cond, body, guard, line, column))
     }
 
+    fn yield_(&mut self) -> ParseResult<Expr> {
+        self.expect(&Tkt::Yield)?;
+
+        let line = self.current.line;
+        let column = self.current.column;
+
+        let value = Box::new(self.expr()?);
+        let yielded = Expr::new(ExprKind::Yield(value), line, column);
+
+        // `yield x; rest` runs `rest` once resumed, dropping the value it's resumed with, unless
+        // the `;` ends a statement of a block
+        if self.in_block || self.current.token != Tkt::Semicolon {
+            return Ok(yielded);
+        }
+        self.next()?;
+
+        let bind = Pattern::Id(Symbol::from("_"));
+        let (value, body) = (Box::new(yielded), Box::new(self.expr()?));
+        Ok(Expr::new(ExprKind::Let { bind, value, body }, line, column))
+    }
+
     fn try_(&mut self) -> ParseResult<Expr> {
         self.expect(&Tkt::Try)?;
 
@@ -332,7 +654,8 @@ impl Parser {
 
         self.expect(&Tkt::Rescue)?;
 
-        let bind = self.var_decl()?;
+        // a single pattern, so `rescue e e` binds `e` instead of matching the variant `e e`
+        let (_, bind) = self.primary_pat()?;
 
         let rescue = Box::new(self.expr()?);
 
@@ -343,11 +666,172 @@ impl Parser {
         ))
     }
 
+    /// `with name = value do ... end`, which closes the value when the block ends, even if it
+    /// raises
+    fn with_(&mut self) -> ParseResult<Expr> {
+        let line = self.current.line;
+        let column = self.current.column;
+
+        self.expect(&Tkt::With)?;
+        let bind = self.var_decl()?;
+        self.expect(&Tkt::Assign)?;
+
+        let outer = std::mem::replace(&mut self.stop_at_do, true);
+        let value = self.expr();
+        self.stop_at_do = outer;
+        let value = Box::new(value?);
+
+        let body = Box::new(self.do_()?);
+
+        Ok(Expr::new(
+            ExprKind::With { bind, value, body },
+            line,
+            column,
+        ))
+    }
+
     fn fn_(&mut self) -> ParseResult<Expr> {
         self.expect(&Tkt::Fn)?;
         self.function(true)
     }
 
+    /// `quote (f x)`, the nodes of an expression as a value
+    fn quote(&mut self) -> ParseResult<Expr> {
+        let line = self.current.line;
+        let column = self.current.column;
+
+        self.expect(&Tkt::Quote)?;
+
+        let outer = std::mem::replace(&mut self.quoting, true);
+        let quoted = self.method_ref();
+        self.quoting = outer;
+
+        Ok(Expr::new(ExprKind::Quote(Box::new(quoted?)), line, column))
+    }
+
+    /// `unquote x`, only allowed in a `quote`, where it splices the value of `x` into the code
+    fn unquote(&mut self) -> ParseResult<Expr> {
+        let line = self.current.line;
+        let column = self.current.column;
+
+        self.expect(&Tkt::Unquote)?;
+        if !self.quoting {
+            ParseError::throw(line, column, "'unquote' outside of a 'quote'".into())?;
+        }
+
+        // the spliced code runs outside of the quote, so it can't splice again
+        self.quoting = false;
+        let value = self.method_ref();
+        self.quoting = true;
+
+        Ok(Expr::new(ExprKind::Unquote(Box::new(value?)), line, column))
+    }
+
+    /// `"x = #{x}"`, whose expressions are parsed with the locals of the code around the string
+    fn interp(
+        &mut self,
+        pieces: Vec<String>,
+        sources: Vec<(String, usize, usize)>,
+    ) -> ParseResult<Expr> {
+        let line = self.current.line;
+        let column = self.current.column;
+
+        let mut exprs = vec![];
+        for (source, line, column) in sources {
+            let mut lexer = Lexer::new(source);
+            lexer.set_state((line, column, 0));
+
+            let outer = self.state();
+            let outer_lexer = std::mem::replace(&mut self.lexer, lexer);
+            let expr =
+                self.next()
+                    .and_then(|()| self.expr())
+                    .and_then(|expr| match self.current.token {
+                        Tkt::Eof => Ok(expr),
+                        _ => self.throw("Expected the '}' that ends the expression in the string"),
+                    });
+            self.lexer = outer_lexer;
+            self.set_state(outer);
+            exprs.push(expr?);
+        }
+        self.next()?;
+
+        Ok(Expr::new(ExprKind::Interp { pieces, exprs }, line, column))
+    }
+
+    /// `dbg (f x)`, which prints `(f x)` and its value before evaluating to it
+    fn dbg(&mut self) -> ParseResult<Expr> {
+        let line = self.current.line;
+        let column = self.current.column;
+
+        self.expect(&Tkt::Dbg)?;
+        if self.current.indent.is_some_and(|it| it <= self.indent) {
+            self.throw("Expected an expression after 'dbg'")?;
+        }
+
+        let start = self.current.start;
+        let value = self.method_ref()?;
+        let source = self.lexer.slice(start, self.last_end);
+
+        Ok(Expr::new(
+            ExprKind::Dbg {
+                source,
+                value: Box::new(value),
+            },
+            line,
+            column,
+        ))
+    }
+
+    /// `@when(debug) println x`, an expression only compiled if the flag is defined
+    fn when_(&mut self) -> ParseResult<Expr> {
+        let line = self.current.line;
+        let column = self.current.column;
+
+        self.expect(&Tkt::At)?;
+        match self.current.token {
+            Tkt::Name(name) if name.as_str() == "when" => self.next()?,
+            ref other => self.throw(format!("Expected 'when' after '@', found '{other}'"))?,
+        }
+
+        self.expect(&Tkt::Lparen)?;
+        let flag = self.var_decl()?;
+        self.expect(&Tkt::Rparen)?;
+
+        let body = self.expr()?;
+
+        Ok(Expr::new(
+            ExprKind::When {
+                flag,
+                body: Box::new(body),
+            },
+            line,
+            column,
+        ))
+    }
+
+    /// `&(it + 1)`, a function of a single argument named `it`
+    fn shorthand_fn(&mut self) -> ParseResult<Expr> {
+        let line = self.current.line;
+        let column = self.current.column;
+
+        self.expect(&Tkt::Amp)?;
+        self.assert(&Tkt::Lparen)?;
+
+        let locals = take(&mut self.locals);
+        let body = self.tuple();
+        self.locals = locals;
+
+        Ok(Expr::new(
+            ExprKind::Lambda {
+                args: vec![Pattern::Id(Symbol::from("it"))],
+                body: Box::new(body?),
+            },
+            line,
+            column,
+        ))
+    }
+
     fn function(&mut self, is_lambda: bool) -> ParseResult<Expr> {
         let line = self.current.line;
         let column = self.current.column;
@@ -403,7 +887,40 @@ impl Parser {
     }
 
     fn pattern(&mut self) -> ParseResult<(Vec<Symbol>, Pattern)> {
-        self.list_pat()
+        let (identifiers, pat) = self.nested(Self::concat_pat)?;
+        if self.current.token != Tkt::Colon {
+            return Ok((identifiers, pat));
+        }
+
+        // `pattern: Type` only matches the values of the type
+        self.next()?;
+        let mut path = vec![self.var_decl()?];
+        while let Tkt::Dot = self.current.token {
+            self.next()?;
+            path.push(self.var_decl()?);
+        }
+
+        Ok((identifiers, Pattern::Typed(Box::new(pat), path)))
+    }
+
+    /// `"prefix" <> rest` or `start <> "suffix"`
+    fn concat_pat(&mut self) -> ParseResult<(Vec<Symbol>, Pattern)> {
+        let (mut identifiers, lhs) = self.list_pat()?;
+        if self.current.token != Tkt::Concat {
+            return Ok((identifiers, lhs));
+        }
+
+        self.next()?;
+        let (ids, rhs) = self.list_pat()?;
+        identifiers.extend(ids);
+
+        let pat = match (lhs, rhs) {
+            (Pattern::Lit(Literal::Str(prefix)), rest) => Pattern::Prefix(prefix, Box::new(rest)),
+            (start, Pattern::Lit(Literal::Str(suffix))) => Pattern::Suffix(Box::new(start), suffix),
+            _ => self.throw("Expected a string on one of the sides of '<>'")?,
+        };
+
+        Ok((identifiers, pat))
     }
 
     fn list_pat(&mut self) -> ParseResult<(Vec<Symbol>, Pattern)> {
@@ -411,7 +928,7 @@ impl Parser {
         let rhs = if self.current.token == Tkt::Cons {
             self.next()?;
 
-            let (ids, pat) = self.list_pat()?;
+            let (ids, pat) = self.nested(Self::list_pat)?;
             identifiers.extend(ids);
 
             Some(pat)
@@ -460,8 +977,21 @@ impl Parser {
         let peek = self.peek()?.token;
 
         let pat = match self.current.token {
+            Tkt::Int(_) | Tkt::Num(_) if peek == Tkt::DotDot => {
+                let start = range_bound(&self.current.token).unwrap();
+                self.next()?;
+                self.next()?;
+                if let Some(end) = range_bound(&self.current.token) {
+                    Pattern::Range(start, end)
+                } else {
+                    let other = &self.current.token;
+                    self.throw(format!("Expected the end of the range, found '{other}'"))?
+                }
+            }
+            Tkt::Int(n) => Pattern::Lit(Literal::Int(n)),
             Tkt::Num(n) => Pattern::Lit(Literal::Num(n)),
             Tkt::Str(ref s) => Pattern::Lit(Literal::Str(s.to_string())),
+            Tkt::Sym(s) => Pattern::Lit(Literal::Sym(s)),
             Tkt::Nil => Pattern::Lit(Literal::Unit),
             Tkt::True => Pattern::Lit(Literal::Bool(true)),
             Tkt::False => Pattern::Lit(Literal::Bool(false)),
@@ -494,6 +1024,7 @@ impl Parser {
                 self.assert(&Tkt::Rbrack)?;
                 Pattern::EmptyList
             }
+            Tkt::Lbrace => return self.fields_pat(),
             ref other => self.throw(format!("Expected pattern, found '{other}'"))?,
         };
 
@@ -502,17 +1033,41 @@ impl Parser {
         Ok((vec![], pat))
     }
 
+    /// `{name: pattern, ...}`, where `{name}` is short for `{name: name}`
+    fn fields_pat(&mut self) -> ParseResult<(Vec<Symbol>, Pattern)> {
+        self.expect(&Tkt::Lbrace)?;
+
+        let mut fields = vec![];
+        let mut identifiers = vec![];
+
+        while self.current.token != Tkt::Rbrace {
+            let name = self.var_decl()?;
+            let pat = if self.current.token == Tkt::Colon {
+                self.next()?;
+                let (ids, pat) = self.pattern()?;
+                identifiers.extend(ids);
+                pat
+            } else {
+                Pattern::Id(name)
+            };
+            fields.push((name, pat));
+
+            if self.current.token != Tkt::Rbrace {
+                self.expect_and_skip(&Tkt::Comma)?;
+            }
+        }
+
+        self.next()?;
+        Ok((identifiers, Pattern::Fields(fields)))
+    }
+
     fn let_(&mut self) -> ParseResult<Expr> {
         let line = self.current.line;
         let column = self.current.column;
 
         self.expect(&Tkt::Let)?;
 
-        let (ids, bind) = self.pattern()?;
-
-        self.expect(&Tkt::Assign)?;
-
-        let value = self.expr()?;
+        let (ids, bind, value) = self.let_bind(line, column)?;
 
         self.expect(&Tkt::In)?;
 
@@ -533,6 +1088,111 @@ impl Parser {
         ))
     }
 
+    /// The names, the pattern and the value of a `let`, `let self.field = value` binds `self`
+    /// again, to a copy of it with the field set to the value
+    fn let_bind(
+        &mut self,
+        line: usize,
+        column: usize,
+    ) -> ParseResult<(Vec<Symbol>, Pattern, Expr)> {
+        let this = Symbol::from("self");
+        if self.current.token == Tkt::Name(this) && self.peek()?.token == Tkt::Dot {
+            self.next()?;
+            self.next()?;
+
+            let field = self.var_decl()?;
+            self.expect(&Tkt::Assign)?;
+            let value = self.expr()?;
+
+            let instance = Box::new(Expr::new(ExprKind::Var(this), line, column));
+            let value = ExprKind::SetField {
+                instance,
+                field,
+                value: Box::new(value),
+            };
+            return Ok((vec![], Pattern::Id(this), Expr::new(value, line, column)));
+        }
+
+        let (ids, bind) = self.pattern()?;
+        self.check_const(&bind, line, column)?;
+        self.expect(&Tkt::Assign)?;
+        let value = self.expr()?;
+
+        Ok((ids, bind, value))
+    }
+
+    /// `do ... end`, a sequence of expressions and `let` bindings, which are in scope until the
+    /// end of the block
+    fn do_(&mut self) -> ParseResult<Expr> {
+        self.expect(&Tkt::Do)?;
+
+        // the block is laid out by the indentation of its first line
+        let outer = self.indent;
+        if let Some(indent) = self.current.indent {
+            self.indent = indent;
+        }
+
+        let in_block = std::mem::replace(&mut self.in_block, true);
+        let body = self.block();
+        self.indent = outer;
+        self.in_block = in_block;
+
+        let body = body?;
+        self.expect(&Tkt::End)?;
+
+        Ok(body)
+    }
+
+    /// The statements of a `do` block, from the current one to the `end`
+    fn block(&mut self) -> ParseResult<Expr> {
+        let line = self.current.line;
+        let column = self.current.column;
+
+        if self.current.token == Tkt::End {
+            return Ok(Expr::new(ExprKind::Lit(Literal::Unit), line, column));
+        }
+
+        let state = self.state();
+        if self.current.token == Tkt::Let {
+            self.next()?;
+
+            let (ids, bind, value) = self.let_bind(line, column)?;
+
+            // `let x = 1 in x` is an expression like any other
+            if self.current.token != Tkt::In {
+                self.skip_semicolon()?;
+                let body = self.nested(Self::block)?;
+
+                for id in ids {
+                    self.locals.remove(&id);
+                }
+
+                let (value, body) = (Box::new(value), Box::new(body));
+                return Ok(Expr::new(ExprKind::Let { bind, value, body }, line, column));
+            }
+
+            self.set_state(state);
+        }
+
+        let value = self.expr()?;
+        self.skip_semicolon()?;
+        if self.current.token == Tkt::End {
+            return Ok(value);
+        }
+
+        // the value of everything but the last expression is dropped
+        let bind = Pattern::Id(Symbol::from("_"));
+        let (value, body) = (Box::new(value), Box::new(self.nested(Self::block)?));
+        Ok(Expr::new(ExprKind::Let { bind, value, body }, line, column))
+    }
+
+    fn skip_semicolon(&mut self) -> ParseResult<()> {
+        if self.current.token == Tkt::Semicolon {
+            self.next()?;
+        }
+        Ok(())
+    }
+
     fn def_(&mut self) -> ParseResult<Expr> {
         self.expect(&Tkt::Def)?;
 
@@ -542,6 +1202,7 @@ impl Parser {
         let name = self.var_decl()?;
 
         self.check_unused(&name)?;
+        self.check_const(&Pattern::Id(name), line, column)?;
         self.locals.insert(name);
 
         let value = self.function(false)?;
@@ -563,23 +1224,45 @@ impl Parser {
     }
 
     fn pipe(&mut self) -> ParseResult<Expr> {
+        let start = self.current.start;
         let mut left = self.logic_or()?;
 
-        while self.current.token == Tkt::Pipe {
+        while let Tkt::Pipe | Tkt::TryPipe = self.current.token {
+            self.deepen()?;
+            let short_circuits = self.current.token == Tkt::TryPipe;
+            let end = self.last_end;
             self.next()?;
 
             let line = self.current.line;
             let column = self.current.column;
 
-            left = Expr::new(
+            // `xs |> dbg` prints the expression piped into it
+            let state = self.state();
+            if self.current.token == Tkt::Dbg && self.logic_or().is_err() {
+                self.set_state(state);
+                self.next()?;
+
+                let source = self.lexer.slice(start, end);
+                let value = Box::new(left);
+                left = Expr::new(ExprKind::Dbg { source, value }, line, column);
+                continue;
+            }
+            self.set_state(state);
+
+            let callee = Box::new(self.logic_or()?);
+            let kind = if short_circuits {
+                ExprKind::TryPipe {
+                    arg: Box::new(left),
+                    callee,
+                }
+            } else {
                 ExprKind::App {
                     args: vec![left],
-                    callee: Box::new(self.logic_or()?),
+                    callee,
                     tail: false,
-                },
-                line,
-                column,
-            );
+                }
+            };
+            left = Expr::new(kind, line, column);
         }
 
         Ok(left)
@@ -589,6 +1272,7 @@ impl Parser {
         let mut left = self.logic_and()?;
 
         while let Tkt::Or = self.current.token {
+            self.deepen()?;
             let op: ast::BinOp = self.current.token.clone().try_into().unwrap();
 
             self.next()?;
@@ -615,6 +1299,7 @@ impl Parser {
         let mut left = self.is()?;
 
         while let Tkt::And = self.current.token {
+            self.deepen()?;
             let op = self.current.token.clone().try_into().unwrap();
 
             self.next()?;
@@ -641,6 +1326,7 @@ impl Parser {
         let mut left = self.eq()?;
 
         while let Tkt::Is = self.current.token {
+            self.deepen()?;
             let line = left.line();
             let column = left.column();
 
@@ -665,6 +1351,7 @@ impl Parser {
         let mut left = self.cmp()?;
 
         while let Tkt::Eq | Tkt::Ne = self.current.token {
+            self.deepen()?;
             let op = self.current.clone();
             self.next()?;
             let right = self.cmp()?;
@@ -687,6 +1374,7 @@ impl Parser {
         let mut left = self.cons()?;
 
         while let Tkt::Less | Tkt::LessEq | Tkt::Greater | Tkt::GreaterEq = self.current.token {
+            self.deepen()?;
             let op = self.current.clone();
             self.next()?;
             let right = self.cons()?;
@@ -709,6 +1397,7 @@ impl Parser {
         let mut left = self.bitwise()?;
 
         while let Tkt::Cons = self.current.token {
+            self.deepen()?;
             let op = self.current.clone();
             self.next()?;
             let right = self.cons()?;
@@ -729,8 +1418,10 @@ impl Parser {
     fn bitwise(&mut self) -> ParseResult<Expr> {
         let mut left = self.term()?;
 
-        while let Tkt::BitOr | Tkt::BitAnd | Tkt::BitXor | Tkt::Shr | Tkt::Shl = self.current.token
+        while let Tkt::BitOr | Tkt::BitAnd | Tkt::BitXor | Tkt::Shr | Tkt::UShr | Tkt::Shl =
+            self.current.token
         {
+            self.deepen()?;
             let op = self.current.clone();
             self.next()?;
             let right = self.term()?;
@@ -753,6 +1444,7 @@ impl Parser {
         let mut left = self.fact()?;
 
         while let Tkt::Add | Tkt::Sub = self.current.token {
+            self.deepen()?;
             let op = self.current.clone();
             self.next()?;
             let right = self.fact()?;
@@ -774,7 +1466,8 @@ impl Parser {
     fn fact(&mut self) -> ParseResult<Expr> {
         let mut left = self.prefix()?;
 
-        while let Tkt::Mul | Tkt::Div | Tkt::Rem = self.current.token {
+        while let Tkt::Mul | Tkt::Div | Tkt::Rem | Tkt::FloorDiv | Tkt::Mod = self.current.token {
+            self.deepen()?;
             let op = self.current.clone();
             self.next()?;
             let right = self.prefix()?;
@@ -794,10 +1487,10 @@ impl Parser {
     }
 
     fn prefix(&mut self) -> ParseResult<Expr> {
-        if let Tkt::Sub | Tkt::Not = &self.current.token {
+        if let Tkt::Sub | Tkt::Not | Tkt::BitNot = &self.current.token {
             let op = self.current.clone();
             self.next()?;
-            let right = self.prefix()?;
+            let right = self.nested(Self::prefix)?;
             Ok(Expr::new(
                 ExprKind::UnOp(op.token.try_into().unwrap(), Box::new(right)),
                 op.line,
@@ -817,7 +1510,10 @@ impl Parser {
         let mut last_state = self.state();
         let mut args = vec![];
 
-        while let Ok(arg) = self.method_ref() {
+        while self.current.indent.is_none_or(|it| it > self.indent) {
+            let Ok(arg) = self.method_ref() else {
+                break;
+            };
             args.push(arg);
             last_state = self.state();
         }
@@ -839,9 +1535,10 @@ impl Parser {
     }
 
     fn method_ref(&mut self) -> ParseResult<Expr> {
-        let mut ty = self.primary()?;
+        let mut ty = self.nested(Self::primary)?;
 
         while self.current.token == Tkt::Dot {
+            self.deepen()?;
             self.next()?;
             let method = self.var_decl()?;
 
@@ -883,20 +1580,23 @@ impl Parser {
         let column = self.current.column;
 
         let mut exprs = Vec::new();
+        // `(x,)` is a tuple of one item, while `(x)` is just `x`
+        let mut trailing_comma = false;
 
         self.expect(&Tkt::Lparen)?;
 
         while self.current.token != Tkt::Rparen {
             exprs.push(self.expr()?); // compiles the argument
 
-            if self.current.token != Tkt::Rparen {
+            trailing_comma = self.current.token != Tkt::Rparen;
+            if trailing_comma {
                 self.expect_and_skip(&Tkt::Comma)?;
             }
         }
 
         self.expect(&Tkt::Rparen)?;
 
-        if exprs.len() == 1 {
+        if exprs.len() == 1 && !trailing_comma {
             Ok(exprs.pop().unwrap())
         } else {
             Ok(Expr::new(ExprKind::Tuple(exprs), line, column))
@@ -909,6 +1609,10 @@ impl Parser {
 
         let obj = match self.current.token.clone() {
             // literals
+            Tkt::Int(n) => {
+                self.next()?;
+                Expr::new(ExprKind::Lit(Literal::Int(n)), line, column)
+            }
             Tkt::Num(n) => {
                 self.next()?;
                 Expr::new(ExprKind::Lit(Literal::Num(n)), line, column)
@@ -917,6 +1621,7 @@ impl Parser {
                 self.next()?;
                 Expr::new(ExprKind::Lit(Literal::Str(s)), line, column)
             }
+            Tkt::Interp(pieces, sources) => self.interp(pieces, sources)?,
             Tkt::True => {
                 self.next()?;
                 Expr::new(ExprKind::Lit(Literal::Bool(true)), line, column)
@@ -939,15 +1644,28 @@ impl Parser {
                 self.next()?;
                 Expr::new(ExprKind::Lit(Literal::Unit), line, column)
             }
+            Tkt::Recur => {
+                self.next()?;
+                Expr::new(ExprKind::Recur, line, column)
+            }
 
             // keywords
             Tkt::Let => self.let_()?,
             Tkt::Def => self.def_()?,
             Tkt::If => self.condition()?,
             Tkt::Fn => self.fn_()?,
+            Tkt::Amp => self.shorthand_fn()?,
+            Tkt::At => self.when_()?,
+            Tkt::Quote => self.quote()?,
+            Tkt::Unquote => self.unquote()?,
+            Tkt::Dbg => self.dbg()?,
             Tkt::FatArrow => self.become_()?,
             Tkt::Match => self.match_()?,
+            Tkt::Receive => self.receive()?,
+            Tkt::Yield => self.yield_()?,
             Tkt::Try => self.try_()?,
+            Tkt::With => self.with_()?,
+            Tkt::Do if !self.stop_at_do => self.do_()?,
 
             // not supported
             other => self.throw(format!("unexpected token '{}'", other))?,
@@ -957,6 +1675,15 @@ impl Parser {
     }
 }
 
+/// The number a token gives as a bound of a range pattern, which is matched as a float
+fn range_bound(token: &Tkt) -> Option<f64> {
+    match *token {
+        Tkt::Int(n) => Some(n as f64),
+        Tkt::Num(n) => Some(n),
+        _ => None,
+    }
+}
+
 #[test]
 fn parse_fn() {
     crate::parse_expr("fn arg -> 10").unwrap();
@@ -987,11 +1714,166 @@ fn parse_global_def() {
     assert!(crate::parse("def c a b = a + b").is_ok());
 }
 
+#[test]
+fn parse_test_decl() {
+    assert!(crate::parse("test \"addition\" = 1 + 1 == 2").is_ok());
+}
+
+#[test]
+fn parse_test_decl_err_no_name() {
+    assert!(crate::parse("test = 1 + 1 == 2").is_err());
+}
+
+#[test]
+fn parse_bench_decl() {
+    assert!(crate::parse("bench \"sum\" = List.fold (fn a b -> a + b) 0 [1, 2, 3]").is_ok());
+}
+
+#[test]
+fn parse_receive() {
+    assert!(crate::parse_expr("receive | (:ping, from) -> send from :pong | _ -> nil").is_ok());
+}
+
+#[test]
+fn parse_yield() {
+    assert!(crate::parse("def count n = let _ = yield n in => count (n + 1)").is_ok());
+}
+
+#[test]
+fn parse_repl_mixed() {
+    let parse = |src| Parser::new(Lexer::new(src)).unwrap().parse_repl().unwrap();
+
+    let (stmts, expr) = parse("type T = a with end let t = T.a");
+    assert_eq!(stmts.len(), 2);
+    assert!(expr.is_none());
+
+    let (stmts, expr) = parse("type T = a with end T.a");
+    assert_eq!(stmts.len(), 1);
+    assert!(expr.is_some());
+
+    let (stmts, expr) = parse("let x = 1 in x + 1");
+    assert!(stmts.is_empty());
+    assert!(expr.is_some());
+
+    let (stmts, expr) = parse("def f x = x in f 1");
+    assert!(stmts.is_empty());
+    assert!(expr.is_some());
+}
+
+#[test]
+fn parse_expression_statements() {
+    let parse = |src| Parser::new(Lexer::new(src)).unwrap().parse().unwrap();
+
+    let stmts = parse("println 1\nprintln 2\nlet x = 1 in x");
+    assert_eq!(stmts.len(), 3);
+    assert!(stmts.iter().all(|it| matches!(it.kind, StmtKind::Expr(_))));
+
+    // indented lines still pass arguments
+    assert_eq!(parse("f\n  1\n  2").len(), 1);
+}
+
+#[test]
+fn parse_repl_err_trailing_stmt() {
+    assert!(crate::parse_repl("1 + 1 let x = 2").is_err());
+}
+
 #[test]
 fn parse_global_let() {
     assert!(crate::parse("let a = 10").is_ok());
 }
 
+#[test]
+fn parse_const_err_redefined() {
+    assert!(crate::parse("const pi = 3.14\nlet tau = pi * 2").is_ok());
+    assert!(crate::parse("const pi = 3.14\nlet pi = 3").is_err());
+    assert!(crate::parse("const pi = 3.14\ndef pi = 3").is_err());
+    assert!(crate::parse("const pi = 3.14\nlet x = let (pi, _) = (1, 2) in pi").is_err());
+}
+
+#[test]
+fn parse_quote() {
+    let expr = Parser::new(Lexer::new("quote (a + unquote (b 1) * 2)"))
+        .unwrap()
+        .parse_expr()
+        .unwrap();
+    let ExprKind::Quote(quoted) = expr.kind else {
+        panic!("expected a quote, found {expr:?}");
+    };
+    let ExprKind::Binary { right, .. } = quoted.kind else {
+        panic!("expected a binary operation, found {quoted:?}");
+    };
+    let ExprKind::Binary { left, .. } = right.kind else {
+        panic!("expected a binary operation, found {right:?}");
+    };
+
+    assert!(matches!(
+        left.kind,
+        ExprKind::Unquote(value) if matches!(value.kind, ExprKind::App { .. })
+    ));
+    assert!(crate::parse_expr("unquote a").is_err());
+    assert!(crate::parse_expr("quote (unquote (unquote a))").is_err());
+}
+
+#[test]
+fn parse_dbg() {
+    let expr = Parser::new(Lexer::new("dbg (f  x) + 1"))
+        .unwrap()
+        .parse_expr()
+        .unwrap();
+    let ExprKind::Binary { left, .. } = expr.kind else {
+        panic!("expected a binary expression, found {expr:?}");
+    };
+
+    assert!(matches!(&left.kind, ExprKind::Dbg { source, .. } if source == "(f  x)"));
+}
+
+#[test]
+fn parse_let_self_field() {
+    let expr = Parser::new(Lexer::new("let self.x = 1 in self"))
+        .unwrap()
+        .parse_expr()
+        .unwrap();
+    let ExprKind::Let { bind, value, .. } = expr.kind else {
+        panic!("expected a let expression, found {expr:?}");
+    };
+
+    assert!(matches!(bind, Pattern::Id(name) if name.as_str() == "self"));
+    assert!(matches!(value.kind, ExprKind::SetField { field, .. } if field.as_str() == "x"));
+}
+
+#[test]
+fn parse_with() {
+    let expr = Parser::new(Lexer::new("with f = open \"x\" do f end"))
+        .unwrap()
+        .parse_expr()
+        .unwrap();
+    let ExprKind::With { value, body, .. } = expr.kind else {
+        panic!("expected a with block, found {expr:?}");
+    };
+
+    assert!(matches!(value.kind, ExprKind::App { ref args, .. } if args.len() == 1));
+    assert!(matches!(body.kind, ExprKind::Var(name) if name.as_str() == "f"));
+}
+
+#[test]
+fn parse_yield_sequences() {
+    let expr = Parser::new(Lexer::new("fn n -> yield n; => recur (n + 1)"))
+        .unwrap()
+        .parse_expr()
+        .unwrap();
+    let ExprKind::Lambda { body, .. } = expr.kind else {
+        panic!("expected a lambda, found {expr:?}");
+    };
+    let ExprKind::Let { value, body, .. } = body.kind else {
+        panic!("expected the yield to run before the call, found {body:?}");
+    };
+
+    assert!(matches!(value.kind, ExprKind::Yield(_)));
+    assert!(matches!(body.kind, ExprKind::App { tail: true, .. }));
+    // in a block, the `;` ends the statement instead
+    assert!(crate::parse_expr("fn n -> do yield n; n end").is_ok());
+}
+
 #[test]
 fn parse_codition_or() {
     assert!(crate::parse_expr("2 || 2").is_ok())
@@ -1006,3 +1888,69 @@ fn parse_codition_and() {
 fn parse_list() {
     assert!(crate::parse_expr("[1, 2, 3]").is_ok());
 }
+
+#[test]
+fn parse_all_recovers() {
+    let src = "def f = )\nlet x = 1\nlet y = )\nlet z = 2";
+    let (stmts, errors) = Parser::new(Lexer::new(src)).unwrap().parse_all();
+
+    assert_eq!(stmts.len(), 2);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[1].line(), 3);
+}
+
+#[test]
+#[allow(clippy::float_cmp)]
+fn parse_field_patterns() {
+    let src = "match res with | {status: 200, body} -> body | _ -> nil";
+    let expr = Parser::new(Lexer::new(src)).unwrap().parse_expr().unwrap();
+    let ExprKind::Match { arms, .. } = expr.kind else {
+        panic!("expected a match");
+    };
+
+    let Pattern::Fields(fields) = &arms[0].cond else {
+        panic!("expected a field pattern");
+    };
+    let names = fields
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["status", "body"]);
+    assert!(matches!(fields[0].1, Pattern::Lit(Literal::Int(200))));
+    assert!(matches!(fields[1].1, Pattern::Id(name) if name.as_str() == "body"));
+
+    assert!(crate::parse_expr("match x with | {status 200} -> 1").is_err());
+}
+
+#[test]
+fn parse_untrusted_code() {
+    // the default stack of the tests is smaller than the one of the main thread
+    let parse = std::thread::Builder::new().stack_size(8 << 20).spawn(|| {
+        let nested = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+        let err = crate::parse_untrusted(nested.as_bytes()).unwrap_err();
+        assert_eq!(err.message(), "Expression nested too deeply");
+
+        let chain = format!("{}1", "1 + ".repeat(10_000));
+        assert!(crate::parse_untrusted(chain.as_bytes()).is_err());
+
+        assert!(crate::parse_untrusted(b"let x = \"\xff\"").is_ok());
+        assert!(crate::parse_untrusted(b"let x = \"\\ud800\"").is_err());
+    });
+    parse.unwrap().join().unwrap();
+}
+
+#[test]
+fn parse_imports() {
+    let stmts = Parser::new(Lexer::new(
+        "module Geo\nimport \"math.yex\"\nopen \"util.yex\"\nopen \"log.txt\" |> close",
+    ))
+    .unwrap()
+    .parse()
+    .unwrap();
+
+    assert!(matches!(stmts[0].kind, StmtKind::Module(name) if name.as_str() == "Geo"));
+    assert!(matches!(&stmts[1].kind, StmtKind::Import { path, open: false } if path == "math.yex"));
+    assert!(matches!(&stmts[2].kind, StmtKind::Import { path, open: true } if path == "util.yex"));
+    // the names can still be called when the statement doesn't end the line
+    assert!(matches!(stmts[3].kind, StmtKind::Expr(_)));
+}
@@ -0,0 +1,236 @@
+//! Incremental compilation of a program that's edited and compiled again, like the file of a REPL
+//! or an editor, where most of the top-level statements don't change between the compilations
+use std::{collections::HashMap, mem::take, ops::Range};
+
+use vm::{gc::GcRef, Bytecode, Fn, FnKind, OpCodeMetadata, Symbol, Value};
+
+use crate::{
+    compiler::Compiler,
+    error::ParseResult,
+    lexer::Lexer,
+    parser::{ast::StmtKind, Parser},
+};
+
+/// How many constants the statements that are gone can leave behind before the session starts
+/// over, at least
+const MIN_GARBAGE: usize = 256;
+
+/// A top-level statement, compiled on its own
+struct Chunk {
+    /// The line the statement was compiled at
+    line: usize,
+    bytecode: Bytecode,
+    /// The constants the statement added
+    constants: Range<usize>,
+}
+
+/// The source of a top-level statement, its column, and if it's the trailing expression of the
+/// program, whose value is left on the stack
+type Key = (String, usize, bool);
+
+/// Compiles the versions of a program, only compiling again the top-level statements whose source
+/// changed since the last version. The statements that only moved to other lines are reused with
+/// their lines updated
+#[derive(Default)]
+pub struct Session {
+    chunks: HashMap<Key, Vec<Chunk>>,
+    /// The constants of every chunk compiled, including the ones of the statements that are gone
+    constants: Vec<Value>,
+    /// The flags the chunks were compiled with
+    defined: Vec<Symbol>,
+    /// How many statements the last version compiled
+    compiled: usize,
+}
+
+impl Session {
+    /// Creates a session that didn't compile anything yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles a version of the program like [`crate::parse`] does
+    pub fn compile(&mut self, source: &str) -> ParseResult<(Bytecode, Vec<Value>)> {
+        let stmts = Parser::new(Lexer::new(source))?.parse_sources()?;
+
+        let used = self
+            .chunks
+            .values()
+            .flatten()
+            .map(|it| it.constants.len())
+            .sum();
+        let garbage = self.constants.len() - used;
+        if Compiler::new().defined() != self.defined || garbage > MIN_GARBAGE.max(used) {
+            self.chunks.clear();
+            self.constants.clear();
+            self.defined = Compiler::new().defined().to_vec();
+        }
+
+        let keys = stmts
+            .iter()
+            .enumerate()
+            .map(|(idx, (stmt, start, source))| {
+                let value = idx + 1 == stmts.len() && matches!(stmt.kind, StmtKind::Expr(_));
+                (source.clone(), start.column, value)
+            })
+            .collect::<Vec<_>>();
+
+        // the chunks are moved before compiling the new ones, which could share the constants of
+        // the chunks where they are now
+        let mut old = take(&mut self.chunks);
+        let mut chunks = stmts
+            .iter()
+            .zip(&keys)
+            .map(|((_, start, _), key)| {
+                let line = start.line;
+                let reused = old.get_mut(key).and_then(|chunks| {
+                    let at = chunks.iter().position(|it| self.movable(it, line))?;
+                    Some(chunks.swap_remove(at))
+                });
+                reused.map(|chunk| self.move_to(chunk, line))
+            })
+            .collect::<Vec<_>>();
+
+        self.compiled = 0;
+        for (((stmt, start, _), key), chunk) in stmts.iter().zip(&keys).zip(&mut chunks) {
+            if chunk.is_some() {
+                continue;
+            }
+
+            self.compiled += 1;
+            let first = self.constants.len();
+            let (bytecode, constants) =
+                Compiler::new().compile_chunk(take(&mut self.constants), stmt, key.2);
+            self.constants = constants;
+
+            *chunk = Some(Chunk {
+                line: start.line,
+                bytecode,
+                constants: first..self.constants.len(),
+            });
+        }
+
+        let mut order = Vec::with_capacity(keys.len());
+        for (key, chunk) in keys.into_iter().zip(chunks.into_iter().flatten()) {
+            let chunks = self.chunks.entry(key.clone()).or_default();
+            chunks.push(chunk);
+            order.push((key, chunks.len() - 1));
+        }
+
+        let bytecode = order
+            .iter()
+            .map(|(key, idx)| &self.chunks[key][*idx].bytecode[..]);
+        Ok(Compiler::new().link_chunks(bytecode, self.constants.clone()))
+    }
+
+    /// How many top-level statements the last call to [`Session::compile`] compiled, the others
+    /// were reused
+    #[must_use]
+    pub fn compiled(&self) -> usize {
+        self.compiled
+    }
+
+    /// Checks if a chunk can be moved to `line`, which needs a new version of its constants, but
+    /// the methods of a type are kept in a module, which is compiled again instead
+    fn movable(&self, chunk: &Chunk, line: usize) -> bool {
+        chunk.line == line
+            || self.constants[chunk.constants.clone()]
+                .iter()
+                .all(|it| !matches!(it, Value::Module(_)))
+    }
+
+    /// Moves a chunk, and the bytecode of the functions it defines, to `line`
+    fn move_to(&mut self, mut chunk: Chunk, line: usize) -> Chunk {
+        if chunk.line == line {
+            return chunk;
+        }
+
+        let from = chunk.line;
+        let moved = |ops: &mut [OpCodeMetadata]| {
+            for op in ops.iter_mut().filter(|op| op.line > 0) {
+                op.line = op.line + line - from;
+            }
+        };
+
+        moved(&mut chunk.bytecode);
+        for constant in &mut self.constants[chunk.constants.clone()] {
+            let Value::Fn(fun) = constant else {
+                continue;
+            };
+
+            let body = match &*fun.body {
+                FnKind::Bytecode(ops) | FnKind::Generator(ops) => {
+                    let mut ops = ops.clone();
+                    moved(&mut ops);
+                    match &*fun.body {
+                        FnKind::Generator(_) => FnKind::Generator(ops),
+                        _ => FnKind::Bytecode(ops),
+                    }
+                }
+                FnKind::Native(_) => continue,
+            };
+            *constant = Value::Fn(GcRef::new(Fn {
+                body: GcRef::new(body),
+                ..(**fun).clone()
+            }));
+        }
+
+        chunk.line = line;
+        chunk
+    }
+}
+
+#[test]
+fn compile_the_changed_statements() {
+    use vm::OpCode;
+
+    let mut session = Session::new();
+    let source = "def inc x = x + 1\ndef double x = x * 2\ndouble (inc 1)";
+    let program = session.compile(source).unwrap();
+    assert_eq!(session.compiled(), 3);
+    assert_eq!(program.0, crate::parse(source).unwrap().0);
+
+    // nothing changed
+    session.compile(source).unwrap();
+    assert_eq!(session.compiled(), 0);
+
+    // `inc` changed, and the statements after it moved a line down
+    let edited = "def inc x = x + 2\n\ndef double x = x * 2\ndouble (inc 1)";
+    let (bytecode, constants) = session.compile(edited).unwrap();
+    assert_eq!(session.compiled(), 1);
+
+    let (expected, _) = crate::parse(edited).unwrap();
+    let position = |ops: &[OpCodeMetadata]| {
+        ops.iter()
+            .map(|op| (op.line, op.column, op.opcode.kind()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(position(&bytecode), position(&expected));
+
+    // the function saved to `double`
+    let Some(
+        &[OpCodeMetadata {
+            opcode: OpCode::Push(double),
+            ..
+        }, _],
+    ) = bytecode
+        .windows(2)
+        .find(|ops| ops[1].opcode == OpCode::Savg(Symbol::new("double")))
+    else {
+        panic!("expected double to be defined");
+    };
+    let Value::Fn(double) = &constants[double] else {
+        panic!("expected the function of double");
+    };
+    let FnKind::Bytecode(body) = &*double.body else {
+        panic!("expected a bytecode function");
+    };
+    assert!(body.iter().all(|op| op.line == 3));
+
+    let mut vm = vm::VirtualMachine::default();
+    vm.set_consts(constants);
+    vm.run(&bytecode).unwrap();
+    assert_eq!(vm.pop_last(), &Value::Num(6.0));
+
+    assert!(session.compile("def double x =").is_err());
+}
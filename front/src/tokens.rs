@@ -1,8 +1,12 @@
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     // Literals
+    Int(i64),
     Num(f64),
     Str(String),
+    /// A string with `#{expr}` in it, its pieces and the sources of the expressions between them,
+    /// with the line and the column they start at
+    Interp(Vec<String>, Vec<(String, usize, usize)>),
     Sym(vm::Symbol),
     Name(vm::Symbol),
     True,
@@ -15,6 +19,7 @@ pub enum TokenType {
     Then,
     Def,
     Let,
+    Const,
     In,
     Fn,
     Type,
@@ -24,6 +29,15 @@ pub enum TokenType {
     Try,
     Rescue,
     Is,
+    Test,
+    Bench,
+    Receive,
+    Yield,
+    Recur,
+    Do,
+    Quote,
+    Unquote,
+    Dbg,
 
     // mathematical operators
     Add,
@@ -31,6 +45,8 @@ pub enum TokenType {
     Mul,
     Div,
     Rem,
+    FloorDiv,
+    Mod,
     Eq,
     Ne,
     Greater,
@@ -39,13 +55,16 @@ pub enum TokenType {
     LessEq,
     Assign,
     Cons,
+    Concat,
 
     // bitwise
     BitOr,
     BitAnd,
     BitXor,
-    Shr, // right-shift
-    Shl, // left-shift
+    Shr,    // arithmetic right-shift
+    UShr,   // logical right-shift
+    Shl,    // left-shift
+    BitNot, // complement
 
     // logical operators
     And,
@@ -66,8 +85,12 @@ pub enum TokenType {
     Arrow,
     FatArrow,
     Dot,
+    DotDot,
     Pipe,
+    TryPipe,
+    Amp,
     Bar,
+    At,
 
     Eof,
 }
@@ -81,8 +104,16 @@ impl Default for TokenType {
 impl std::fmt::Display for TokenType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let res = match self {
+            Self::Int(n) => n.to_string(),
             Self::Num(n) => n.to_string(),
             Self::Str(s) => "\"".to_owned() + s + "\"",
+            Self::Interp(pieces, exprs) => {
+                let mut s = format!("\"{}", pieces[0]);
+                for ((expr, ..), piece) in exprs.iter().zip(&pieces[1..]) {
+                    s = s + "#{" + expr + "}" + piece;
+                }
+                s + "\""
+            }
             Self::Sym(s) => format!(":{}", s),
             Self::Name(v) => format!("{}", v),
             Self::True => "true".to_string(),
@@ -94,6 +125,7 @@ impl std::fmt::Display for TokenType {
             Self::Then => "then".into(),
             Self::Def => "def".into(),
             Self::Let => "let".into(),
+            Self::Const => "const".into(),
             Self::In => "in".into(),
             Self::Fn => "fn".into(),
             Self::Type => "type".into(),
@@ -103,12 +135,23 @@ impl std::fmt::Display for TokenType {
             Self::Try => "try".into(),
             Self::Rescue => "rescue".into(),
             Self::Is => "is".into(),
+            Self::Test => "test".into(),
+            Self::Bench => "bench".into(),
+            Self::Receive => "receive".into(),
+            Self::Yield => "yield".into(),
+            Self::Recur => "recur".into(),
+            Self::Do => "do".into(),
+            Self::Quote => "quote".into(),
+            Self::Unquote => "unquote".into(),
+            Self::Dbg => "dbg".into(),
 
             Self::Add => '+'.into(),
             Self::Sub => '-'.into(),
             Self::Mul => '*'.into(),
             Self::Div => '/'.into(),
             Self::Rem => '%'.into(),
+            Self::FloorDiv => "%/%".into(),
+            Self::Mod => "%%".into(),
             Self::Eq => "==".into(),
             Self::Ne => "!=".into(),
             Self::Greater => ">".into(),
@@ -118,12 +161,16 @@ impl std::fmt::Display for TokenType {
             Self::Assign => '='.into(),
             Self::Cons => "::".into(),
             Self::Dot => '.'.into(),
+            Self::Concat => "<>".into(),
+            Self::DotDot => "..".into(),
 
             Self::BitAnd => "&&&".into(),
             Self::BitOr => "|||".into(),
             Self::BitXor => "^^^".into(),
             Self::Shr => ">>>".into(),
             Self::Shl => "<<<".into(),
+            Self::UShr => ">>>>".into(),
+            Self::BitNot => "~~~".into(),
 
             Self::And => "&&".into(),
             Self::Or => "||".into(),
@@ -142,7 +189,10 @@ impl std::fmt::Display for TokenType {
             Self::Arrow => "->".into(),
             Self::FatArrow => "=>".into(),
             Self::Pipe => "|>".into(),
+            Self::TryPipe => "|>?".into(),
+            Self::Amp => '&'.into(),
             Self::Bar => '|'.into(),
+            Self::At => '@'.into(),
 
             Self::Eof => "<eof>".into(),
         };
@@ -151,6 +201,13 @@ impl std::fmt::Display for TokenType {
     }
 }
 
+/// Every reserved word of the language
+pub const KEYWORDS: &[&str] = &[
+    "if", "else", "then", "def", "let", "const", "in", "true", "false", "nil", "fn", "type",
+    "match", "with", "end", "try", "rescue", "is", "test", "bench", "receive", "yield", "do",
+    "quote", "unquote", "dbg", "recur",
+];
+
 pub fn fetch_keyword<T: AsRef<str>>(word: T) -> Option<TokenType> {
     match word.as_ref() {
         "if" => Some(TokenType::If),
@@ -158,6 +215,7 @@ pub fn fetch_keyword<T: AsRef<str>>(word: T) -> Option<TokenType> {
         "then" => Some(TokenType::Then),
         "def" => Some(TokenType::Def),
         "let" => Some(TokenType::Let),
+        "const" => Some(TokenType::Const),
         "in" => Some(TokenType::In),
         "true" => Some(TokenType::True),
         "false" => Some(TokenType::False),
@@ -170,6 +228,15 @@ pub fn fetch_keyword<T: AsRef<str>>(word: T) -> Option<TokenType> {
         "try" => Some(TokenType::Try),
         "rescue" => Some(TokenType::Rescue),
         "is" => Some(TokenType::Is),
+        "test" => Some(TokenType::Test),
+        "bench" => Some(TokenType::Bench),
+        "receive" => Some(TokenType::Receive),
+        "yield" => Some(TokenType::Yield),
+        "recur" => Some(TokenType::Recur),
+        "do" => Some(TokenType::Do),
+        "quote" => Some(TokenType::Quote),
+        "unquote" => Some(TokenType::Unquote),
+        "dbg" => Some(TokenType::Dbg),
         _ => None,
     }
 }
@@ -179,6 +246,12 @@ pub struct Token {
     pub line: usize,
     pub column: usize,
     pub token: TokenType,
+    /// The indentation of the line, if the token is the first one on it
+    pub indent: Option<usize>,
+    /// The index of the first character of the token in the source
+    pub start: usize,
+    /// The index of the character right after the token in the source
+    pub end: usize,
 }
 
 impl Default for Token {
@@ -187,6 +260,9 @@ impl Default for Token {
             line: 0,
             column: 0,
             token: TokenType::Eof,
+            indent: None,
+            start: 0,
+            end: 0,
         }
     }
 }
@@ -0,0 +1,116 @@
+//! Reads values back from the code their `Display` writes, without compiling or running it
+use vm::{gc::GcRef, List, Symbol, Tuple, Value, VirtualMachine, YexModule};
+
+use crate::parser::ast::{BinOp, Expr, ExprKind, UnOp};
+
+type ValueResult<T> = Result<T, String>;
+
+/// Evaluates an expression made only of literals, lists, tuples and the variants of the types
+/// defined in `vm`
+pub(crate) fn eval(vm: &VirtualMachine, expr: &Expr) -> ValueResult<Value> {
+    match &expr.kind {
+        ExprKind::Lit(lit) => Ok(lit.clone().into()),
+        ExprKind::List(items) => {
+            let items = items
+                .iter()
+                .map(|item| eval(vm, item))
+                .collect::<ValueResult<List>>()?;
+            Ok(Value::List(items))
+        }
+        ExprKind::Tuple(items) => {
+            let items = items
+                .iter()
+                .map(|item| eval(vm, item))
+                .collect::<ValueResult<Vec<_>>>()?;
+            Ok(Value::Tuple(Tuple::from(items)))
+        }
+        ExprKind::UnOp(UnOp::Neg, value) => match eval(vm, value)? {
+            Value::Int(n) => n
+                .checked_neg()
+                .map(Value::Int)
+                .ok_or(format!("Can't negate '{n}'")),
+            Value::Num(n) => Ok(Value::Num(-n)),
+            other => Err(format!("Can't negate '{other}'")),
+        },
+        // the numbers that have no literals, like `(1 / 0)`
+        ExprKind::Binary {
+            left,
+            op: BinOp::Div,
+            right,
+        } => match (eval(vm, left)?, eval(vm, right)?) {
+            (x, y) if x.as_num().is_some() && y.as_num().is_some() => {
+                Ok(Value::Num(x.as_num().unwrap() / y.as_num().unwrap()))
+            }
+            (x, y) => Err(format!("Can't divide '{x}' by '{y}'")),
+        },
+        ExprKind::Var(name) => Ok(Value::Module(ty(vm, *name)?)),
+        ExprKind::MethodRef { .. } => variant(vm, expr, vec![]),
+        ExprKind::App { callee, args, .. } => {
+            let args = args
+                .iter()
+                .map(|arg| eval(vm, arg))
+                .collect::<ValueResult<Vec<_>>>()?;
+            variant(vm, callee, args)
+        }
+        _ => Err("Only literals, lists, tuples and variants can be read".to_string()),
+    }
+}
+
+/// Finds the type called `name`
+fn ty(vm: &VirtualMachine, name: Symbol) -> ValueResult<GcRef<YexModule>> {
+    match vm.get_global(name) {
+        Some(Value::Module(ty)) => Ok(ty),
+        Some(other) => Err(format!("Expected '{name}' to be a type, found '{other}'")),
+        None => Err(format!("Undefined type '{name}'")),
+    }
+}
+
+/// Builds the value of the variant named by `path`, like `Opt.some`, holding `args`
+fn variant(vm: &VirtualMachine, path: &Expr, args: Vec<Value>) -> ValueResult<Value> {
+    let ExprKind::MethodRef { ty: name, method } = &path.kind else {
+        return Err("Only the variants of a type can be applied".to_string());
+    };
+    let ExprKind::Var(name) = &name.kind else {
+        return Err("Expected the name of a type".to_string());
+    };
+
+    let ty = ty(vm, *name)?;
+    let tag = Symbol::new(format!("{name}.{method}"));
+    match ty.variants.get(&tag) {
+        Some(fields) if fields.len() == args.len() => {
+            Ok(Value::Tagged(ty.clone(), tag, Tuple::from(args)))
+        }
+        Some(fields) => Err(format!(
+            "The variant '{tag}' has {} fields, found {}",
+            fields.len(),
+            args.len()
+        )),
+        None => Err(format!("The type '{name}' has no variant '{method}'")),
+    }
+}
+
+#[test]
+fn read_displayed_values_back() {
+    let mut vm = VirtualMachine::default();
+    let (bytecode, constants) =
+        crate::parse("type Opt = some x | none with\n def id x = x\nend").unwrap();
+    vm.set_consts(constants);
+    vm.run(&bytecode).unwrap();
+
+    vm::literal::set_parser(crate::parse_value);
+    let round_trip = |source: &str| {
+        let value = Value::parse(&vm, source).unwrap();
+        assert_eq!(value.to_string(), source);
+        value
+    };
+
+    round_trip("[1, -2.5, (0 / 0), (-1 / 0), :ok, :\"two words\", nil]");
+    round_trip("(\"a \\\"quote\\\"\\n\\ttab\", (true,), ())");
+    round_trip("[(Opt.some (-1)), (Opt.some (Opt.some (1, 2))), Opt.none, Opt]");
+    assert!(matches!(round_trip("\"\\u0001\""), Value::Str(s) if *s == "\u{1}"));
+
+    assert!(Value::parse(&vm, "Opt.some").is_err());
+    assert!(Value::parse(&vm, "(Opt.some 1 2)").is_err());
+    assert!(Value::parse(&vm, "[1] ++ [2]").is_err());
+    assert!(Value::parse(&vm, "1 2").is_err());
+}
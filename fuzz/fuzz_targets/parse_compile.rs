@@ -0,0 +1,18 @@
+//! Feeds any bytes to the parser and to the compiler, which must reject the bad code with an error
+//! instead of panicking, and checks that the bytecode they make is valid and can be packed
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data);
+    let _ = front::check(&*source);
+    let _ = front::ast_json(&*source, false);
+
+    let Ok((bytecode, _)) = front::parse_untrusted(data) else {
+        return;
+    };
+
+    let packed = vm::encoding::encode(&bytecode);
+    assert_eq!(vm::encoding::decode(&mut &packed[..]), Some(bytecode));
+});
@@ -0,0 +1,61 @@
+//! Hooks to stop and inspect running programs, used by debuggers
+use std::ops::Range;
+
+use crate::{Value, VirtualMachine};
+
+/// Where the virtual machine is, right before running an instruction
+#[derive(Debug, Clone)]
+pub struct Position {
+    /// Source's code line
+    pub line: usize,
+    /// Source's code column
+    pub column: usize,
+    /// How many calls deep the instruction is, starting at 1 for the top-level code
+    pub depth: usize,
+    /// Where the locals of the running function are, see [`VirtualMachine::locals_at`]
+    locals: Range<usize>,
+}
+
+/// Hook called before every instruction, used to implement breakpoints and stepping
+///
+/// The virtual machine waits for the hook to return, so a debugger can stop the program by not
+/// returning until the user asks it to continue.
+pub trait Debugger {
+    /// Called before running the instruction at `position`
+    fn before_op(&mut self, vm: &VirtualMachine, position: &Position);
+}
+
+impl VirtualMachine {
+    /// Sets (or removes, with `None`) the hook called before every instruction
+    pub fn set_debugger(&mut self, debugger: Option<Box<dyn Debugger>>) {
+        self.debugger = debugger;
+    }
+
+    /// The local variables of the function that was running at `position`, the position must be
+    /// of a function that is still running
+    #[must_use]
+    pub fn locals_at(&self, position: &Position) -> &[Value] {
+        self.locals.get(position.locals.clone()).unwrap_or_default()
+    }
+
+    /// The values on the stack, from the bottom to the top
+    pub fn stack_values(&self) -> impl Iterator<Item = &Value> {
+        self.stack.iter()
+    }
+
+    pub(crate) fn debug_hook(&mut self, line: usize, column: usize, frame_locals: usize) {
+        let Some(mut debugger) = self.debugger.take() else {
+            return;
+        };
+
+        let position = Position {
+            line,
+            column,
+            depth: self.depth,
+            locals: self.used_locals - frame_locals..self.used_locals,
+        };
+        debugger.before_op(self, &position);
+
+        self.debugger = Some(debugger);
+    }
+}
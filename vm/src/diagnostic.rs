@@ -0,0 +1,232 @@
+//! Errors and warnings about some code, shared by the parser, the compiler passes and the VM so
+//! the tools built on them can render all of them the same way
+use std::fmt;
+
+use crate::error::{InterpretError, TraceEntry};
+
+/// The stable code of each kind of error, which never changes once given, so tools can match on
+/// it. New kinds are added at the end
+pub const ERROR_CODES: &[(&str, &str)] = &[
+    ("E0001", "SyntaxError"),
+    ("E0002", "NameError"),
+    ("E0003", "TypeError"),
+    ("E0004", "ValueError"),
+    ("E0005", "ArityError"),
+    ("E0006", "CallError"),
+    ("E0007", "FieldError"),
+    ("E0008", "FrozenError"),
+    ("E0009", "RecursionError"),
+    ("E0010", "TailCallError"),
+    ("E0011", "LocalError"),
+    ("E0012", "ScopeError"),
+    ("E0013", "CompileError"),
+    ("E0014", "DecodeError"),
+    ("E0015", "IoError"),
+    ("E0016", "AssertionError"),
+    ("E0017", "NativeError"),
+    ("E0018", "ExtensionError"),
+    ("E0019", "GeneratorError"),
+    ("E0020", "YieldError"),
+    ("E0021", "FiberError"),
+    ("E0022", "ChannelError"),
+    ("E0023", "PromiseError"),
+    ("E0024", "ProcessError"),
+    ("E0025", "ThreadError"),
+    ("E0026", "RethrowError"),
+    ("E0027", "ReplayError"),
+    ("E0028", "RecurError"),
+    ("E0029", "OverflowError"),
+    ("E0030", "ZeroDivisionError"),
+];
+
+/// The kind of the errors found by the parser
+pub const SYNTAX_ERROR: &str = "SyntaxError";
+
+/// The stable code of an error kind, if it's one of the built-in kinds
+#[must_use]
+pub fn error_code(kind: &str) -> Option<&'static str> {
+    ERROR_CODES
+        .iter()
+        .find(|(_, name)| *name == kind)
+        .map(|(code, _)| *code)
+}
+
+/// How bad a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The code can't be compiled or failed while running
+    Error,
+    /// The code works, but probably not as intended
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Where some code starts, both counted from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// The line of the code
+    pub line: usize,
+    /// The column of the code
+    pub column: usize,
+}
+
+/// An error or warning about some code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// How bad it is
+    pub severity: Severity,
+    /// The kind of the diagnostic, like the error raised at runtime, see [`ERROR_CODES`] for
+    /// their stable codes
+    pub code: Option<String>,
+    /// Where it was found, if it's known
+    pub span: Option<Span>,
+    /// What went wrong
+    pub message: String,
+    /// Extra hints, shown after the code
+    pub notes: Vec<String>,
+    /// The functions a runtime error was raised out of, the innermost first
+    pub trace: Vec<TraceEntry>,
+}
+
+impl Diagnostic {
+    /// Creates an error without position
+    #[must_use]
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            span: None,
+            message: message.into(),
+            notes: vec![],
+            trace: vec![],
+        }
+    }
+
+    /// Creates a warning without position
+    #[must_use]
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    /// Points the diagnostic to `line:column`
+    #[must_use]
+    pub fn at(self, line: usize, column: usize) -> Self {
+        Self {
+            span: Some(Span { line, column }),
+            ..self
+        }
+    }
+
+    /// Sets the kind of the diagnostic
+    #[must_use]
+    pub fn with_code(self, code: impl Into<String>) -> Self {
+        Self {
+            code: Some(code.into()),
+            ..self
+        }
+    }
+
+    /// The stable code of the kind of the diagnostic, see [`ERROR_CODES`]
+    #[must_use]
+    pub fn error_code(&self) -> Option<&'static str> {
+        self.code.as_deref().and_then(error_code)
+    }
+
+    /// Checks if the diagnostic is a syntax error, found before running any code
+    #[must_use]
+    pub fn is_syntax_error(&self) -> bool {
+        self.code.as_deref() == Some(SYNTAX_ERROR)
+    }
+
+    /// Adds a hint to the diagnostic
+    #[must_use]
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+impl From<InterpretError> for Diagnostic {
+    fn from(err: InterpretError) -> Self {
+        Self {
+            trace: err.trace,
+            ..Self::error(err.msg)
+                .at(err.line, err.column)
+                .with_code(err.err.as_str())
+        }
+    }
+}
+
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Self::error(message)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(Span { line, column }) = self.span.filter(|span| span.line > 0) {
+            write!(f, "[{line}:{column}] ")?;
+        }
+
+        if self.severity == Severity::Warning {
+            write!(f, "warning: ")?;
+        }
+
+        if let Some(code) = self.error_code() {
+            write!(f, "{code} ")?;
+        }
+
+        match &self.code {
+            Some(code) => write!(f, ":{code}\n  {}", self.message)?,
+            None => write!(f, "{}", self.message)?,
+        }
+
+        for note in &self.notes {
+            write!(f, "\n  note: {note}")?;
+        }
+        for entry in &self.trace {
+            write!(f, "\n    {entry}")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn diagnostics_from_runtime_errors() {
+    let err: InterpretError = crate::raise_err!(TypeError, "Expected a number");
+    let diagnostic = Diagnostic::from(InterpretError {
+        line: 2,
+        column: 5,
+        ..err
+    });
+
+    assert_eq!(diagnostic.severity, Severity::Error);
+    assert_eq!(diagnostic.span, Some(Span { line: 2, column: 5 }));
+    assert_eq!(
+        diagnostic.to_string(),
+        "[2:5] E0003 :TypeError\n  Expected a number"
+    );
+    assert_eq!(error_code("SyntaxError"), Some("E0001"));
+    assert_eq!(
+        Diagnostic::error("Oops").with_code("MyError").error_code(),
+        None
+    );
+
+    let warning = Diagnostic::warning("Unused value").with_note("bind it with let");
+    assert_eq!(
+        warning.to_string(),
+        "warning: Unused value\n  note: bind it with let"
+    );
+}
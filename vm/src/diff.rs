@@ -0,0 +1,170 @@
+//! Where two values differ, to tell what's wrong when a value isn't the expected one, like in the
+//! errors of `assert_eq`
+use std::fmt;
+
+use crate::{Value, YexModule};
+
+/// How many differences are shown, the others are only counted
+const SHOWN: usize = 8;
+
+/// A part of two values that isn't equal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    /// Where the part is, like `[2].name`, empty for the values themselves
+    pub path: String,
+    /// The part of the expected value, `None` if it doesn't have it, like an item past the end of
+    /// a shorter list
+    pub expected: Option<Value>,
+    /// The part of the actual value, `None` if it doesn't have it
+    pub actual: Option<Value>,
+}
+
+/// Every part where two values differ, from the first to the last, empty if they're equal
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Diff(Vec<Difference>);
+
+impl Diff {
+    /// Checks if the values are equal
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The parts that differ
+    #[must_use]
+    pub fn differences(&self) -> &[Difference] {
+        &self.0
+    }
+
+    /// Checks if the values differ as a whole, like two numbers or values of different types, and
+    /// not only in some of their items
+    #[must_use]
+    pub fn is_whole(&self) -> bool {
+        self.0.iter().all(|it| it.path.is_empty())
+    }
+
+    fn compare(&mut self, path: &str, expected: Option<&Value>, actual: Option<&Value>) {
+        let (expected, actual) = match (expected, actual) {
+            (Some(expected), Some(actual)) if expected == actual => return,
+            (Some(expected), Some(actual)) => (expected, actual),
+            (expected, actual) => {
+                self.0.push(Difference {
+                    path: path.to_string(),
+                    expected: expected.cloned(),
+                    actual: actual.cloned(),
+                });
+                return;
+            }
+        };
+
+        match (expected, actual) {
+            (Value::List(xs), Value::List(ys)) => {
+                for idx in 0..xs.len().max(ys.len()) {
+                    let item = |list: &crate::List| (idx < list.len()).then(|| list.index(idx));
+                    let path = format!("{path}[{idx}]");
+                    self.compare(&path, item(xs).as_ref(), item(ys).as_ref());
+                }
+            }
+            (Value::Tuple(xs), Value::Tuple(ys)) if xs.len() == ys.len() => {
+                for (idx, (x, y)) in xs.0.iter().zip(ys.0.iter()).enumerate() {
+                    self.compare(&format!("{path}.{idx}"), Some(x), Some(y));
+                }
+            }
+            (Value::Tagged(ty, tag, xs), Value::Tagged(other, other_tag, ys))
+                if YexModule::same(ty, other) && tag == other_tag && xs.len() == ys.len() =>
+            {
+                let names = ty.variants.get(tag);
+                for (idx, (x, y)) in xs.0.iter().zip(ys.0.iter()).enumerate() {
+                    let path = match names.and_then(|it| it.get(idx)) {
+                        Some(name) => format!("{path}.{name}"),
+                        None => format!("{path}.{idx}"),
+                    };
+                    self.compare(&path, Some(x), Some(y));
+                }
+            }
+            _ => self.0.push(Difference {
+                path: path.to_string(),
+                expected: Some(expected.clone()),
+                actual: Some(actual.clone()),
+            }),
+        }
+    }
+}
+
+impl Value {
+    /// Compares the value with the `actual` one, going into the lists, the tuples and the
+    /// instances of the types to find the parts that differ
+    #[must_use]
+    pub fn diff(&self, actual: &Self) -> Diff {
+        let mut diff = Diff::default();
+        diff.compare("", Some(self), Some(actual));
+        diff
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "at {}: ", self.path)?;
+        }
+
+        match &self.expected {
+            Some(expected) => write!(f, "expected {expected}, ")?,
+            None => write!(f, "expected nothing, ")?,
+        }
+        match &self.actual {
+            Some(actual) => write!(f, "found {actual}"),
+            None => write!(f, "found nothing"),
+        }
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, difference) in self.0.iter().take(SHOWN).enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{difference}")?;
+        }
+
+        if self.0.len() > SHOWN {
+            write!(f, "\nand {} more differences", self.0.len() - SHOWN)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn find_where_values_differ() {
+    let list = |items: Vec<Value>| Value::List(items.into_iter().collect());
+    let tuple = |items: Vec<Value>| Value::Tuple(crate::Tuple::from(items));
+
+    let expected = list(vec![
+        Value::Int(1),
+        tuple(vec![Value::Int(2), Value::Bool(true)]),
+    ]);
+    let actual = list(vec![
+        Value::Int(1),
+        tuple(vec![Value::Int(3), Value::Bool(true)]),
+        Value::Nil,
+    ]);
+
+    assert!(expected.diff(&expected).is_empty());
+
+    let diff = expected.diff(&actual);
+    assert!(!diff.is_whole());
+    assert_eq!(
+        diff.to_string(),
+        "at [1].0: expected 2, found 3\nat [2]: expected nothing, found nil"
+    );
+
+    let diff = Value::Int(1).diff(&list(vec![]));
+    assert!(diff.is_whole());
+    assert_eq!(diff.to_string(), "expected 1, found []");
+
+    let many = list((0..10).map(Value::Int).collect());
+    let diff = list(vec![]).diff(&many);
+    assert_eq!(diff.differences().len(), 10);
+    assert!(diff.to_string().ends_with("\nand 2 more differences"));
+}
@@ -0,0 +1,588 @@
+//! The handlers of the interpreter loop
+//!
+//! Every opcode has its own handler, the loop either `match`es the opcode to call it (the default)
+//! or, with the `dispatch-table` feature, jumps straight to it by indexing a table with
+//! [`OpCode::kind`]. The match is usually faster, since the handlers can be inlined into it, but
+//! the table can do better on CPUs with good indirect branch prediction, compare both with
+//! `yex bench benches`. The opcodes that change the instruction pointer are handled by the loop.
+use crate::{
+    error::InterpretResult,
+    gc::GcRef,
+    literal::{num, TryGet},
+    raise, suggest, Fn, List, OpCode, Operand, Operator, Symbol, Tuple, Value, VirtualMachine,
+    YexModule, NIL,
+};
+
+/// Runs an opcode, receiving the number of locals of the running frame
+#[cfg(any(test, feature = "dispatch-table"))]
+type Handler = fn(&mut VirtualMachine, OpCode, &mut usize) -> InterpretResult<()>;
+
+/// Declares the handlers, which must be in the same order as the [`OpCode`] variants
+macro_rules! handlers {
+    ($($variant:ident $(($($arg:ident),*))? => |$vm:ident, $locals:ident| $body:expr;)*) => {
+        /// The name of the opcode of each handler, used to check that they are in order
+        #[cfg(test)]
+        const NAMES: &[&str] = &[$(stringify!($variant)),*];
+
+        /// One handler per opcode, named after it
+        #[allow(non_snake_case, unused_variables)]
+        mod handlers {
+            use super::*;
+
+            $(
+                #[inline]
+                pub(super) fn $variant(
+                    $vm: &mut VirtualMachine,
+                    op: OpCode,
+                    $locals: &mut usize,
+                ) -> InterpretResult<()> {
+                    let OpCode::$variant $(($($arg),*))? = op else {
+                        unreachable!()
+                    };
+                    $body
+                }
+            )*
+        }
+
+        /// The handlers of each opcode, indexed by [`OpCode::kind`]
+        #[cfg(any(test, feature = "dispatch-table"))]
+        static HANDLERS: &[Handler] = &[$(handlers::$variant),*];
+
+        /// Runs an opcode with its handler
+        #[cfg(feature = "dispatch-table")]
+        #[inline]
+        pub(crate) fn run(vm: &mut VirtualMachine, op: OpCode, locals: &mut usize) -> InterpretResult<()> {
+            HANDLERS[op.kind()](vm, op, locals)
+        }
+
+        /// Runs an opcode with its handler
+        #[cfg(not(feature = "dispatch-table"))]
+        #[inline]
+        pub(crate) fn run(vm: &mut VirtualMachine, op: OpCode, locals: &mut usize) -> InterpretResult<()> {
+            match op {
+                $(OpCode::$variant { .. } => handlers::$variant(vm, op, locals),)*
+            }
+        }
+    };
+}
+
+// these opcodes are handled by the run function, since they can manipulate the ip
+fn handled_by_loop() -> InterpretResult<()> {
+    unreachable!()
+}
+
+handlers! {
+    Nop => |vm, locals| Ok(());
+
+    // Stack manipulation
+    Push(index) => |vm, locals| {
+        let value = vm.constant(index).clone();
+        vm.push(value);
+        Ok(())
+    };
+    Pop => |vm, locals| {
+        vm.pop();
+        Ok(())
+    };
+    Dup => |vm, locals| {
+        let value = vm.pop();
+        vm.push(value.clone());
+        vm.push(value);
+        Ok(())
+    };
+    Swap(a, b) => |vm, locals| {
+        vm.stack.swap(a, b);
+        Ok(())
+    };
+
+    // locals manipulation
+    Load(offset) => |vm, locals| {
+        if offset >= *locals {
+            raise!(
+                LocalError,
+                "Tried to load the local {}, but the frame has {} locals",
+                offset,
+                *locals
+            )?;
+        }
+
+        let value = vm.locals[offset + vm.used_locals - *locals].clone();
+        vm.push(value);
+        Ok(())
+    };
+    Save(offset) => |vm, locals| {
+        let value = vm.pop();
+        let base = vm.used_locals - *locals;
+        vm.set_local(base + offset, value);
+
+        // the frame covers every slot it has saved to, the compiler reuses the slots of the
+        // variables that went out of scope, so saving again doesn't grow it
+        if offset >= *locals {
+            *locals = offset + 1;
+            vm.used_locals = base + *locals;
+        }
+        Ok(())
+    };
+
+    // globals manipulation
+    Loag(name) => |vm, locals| {
+        let Some(value) = vm.get_global(name) else {
+            let names = vm.globals.keys().collect::<Vec<_>>();
+            let close = suggest::closest(name.as_str(), names.iter().map(Symbol::as_str));
+            return raise!(
+                NameError,
+                "Undefined variable '{}'{}",
+                name,
+                suggest::DidYouMean(close)
+            );
+        };
+        vm.push(value);
+        Ok(())
+    };
+    Savg(name) => |vm, locals| {
+        let value = vm.pop();
+        if vm.globals.contains_key(&name) {
+            raise!(NameError, "Tried to reassign global variable '{}'", name)?;
+        }
+        vm.set_global(name, value);
+        Ok(())
+    };
+
+    Drop(index) => |vm, locals| {
+        // only the last slot can be given back, without moving the others
+        if index + 1 == *locals {
+            *locals -= 1;
+            vm.used_locals -= 1;
+        }
+        Ok(())
+    };
+
+    Jmf(_to) => |vm, locals| handled_by_loop();
+    Jmp(_to) => |vm, locals| handled_by_loop();
+
+    // function calls
+    Call(_arity) => |vm, locals| handled_by_loop();
+    TCall(_arity) => |vm, locals| handled_by_loop();
+
+    // list manipulation
+    Prep => |vm, locals| {
+        let list: List = vm.pop().get()?;
+        let value = vm.pop();
+
+        vm.push(list.prepend(value).into());
+        Ok(())
+    };
+
+    Rev => |vm, locals| {
+        let (a, b) = vm.pop_two();
+        vm.push(b);
+        vm.push(a);
+        Ok(())
+    };
+    RevN(n) => |vm, locals| {
+        let mut vec = vec![];
+        for _ in 0..n {
+            vec.push(vm.pop());
+        }
+        for elem in vec {
+            vm.push(elem);
+        }
+        Ok(())
+    };
+
+    // mathematical operators
+    Add => |vm, locals| vm.arith(Operator::Add, |a, b| a + b);
+    Rem => |vm, locals| vm.arith(Operator::Rem, |a, b| a % b);
+    Sub => |vm, locals| vm.arith(Operator::Sub, |a, b| a - b);
+    Mul => |vm, locals| vm.arith(Operator::Mul, |a, b| a * b);
+    Div => |vm, locals| vm.arith(Operator::Div, |a, b| a / b);
+    FloorDiv => |vm, locals| vm.binop(Value::floor_div);
+    Mod => |vm, locals| vm.binop(Value::floor_mod);
+
+    // unary operators
+    Neg => |vm, locals| {
+        let value = vm.pop();
+        vm.try_push(-value)
+    };
+    Len => |vm, locals| {
+        let value = vm.pop();
+        vm.push(value.len().into());
+        Ok(())
+    };
+    Not => |vm, locals| {
+        let value = vm.pop();
+        vm.push(!value);
+        Ok(())
+    };
+
+    // bitwise operators
+    Xor => |vm, locals| vm.binop(|a, b| a ^ b);
+    Shr => |vm, locals| vm.binop(|a, b| a >> b);
+    UShr => |vm, locals| vm.binop(Value::logical_shr);
+    Shl => |vm, locals| vm.binop(|a, b| a << b);
+    BitAnd => |vm, locals| vm.binop(|a, b| a & b);
+    BitOr => |vm, locals| vm.binop(|a, b| a | b);
+    BitNot => |vm, locals| {
+        let value = vm.pop();
+        vm.try_push(value.bit_not())
+    };
+
+    // comparison operators
+    Eq => |vm, locals| vm.arith(Operator::Eq, |a, b| Ok(a == b));
+    Less => |vm, locals| {
+        if vm.num_op(Operator::Less) {
+            return Ok(());
+        }
+        let (a, b) = vm.pop_two();
+        vm.push(a.ord_cmp(&b)?.is_lt().into());
+        Ok(())
+    };
+    LessEq => |vm, locals| {
+        if vm.num_op(Operator::LessEq) {
+            return Ok(());
+        }
+        let (a, b) = vm.pop_two();
+        vm.push(a.ord_cmp(&b)?.is_le().into());
+        Ok(())
+    };
+
+    New => |vm, locals| todo!();
+
+    Type => |vm, locals| {
+        let value = vm.pop();
+        vm.push(Value::Module(value.type_of()));
+        Ok(())
+    };
+    IsA => |vm, locals| {
+        let ty: GcRef<YexModule> = vm.pop().get()?;
+        let value = vm.pop();
+        vm.push(value.is_a(&ty).into());
+        Ok(())
+    };
+    Ref(method) => |vm, locals| {
+        let value = vm.pop();
+        let field = if let Value::Tagged(ty, ..) = &value {
+            ty.instance_field(&value, method)?
+        } else {
+            let ty: GcRef<YexModule> = value.get()?;
+            ty.field(method)?
+        };
+        vm.push(field);
+        Ok(())
+    };
+
+    Try(_to) => |vm, locals| handled_by_loop();
+    EndTry => |vm, locals| handled_by_loop();
+
+    Tup(len) => |vm, locals| {
+        let mut tup = vec![];
+        for _ in 0..len {
+            tup.push(vm.pop());
+        }
+        vm.push(tup.into());
+        Ok(())
+    };
+    TupGet(index) => |vm, locals| {
+        let tup: Tuple = vm.pop().get()?;
+        let elem = tup.0.get(index).unwrap(); // this SHOULD be unreachable
+        vm.push(elem.clone());
+        Ok(())
+    };
+
+    Tag(tag) => |vm, locals| {
+        let module: GcRef<YexModule> = vm.pop().get()?;
+        let tup: Tuple = vm.pop().get()?;
+
+        vm.push(Value::Tagged(module, tag, tup));
+        Ok(())
+    };
+    TagOf => |vm, locals| {
+        match vm.pop() {
+            Value::Tagged(_, tag, _) => vm.push(tag.into()),
+            _ => vm.push(NIL),
+        }
+        Ok(())
+    };
+    TagTup => |vm, locals| {
+        let (_, _, tup) = vm.pop().get()?;
+        vm.push(tup.into());
+        Ok(())
+    };
+
+    Yield => |vm, locals| handled_by_loop();
+
+    Bin(operator, left, right) => |vm, locals| {
+        let nums = (peek_operand(vm, left, *locals), peek_operand(vm, right, *locals));
+        if let (Some(x), Some(y)) = nums {
+            if let Some(value) = on_nums(operator, x, y) {
+                match left {
+                    Operand::Stack => *vm.stack.last_mut().unwrap() = value,
+                    _ => vm.push(value),
+                }
+                return Ok(());
+            }
+        }
+
+        // the right operand never comes from the stack, so the left one is on its top
+        let right = operand(vm, right, *locals);
+        let left = operand(vm, left, *locals);
+
+        let value = match operator {
+            Operator::Add => (left + right)?,
+            Operator::Sub => (left - right)?,
+            Operator::Mul => (left * right)?,
+            Operator::Div => (left / right)?,
+            Operator::Rem => (left % right)?,
+            Operator::Eq => Value::Bool(left == right),
+            Operator::Less => left.ord_cmp(&right)?.is_lt().into(),
+            Operator::LessEq => left.ord_cmp(&right)?.is_le().into(),
+        };
+        vm.push(value);
+        Ok(())
+    };
+
+    Ext(id, arity) => |vm, locals| vm.run_extension(id, arity);
+
+    SetField(field) => |vm, locals| {
+        let value = vm.pop();
+        let (ty, tag, tup): (GcRef<YexModule>, Symbol, Tuple) = vm.pop().get()?;
+        let tup = ty.set_field(tag, &tup, field, value)?;
+        vm.push(Value::Tagged(ty, tag, tup));
+        Ok(())
+    };
+
+    Rethrow => |vm, locals| handled_by_loop();
+    EnterScope => |vm, locals| handled_by_loop();
+    ExitScope => |vm, locals| handled_by_loop();
+
+    HasField(field) => |vm, locals| {
+        let has = match vm.pop() {
+            Value::Tagged(ty, tag, _) => ty.has_field(tag, field),
+            _ => false,
+        };
+        vm.push(has.into());
+        Ok(())
+    };
+
+    Recur => |vm, locals| handled_by_loop();
+
+    Closure(len) => |vm, locals| {
+        let fun: GcRef<Fn> = vm.pop().get()?;
+        let mut captures = vec![NIL; len].into_boxed_slice();
+        for capture in captures.iter_mut().rev() {
+            *capture = vm.pop();
+        }
+        vm.push(Value::Fn(GcRef::new(Fn { captures, ..(*fun).clone() })));
+        Ok(())
+    };
+    Loac(_index) => |vm, locals| handled_by_loop();
+}
+
+/// Reads an operand of a register-style instruction
+#[inline]
+fn operand(vm: &mut VirtualMachine, operand: Operand, locals: usize) -> Value {
+    match operand {
+        Operand::Stack => vm.pop(),
+        Operand::Local(index) => vm.locals[index as usize + vm.used_locals - locals].clone(),
+        Operand::Const(index) => vm.constant(index as usize).clone(),
+    }
+}
+
+/// Reads an operand of a register-style instruction without taking it
+#[inline]
+fn peek_operand(vm: &VirtualMachine, operand: Operand, locals: usize) -> Option<&Value> {
+    Some(match operand {
+        Operand::Stack => vm.stack.last()?,
+        Operand::Local(index) => &vm.locals[index as usize + vm.used_locals - locals],
+        Operand::Const(index) => vm.constant(index as usize),
+    })
+}
+
+/// Applies an operator to two floats or two integers, `None` if they aren't, or if the operator
+/// raises with them
+#[inline]
+pub(crate) fn on_nums(operator: Operator, x: &Value, y: &Value) -> Option<Value> {
+    match (x, y) {
+        (Value::Num(x), Value::Num(y)) => on_floats(operator, *x, *y),
+        (Value::Int(x), Value::Int(y)) => on_ints(operator, *x, *y),
+        _ => None,
+    }
+}
+
+/// Applies an operator to two integers, `None` for a remainder by zero or an overflow that raises
+#[inline]
+fn on_ints(operator: Operator, x: i64, y: i64) -> Option<Value> {
+    Some(match operator {
+        Operator::Add => Value::Int(num::int_op(
+            x,
+            y,
+            i64::overflowing_add,
+            i64::saturating_add,
+        )?),
+        Operator::Sub => Value::Int(num::int_op(
+            x,
+            y,
+            i64::overflowing_sub,
+            i64::saturating_sub,
+        )?),
+        Operator::Mul => Value::Int(num::int_op(
+            x,
+            y,
+            i64::overflowing_mul,
+            i64::saturating_mul,
+        )?),
+        Operator::Div => Value::Num(x as f64 / y as f64),
+        Operator::Rem if y == 0 => return None,
+        Operator::Rem => Value::Int(x.wrapping_rem(y)),
+        Operator::Eq => Value::Bool(x == y),
+        Operator::Less => Value::Bool(x < y),
+        Operator::LessEq => Value::Bool(x <= y),
+    })
+}
+
+/// Applies an operator to two floats, `None` if they can't be compared
+#[inline]
+#[allow(clippy::float_cmp)]
+fn on_floats(operator: Operator, x: f64, y: f64) -> Option<Value> {
+    Some(match operator {
+        Operator::Add => Value::Num(x + y),
+        Operator::Sub => Value::Num(x - y),
+        Operator::Mul => Value::Num(x * y),
+        Operator::Div => Value::Num(x / y),
+        Operator::Rem => Value::Num(x % y),
+        Operator::Eq => Value::Bool(x == y),
+        Operator::Less => Value::Bool(x.partial_cmp(&y)?.is_lt()),
+        Operator::LessEq => Value::Bool(x.partial_cmp(&y)?.is_le()),
+    })
+}
+
+#[test]
+fn handlers_are_in_order() {
+    use crate::Symbol;
+
+    let sym = Symbol::new("x");
+    let opcodes = [
+        OpCode::Nop,
+        OpCode::Push(0),
+        OpCode::Pop,
+        OpCode::Dup,
+        OpCode::Swap(0, 1),
+        OpCode::Load(0),
+        OpCode::Save(0),
+        OpCode::Loag(sym),
+        OpCode::Savg(sym),
+        OpCode::Drop(0),
+        OpCode::Jmf(0),
+        OpCode::Jmp(0),
+        OpCode::Call(0),
+        OpCode::TCall(0),
+        OpCode::Prep,
+        OpCode::Rev,
+        OpCode::RevN(0),
+        OpCode::Add,
+        OpCode::Rem,
+        OpCode::Sub,
+        OpCode::Mul,
+        OpCode::Div,
+        OpCode::FloorDiv,
+        OpCode::Mod,
+        OpCode::Neg,
+        OpCode::Len,
+        OpCode::Not,
+        OpCode::Xor,
+        OpCode::Shr,
+        OpCode::UShr,
+        OpCode::Shl,
+        OpCode::BitAnd,
+        OpCode::BitOr,
+        OpCode::BitNot,
+        OpCode::Eq,
+        OpCode::Less,
+        OpCode::LessEq,
+        OpCode::New,
+        OpCode::Type,
+        OpCode::IsA,
+        OpCode::Ref(sym),
+        OpCode::Try(0),
+        OpCode::EndTry,
+        OpCode::Tup(0),
+        OpCode::TupGet(0),
+        OpCode::Tag(sym),
+        OpCode::TagOf,
+        OpCode::TagTup,
+        OpCode::Yield,
+        OpCode::Bin(Operator::Add, Operand::Stack, Operand::Const(0)),
+        OpCode::Ext(0, 0),
+        OpCode::SetField(sym),
+        OpCode::Rethrow,
+        OpCode::EnterScope,
+        OpCode::ExitScope,
+        OpCode::HasField(sym),
+        OpCode::Recur,
+        OpCode::Closure(0),
+        OpCode::Loac(0),
+    ];
+
+    assert_eq!(opcodes.len(), HANDLERS.len());
+    for op in opcodes {
+        let name = format!("{op:?}");
+        let name = name.split('(').next().unwrap();
+        assert_eq!(NAMES[op.kind()], name);
+    }
+}
+
+#[test]
+fn load_only_saved_locals() {
+    use crate::OpCodeMetadata;
+
+    let run = |ops: &[OpCode]| {
+        let mut vm = VirtualMachine::default();
+        vm.set_consts(vec![Value::Num(1.0)]);
+        let bytecode = ops.iter().map(|op| OpCodeMetadata::new(1, 1, *op));
+        vm.run(&bytecode.collect())
+    };
+
+    // saving to the same slot again doesn't make the frame bigger
+    let ops = [
+        OpCode::Push(0),
+        OpCode::Save(0),
+        OpCode::Push(0),
+        OpCode::Save(0),
+    ];
+    assert!(run(&[&ops[..], &[OpCode::Load(0), OpCode::Pop]].concat()).is_ok());
+
+    let err = run(&[&ops[..], &[OpCode::Load(1)]].concat()).unwrap_err();
+    assert_eq!(err.err.as_str(), "LocalError");
+}
+
+#[test]
+fn numbers_are_computed_in_place() {
+    use crate::OpCodeMetadata;
+
+    let run = |ops: &[OpCode]| {
+        let mut vm = VirtualMachine::default();
+        vm.set_consts(vec![Value::Num(6.0), Value::Num(f64::NAN), Value::Num(4.0)]);
+        let bytecode = ops.iter().map(|op| OpCodeMetadata::new(1, 1, *op));
+        vm.run(&bytecode.collect())?;
+        Ok::<_, crate::error::InterpretError>(vm.pop_last().clone())
+    };
+
+    let less = [
+        OpCode::Push(0),
+        OpCode::Push(2),
+        OpCode::Sub,
+        OpCode::Push(2),
+        OpCode::Less,
+    ];
+    assert_eq!(run(&less).unwrap(), Value::Bool(true));
+
+    let bin = [
+        OpCode::Push(0),
+        OpCode::Bin(Operator::Rem, Operand::Stack, Operand::Const(2)),
+    ];
+    assert_eq!(run(&bin).unwrap(), Value::Num(2.0));
+
+    // NaN can't be ordered, the same as without the fast path
+    let nan = [OpCode::Push(1), OpCode::Push(0), OpCode::Less];
+    assert_eq!(run(&nan).unwrap_err().err.as_str(), "TypeError");
+}
@@ -0,0 +1,925 @@
+//! The packed encoding of the bytecode and of the values
+//!
+//! Every instruction is a byte with its [`OpCode::kind`], followed by its operands and by the line
+//! and the column it came from, all of them as LEB128 varints. Symbols are a varint length
+//! followed by their bytes. Most instructions take 3 to 5 bytes, instead of the size of
+//! [`OpCode`] plus the ones of the position.
+//!
+//! Values are a byte with their kind followed by their contents, the ones holding other values
+//! have their length followed by them. Only plain data, the functions compiled from yex and the
+//! types defined in yex, with their methods, can be encoded, native functions and foreign data
+//! depend on the virtual machine that made them. The bytecode of a function points to the
+//! constants of the program it's from, so they are only useful packed together, with
+//! [`encode_program`].
+//!
+//! Packed programs start with [`MAGIC`], and they and the packed globals with the
+//! [`BYTECODE_VERSION`] they were packed with. Unpacking them with another version raises a
+//! `DecodeError` instead of running opcodes that mean something else.
+use std::collections::HashMap;
+
+use crate::{
+    error::InterpretResult, gc::GcRef, raise, Bytecode, EnvTable, Fn, FnKind, List, OpCode,
+    OpCodeMetadata, Operand, Operator, Symbol, Tuple, Value, VirtualMachine, YexModule,
+    BYTECODE_VERSION,
+};
+
+/// The bytes packed programs start with, the `.yexc` files
+pub const MAGIC: [u8; 4] = *b"yexc";
+
+/// Writes an unsigned LEB128 varint
+pub fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Reads an unsigned LEB128 varint, advancing `bytes` past it
+pub fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+
+        n |= u64::from(byte & 0x7f).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(n);
+        }
+    }
+    None
+}
+
+fn write_usize(out: &mut Vec<u8>, n: usize) {
+    write_varint(out, n as u64);
+}
+
+fn read_usize(bytes: &mut &[u8]) -> Option<usize> {
+    usize::try_from(read_varint(bytes)?).ok()
+}
+
+/// Writes a string as its length followed by its bytes
+pub fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_usize(out, s.len());
+    out.extend(s.as_bytes());
+}
+
+/// Reads a string written by [`write_str`]
+pub fn read_str(bytes: &mut &[u8]) -> Option<String> {
+    let len = read_usize(bytes)?;
+    if bytes.len() < len {
+        return None;
+    }
+
+    let (s, rest) = bytes.split_at(len);
+    *bytes = rest;
+    String::from_utf8(s.to_vec()).ok()
+}
+
+fn write_operand(out: &mut Vec<u8>, operand: Operand) {
+    match operand {
+        Operand::Stack => out.push(0),
+        Operand::Local(index) => {
+            out.push(1);
+            write_varint(out, index.into());
+        }
+        Operand::Const(index) => {
+            out.push(2);
+            write_varint(out, index.into());
+        }
+    }
+}
+
+fn read_operand(bytes: &mut &[u8]) -> Option<Operand> {
+    let (&kind, rest) = bytes.split_first()?;
+    *bytes = rest;
+
+    let mut index = || u32::try_from(read_varint(bytes)?).ok();
+    Some(match kind {
+        0 => Operand::Stack,
+        1 => Operand::Local(index()?),
+        2 => Operand::Const(index()?),
+        _ => return None,
+    })
+}
+
+const OPERATORS: [Operator; 8] = [
+    Operator::Add,
+    Operator::Sub,
+    Operator::Mul,
+    Operator::Div,
+    Operator::Rem,
+    Operator::Eq,
+    Operator::Less,
+    Operator::LessEq,
+];
+
+impl OpCode {
+    /// Appends the packed encoding of the opcode to `out`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.kind() as u8);
+
+        match *self {
+            OpCode::Push(n)
+            | OpCode::Load(n)
+            | OpCode::Save(n)
+            | OpCode::Drop(n)
+            | OpCode::Jmf(n)
+            | OpCode::Jmp(n)
+            | OpCode::Call(n)
+            | OpCode::TCall(n)
+            | OpCode::RevN(n)
+            | OpCode::Try(n)
+            | OpCode::Tup(n)
+            | OpCode::TupGet(n)
+            | OpCode::Closure(n)
+            | OpCode::Loac(n) => write_usize(out, n),
+            OpCode::Swap(a, b) => {
+                write_usize(out, a);
+                write_usize(out, b);
+            }
+            OpCode::Loag(sym)
+            | OpCode::Savg(sym)
+            | OpCode::Ref(sym)
+            | OpCode::Tag(sym)
+            | OpCode::SetField(sym)
+            | OpCode::HasField(sym) => {
+                write_str(out, sym.as_str());
+            }
+            OpCode::Bin(operator, left, right) => {
+                let operator = OPERATORS.iter().position(|it| *it == operator).unwrap();
+                out.push(operator as u8);
+                write_operand(out, left);
+                write_operand(out, right);
+            }
+            OpCode::Ext(id, n) => {
+                out.push(id);
+                write_usize(out, n);
+            }
+            _ => (),
+        }
+    }
+
+    /// Reads an opcode encoded by [`OpCode::encode`], advancing `bytes` past it
+    pub fn decode(bytes: &mut &[u8]) -> Option<Self> {
+        let (&kind, rest) = bytes.split_first()?;
+        *bytes = rest;
+
+        let mut n = || read_usize(bytes);
+        Some(match kind {
+            0 => OpCode::Nop,
+            1 => OpCode::Push(n()?),
+            2 => OpCode::Pop,
+            3 => OpCode::Dup,
+            4 => OpCode::Swap(n()?, n()?),
+            5 => OpCode::Load(n()?),
+            6 => OpCode::Save(n()?),
+            7 => OpCode::Loag(Symbol::new(read_str(bytes)?)),
+            8 => OpCode::Savg(Symbol::new(read_str(bytes)?)),
+            9 => OpCode::Drop(n()?),
+            10 => OpCode::Jmf(n()?),
+            11 => OpCode::Jmp(n()?),
+            12 => OpCode::Call(n()?),
+            13 => OpCode::TCall(n()?),
+            14 => OpCode::Prep,
+            15 => OpCode::Rev,
+            16 => OpCode::RevN(n()?),
+            17 => OpCode::Add,
+            18 => OpCode::Rem,
+            19 => OpCode::Sub,
+            20 => OpCode::Mul,
+            21 => OpCode::Div,
+            22 => OpCode::FloorDiv,
+            23 => OpCode::Mod,
+            24 => OpCode::Neg,
+            25 => OpCode::Len,
+            26 => OpCode::Not,
+            27 => OpCode::Xor,
+            28 => OpCode::Shr,
+            29 => OpCode::UShr,
+            30 => OpCode::Shl,
+            31 => OpCode::BitAnd,
+            32 => OpCode::BitOr,
+            33 => OpCode::BitNot,
+            34 => OpCode::Eq,
+            35 => OpCode::Less,
+            36 => OpCode::LessEq,
+            37 => OpCode::New,
+            38 => OpCode::Type,
+            39 => OpCode::IsA,
+            40 => OpCode::Ref(Symbol::new(read_str(bytes)?)),
+            41 => OpCode::Try(n()?),
+            42 => OpCode::EndTry,
+            43 => OpCode::Tup(n()?),
+            44 => OpCode::TupGet(n()?),
+            45 => OpCode::Tag(Symbol::new(read_str(bytes)?)),
+            46 => OpCode::TagOf,
+            47 => OpCode::TagTup,
+            48 => OpCode::Yield,
+            49 => {
+                let (&operator, rest) = bytes.split_first()?;
+                *bytes = rest;
+                let operator = *OPERATORS.get(usize::from(operator))?;
+                OpCode::Bin(operator, read_operand(bytes)?, read_operand(bytes)?)
+            }
+            50 => {
+                let (&id, rest) = bytes.split_first()?;
+                *bytes = rest;
+                OpCode::Ext(id, read_usize(bytes)?)
+            }
+            51 => OpCode::SetField(Symbol::new(read_str(bytes)?)),
+            52 => OpCode::Rethrow,
+            53 => OpCode::EnterScope,
+            54 => OpCode::ExitScope,
+            55 => OpCode::HasField(Symbol::new(read_str(bytes)?)),
+            56 => OpCode::Recur,
+            57 => OpCode::Closure(read_usize(bytes)?),
+            58 => OpCode::Loac(read_usize(bytes)?),
+            _ => return None,
+        })
+    }
+}
+
+/// Packs some bytecode, with the position of every opcode
+#[must_use]
+pub fn encode(bytecode: &[OpCodeMetadata]) -> Vec<u8> {
+    let mut out = vec![];
+    write_usize(&mut out, bytecode.len());
+
+    for op in bytecode {
+        op.opcode.encode(&mut out);
+        write_usize(&mut out, op.line);
+        write_usize(&mut out, op.column);
+    }
+
+    out
+}
+
+/// Unpacks bytecode packed by [`encode`], returns `None` if it's malformed, advancing `bytes` past
+/// it
+pub fn decode(bytes: &mut &[u8]) -> Option<Bytecode> {
+    let len = read_usize(bytes)?;
+
+    // every opcode takes 3 bytes at least, which bounds the allocation of malformed bytecode
+    let mut bytecode = Vec::with_capacity(len.min(bytes.len() / 3));
+    for _ in 0..len {
+        let opcode = OpCode::decode(bytes)?;
+        let line = read_usize(bytes)?;
+        let column = read_usize(bytes)?;
+        bytecode.push(OpCodeMetadata {
+            line,
+            column,
+            opcode,
+        });
+    }
+
+    Some(bytecode)
+}
+
+/// Writes the [`BYTECODE_VERSION`], which starts every packed program
+fn write_version(out: &mut Vec<u8>) {
+    write_varint(out, BYTECODE_VERSION.into());
+}
+
+/// Reads the version written by [`write_version`], raising a `DecodeError` if it isn't the
+/// [`BYTECODE_VERSION`] of this virtual machine
+pub fn check_version(bytes: &mut &[u8]) -> InterpretResult<()> {
+    match read_varint(bytes) {
+        Some(version) if version == u64::from(BYTECODE_VERSION) => Ok(()),
+        Some(version) => raise!(
+            DecodeError,
+            "The bytecode was packed for version {} of the instruction set, this virtual machine \
+             runs version {}",
+            version,
+            BYTECODE_VERSION
+        ),
+        None => raise!(DecodeError, "The bytecode doesn't start with its version"),
+    }
+}
+
+/// Packs some bytecode along with its constants, raising a `TypeError` if one of them can't be
+/// encoded
+pub fn encode_program(
+    bytecode: &[OpCodeMetadata],
+    constants: &[Value],
+) -> InterpretResult<Vec<u8>> {
+    let mut out = MAGIC.to_vec();
+    write_version(&mut out);
+    out.extend(encode(bytecode));
+    encode_all(&mut out, constants.iter())?;
+    Ok(out)
+}
+
+/// Unpacks a program packed by [`encode_program`], raising a `DecodeError` if it isn't one, if it
+/// was packed for another version of the instruction set or if it's malformed
+pub fn decode_program(
+    bytes: &[u8],
+    vm: &VirtualMachine,
+) -> InterpretResult<(Bytecode, Vec<Value>)> {
+    let Some(mut bytes) = bytes.strip_prefix(&MAGIC) else {
+        return raise!(DecodeError, "The bytes aren't a packed yex program");
+    };
+    check_version(&mut bytes)?;
+    let malformed = || crate::raise_err!(DecodeError, "The packed program is malformed");
+    let bytecode = decode(&mut bytes).ok_or_else(malformed)?;
+    let constants = decode_all(&mut bytes, vm).ok_or_else(malformed)?;
+    Ok((bytecode, constants))
+}
+
+/// Checks that some bytecode, and the one of the functions among its constants, only points to
+/// the constants and the opcodes there are, which the virtual machine doesn't check while running
+/// it. The bytecode compiled by `front` always passes, but decoded bytecode may not, raises a
+/// `DecodeError` at the first opcode that doesn't
+pub fn verify(bytecode: &[OpCodeMetadata], constants: &[Value]) -> InterpretResult<()> {
+    verify_body(bytecode, constants)?;
+    constants
+        .iter()
+        .try_for_each(|value| verify_value(value, constants))
+}
+
+/// Verifies the bodies of the functions in a value, including the methods of a type
+fn verify_value(value: &Value, constants: &[Value]) -> InterpretResult<()> {
+    match value {
+        Value::Fn(fun) => {
+            match &*fun.body {
+                FnKind::Bytecode(body) | FnKind::Generator(body) => {
+                    verify_body(body, constants)?;
+                }
+                FnKind::Native(_) => (),
+            }
+            fun.args
+                .iter()
+                .chain(fun.captures.iter())
+                .try_for_each(|arg| verify_value(arg, constants))
+        }
+        Value::Module(module) => module
+            .fields
+            .values()
+            .try_for_each(|field| verify_value(&field, constants)),
+        Value::List(xs) => xs.iter().try_for_each(|x| verify_value(x, constants)),
+        Value::Tuple(xs) | Value::Tagged(_, _, xs) => {
+            xs.0.iter().try_for_each(|x| verify_value(x, constants))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn verify_body(body: &[OpCodeMetadata], constants: &[Value]) -> InterpretResult<()> {
+    for op in body {
+        let constant = |index: usize| index < constants.len();
+        let valid = match op.opcode {
+            OpCode::Push(index) => constant(index),
+            OpCode::Bin(_, left, right) => [left, right].into_iter().all(|operand| match operand {
+                Operand::Const(index) => constant(index as usize),
+                Operand::Stack | Operand::Local(_) => true,
+            }),
+            OpCode::Jmp(to) | OpCode::Jmf(to) | OpCode::Try(to) => to <= body.len(),
+            _ => true,
+        };
+
+        if !valid {
+            let err = crate::raise_err!(
+                DecodeError,
+                "The opcode {:?} points past the {} constants or the {} opcodes there are",
+                op.opcode,
+                constants.len(),
+                body.len()
+            );
+            return Err(crate::error::InterpretError {
+                line: op.line,
+                column: op.column,
+                ..err
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_all<'a>(
+    out: &mut Vec<u8>,
+    mut values: impl ExactSizeIterator<Item = &'a Value>,
+) -> InterpretResult<()> {
+    write_usize(out, values.len());
+    values.try_for_each(|value| value.encode(out))
+}
+
+fn decode_all(bytes: &mut &[u8], vm: &VirtualMachine) -> Option<Vec<Value>> {
+    let len = read_usize(bytes)?;
+    // every value takes a byte at least, so a bogus length can't allocate too much
+    if bytes.len() < len {
+        return None;
+    }
+
+    (0..len).map(|_| Value::decode(bytes, vm)).collect()
+}
+
+impl Value {
+    /// Appends the packed encoding of the value, raising a `TypeError` if it holds a native
+    /// function or foreign data
+    pub fn encode(&self, out: &mut Vec<u8>) -> InterpretResult<()> {
+        match self {
+            Value::Nil => out.push(0),
+            Value::Bool(false) => out.push(1),
+            Value::Bool(true) => out.push(2),
+            Value::Num(n) => {
+                out.push(3);
+                out.extend(n.to_le_bytes());
+            }
+            Value::Int(n) => {
+                out.push(12);
+                out.extend(n.to_le_bytes());
+            }
+            Value::Str(s) => {
+                out.push(4);
+                write_str(out, s);
+            }
+            Value::Sym(s) => {
+                out.push(5);
+                write_str(out, s.as_str());
+            }
+            Value::List(xs) => {
+                out.push(6);
+                encode_all(out, xs.iter())?;
+            }
+            Value::Tuple(xs) => {
+                out.push(7);
+                encode_all(out, xs.0.iter())?;
+            }
+            Value::Tagged(module, tag, xs) => {
+                out.push(8);
+                write_str(out, module.name.as_str());
+                write_str(out, tag.as_str());
+                encode_all(out, xs.0.iter())?;
+            }
+            Value::Fn(fun) => {
+                let (kind, body) = match &*fun.body {
+                    FnKind::Bytecode(body) => (9, body),
+                    FnKind::Generator(body) => (10, body),
+                    FnKind::Native(_) => {
+                        return raise!(TypeError, "Native functions can't be encoded")
+                    }
+                };
+                out.push(kind);
+                write_usize(out, fun.arity);
+                out.extend(encode(body));
+                encode_all(out, fun.args[..].iter())?;
+                encode_all(out, fun.captures.iter())?;
+            }
+            Value::Module(module) => {
+                out.push(11);
+                write_str(out, module.name.as_str());
+
+                // sorted, so a program is always packed the same way
+                let mut variants = module.variants.iter().collect::<Vec<_>>();
+                variants.sort_by_key(|(tag, _)| tag.as_str());
+                write_usize(out, variants.len());
+                for (tag, names) in variants {
+                    write_str(out, tag.as_str());
+                    write_usize(out, names.len());
+                    for name in names {
+                        write_str(out, name.as_str());
+                    }
+                }
+
+                let mut fields = module.fields.iter().collect::<Vec<_>>();
+                fields.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+                write_usize(out, fields.len());
+                for (name, value) in fields {
+                    write_str(out, name.as_str());
+                    value.encode(out)?;
+                }
+            }
+            other @ (Value::UserData(_) | Value::FFI(_)) => {
+                raise!(
+                    TypeError,
+                    "Values of type '{}' can't be encoded",
+                    other.type_of().name
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a value written by [`Value::encode`], advancing `bytes` past it, the tagged values
+    /// look their type up in the globals of `vm`
+    pub fn decode(bytes: &mut &[u8], vm: &VirtualMachine) -> Option<Value> {
+        let (&kind, rest) = bytes.split_first()?;
+        *bytes = rest;
+
+        Some(match kind {
+            0 => Value::Nil,
+            1 => Value::Bool(false),
+            2 => Value::Bool(true),
+            3 => {
+                let (n, rest) = bytes.split_first_chunk::<8>()?;
+                *bytes = rest;
+                Value::Num(f64::from_le_bytes(*n))
+            }
+            12 => {
+                let (n, rest) = bytes.split_first_chunk::<8>()?;
+                *bytes = rest;
+                Value::Int(i64::from_le_bytes(*n))
+            }
+            4 => Value::Str(GcRef::new(read_str(bytes)?)),
+            5 => Value::Sym(Symbol::from(read_str(bytes)?).into()),
+            6 => Value::List(decode_all(bytes, vm)?.into_iter().collect::<List>()),
+            7 => Value::Tuple(Tuple::from(decode_all(bytes, vm)?)),
+            8 => {
+                let name = Symbol::from(read_str(bytes)?);
+                let tag = Symbol::from(read_str(bytes)?);
+                // like the values sent to other threads, a type that isn't defined gets an empty
+                // module with its name
+                let module = match vm.get_global(name) {
+                    Some(Value::Module(module)) => module,
+                    _ => GcRef::new(YexModule::new(name, EnvTable::new())),
+                };
+                Value::Tagged(module, tag, Tuple::from(decode_all(bytes, vm)?))
+            }
+            9 | 10 => {
+                let arity = read_usize(bytes)?;
+                let body = decode(bytes)?;
+                let args = decode_all(bytes, vm)?;
+                let captures = decode_all(bytes, vm)?;
+                Value::Fn(GcRef::new(Fn {
+                    arity,
+                    body: GcRef::new(if kind == 9 {
+                        FnKind::Bytecode(body)
+                    } else {
+                        FnKind::Generator(body)
+                    }),
+                    args: args.into_iter().collect(),
+                    signature: None,
+                    captures: captures.into(),
+                }))
+            }
+            11 => {
+                let name = Symbol::from(read_str(bytes)?);
+                let len = read_usize(bytes)?;
+                let variants = (0..len)
+                    .map(|_| {
+                        let tag = Symbol::from(read_str(bytes)?);
+                        let len = read_usize(bytes)?;
+                        let names = (0..len)
+                            .map(|_| read_str(bytes).map(Symbol::from))
+                            .collect::<Option<Vec<_>>>()?;
+                        Some((tag, names))
+                    })
+                    .collect::<Option<HashMap<_, _>>>()?;
+                let len = read_usize(bytes)?;
+                let fields = (0..len)
+                    .map(|_| Some((Symbol::from(read_str(bytes)?), Value::decode(bytes, vm)?)))
+                    .collect::<Option<Vec<_>>>()?;
+
+                let mut module = GcRef::new(YexModule {
+                    variants,
+                    ..YexModule::new(name, EnvTable::new())
+                });
+                for (field, value) in fields {
+                    // the variants without fields are instances of the type being decoded
+                    let value = match value {
+                        Value::Tagged(ty, tag, xs) if ty.name == name => {
+                            Value::Tagged(module.clone(), tag, xs)
+                        }
+                        other => other,
+                    };
+                    unsafe { module.mut_ref() }.fields.insert(field, value);
+                }
+                Value::Module(module)
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Points the bytecode of the functions in a decoded value past the `old_len` constants before the
+/// ones it was packed with. The decoded functions don't share their bodies, so none of them is
+/// moved twice
+fn relocate(value: &Value, old_len: usize) {
+    match value {
+        Value::Fn(fun) => {
+            let mut body = fun.body.clone();
+            match unsafe { body.mut_ref() } {
+                FnKind::Bytecode(ops) | FnKind::Generator(ops) => {
+                    crate::patch_bytecode(ops, old_len);
+                }
+                FnKind::Native(_) => (),
+            }
+            fun.args
+                .iter()
+                .chain(fun.captures.iter())
+                .for_each(|arg| relocate(arg, old_len));
+        }
+        Value::Module(module) => {
+            for field in module.fields.values() {
+                relocate(&field, old_len);
+            }
+        }
+        Value::List(xs) => xs.iter().for_each(|x| relocate(x, old_len)),
+        Value::Tuple(xs) | Value::Tagged(_, _, xs) => {
+            xs.0.iter().for_each(|x| relocate(x, old_len));
+        }
+        _ => (),
+    }
+}
+
+impl VirtualMachine {
+    /// Packs the globals defined since the virtual machine started, along with its constants, to
+    /// restore them later with [`VirtualMachine::load_globals`], even in another process. The
+    /// globals that can't be encoded, like types and native functions, are left out, and so are
+    /// the ones of the prelude
+    #[must_use]
+    pub fn dump_globals(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_version(&mut out);
+
+        // the constants keep their indexes, the ones that can't be encoded are written as nil
+        write_usize(&mut out, self.constants.len());
+        for constant in &self.constants {
+            let mut encoded = Vec::new();
+            match constant.encode(&mut encoded) {
+                Ok(()) => out.extend(encoded),
+                Err(_) => out.push(0),
+            }
+        }
+
+        let mut globals = Vec::new();
+        let mut len = 0;
+        for (name, value) in self.globals.iter() {
+            if crate::prelude::is_prelude(&name, &value) || matches!(value, Value::Module(_)) {
+                continue;
+            }
+
+            let mut encoded = Vec::new();
+            write_str(&mut encoded, name.as_str());
+            if value.encode(&mut encoded).is_ok() {
+                globals.extend(encoded);
+                len += 1;
+            }
+        }
+        write_usize(&mut out, len);
+        out.extend(globals);
+        out
+    }
+
+    /// Unpacks a program packed by [`encode_program`], like a `.yexc` file, checking its bytecode
+    /// with [`verify`] and appending its constants to the ones of the virtual machine like
+    /// [`VirtualMachine::link`] does. The bytecode returned is the top-level code of the program,
+    /// ready to [`VirtualMachine::run`]
+    pub fn load_chunk(&mut self, bytes: &[u8]) -> InterpretResult<Bytecode> {
+        let (mut bytecode, constants) = decode_program(bytes, self)?;
+        verify(&bytecode, &constants)?;
+        self.link(&mut bytecode, constants);
+        Ok(bytecode)
+    }
+
+    /// Defines the globals packed by [`VirtualMachine::dump_globals`], appending their constants
+    /// to the ones of the virtual machine like [`VirtualMachine::link`] does. The instances of a
+    /// type are only tied to it if the type is already defined, raises a `DecodeError` if the
+    /// bytes are malformed or were packed for another version of the instruction set
+    pub fn load_globals(&mut self, mut bytes: &[u8]) -> InterpretResult<()> {
+        check_version(&mut bytes)?;
+        let malformed = || crate::raise_err!(DecodeError, "The saved globals are malformed");
+
+        let constants = decode_all(&mut bytes, self).ok_or_else(malformed)?;
+        verify(&[], &constants)?;
+
+        let len = read_usize(&mut bytes).ok_or_else(malformed)?;
+        // every global takes two bytes at least
+        if bytes.len() / 2 < len {
+            return Err(malformed());
+        }
+        let globals = (0..len)
+            .map(|_| {
+                let name = Symbol::from(read_str(&mut bytes)?);
+                Some((name, Value::decode(&mut bytes, self)?))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(malformed)?;
+        if !bytes.is_empty() {
+            return Err(malformed());
+        }
+
+        let old_len = self.constants.len();
+        for value in constants
+            .iter()
+            .chain(globals.iter().map(|(_, value)| value))
+        {
+            relocate(value, old_len);
+        }
+        self.constants.extend(constants);
+        for (name, value) in globals {
+            self.set_global(name, value);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn encode_and_decode_opcodes() {
+    let sym = Symbol::new("x");
+    let bytecode = [
+        OpCode::Nop,
+        OpCode::Push(300),
+        OpCode::Swap(1, 2),
+        OpCode::Loag(sym),
+        OpCode::Jmf(u32::MAX as usize),
+        OpCode::Ref(sym),
+        OpCode::Tag(sym),
+        OpCode::Yield,
+        OpCode::Bin(Operator::LessEq, Operand::Stack, Operand::Const(7)),
+        OpCode::Bin(Operator::Rem, Operand::Local(1), Operand::Local(200)),
+        OpCode::Ext(3, 300),
+        OpCode::SetField(sym),
+        OpCode::EnterScope,
+        OpCode::ExitScope,
+        OpCode::HasField(Symbol::from("x")),
+        OpCode::Recur,
+        OpCode::Closure(2),
+        OpCode::Loac(300),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(i, opcode)| OpCodeMetadata::new(i + 1, 2 * i, opcode))
+    .collect::<Vec<_>>();
+
+    let bytes = encode(&bytecode);
+    assert_eq!(bytes[1], OpCode::Nop.kind() as u8);
+
+    let position = |op: &OpCodeMetadata| (op.line, op.column, op.opcode);
+    let decoded = decode(&mut &bytes[..]).unwrap();
+    assert!(decoded
+        .iter()
+        .map(position)
+        .eq(bytecode.iter().map(position)));
+
+    // a truncated or unknown opcode isn't decoded
+    assert_eq!(decode(&mut &bytes[..bytes.len() - 1]), None);
+    assert_eq!(OpCode::decode(&mut &[u8::MAX][..]), None);
+
+    // and every kind is decoded as the variant it was encoded from
+    let last = OpCode::Loac(0).kind();
+    for kind in 0..=last {
+        let bytes = [kind as u8, 0, 0, 0, 0];
+        assert_eq!(OpCode::decode(&mut &bytes[..]).unwrap().kind(), kind);
+    }
+}
+
+#[test]
+fn encode_and_decode_values() {
+    let vm = VirtualMachine::default();
+    let values = [
+        Value::Nil,
+        Value::Bool(true),
+        Value::Num(-1.5),
+        Value::Int(i64::MIN),
+        Value::Str(GcRef::new(String::from("hello"))),
+        Value::Sym(Symbol::from("ok").into()),
+        Value::List(vec![Value::Num(1.0), Value::Nil].into_iter().collect()),
+        Value::Tuple(Tuple::from(vec![
+            Value::Bool(false),
+            Value::List(List::new()),
+        ])),
+        Value::Fn(GcRef::new(Fn {
+            captures: Box::new([Value::Num(3.0)]),
+            ..Fn::new_bt(1, vec![OpCodeMetadata::new(1, 1, OpCode::Loac(0))])
+        })),
+    ];
+
+    let mut out = vec![];
+    for value in &values {
+        value.encode(&mut out).unwrap();
+    }
+
+    let mut bytes = &out[..];
+    for value in &values {
+        assert_eq!(Value::decode(&mut bytes, &vm).as_ref(), Some(value));
+    }
+    assert!(bytes.is_empty());
+
+    // a string cut short
+    assert_eq!(Value::decode(&mut &out[20..24], &vm), None);
+
+    let err = vm
+        .get_global("println")
+        .unwrap()
+        .encode(&mut out)
+        .unwrap_err();
+    assert_eq!(err.err.as_str(), "TypeError");
+}
+
+#[test]
+fn encode_and_decode_programs() {
+    let vm = VirtualMachine::default();
+    let body = [OpCode::Push(0), OpCode::Add]
+        .map(|op| OpCodeMetadata::new(2, 3, op))
+        .to_vec();
+    let fun = Fn::new_bt(1, body);
+    let bytecode = [OpCode::Push(1), OpCode::Savg(Symbol::new("add_two"))]
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .to_vec();
+    let constants = [Value::Num(2.0), Value::Fn(GcRef::new(fun.clone()))];
+
+    let bytes = encode_program(&bytecode, &constants).unwrap();
+    let (decoded, decoded_constants) = decode_program(&bytes, &vm).unwrap();
+    assert_eq!(decoded, bytecode);
+    assert_eq!(decoded_constants[0], Value::Num(2.0));
+
+    let Value::Fn(decoded_fun) = &decoded_constants[1] else {
+        panic!("expected a function")
+    };
+    assert_eq!(decoded_fun.arity, 1);
+    assert_eq!(*decoded_fun.body, *fun.body);
+
+    // a program packed by another version of the instruction set
+    let mut other = MAGIC.to_vec();
+    write_varint(&mut other, u64::from(BYTECODE_VERSION) + 1);
+    other.extend(&bytes[MAGIC.len() + 1..]);
+    let err = decode_program(&other, &vm).unwrap_err();
+    assert_eq!(err.err.as_str(), "DecodeError");
+    assert!(err
+        .msg
+        .contains(&format!("runs version {BYTECODE_VERSION}")));
+    assert!(decode_program(&bytes[..bytes.len() - 1], &vm).is_err());
+    // bytes that aren't a packed program
+    assert!(decode_program(&bytes[MAGIC.len()..], &vm).is_err());
+}
+
+#[test]
+fn verify_the_decoded_bytecode() {
+    let ops = |ops: &[OpCode]| {
+        ops.iter()
+            .map(|&op| OpCodeMetadata::new(1, 1, op))
+            .collect::<Vec<_>>()
+    };
+    let constants = [
+        Value::Num(1.0),
+        Value::Fn(GcRef::new(Fn::new_bt(0, ops(&[OpCode::Push(2)])))),
+    ];
+
+    let valid = ops(&[
+        OpCode::Push(0),
+        OpCode::Jmf(3),
+        OpCode::Bin(Operator::Add, Operand::Local(9), Operand::Const(0)),
+    ]);
+    verify(&valid, &constants[..1]).unwrap();
+
+    // the body of the function pushes a constant past the end
+    let err = verify(&valid, &constants).unwrap_err();
+    assert_eq!(err.err.as_str(), "DecodeError");
+
+    for bad in [OpCode::Jmp(3), OpCode::Push(1), OpCode::Try(7)] {
+        assert!(verify(&ops(&[OpCode::Nop, bad]), &constants[..1]).is_err());
+    }
+}
+
+#[test]
+fn dump_and_load_globals() {
+    use crate::Operator;
+
+    let mut vm = VirtualMachine::default();
+    let body = [
+        OpCode::Save(0),
+        OpCode::Bin(Operator::Add, Operand::Local(0), Operand::Const(1)),
+    ]
+    .map(|op| OpCodeMetadata::new(1, 1, op))
+    .to_vec();
+    let add_two = Value::Fn(GcRef::new(Fn::new_bt(1, body)));
+    vm.set_consts(vec![add_two.clone(), Value::Num(2.0)]);
+    vm.set_global("add_two", add_two);
+    vm.set_global(
+        "point",
+        Value::Tuple(Tuple::from(vec![Value::Num(1.0), Value::Nil])),
+    );
+    vm.set_global("lib", vm.get_global("List").unwrap());
+
+    let bytes = vm.dump_globals();
+
+    // the constants of another program come first, so the loaded ones are moved past them
+    let mut restored = VirtualMachine::default();
+    restored.set_consts(vec![Value::Nil, Value::Num(40.0)]);
+    restored.load_globals(&bytes).unwrap();
+    assert_eq!(restored.constants.len(), 4);
+    assert_eq!(restored.get_global("point"), vm.get_global("point"));
+    assert!(restored.get_global("lib").is_none());
+
+    let call = [
+        OpCode::Push(1),
+        OpCode::Loag(Symbol::new("add_two")),
+        OpCode::Call(1),
+    ]
+    .map(|op| OpCodeMetadata::new(1, 1, op))
+    .to_vec();
+    restored.run(&call).unwrap();
+    assert_eq!(restored.pop_last(), &Value::Num(42.0));
+
+    let mut truncated = VirtualMachine::default();
+    let err = truncated
+        .load_globals(&bytes[..bytes.len() - 1])
+        .unwrap_err();
+    assert_eq!(err.err.as_str(), "DecodeError");
+}
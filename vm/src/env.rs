@@ -5,6 +5,7 @@ use std::{
 };
 
 use crate::{
+    gc::{Trace, Traced},
     literal::{nil, Value},
     Symbol,
 };
@@ -12,7 +13,7 @@ use crate::{
 type Key = Symbol;
 
 #[derive(Debug, Clone)]
-struct Entry {
+struct Slot {
     pub key: Option<Key>,
     pub value: Value,
 }
@@ -23,7 +24,9 @@ struct Entry {
 pub struct EnvTable {
     capacity: usize,
     count: usize,
-    entries: *mut Entry,
+    /// the entries of removed keys, which don't end the probing for a key
+    tombstones: usize,
+    entries: *mut Slot,
 }
 
 impl EnvTable {
@@ -40,9 +43,9 @@ impl EnvTable {
     pub fn with_capacity(capacity: usize) -> Self {
         let entries = unsafe {
             #[allow(clippy::cast_ptr_alignment)]
-            let entries = alloc(Layout::array::<Entry>(capacity).unwrap()).cast::<Entry>();
+            let entries = alloc(Layout::array::<Slot>(capacity).unwrap()).cast::<Slot>();
             for index in 0..capacity {
-                entries.add(index).write(Entry {
+                entries.add(index).write(Slot {
                     key: None,
                     value: nil(),
                 });
@@ -52,13 +55,14 @@ impl EnvTable {
         Self {
             capacity,
             count: 0,
+            tombstones: 0,
             entries,
         }
     }
 
-    unsafe fn find_entry(entries: *mut Entry, capacity: usize, key: &Symbol) -> (*mut Entry, bool) {
+    unsafe fn find_entry(entries: *mut Slot, capacity: usize, key: &Symbol) -> (*mut Slot, bool) {
         let mut index = key.hash & (capacity - 1);
-        let mut last_null: *mut Entry = null_mut();
+        let mut last_null: *mut Slot = null_mut();
 
         loop {
             let entry = entries.add(index);
@@ -80,31 +84,87 @@ impl EnvTable {
         }
     }
 
-    /// Inserts an item in the table
-    pub fn insert(&mut self, key: Symbol, value: Value) {
-        if self.count + (self.capacity / Self::BASE_VALUE) >= self.capacity {
+    /// Grows the table if it's too full to insert another item
+    fn grow_if_full(&mut self) {
+        if self.count + self.tombstones + (self.capacity / Self::BASE_VALUE) >= self.capacity {
             let len = self.capacity * 2;
             self.realloc(len);
         }
+    }
+
+    /// Fills a slot, returned by `find_entry` as not initialized, with a new item
+    unsafe fn fill(&mut self, entry: *mut Slot, key: Symbol, value: Value) {
+        self.count += 1;
+        if !(*entry).value.is_nil() {
+            self.tombstones -= 1;
+        }
+        (*entry).key = Some(key);
+        (*entry).value = value;
+    }
+
+    /// Inserts an item in the table
+    pub fn insert(&mut self, key: Symbol, value: Value) {
+        self.grow_if_full();
 
         let (entry, init) = unsafe { Self::find_entry(self.entries, self.capacity, &key) };
 
         unsafe {
+            if init {
+                (*entry).value = value;
+            } else {
+                self.fill(entry, key, value);
+            }
+        }
+    }
+
+    /// The entry of a key, to read, insert or update its item with a single lookup
+    #[must_use]
+    pub fn entry(&mut self, key: Symbol) -> Entry<'_> {
+        self.grow_if_full();
+
+        let (slot, occupied) = unsafe { Self::find_entry(self.entries, self.capacity, &key) };
+        Entry {
+            table: self,
+            key,
+            slot,
+            occupied,
+        }
+    }
+
+    /// Removes an item from the table, returning its value
+    pub fn remove(&mut self, key: &Symbol) -> Option<Value> {
+        unsafe {
+            let (entry, init) = Self::find_entry(self.entries, self.capacity, key);
             if !init {
-                self.count += 1;
+                return None;
             }
-            (*entry).key = Some(key);
-            (*entry).value = value;
+
+            // the entry is left as a tombstone, a non-nil value without a key, so the keys after
+            // it can still be found
+            self.count -= 1;
+            self.tombstones += 1;
+            (*entry).key = None;
+            Some(std::mem::replace(&mut (*entry).value, Value::Bool(true)))
+        }
+    }
+
+    /// Makes room for `additional` more items, so inserting them doesn't grow the table again
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.count + self.tombstones + additional;
+        // the same load factor `insert` keeps
+        let len = (needed + needed / (Self::BASE_VALUE - 1) + 1).next_power_of_two();
+        if len > self.capacity {
+            self.realloc(len);
         }
     }
 
     fn realloc(&mut self, len: usize) {
         #[allow(clippy::cast_ptr_alignment)]
-        let entries = unsafe { alloc(Layout::array::<Entry>(len).unwrap()).cast::<Entry>() };
+        let entries = unsafe { alloc(Layout::array::<Slot>(len).unwrap()).cast::<Slot>() };
 
         for index in 0..len {
             unsafe {
-                entries.add(index).write(Entry {
+                entries.add(index).write(Slot {
                     key: None,
                     value: nil(),
                 });
@@ -128,12 +188,13 @@ impl EnvTable {
         unsafe {
             dealloc(
                 self.entries.cast::<u8>(),
-                Layout::array::<Entry>(self.capacity).unwrap(),
+                Layout::array::<Slot>(self.capacity).unwrap(),
             );
         }
 
         self.entries = entries;
         self.capacity = len;
+        self.tombstones = 0;
     }
 
     /// Indexes an item in the table
@@ -149,6 +210,27 @@ impl EnvTable {
         }
     }
 
+    /// Indexes an item in the table by the name of its key, without creating a symbol for it
+    #[must_use]
+    pub fn get_str(&self, key: &str) -> Option<Value> {
+        Symbol::lookup(key).and_then(|key| self.get(&key))
+    }
+
+    /// Borrows an item of the table, to change it in place
+    #[must_use]
+    pub fn get_mut(&mut self, key: &Symbol) -> Option<&mut Value> {
+        unsafe {
+            let (entry, init) = Self::find_entry(self.entries, self.capacity, key);
+            init.then(|| &mut (*entry).value)
+        }
+    }
+
+    /// Checks if the table has an item with the key
+    #[must_use]
+    pub fn contains_key(&self, key: &Symbol) -> bool {
+        unsafe { Self::find_entry(self.entries, self.capacity, key).1 }
+    }
+
     /// Returns the table length
     #[must_use]
     pub fn len(&self) -> usize {
@@ -171,6 +253,94 @@ impl EnvTable {
                 .map(|it| (it.key.unwrap(), it.value.clone()))
         }
     }
+
+    /// Iterates over the keys of the table
+    pub fn keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Iterates over the values of the table
+    pub fn values(&self) -> impl Iterator<Item = Value> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+}
+
+/// The slot of a key in an [`EnvTable`], which may be empty, returned by [`EnvTable::entry`]
+pub struct Entry<'a> {
+    table: &'a mut EnvTable,
+    key: Symbol,
+    slot: *mut Slot,
+    occupied: bool,
+}
+
+impl<'a> Entry<'a> {
+    /// The key of the entry
+    #[must_use]
+    pub fn key(&self) -> Symbol {
+        self.key
+    }
+
+    /// The item of the entry, if the table has one
+    #[must_use]
+    pub fn get(&self) -> Option<&Value> {
+        self.occupied.then(|| unsafe { &(*self.slot).value })
+    }
+
+    /// Changes the item of the entry, if the table has one
+    #[must_use]
+    pub fn and_modify(self, f: impl FnOnce(&mut Value)) -> Self {
+        if self.occupied {
+            f(unsafe { &mut (*self.slot).value });
+        }
+        self
+    }
+
+    /// Inserts `value` if the entry is empty, returning the item of the entry
+    #[must_use]
+    pub fn or_insert(self, value: Value) -> &'a mut Value {
+        self.or_insert_with(|| value)
+    }
+
+    /// Inserts the value returned by `f` if the entry is empty, returning the item of the entry
+    pub fn or_insert_with(self, f: impl FnOnce() -> Value) -> &'a mut Value {
+        unsafe {
+            if !self.occupied {
+                self.table.fill(self.slot, self.key, f());
+            }
+            &mut (*self.slot).value
+        }
+    }
+
+    /// Sets the item of the entry, returning the old one
+    #[must_use]
+    pub fn insert(self, value: Value) -> Option<Value> {
+        unsafe {
+            if self.occupied {
+                Some(std::mem::replace(&mut (*self.slot).value, value))
+            } else {
+                self.table.fill(self.slot, self.key, value);
+                None
+            }
+        }
+    }
+}
+
+impl Extend<(Symbol, Value)> for EnvTable {
+    fn extend<T: IntoIterator<Item = (Symbol, Value)>>(&mut self, iter: T) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl FromIterator<(Symbol, Value)> for EnvTable {
+    fn from_iter<T: IntoIterator<Item = (Symbol, Value)>>(iter: T) -> Self {
+        let mut table = Self::new();
+        table.extend(iter);
+        table
+    }
 }
 
 impl std::fmt::Display for EnvTable {
@@ -193,12 +363,21 @@ impl Default for EnvTable {
     }
 }
 
+impl Trace for EnvTable {
+    fn trace(&self, visit: &mut dyn FnMut(Traced)) {
+        // the slots of removed keys can still hold their values
+        for slot in unsafe { slice::from_raw_parts(self.entries, self.capacity) } {
+            slot.value.trace(visit);
+        }
+    }
+}
+
 impl Drop for EnvTable {
     fn drop(&mut self) {
         unsafe {
             dealloc(
                 self.entries.cast::<u8>(),
-                Layout::array::<Entry>(self.capacity).unwrap(),
+                Layout::array::<Slot>(self.capacity).unwrap(),
             );
         }
     }
@@ -214,3 +393,59 @@ impl PartialEq for EnvTable {
             .all(|(key, value)| other.get(&key).map_or(false, |v| value == v))
     }
 }
+
+#[test]
+fn remove_and_extend_tables() {
+    let keys = (0..100)
+        .map(|n| Symbol::from(format!("key{n}")))
+        .collect::<Vec<_>>();
+    let mut table = keys
+        .iter()
+        .map(|key| (*key, Value::Num(1.0)))
+        .collect::<EnvTable>();
+    assert_eq!(table.len(), 100);
+
+    for key in &keys[..50] {
+        assert_eq!(table.remove(key), Some(Value::Num(1.0)));
+    }
+    assert_eq!(table.remove(&keys[0]), None);
+    assert!(!table.contains_key(&keys[0]));
+    assert!(table.contains_key(&keys[50]));
+    assert_eq!(table.len(), 50);
+    assert_eq!(table.keys().count(), 50);
+
+    // removing and inserting again reuses the tombstones, without losing any key
+    for _ in 0..10 {
+        for key in &keys[..50] {
+            table.insert(*key, Value::Nil);
+            table.remove(key);
+        }
+    }
+    table.extend(keys[..50].iter().map(|key| (*key, Value::Nil)));
+    assert_eq!(table.len(), 100);
+    assert!(keys.iter().all(|key| table.contains_key(key)));
+}
+
+#[test]
+fn update_items_through_entries() {
+    let mut table = EnvTable::new();
+    let key = Symbol::from("count");
+
+    for _ in 0..3 {
+        let count = table
+            .entry(key)
+            .and_modify(|n| *n = (n.clone() + Value::Num(1.0)).unwrap())
+            .or_insert(Value::Num(1.0));
+        assert!(matches!(count, Value::Num(_)));
+    }
+    assert_eq!(table.get_str("count"), Some(Value::Num(3.0)));
+    assert_eq!(table.len(), 1);
+
+    assert_eq!(table.entry(key).insert(Value::Nil), Some(Value::Num(3.0)));
+    assert_eq!(table.entry(Symbol::from("other")).get(), None);
+    assert_eq!(table.len(), 1);
+
+    *table.get_mut(&key).unwrap() = Value::Bool(true);
+    assert_eq!(table.get(&key), Some(Value::Bool(true)));
+    assert_eq!(table.get_str("never a key anywhere"), None);
+}
@@ -1,13 +1,19 @@
-use std::{fmt, io};
+use std::{fmt, io, sync::Arc};
 
-use crate::{raise_err, Symbol};
+use crate::{literal::sendable::Sendable, raise_err, Symbol};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct InterpretError {
     pub msg: String,
     pub err: Symbol,
     pub line: usize,
     pub column: usize,
+    /// The functions the error was raised out of, the innermost first, filled in as it leaves
+    /// each one
+    pub trace: Vec<TraceEntry>,
+    /// The value thrown with the error, if it has one, copied so the error can still be sent to
+    /// other threads, see [`InterpretError::exception`]
+    pub data: Option<Arc<Sendable>>,
 }
 
 impl fmt::Display for InterpretError {
@@ -16,13 +22,40 @@ impl fmt::Display for InterpretError {
             f,
             "[{}:{}] :{}\n  {}",
             self.line, self.column, self.err, self.msg
-        )
+        )?;
+        for entry in &self.trace {
+            write!(f, "\n    {entry}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A function that was running when an error was raised, with the instruction it was running,
+/// which is the call to the next function of the trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The name the function is bound to, `<fn>` if it has none, or `<main>` for the code outside
+    /// of any function
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+    /// How many times the same entry follows it, when a function recurses from the same place
+    pub repeated: usize,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "in {} at {}:{}", self.name, self.line, self.column)?;
+        if self.repeated > 0 {
+            write!(f, " (repeated {} more times)", self.repeated)?;
+        }
+        Ok(())
     }
 }
 
 impl From<io::Error> for InterpretError {
     fn from(_: io::Error) -> Self {
-        raise_err!(IOError, "Internal IO error")
+        raise_err!(IoError, "Internal IO error")
     }
 }
 
@@ -0,0 +1,44 @@
+//! Custom instructions, installed by embedders that generate their own bytecode
+use crate::{error::InterpretResult, raise, Value, VirtualMachine};
+
+/// Runs an [`crate::OpCode::Ext`] instruction, receiving the values it pops, in the order they
+/// were pushed, and returning the value it pushes
+pub type Extension = fn(&mut VirtualMachine, Vec<Value>) -> InterpretResult<Value>;
+
+impl VirtualMachine {
+    /// Installs (or removes, with `None`) the handler of the `Ext` instructions with the given id
+    pub fn set_extension(&mut self, id: u8, extension: Option<Extension>) {
+        self.extensions[usize::from(id)] = extension;
+    }
+
+    /// Runs the extension with the given id, popping `arity` values for it
+    pub(crate) fn run_extension(&mut self, id: u8, arity: usize) -> InterpretResult<()> {
+        let Some(extension) = self.extensions[usize::from(id)] else {
+            return raise!(ExtensionError, "No extension installed for the id {}", id);
+        };
+
+        let mut args = (0..arity).map(|_| self.pop()).collect::<Vec<_>>();
+        args.reverse();
+        let value = extension(self, args)?;
+        self.push(value);
+        Ok(())
+    }
+}
+
+#[test]
+fn run_extensions() {
+    use crate::{OpCode, OpCodeMetadata};
+
+    let mut vm = VirtualMachine::default();
+    vm.set_consts(vec![Value::Num(2.0), Value::Num(3.0)]);
+    vm.set_extension(7, Some(|_, args| args[0].clone() - args[1].clone()));
+
+    let bytecode = [OpCode::Push(0), OpCode::Push(1), OpCode::Ext(7, 2)]
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .to_vec();
+    vm.run(&bytecode).unwrap();
+    assert_eq!(vm.pop_last(), &Value::Num(-1.0));
+
+    let err = vm.run(&[OpCodeMetadata::new(1, 1, OpCode::Ext(8, 0))].to_vec());
+    assert_eq!(err.unwrap_err().err.as_str(), "ExtensionError");
+}
@@ -0,0 +1,52 @@
+//! The frames of the running functions. Calling a function compiled from yex pushes a frame and
+//! keeps running in the same loop, instead of running the function in a new Rust call, so a
+//! program can recurse as deep as the recursion limit allows without overflowing the host's stack
+use crate::{error::InterpretError, gc::GcRef, Fn, FnKind, OpCodeMetadata};
+
+/// A function running in the interpreter loop, the callers of the running one wait in
+/// `VirtualMachine::frames`
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CallFrame {
+    /// The body of the function, `None` for the code the loop started with
+    pub body: Option<GcRef<FnKind>>,
+    /// The instruction to run next
+    pub ip: usize,
+    /// How many locals the frame has, the last ones in use
+    pub locals: usize,
+    /// The size of the stack when the frame started, after its arguments
+    pub stack_base: usize,
+    /// The addresses of the enclosing `try` blocks, with the number of scopes open when they
+    /// started
+    pub try_stack: Vec<(usize, usize)>,
+    /// The number of locals the frame had when each of its open scopes was entered
+    pub scopes: Vec<usize>,
+    /// If the frame belongs to a generator, and so is allowed to `yield`
+    pub generator: bool,
+    /// The function running in the frame, which `recur` refers to and `Loac` reads the captures of
+    pub callee: Option<GcRef<Fn>>,
+    /// The last error caught by a `Try`, which `Rethrow` raises again
+    pub caught: Option<InterpretError>,
+}
+
+impl CallFrame {
+    /// A frame running `fun` from its first instruction
+    pub fn new(fun: GcRef<Fn>, stack_base: usize) -> Self {
+        Self {
+            body: Some(fun.body.clone()),
+            stack_base,
+            callee: Some(fun),
+            ..Self::default()
+        }
+    }
+
+    /// The instructions of the frame, which are `code` if it's the one the loop started with
+    pub fn bytecode<'a>(
+        body: Option<&'a GcRef<FnKind>>,
+        code: &'a [OpCodeMetadata],
+    ) -> &'a [OpCodeMetadata] {
+        match body.map(|it| &**it) {
+            Some(FnKind::Bytecode(ops) | FnKind::Generator(ops)) => ops,
+            _ => code,
+        }
+    }
+}
@@ -1,29 +1,264 @@
-use std::{cell::Cell, fmt::Debug, ptr::NonNull};
+//! Shared values, counted by reference and freed when their last reference is dropped. [`collect`]
+//! frees the cycles between them, which their counts keep alive forever, like a type whose
+//! nullary variants point back to it
+//!
+//! Every shared value but a type is immutable once built, so it can only point to values built
+//! before it, and any cycle between them goes through a type. So only the types are tracked, and
+//! the collector marks and sweeps the values reachable from them.
+//!
+//! The collector doesn't enumerate its roots, the stack, the locals, the globals and the
+//! constants of a virtual machine, since it can't know every holder of a value: the values are
+//! shared by all the virtual machines of a thread, and native functions hold them in Rust while
+//! they run. Instead, the roots are found by subtracting the references between the values from
+//! their counts, like a trial deletion, and whatever is left comes from outside of them, from a
+//! virtual machine or from Rust. So collecting is safe at any point, and the values a native
+//! function is working on are never freed under it.
+//!
+//! The limitation is that a cycle closed outside of the shared values is never collected, since
+//! its references look like roots. The fibers, channels, generators and promises of a virtual
+//! machine are kept in its tables, not shared, so a channel holding a message with its own handle,
+//! or a fiber whose state holds its own handle, stays alive until the virtual machine is dropped.
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Debug,
+    mem::ManuallyDrop,
+    ptr::NonNull,
+};
 
+/// Values that can hold references to other shared values
+pub trait Trace {
+    /// If the values of the type can hold references, the collector only follows those
+    const TRACED: bool = false;
+    /// If the values of the type can be changed after they are shared, and so close a cycle, the
+    /// collector starts from those
+    const CYCLIC: bool = false;
+
+    /// Calls `visit` with every reference the value holds, a reference the collector doesn't see
+    /// keeps the value it points to alive
+    fn trace(&self, _visit: &mut dyn FnMut(Traced)) {}
+}
+
+const TRACKED: u8 = 1;
+const REACHED: u8 = 2;
+const MARKED: u8 = 4;
+/// Set once the contents of the value are dropped, or are about to be, a tracked value stays
+/// allocated until the next collection, which frees it
+const DEAD: u8 = 8;
+
+/// What the collector knows about a shared value, it's stored before the value itself
+struct Header {
+    count: Cell<usize>,
+    flags: Cell<u8>,
+}
+
+impl Header {
+    fn is(&self, flag: u8) -> bool {
+        self.flags.get() & flag != 0
+    }
+
+    /// Sets or clears a flag, returning if it was set
+    fn set(&self, flag: u8, on: bool) -> bool {
+        let was = self.is(flag);
+        let flags = self.flags.get() & !flag;
+        self.flags.set(if on { flags | flag } else { flags });
+        was
+    }
+}
+
+struct VTable {
+    trace: unsafe fn(NonNull<Header>, &mut dyn FnMut(Traced)),
+    drop_value: unsafe fn(NonNull<Header>),
+    free: unsafe fn(NonNull<Header>),
+}
+
+/// A reference the collector follows, with the functions that handle the value it points to
+#[derive(Clone, Copy)]
+pub struct Traced {
+    header: NonNull<Header>,
+    vtable: &'static VTable,
+}
+
+impl Traced {
+    fn header(&self) -> &Header {
+        unsafe { self.header.as_ref() }
+    }
+
+    fn trace(&self, visit: &mut dyn FnMut(Traced)) {
+        unsafe { (self.vtable.trace)(self.header, visit) }
+    }
+}
+
+#[repr(C)]
 struct Ref<T> {
-    pub(in crate::gc) inner: T,
-    pub(in crate::gc) count: Cell<usize>,
+    header: Header,
+    inner: ManuallyDrop<T>,
+}
+
+impl<T: Trace> Ref<T> {
+    const VTABLE: VTable = VTable {
+        trace: |header, visit| unsafe { header.cast::<Self>().as_ref().inner.trace(visit) },
+        drop_value: |header| unsafe {
+            ManuallyDrop::drop(&mut (*header.cast::<Self>().as_ptr()).inner);
+        },
+        free: |header| unsafe { drop(Box::from_raw(header.cast::<Self>().as_ptr())) },
+    };
 }
 
 pub struct GcRef<T> {
     inner: NonNull<Ref<T>>,
 }
 
-impl<T> GcRef<T> {
-    pub fn new(constant: T) -> Self {
-        // SAFETY:
-        // We pass the box to into_raw after the allocation, everything is properly aligned and
-        // nothing can be null
-        unsafe {
-            Self {
-                inner: NonNull::new_unchecked(Box::into_raw(Box::new(Ref {
-                    inner: constant,
-                    count: Cell::new(1),
-                }))),
+/// How many values are allocated between two collections, at least
+const MIN_THRESHOLD: usize = 100_000;
+
+/// The tracked values of a thread, including the dead ones that weren't freed yet
+struct Tracked(RefCell<Vec<Traced>>);
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        // the values still alive when the thread exits are freed when they are dropped
+        for it in self.0.get_mut().drain(..) {
+            if it.header().set(TRACKED, false) && it.header().is(DEAD) {
+                unsafe { (it.vtable.free)(it.header) };
             }
         }
     }
+}
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    /// How many values can be allocated before the next collection
+    static BUDGET: Cell<usize> = const { Cell::new(MIN_THRESHOLD) };
+    static COLLECTING: Cell<bool> = const { Cell::new(false) };
+    static TRACKED_VALUES: Tracked = const { Tracked(RefCell::new(Vec::new())) };
+}
+
+/// How many values were allocated with [`GcRef::new`] on this thread
+#[must_use]
+pub fn allocations() -> u64 {
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// Checks if enough values were allocated since the last collection to run another one
+pub(crate) fn should_collect() -> bool {
+    BUDGET.with(Cell::get) == 0
+}
+
+/// Frees the values of this thread that are only referenced by each other, returning how many
+/// were freed
+#[must_use]
+pub fn collect() -> usize {
+    if COLLECTING.with(|it| it.replace(true)) {
+        return 0;
+    }
+
+    // the tracked values dropped since the last collection are freed first
+    let mut tracked = TRACKED_VALUES.with(|it| it.0.take());
+    tracked.retain(|it| {
+        let dead = it.header().is(DEAD);
+        if dead {
+            unsafe { (it.vtable.free)(it.header) };
+        }
+        !dead
+    });
+
+    let mut values = tracked.clone();
+    let mut pending = tracked;
+    for it in &values {
+        it.header().set(REACHED, true);
+    }
+    while let Some(it) = pending.pop() {
+        it.trace(&mut |child| {
+            if !child.header().set(REACHED, true) {
+                values.push(child);
+                pending.push(child);
+            }
+        });
+    }
 
+    // the references left after subtracting the ones between the values come from the roots
+    let count = |it: Traced, by: isize| {
+        let count = &it.header().count;
+        count.set(count.get().wrapping_add_signed(by));
+    };
+    for it in &values {
+        it.trace(&mut |child| count(child, -1));
+    }
+    pending.extend(values.iter().filter(|it| it.header().count.get() > 0));
+    for it in &pending {
+        it.header().set(MARKED, true);
+    }
+    while let Some(it) = pending.pop() {
+        it.trace(&mut |child| {
+            if !child.header().set(MARKED, true) {
+                pending.push(child);
+            }
+        });
+    }
+    for it in &values {
+        it.trace(&mut |child| count(child, 1));
+    }
+
+    let reached = values.len();
+    let (alive, garbage): (Vec<_>, Vec<_>) = values.into_iter().partition(|it| {
+        it.header().set(REACHED, false);
+        it.header().set(MARKED, false)
+    });
+
+    // the values in the cycles are only freed after all of them are dropped, since dropping one
+    // drops its references to the others
+    for it in &garbage {
+        it.header().set(DEAD, true);
+    }
+    for it in &garbage {
+        unsafe { (it.vtable.drop_value)(it.header) };
+    }
+    for it in &garbage {
+        it.header().set(TRACKED, false);
+        unsafe { (it.vtable.free)(it.header) };
+    }
+
+    TRACKED_VALUES.with(|it| {
+        let alive = alive.into_iter().filter(|it| it.header().is(TRACKED));
+        it.0.borrow_mut().extend(alive);
+    });
+    BUDGET.with(|it| it.set(MIN_THRESHOLD.max(reached)));
+    COLLECTING.with(|it| it.set(false));
+    garbage.len()
+}
+
+impl<T: Trace> GcRef<T> {
+    pub fn new(constant: T) -> Self {
+        let inner = NonNull::from(Box::leak(Box::new(Ref {
+            header: Header {
+                count: Cell::new(1),
+                flags: Cell::new(0),
+            },
+            inner: ManuallyDrop::new(constant),
+        })));
+
+        ALLOCATIONS.with(|it| it.set(it.get() + 1));
+        BUDGET.with(|it| it.set(it.get().saturating_sub(1)));
+        let this = Self { inner };
+        if T::CYCLIC {
+            // the values allocated while the thread is exiting aren't tracked
+            let _ = TRACKED_VALUES.try_with(|tracked| {
+                tracked.0.borrow_mut().push(this.traced());
+                this.header().set(TRACKED, true);
+            });
+        }
+        this
+    }
+
+    fn traced(&self) -> Traced {
+        Traced {
+            header: NonNull::from(self.header()),
+            vtable: &Ref::<T>::VTABLE,
+        }
+    }
+}
+
+impl<T> GcRef<T> {
     pub unsafe fn mut_ref(&mut self) -> &mut T {
         &mut (*self.inner.as_ptr()).inner
     }
@@ -32,25 +267,55 @@ impl<T> GcRef<T> {
         Self { inner }
     }
 
+    fn header(&self) -> &Header {
+        unsafe { &self.inner.as_ref().header }
+    }
+
     fn inc_ref(&self) {
-        unsafe {
-            let ref_count = self.ref_count();
-            self.inner.as_ref().count.set(ref_count + 1);
-        }
+        let ref_count = self.ref_count();
+        self.header().count.set(ref_count + 1);
     }
 
     fn dec_ref(&self) {
-        unsafe {
-            let ref_count = self.ref_count();
-            self.inner.as_ref().count.set(ref_count - 1);
-        }
+        let ref_count = self.ref_count();
+        self.header().count.set(ref_count - 1);
+    }
+
+    /// Checks if this is the only reference to the value
+    #[must_use]
+    pub fn is_unique(&self) -> bool {
+        self.ref_count() == 1 && !self.header().is(DEAD)
     }
 
     fn ref_count(&self) -> usize {
-        unsafe { self.inner.as_ref().count.get() }
+        self.header().count.get()
+    }
+
+    /// Checks if both references point to the same value
+    #[must_use]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.inner == other.inner
+    }
+}
+
+impl<T: Trace> Trace for GcRef<T> {
+    fn trace(&self, visit: &mut dyn FnMut(Traced)) {
+        if T::TRACED {
+            visit(self.traced());
+        }
+    }
+}
+
+impl<T: Trace> Trace for Option<T> {
+    fn trace(&self, visit: &mut dyn FnMut(Traced)) {
+        if let Some(it) = self {
+            it.trace(visit);
+        }
     }
 }
 
+impl Trace for String {}
+
 impl<T> Clone for GcRef<T> {
     #[inline]
     fn clone(&self) -> Self {
@@ -72,8 +337,17 @@ impl<T> Drop for GcRef<T> {
     fn drop(&mut self) {
         self.dec_ref();
 
-        if self.ref_count() == 0 {
-            unsafe { drop(Box::from_raw(self.inner.as_ptr())) };
+        // the tracked values, and the ones of a cycle being collected, are freed by the collector
+        let header = self.header();
+        if header.count.get() == 0 && !header.set(DEAD, true) {
+            if header.is(TRACKED) {
+                unsafe { ManuallyDrop::drop(&mut (*self.inner.as_ptr()).inner) };
+            } else {
+                unsafe {
+                    let mut boxed = Box::from_raw(self.inner.as_ptr());
+                    ManuallyDrop::drop(&mut boxed.inner);
+                }
+            }
         }
     }
 }
@@ -91,3 +365,36 @@ impl<T: Debug> Debug for GcRef<T> {
         write!(f, "{:#?}", **self)
     }
 }
+
+#[test]
+fn collect_cycles() {
+    use crate::{Symbol, Tuple, Value, YexModule};
+
+    let cycle = || {
+        let mut module = GcRef::new(YexModule::default());
+        let instance = Value::Tagged(module.clone(), Symbol::new("T.nil"), Tuple::from(vec![]));
+        unsafe { module.mut_ref() }
+            .fields
+            .insert(Symbol::new("nil"), instance);
+        module
+    };
+
+    // the module and the tuple of its nullary variant are only referenced by each other
+    drop(cycle());
+    assert_eq!(collect(), 2);
+    assert_eq!(collect(), 0);
+
+    // the values held by Rust code are roots, and so are the ones they point to
+    let module = cycle();
+    let tuple = Tuple::from(vec![Value::Module(module.clone())]);
+    drop(module);
+    assert_eq!(collect(), 0);
+    let Value::Module(module) = &tuple.0[0] else {
+        panic!("expected the module to be alive");
+    };
+    assert!(module.fields.get(&Symbol::new("nil")).is_some());
+
+    // the tuple isn't part of the cycle, so it's dropped when its last reference is
+    drop(tuple);
+    assert_eq!(collect(), 2);
+}
@@ -0,0 +1,952 @@
+//! Compiles hot functions to native code with Cranelift, enabled by the `jit` feature
+//!
+//! Only functions that work on numbers are compiled: their bytecode is checked to only use
+//! arithmetic, comparisons, locals, jumps and calls to themselves, with every value being a float,
+//! an integer or a boolean. A function is compiled for each of the argument types it's called
+//! with, anything else keeps running on the interpreter.
+use std::{
+    collections::{hash_map, HashMap},
+    ptr,
+};
+
+use cranelift_codegen::{
+    entity::EntityRef,
+    ir::{
+        self,
+        condcodes::{FloatCC, IntCC},
+        types, AbiParam, Block, InstBuilder, MemFlags, StackSlotData, StackSlotKind,
+    },
+    Context,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+use crate::{
+    error::InterpretResult,
+    literal::{
+        fun::FnBody,
+        num::{self, Overflow},
+    },
+    Bytecode, Fn, FnKind, OpCode, Operand, Operator, Symbol, Value, VirtualMachine,
+};
+
+/// How many times a function is called before being compiled
+const HOT_CALLS: usize = 100;
+
+/// How many calls deep compiled code can go on the host stack, the interpreter does the calls
+/// past it, which push frames instead
+const NATIVE_DEPTH: usize = 1000;
+
+/// Compiled functions receive a pointer to their arguments, a flag and how many calls deep they
+/// can still go. The flag is set to [`BAIL`] when the function can't continue natively (e.g
+/// comparing NaN or an integer overflow, which raise an error), in that case the call is done
+/// again by the interpreter, or to [`TOO_DEEP`] when a call to itself would go past the maximum
+/// depth. The arguments and the result are the bits of the values, see [`to_bits`]
+type Code = extern "C" fn(*const u64, *mut u8, u64) -> u64;
+
+/// The flag of a call that has to be done again by the interpreter
+const BAIL: u8 = 0;
+/// The flag of a call that finished natively
+const DONE: u8 = 1;
+/// The flag of a call that went past the maximum depth, which raises a `RecursionError`
+const TOO_DEEP: u8 = 2;
+
+enum State {
+    Cold(usize),
+    /// The code compiled for each signature it was called with, `None` if it couldn't be
+    Hot(HashMap<Signature, Option<Compiled>>),
+}
+
+/// What a function is compiled for: the types of its arguments, and if the integer operators
+/// wrap, otherwise their overflow is left to the interpreter
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Signature {
+    args: Vec<Ty>,
+    wrap: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Compiled {
+    code: Code,
+    /// The type of the result
+    ret: Ty,
+}
+
+struct Entry {
+    // keeps the function alive, so its address isn't reused by another function
+    _body: FnBody,
+    state: State,
+}
+
+/// The compiled functions and the call counters of the ones that weren't compiled yet
+#[derive(Default)]
+pub(crate) struct Jit {
+    module: Option<JITModule>,
+    functions: HashMap<*const FnKind, Entry>,
+    /// The depths where the calls are left to the interpreter, below a call that couldn't finish
+    /// natively, so they don't each go as deep as it did again
+    interpreted: std::ops::Range<usize>,
+}
+
+/// The type of a value on the stack of a function being compiled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Ty {
+    Num,
+    Int,
+    Bool,
+    /// The function itself, which can only be called
+    Recur,
+}
+
+/// The types of the stack and of the locals before an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Types {
+    stack: Vec<Ty>,
+    locals: Vec<Option<Ty>>,
+}
+
+fn pop(stack: &mut Vec<Ty>, ty: Ty) -> Option<()> {
+    (stack.pop()? == ty).then_some(())
+}
+
+fn const_ty(value: &Value) -> Option<Ty> {
+    match value {
+        Value::Num(_) => Some(Ty::Num),
+        Value::Int(_) => Some(Ty::Int),
+        Value::Bool(_) => Some(Ty::Bool),
+        _ => None,
+    }
+}
+
+fn is_number(ty: Ty) -> bool {
+    matches!(ty, Ty::Num | Ty::Int)
+}
+
+/// The type of the result of a binary operator, if it can be compiled
+fn binary_ty(operator: Operator, left: Ty, right: Ty) -> Option<Ty> {
+    match operator {
+        Operator::Add | Operator::Sub | Operator::Mul if (left, right) == (Ty::Int, Ty::Int) => {
+            Some(Ty::Int)
+        }
+        // an integer mixed with a float is converted, and dividing integers gives a float
+        Operator::Add | Operator::Sub | Operator::Mul | Operator::Div
+            if is_number(left) && is_number(right) =>
+        {
+            Some(Ty::Num)
+        }
+        Operator::Less | Operator::LessEq if left == right && is_number(left) => Some(Ty::Bool),
+        Operator::Eq if left == right && left != Ty::Recur => Some(Ty::Bool),
+        _ => None,
+    }
+}
+
+/// Computes the types before every reachable instruction of a function called with `args`,
+/// assuming it returns `ret`, returns `None` if the function uses something that can't be
+/// compiled
+fn infer(
+    bytecode: &Bytecode,
+    constants: &[Value],
+    args: &[Ty],
+    ret: Ty,
+    is_self: &dyn std::ops::Fn(Symbol) -> bool,
+) -> Option<Vec<Option<Types>>> {
+    let arity = args.len();
+    let entry = Types {
+        stack: args.to_vec(),
+        locals: vec![],
+    };
+
+    let mut types = vec![None; bytecode.len()];
+    types[0] = Some(entry.clone());
+    let mut queue = vec![0];
+
+    while let Some(ip) = queue.pop() {
+        let mut state = types[ip].clone()?;
+        let stack = &mut state.stack;
+        let mut next = vec![ip + 1];
+
+        match bytecode[ip].opcode {
+            OpCode::Nop | OpCode::Drop(_) => (),
+            OpCode::Push(index) => stack.push(const_ty(constants.get(index)?)?),
+            OpCode::Pop => {
+                stack.pop()?;
+            }
+            OpCode::Dup => stack.push(*stack.last()?),
+            OpCode::Rev => {
+                let len = stack.len();
+                stack.get(len.checked_sub(2)?)?;
+                stack.swap(len - 1, len - 2);
+            }
+            OpCode::RevN(n) => {
+                let start = stack.len().checked_sub(n)?;
+                stack[start..].reverse();
+            }
+            OpCode::Load(offset) => stack.push((*state.locals.get(offset)?)?),
+            OpCode::Save(offset) => {
+                let ty = stack.pop()?;
+                if state.locals.len() <= offset {
+                    state.locals.resize(offset + 1, None);
+                }
+                state.locals[offset] = Some(ty);
+            }
+            OpCode::Loag(name) if is_self(name) => stack.push(Ty::Recur),
+            OpCode::Add
+            | OpCode::Sub
+            | OpCode::Mul
+            | OpCode::Div
+            | OpCode::Eq
+            | OpCode::Less
+            | OpCode::LessEq => {
+                let right = stack.pop()?;
+                let left = stack.pop()?;
+                let operator = Operator::try_from(bytecode[ip].opcode).ok()?;
+                stack.push(binary_ty(operator, left, right)?);
+            }
+            OpCode::Bin(operator, left, right) => {
+                let mut ty = |operand| match operand {
+                    Operand::Stack => stack.pop(),
+                    Operand::Local(index) => *state.locals.get(index as usize)?,
+                    Operand::Const(index) => const_ty(constants.get(index as usize)?),
+                };
+                let (right, left) = (ty(right)?, ty(left)?);
+                stack.push(binary_ty(operator, left, right)?);
+            }
+            OpCode::Neg => {
+                let ty = stack.pop()?;
+                stack.push(Some(ty).filter(|ty| is_number(*ty))?);
+            }
+            OpCode::Not => {
+                pop(stack, Ty::Bool)?;
+                stack.push(Ty::Bool);
+            }
+            OpCode::Jmf(to) => {
+                pop(stack, Ty::Bool)?;
+                next.push(to);
+            }
+            OpCode::Jmp(to) => next = vec![to],
+            OpCode::Call(n) if n == arity => {
+                pop(stack, Ty::Recur)?;
+                for ty in args.iter().rev() {
+                    pop(stack, *ty)?;
+                }
+                stack.push(ret);
+            }
+            // a tail call starts the function over, so only the arguments can be on the stack
+            OpCode::TCall(n) if n == arity => {
+                pop(stack, Ty::Recur)?;
+                if *stack != entry.stack {
+                    return None;
+                }
+                state = entry.clone();
+                next = vec![0];
+            }
+            _ => return None,
+        }
+
+        for to in next {
+            // falling or jumping out of the bytecode returns the stack top
+            if to >= bytecode.len() {
+                if state.stack != [ret] {
+                    return None;
+                }
+                continue;
+            }
+
+            match &mut types[to] {
+                slot @ None => {
+                    *slot = Some(state.clone());
+                    queue.push(to);
+                }
+                Some(old) => {
+                    if old.stack != state.stack {
+                        return None;
+                    }
+
+                    // a local is only known if it has the same type in every path
+                    let mut changed = false;
+                    for (i, ty) in old.locals.iter_mut().enumerate() {
+                        if ty.is_some() && state.locals.get(i).copied().flatten() != *ty {
+                            *ty = None;
+                            changed = true;
+                        }
+                    }
+                    if changed {
+                        queue.push(to);
+                    }
+                }
+            }
+        }
+    }
+
+    Some(types)
+}
+
+/// Where basic blocks start: the entry, jump targets and the instructions after jumps
+fn block_starts(bytecode: &Bytecode) -> Vec<usize> {
+    let mut starts = vec![0];
+    for (ip, op) in bytecode.iter().enumerate() {
+        match op.opcode {
+            OpCode::Jmf(to) | OpCode::Jmp(to) => starts.extend([to, ip + 1]),
+            OpCode::TCall(_) => starts.push(ip + 1),
+            _ => (),
+        }
+    }
+    starts.retain(|ip| *ip < bytecode.len());
+    starts.sort_unstable();
+    starts.dedup();
+    starts
+}
+
+/// The offset of the `i`th argument of a compiled function
+fn offset(i: usize) -> i32 {
+    i32::try_from(i * 8).unwrap()
+}
+
+fn new_module() -> Option<JITModule> {
+    let builder =
+        JITBuilder::with_flags(&[("opt_level", "speed")], default_libcall_names()).ok()?;
+    Some(JITModule::new(builder))
+}
+
+/// The bits of a value as compiled code holds it, floats as their IEEE 754 bits, integers as
+/// their two's complement and booleans as 0 or 1
+fn to_bits(value: &Value) -> Option<(u64, Ty)> {
+    match value {
+        Value::Num(n) => Some((n.to_bits(), Ty::Num)),
+        Value::Int(n) => Some((n.cast_unsigned(), Ty::Int)),
+        Value::Bool(b) => Some((u64::from(*b), Ty::Bool)),
+        _ => None,
+    }
+}
+
+/// The value of the bits of a `ty`, the inverse of [`to_bits`]
+fn from_bits(bits: u64, ty: Ty) -> Value {
+    match ty {
+        Ty::Num => Value::Num(f64::from_bits(bits)),
+        Ty::Int => Value::Int(bits.cast_signed()),
+        _ => Value::Bool(bits != 0),
+    }
+}
+
+/// Reads a number as a float, converting integers
+fn float(b: &mut FunctionBuilder, x: ir::Value, ty: Ty) -> ir::Value {
+    if ty == Ty::Int {
+        b.ins().fcvt_from_sint(types::F64, x)
+    } else {
+        b.ins().bitcast(types::F64, MemFlags::new(), x)
+    }
+}
+
+/// A comparison's result as a boolean
+fn boolean(b: &mut FunctionBuilder, cmp: ir::Value) -> ir::Value {
+    b.ins().uextend(types::I64, cmp)
+}
+
+/// Emits a binary operator on operands of the given types, comparisons jump to `bail` if the
+/// operands are NaN, and the integer operators if they overflow, unless they `wrap`
+fn binary(
+    b: &mut FunctionBuilder,
+    operator: Operator,
+    (x, left): (ir::Value, Ty),
+    (y, right): (ir::Value, Ty),
+    wrap: bool,
+    bail: Block,
+) -> ir::Value {
+    match (left, right) {
+        (Ty::Int, Ty::Int) if operator != Operator::Div => {
+            int_binary(b, operator, x, y, wrap, bail)
+        }
+        (Ty::Bool, Ty::Bool) => {
+            let cmp = b.ins().icmp(IntCC::Equal, x, y);
+            boolean(b, cmp)
+        }
+        _ => {
+            let (x, y) = (float(b, x, left), float(b, y, right));
+            float_binary(b, operator, x, y, bail)
+        }
+    }
+}
+
+fn int_binary(
+    b: &mut FunctionBuilder,
+    operator: Operator,
+    x: ir::Value,
+    y: ir::Value,
+    wrap: bool,
+    bail: Block,
+) -> ir::Value {
+    let cc = match operator {
+        Operator::Add if wrap => return b.ins().iadd(x, y),
+        Operator::Sub if wrap => return b.ins().isub(x, y),
+        Operator::Mul if wrap => return b.ins().imul(x, y),
+        Operator::Add | Operator::Sub | Operator::Mul => {
+            let (value, overflow) = match operator {
+                Operator::Add => b.ins().sadd_overflow(x, y),
+                Operator::Sub => b.ins().ssub_overflow(x, y),
+                _ => b.ins().smul_overflow(x, y),
+            };
+
+            // the interpreter raises the error, or saturates the result
+            let fits = b.create_block();
+            b.ins().brif(overflow, bail, &[], fits, &[]);
+            b.switch_to_block(fits);
+            return value;
+        }
+        Operator::Div | Operator::Rem => unreachable!(),
+        Operator::Eq => IntCC::Equal,
+        Operator::Less => IntCC::SignedLessThan,
+        Operator::LessEq => IntCC::SignedLessThanOrEqual,
+    };
+
+    let cmp = b.ins().icmp(cc, x, y);
+    boolean(b, cmp)
+}
+
+fn float_binary(
+    b: &mut FunctionBuilder,
+    operator: Operator,
+    x: ir::Value,
+    y: ir::Value,
+    bail: Block,
+) -> ir::Value {
+    let value = match operator {
+        Operator::Add => b.ins().fadd(x, y),
+        Operator::Sub => b.ins().fsub(x, y),
+        Operator::Mul => b.ins().fmul(x, y),
+        Operator::Div => b.ins().fdiv(x, y),
+        Operator::Rem => unreachable!(),
+        Operator::Eq => {
+            let cmp = b.ins().fcmp(FloatCC::Equal, x, y);
+            return boolean(b, cmp);
+        }
+        Operator::Less | Operator::LessEq => {
+            // comparing NaN raises an error, which is left to the interpreter
+            let nan = b.ins().fcmp(FloatCC::Unordered, x, y);
+            let ordered = b.create_block();
+            b.ins().brif(nan, bail, &[], ordered, &[]);
+            b.switch_to_block(ordered);
+
+            let cc = if operator == Operator::Less {
+                FloatCC::LessThan
+            } else {
+                FloatCC::LessThanOrEqual
+            };
+            let cmp = b.ins().fcmp(cc, x, y);
+            return boolean(b, cmp);
+        }
+    };
+
+    b.ins().bitcast(types::I64, MemFlags::new(), value)
+}
+
+/// Compiles a function to native code for `signature`, `is_self` tells if a global is the
+/// function itself
+fn compile(
+    module: &mut JITModule,
+    bytecode: &Bytecode,
+    constants: &[Value],
+    signature: &Signature,
+    is_self: &dyn std::ops::Fn(Symbol) -> bool,
+) -> Option<Compiled> {
+    let arity = signature.args.len();
+    let (types, ret) = [Ty::Int, Ty::Num, Ty::Bool].into_iter().find_map(|ret| {
+        Some((
+            infer(bytecode, constants, &signature.args, ret, is_self)?,
+            ret,
+        ))
+    })?;
+
+    let max_stack = types.iter().flatten().map(|it| it.stack.len() + 1).max()?;
+    let max_locals = types.iter().flatten().map(|it| it.locals.len()).max()?;
+    let stack_var = Variable::new;
+    let local_var = |offset| Variable::new(max_stack + offset);
+
+    let ptr_ty = module.target_config().pointer_type();
+    let mut ctx = Context::new();
+    ctx.func.signature.params.push(AbiParam::new(ptr_ty));
+    ctx.func.signature.params.push(AbiParam::new(ptr_ty));
+    ctx.func.signature.params.push(AbiParam::new(types::I64));
+    ctx.func.signature.returns.push(AbiParam::new(types::I64));
+
+    let id = module
+        .declare_anonymous_function(&ctx.func.signature)
+        .ok()?;
+
+    let mut fn_ctx = FunctionBuilderContext::new();
+    let mut b = FunctionBuilder::new(&mut ctx.func, &mut fn_ctx);
+    let this = module.declare_func_in_func(id, b.func);
+
+    for i in 0..max_stack + max_locals {
+        b.declare_var(Variable::new(i), types::I64);
+    }
+
+    let entry = b.create_block();
+    let exit = b.create_block();
+    let bail = b.create_block();
+    let too_deep = b.create_block();
+    // a call to itself that didn't finish returns right away, keeping the flag it set
+    let unwind = b.create_block();
+    let blocks: HashMap<usize, Block> = block_starts(bytecode)
+        .into_iter()
+        .filter(|ip| types[*ip].is_some())
+        .map(|ip| (ip, b.create_block()))
+        .collect();
+    let target = |ip: usize| blocks.get(&ip).copied().unwrap_or(exit);
+
+    b.append_block_params_for_function_params(entry);
+    b.switch_to_block(entry);
+    let (args, ok, room) = (
+        b.block_params(entry)[0],
+        b.block_params(entry)[1],
+        b.block_params(entry)[2],
+    );
+    for i in 0..arity {
+        let arg = b
+            .ins()
+            .load(types::I64, MemFlags::trusted(), args, offset(i));
+        b.def_var(stack_var(i), arg);
+    }
+    b.ins().jump(blocks[&0], &[]);
+
+    // the arguments of recursive calls
+    let slot = b.create_sized_stack_slot(StackSlotData::new(
+        StackSlotKind::ExplicitSlot,
+        (arity.max(1) * 8) as u32,
+        3,
+    ));
+
+    let mut filled = true;
+    for (ip, op) in bytecode.iter().enumerate() {
+        if let Some(&block) = blocks.get(&ip) {
+            if !filled {
+                b.ins().jump(block, &[]);
+            }
+            b.switch_to_block(block);
+            filled = false;
+        }
+
+        let Some(state) = &types[ip] else {
+            continue;
+        };
+        let top = state.stack.len();
+
+        match op.opcode {
+            OpCode::Push(index) => {
+                let value = constant(&mut b, &constants[index]);
+                b.def_var(stack_var(top), value);
+            }
+            OpCode::Dup => {
+                let value = b.use_var(stack_var(top - 1));
+                b.def_var(stack_var(top), value);
+            }
+            OpCode::Rev | OpCode::RevN(_) => {
+                let n = if let OpCode::RevN(n) = op.opcode {
+                    n
+                } else {
+                    2
+                };
+                let values = (top - n..top)
+                    .map(|i| b.use_var(stack_var(i)))
+                    .collect::<Vec<_>>();
+                for (i, value) in (top - n..top).zip(values.into_iter().rev()) {
+                    b.def_var(stack_var(i), value);
+                }
+            }
+            OpCode::Load(offset) => {
+                let value = b.use_var(local_var(offset));
+                b.def_var(stack_var(top), value);
+            }
+            OpCode::Save(offset) => {
+                let value = b.use_var(stack_var(top - 1));
+                b.def_var(local_var(offset), value);
+            }
+            OpCode::Add
+            | OpCode::Sub
+            | OpCode::Mul
+            | OpCode::Div
+            | OpCode::Eq
+            | OpCode::Less
+            | OpCode::LessEq => {
+                let x = (b.use_var(stack_var(top - 2)), state.stack[top - 2]);
+                let y = (b.use_var(stack_var(top - 1)), state.stack[top - 1]);
+                let operator = Operator::try_from(op.opcode).unwrap();
+                let value = binary(&mut b, operator, x, y, signature.wrap, bail);
+                b.def_var(stack_var(top - 2), value);
+            }
+            OpCode::Bin(operator, left, right) => {
+                let read = |b: &mut FunctionBuilder, operand| match operand {
+                    Operand::Stack => (b.use_var(stack_var(top - 1)), state.stack[top - 1]),
+                    Operand::Local(index) => (
+                        b.use_var(local_var(index as usize)),
+                        state.locals[index as usize].unwrap(),
+                    ),
+                    Operand::Const(index) => {
+                        let value = &constants[index as usize];
+                        (constant(b, value), const_ty(value).unwrap())
+                    }
+                };
+                let (y, x) = (read(&mut b, right), read(&mut b, left));
+                let value = binary(&mut b, operator, x, y, signature.wrap, bail);
+
+                // the result replaces the left operand if it was on the stack
+                let to = if left == Operand::Stack { top - 1 } else { top };
+                b.def_var(stack_var(to), value);
+            }
+            OpCode::Neg => {
+                let x = b.use_var(stack_var(top - 1));
+                let value = if state.stack[top - 1] == Ty::Int {
+                    let zero = b.ins().iconst(types::I64, 0);
+                    int_binary(&mut b, Operator::Sub, zero, x, signature.wrap, bail)
+                } else {
+                    let x = float(&mut b, x, Ty::Num);
+                    let value = b.ins().fneg(x);
+                    b.ins().bitcast(types::I64, MemFlags::new(), value)
+                };
+                b.def_var(stack_var(top - 1), value);
+            }
+            OpCode::Not => {
+                let x = b.use_var(stack_var(top - 1));
+                let value = b.ins().bxor_imm(x, 1);
+                b.def_var(stack_var(top - 1), value);
+            }
+            OpCode::Jmf(to) => {
+                let cond = b.use_var(stack_var(top - 1));
+                b.ins().brif(cond, target(ip + 1), &[], target(to), &[]);
+                filled = true;
+            }
+            OpCode::Jmp(to) => {
+                b.ins().jump(target(to), &[]);
+                filled = true;
+            }
+            OpCode::Call(n) => {
+                // the calls to itself count towards the maximum depth, like the interpreted ones
+                let call = b.create_block();
+                b.ins().brif(room, call, &[], too_deep, &[]);
+                b.switch_to_block(call);
+
+                // the arguments are below the function
+                let base = top - 1 - n;
+                for i in 0..n {
+                    let value = b.use_var(stack_var(base + i));
+                    b.ins().stack_store(value, slot, offset(i));
+                }
+                let args = b.ins().stack_addr(ptr_ty, slot, 0);
+                let inner_room = b.ins().iadd_imm(room, -1);
+                let call = b.ins().call(this, &[args, ok, inner_room]);
+                let value = b.inst_results(call)[0];
+
+                let done = b.create_block();
+                let flag = b.ins().load(types::I8, MemFlags::trusted(), ok, 0);
+                let finished = b.ins().icmp_imm(IntCC::Equal, flag, i64::from(DONE));
+                b.ins().brif(finished, done, &[], unwind, &[]);
+                b.switch_to_block(done);
+                b.def_var(stack_var(base), value);
+            }
+            OpCode::TCall(n) => {
+                let values = (top - 1 - n..top - 1)
+                    .map(|i| b.use_var(stack_var(i)))
+                    .collect::<Vec<_>>();
+                for (i, value) in values.into_iter().enumerate() {
+                    b.def_var(stack_var(i), value);
+                }
+                b.ins().jump(blocks[&0], &[]);
+                filled = true;
+            }
+            _ => (),
+        }
+    }
+
+    if !filled {
+        b.ins().jump(exit, &[]);
+    }
+
+    b.switch_to_block(exit);
+    let value = b.use_var(stack_var(0));
+    b.ins().return_(&[value]);
+
+    for (block, flag) in [(bail, BAIL), (too_deep, TOO_DEEP)] {
+        b.switch_to_block(block);
+        let flag = b.ins().iconst(types::I8, i64::from(flag));
+        b.ins().store(MemFlags::trusted(), flag, ok, 0);
+        b.ins().jump(unwind, &[]);
+    }
+
+    b.switch_to_block(unwind);
+    let value = b.ins().iconst(types::I64, 0);
+    b.ins().return_(&[value]);
+
+    b.seal_all_blocks();
+    b.finalize();
+
+    module.define_function(id, &mut ctx).ok()?;
+    module.finalize_definitions().ok()?;
+
+    // SAFETY: the function was compiled with the signature of `Code`
+    let code = unsafe { std::mem::transmute::<*const u8, Code>(module.get_finalized_function(id)) };
+    Some(Compiled { code, ret })
+}
+
+/// Emits a constant, see [`to_bits`]
+fn constant(b: &mut FunctionBuilder, value: &Value) -> ir::Value {
+    let (bits, _) = to_bits(value).unwrap();
+    b.ins().iconst(types::I64, bits.cast_signed())
+}
+
+impl VirtualMachine {
+    /// Runs a call with native code if the function is hot and could be compiled, the arguments
+    /// must be on the stack top, returns whether the call was done
+    pub(crate) fn call_jit(&mut self, fun: &Fn) -> InterpretResult<bool> {
+        let FnKind::Bytecode(bytecode) = &*fun.body else {
+            return Ok(false);
+        };
+        if self.debugger.is_some() || !fun.args.is_empty() || self.stack.len() < fun.arity {
+            return Ok(false);
+        }
+        // the interpreter raises the error of a call past the maximum depth
+        let Some(room) = self.max_depth.checked_sub(self.depth + 1) else {
+            return Ok(false);
+        };
+        if self.jit.interpreted.contains(&self.depth) {
+            return Ok(false);
+        }
+
+        let key = ptr::from_ref::<FnKind>(&fun.body);
+        let entry = self.jit.functions.entry(key).or_insert_with(|| Entry {
+            _body: fun.body.clone(),
+            state: State::Cold(0),
+        });
+
+        if let State::Cold(calls) = &mut entry.state {
+            *calls += 1;
+            if *calls < HOT_CALLS {
+                return Ok(false);
+            }
+            entry.state = State::Hot(HashMap::new());
+        }
+        let State::Hot(compiled) = &mut entry.state else {
+            unreachable!()
+        };
+
+        let args = self.stack[self.stack.len() - fun.arity..]
+            .iter()
+            .map(to_bits)
+            .collect::<Option<Vec<_>>>();
+        let Some(args) = args else {
+            return Ok(false);
+        };
+        let (args, tys): (Vec<_>, Vec<_>) = args.into_iter().unzip();
+        let signature = Signature {
+            args: tys,
+            wrap: num::overflow() == Overflow::Wrap,
+        };
+
+        let compiled = match compiled.entry(signature) {
+            hash_map::Entry::Occupied(it) => *it.get(),
+            hash_map::Entry::Vacant(it) => {
+                let globals = &self.globals;
+                let is_self = |name: Symbol| match globals.get(&name) {
+                    Some(Value::Fn(f)) => ptr::eq(ptr::from_ref::<FnKind>(&f.body), key),
+                    _ => false,
+                };
+
+                if self.jit.module.is_none() {
+                    self.jit.module = new_module();
+                }
+                let code = self.jit.module.as_mut().and_then(|module| {
+                    compile(module, bytecode, &self.constants, it.key(), &is_self)
+                });
+                *it.insert(code)
+            }
+        };
+        let Some(Compiled { code, ret }) = compiled else {
+            return Ok(false);
+        };
+
+        let mut flag = DONE;
+        let bits = code(args.as_ptr(), &raw mut flag, room.min(NATIVE_DEPTH) as u64);
+        match flag {
+            DONE => (),
+            TOO_DEEP if room <= NATIVE_DEPTH => {
+                // the calls to itself went as deep as they could
+                self.stats.max_depth = self.max_depth;
+                return Err(self.recursion_error());
+            }
+            _ => {
+                self.jit.interpreted = self.depth + 1..self.depth + NATIVE_DEPTH + 1;
+                return Ok(false);
+            }
+        }
+
+        for _ in 0..fun.arity {
+            self.pop();
+        }
+        self.push(from_bits(bits, ret));
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+fn op(opcode: OpCode) -> crate::OpCodeMetadata {
+    crate::OpCodeMetadata::new(1, 1, opcode)
+}
+
+#[cfg(test)]
+fn signature(args: &[Ty], wrap: bool) -> Signature {
+    Signature {
+        args: args.to_vec(),
+        wrap,
+    }
+}
+
+/// Runs compiled code, returning its result and the flag it set
+#[cfg(test)]
+fn run(compiled: Compiled, args: &[Value], room: u64) -> (Value, u8) {
+    let args = args
+        .iter()
+        .map(|it| to_bits(it).unwrap().0)
+        .collect::<Vec<_>>();
+    let mut flag = DONE;
+    let bits = (compiled.code)(args.as_ptr(), &raw mut flag, room);
+    (from_bits(bits, compiled.ret), flag)
+}
+
+#[test]
+fn compile_recursive_function() {
+    // def fib n = if n < 2 then n else fib (n - 1) + fib (n - 2)
+    let fib = Symbol::new("fib");
+    let bytecode = [
+        OpCode::Save(0),
+        OpCode::Load(0),
+        OpCode::Push(0),
+        OpCode::Less,
+        OpCode::Jmf(7),
+        OpCode::Load(0),
+        OpCode::Jmp(16),
+        OpCode::Load(0),
+        OpCode::Push(1),
+        OpCode::Sub,
+        OpCode::Loag(fib),
+        OpCode::Call(1),
+        OpCode::Bin(Operator::Sub, Operand::Local(0), Operand::Const(0)),
+        OpCode::Loag(fib),
+        OpCode::Call(1),
+        OpCode::Add,
+    ]
+    .map(op)
+    .to_vec();
+    let is_self = |name| name == fib;
+
+    let mut module = new_module().unwrap();
+    let ints = [Value::Int(2), Value::Int(1)];
+    let code = compile(
+        &mut module,
+        &bytecode,
+        &ints,
+        &signature(&[Ty::Int], false),
+        &is_self,
+    );
+    assert_eq!(
+        run(code.unwrap(), &[Value::Int(20)], 100),
+        (Value::Int(6765), DONE)
+    );
+
+    // integers aren't compared with floats, which are compared exactly
+    let code = compile(
+        &mut module,
+        &bytecode,
+        &ints,
+        &signature(&[Ty::Num], false),
+        &is_self,
+    );
+    assert!(code.is_none());
+
+    let floats = [Value::Num(2.0), Value::Num(1.0)];
+    let code = compile(
+        &mut module,
+        &bytecode,
+        &floats,
+        &signature(&[Ty::Num], false),
+        &is_self,
+    );
+    let code = code.unwrap();
+    assert_eq!(
+        run(code, &[Value::Num(20.0)], 100),
+        (Value::Num(6765.0), DONE)
+    );
+
+    // NaN can't be compared, so the interpreter has to raise the error
+    assert_eq!(run(code, &[Value::Num(f64::NAN)], 100).1, BAIL);
+
+    // the calls to itself can't go deeper than they are allowed to
+    assert_eq!(run(code, &[Value::Num(20.0)], 10).1, TOO_DEEP);
+}
+
+#[test]
+fn leave_integer_overflows_to_the_interpreter() {
+    // def fact n = if n < 2 then 1 else n * fact (n - 1)
+    let fact = Symbol::new("fact");
+    let bytecode = [
+        OpCode::Save(0),
+        OpCode::Bin(Operator::Less, Operand::Local(0), Operand::Const(0)),
+        OpCode::Jmf(5),
+        OpCode::Push(1),
+        OpCode::Jmp(10),
+        OpCode::Load(0),
+        OpCode::Bin(Operator::Sub, Operand::Local(0), Operand::Const(1)),
+        OpCode::Loag(fact),
+        OpCode::Call(1),
+        OpCode::Mul,
+    ]
+    .map(op)
+    .to_vec();
+    let constants = [Value::Int(2), Value::Int(1)];
+    let is_self = |name| name == fact;
+
+    let mut module = new_module().unwrap();
+    let checked = compile(
+        &mut module,
+        &bytecode,
+        &constants,
+        &signature(&[Ty::Int], false),
+        &is_self,
+    )
+    .unwrap();
+    assert_eq!(
+        run(checked, &[Value::Int(20)], 100),
+        (Value::Int(2_432_902_008_176_640_000), DONE)
+    );
+    assert_eq!(run(checked, &[Value::Int(21)], 100).1, BAIL);
+
+    let wrapping = compile(
+        &mut module,
+        &bytecode,
+        &constants,
+        &signature(&[Ty::Int], true),
+        &is_self,
+    )
+    .unwrap();
+    let wrapped = (2_432_902_008_176_640_000_i64).wrapping_mul(21);
+    assert_eq!(
+        run(wrapping, &[Value::Int(21)], 100),
+        (Value::Int(wrapped), DONE)
+    );
+}
+
+#[test]
+fn reject_unsupported_functions() {
+    // def greet name = "hello " + name
+    let bytecode = [
+        OpCode::Save(0),
+        OpCode::Push(0),
+        OpCode::Load(0),
+        OpCode::Add,
+    ]
+    .map(op)
+    .to_vec();
+    let constants = [Value::Str(crate::gc::GcRef::new("hello ".into()))];
+
+    let mut module = new_module().unwrap();
+    let greet = signature(&[Ty::Num], false);
+    assert!(compile(&mut module, &bytecode, &constants, &greet, &|_| false).is_none());
+}
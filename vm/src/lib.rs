@@ -1,7 +1,6 @@
 #![deny(missing_docs, clippy::all)]
 #![warn(clippy::pedantic)]
 #![allow(
-    unused_unsafe,
     clippy::unit_arg,
     clippy::option_map_unit_fn,
     clippy::module_name_repetitions,
@@ -15,25 +14,47 @@
     clippy::missing_panics_doc
 )]
 //! Virtual Machine implementation for the yex programming language
+pub mod debug;
+pub mod diagnostic;
+pub mod diff;
+mod dispatch;
+pub mod encoding;
 #[allow(missing_docs)]
 pub mod env;
 #[allow(missing_docs)]
 pub mod error;
+pub mod extension;
+mod frame;
 #[doc(hidden)]
 pub mod gc;
+#[cfg(feature = "jit")]
+mod jit;
 #[allow(missing_docs)]
 pub mod literal;
 mod opcode;
+mod output;
 mod prelude;
+pub mod replay;
+mod scope;
+mod snapshot;
 mod stack;
+pub mod stats;
+pub mod suggest;
+pub mod trace;
 
+use frame::CallFrame;
 use gc::GcRef;
 use literal::{
-    fun::{FnArgs, NativeFn},
+    channel::Channel,
+    fiber::Fiber,
+    fun::{FnArgs, FnBody, NativeFn, Signature},
+    generator::{Frame, Generator, GeneratorState},
+    handle::Handles,
+    promise::{EventLoop, PromiseState},
     TryGet,
 };
 
-use crate::error::InterpretResult;
+use crate::error::{InterpretError, InterpretResult, TraceEntry};
 
 pub use crate::{
     env::EnvTable,
@@ -45,15 +66,52 @@ pub use crate::{
         yexmodule::YexModule,
         Value,
     },
-    opcode::{OpCode, OpCodeMetadata},
+    opcode::{OpCode, OpCodeMetadata, Operand, Operator, BYTECODE_VERSION},
+    snapshot::Snapshot,
     stack::StackVec,
 };
 
-const STACK_SIZE: usize = 512;
+/// How many values the stack holds before it grows
+const STACK_SIZE: usize = 2048;
+/// How many calls deep a program can go before raising a `RecursionError`, unless changed with
+/// [`set_default_max_depth`] or [`VirtualMachine::set_max_depth`]
+pub const DEFAULT_MAX_DEPTH: usize = 100_000;
+/// How much of the host stack the loops and native calls running inside each other can use,
+/// below the 2 MiB threads get by default. The frames of yex functions live on the heap, but a
+/// native function that calls back into yex, like `List.map`, runs a new loop on the host stack
+const HOST_STACK: usize = 1 << 20;
+/// The number of locals there's room for at first, the storage grows as more are needed
+const LOCALS: usize = 1024;
 const NIL: Value = Value::Nil;
 
-static mut LINE: usize = 1;
-static mut COLUMN: usize = 1;
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_DEPTH);
+
+/// Sets how many calls deep the programs run by the virtual machines created after it can go
+pub fn set_default_max_depth(depth: usize) {
+    MAX_DEPTH.store(depth, Ordering::Relaxed);
+}
+
+/// The source of the part of the prelude written in yex
+pub const PRELUDE_SOURCE: &str = include_str!("prelude/prelude.yex");
+
+/// The constants of the yex prelude are indexed from here, so they don't move when the constants
+/// of a virtual machine are replaced
+const PRELUDE_BASE: usize = 1 << 28;
+
+pub use prelude::Loader as PreludeLoader;
+
+/// Sets how [`PRELUDE_SOURCE`] is compiled, which the virtual machine can't do by itself, the
+/// virtual machines created after it have the functions it defines. The compiler sets one that
+/// compiles it with `front::init`, or it can be unpacked from bytecode compiled ahead of time
+pub fn set_prelude_loader(loader: PreludeLoader) {
+    prelude::set_loader(loader);
+}
+
+thread_local! {
+    // the position of the running opcode, used to locate the errors
+    static LINE: Cell<usize> = const { Cell::new(1) };
+    static COLUMN: Cell<usize> = const { Cell::new(1) };
+}
 
 #[macro_export]
 #[doc(hidden)]
@@ -66,42 +124,143 @@ macro_rules! raise {
 #[macro_export]
 #[doc(hidden)]
 macro_rules! raise_err {
-    ($error: ident, $($fmtargs:expr),*) => {
-        unsafe {
-            let msg = $crate::Symbol::new(stringify!($error));
-            $crate::error::InterpretError {
-                line: $crate::LINE,
-                column: $crate::COLUMN,
-                err: msg,
-                msg: format!($($fmtargs),*),
-            }
+    ($error: ident, $($fmtargs:expr),*) => {{
+        let msg = $crate::Symbol::new(stringify!($error));
+        $crate::error::InterpretError {
+            line: $crate::LINE.with(::std::cell::Cell::get),
+            column: $crate::COLUMN.with(::std::cell::Cell::get),
+            err: msg,
+            msg: format!($($fmtargs),*),
+            trace: ::std::vec::Vec::new(),
+            data: ::std::option::Option::None,
         }
-    };
+    }};
 }
 
-type Stack = StackVec<Value, STACK_SIZE>;
+/// The values the running functions work on, it grows with the calls still waiting for a result
+type Stack = Vec<Value>;
 
 /// Bytecode for the virtual machine, contains the instructions to be executed and the constants to
 /// be loaded
 pub type Bytecode = Vec<OpCodeMetadata>;
 
 type BytecodeRef<'a> = &'a Bytecode;
-use std::{mem::swap, ops, ptr};
+use std::{
+    cell::Cell,
+    mem::{self, swap},
+    ops,
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 /// Implements the Yex virtual machine, which runs the [`crate::OpCode`] instructions in a stack
 /// model
 pub struct VirtualMachine {
     stack: Stack,
+    /// the locals of every running function, one after the other
     locals: Vec<Value>,
     used_locals: usize,
     /// the constants of the bytecode
     pub constants: Vec<Value>,
+    /// the constants of the yex prelude, indexed from `PRELUDE_BASE`
+    prelude_constants: Vec<Value>,
     globals: EnvTable,
+    fibers: Handles<Fiber>,
+    channels: Handles<Channel>,
+    /// the mailbox of the main program
+    mailbox: Channel,
+    /// the id of the main process
+    pid: Value,
+    current_fiber: Option<usize>,
+    scheduler: literal::fiber::Scheduler,
+    registry: EnvTable,
+    generators: Handles<Generator>,
+    suspended: Option<Frame>,
+    promises: Handles<PromiseState>,
+    events: EventLoop,
+    debugger: Option<Box<dyn debug::Debugger>>,
+    /// the last instructions run, if they're being recorded
+    trace: Option<trace::Trace>,
+    /// the counters of what the VM did, but the allocations
+    stats: stats::Stats,
+    /// how many values the thread allocated before the counters started
+    allocated: u64,
+    /// where the programs print to
+    output: output::Output,
+    /// the handlers of the `Ext` instructions, by their id
+    extensions: Box<[Option<extension::Extension>; 256]>,
+    /// the frames of the callers of the running functions, the innermost last
+    frames: Vec<frame::CallFrame>,
+    /// how many frames are running, counting the ones started from Rust
+    depth: usize,
+    /// how many loops and native calls are running inside each other
+    nesting: usize,
+    /// how many frames can run at once
+    max_depth: usize,
+    /// the address of the host stack when the outermost loop or native call started
+    host_stack: usize,
+    /// if unbalanced scopes and misplaced `Drop`s raise errors
+    check_scopes: bool,
+    /// the state of the random number generator
+    rng: u64,
+    started: std::time::Instant,
+    deterministic: Option<replay::Deterministic>,
+    #[cfg(feature = "jit")]
+    jit: jit::Jit,
+}
+
+/// The address of a value on the host stack, which grows along with the calls running in Rust
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0_u8;
+    std::ptr::addr_of!(marker) as usize
+}
+
+/// Moves the constants used by the bytecode past the `old_len` constants before them, the ones of
+/// the yex prelude stay where they are
+fn patch_bytecode(ops: &mut [OpCodeMetadata], old_len: usize) {
+    let patch = |operand| match operand {
+        Operand::Const(idx) if (idx as usize) < PRELUDE_BASE => {
+            Operand::Const(idx + u32::try_from(old_len).unwrap())
+        }
+        operand => operand,
+    };
+
+    for op in ops.iter_mut() {
+        match &mut op.opcode {
+            OpCode::Push(idx) if *idx < PRELUDE_BASE => *idx += old_len,
+            OpCode::Bin(_, left, right) => {
+                *left = patch(*left);
+                *right = patch(*right);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Patches the bytecode of the functions in a new constant, the methods and constructors of a
+/// type are only reachable from the type itself
+fn patch_constant(value: &Value, old_len: usize) {
+    match value {
+        Value::Fn(fun) => {
+            let mut body = fun.body.clone();
+            match unsafe { body.mut_ref() } {
+                FnKind::Bytecode(ops) | FnKind::Generator(ops) => patch_bytecode(ops, old_len),
+                FnKind::Native(_) => (),
+            }
+        }
+        Value::Module(module) => {
+            for field in module.fields.values() {
+                patch_constant(&field, old_len);
+            }
+        }
+        _ => (),
+    }
 }
 
 impl VirtualMachine {
     /// Reset the instruction pointer and the stack
     pub fn reset(&mut self) {
-        self.stack = stackvec![];
+        self.stack.clear();
     }
 
     /// sets the constants for execution
@@ -109,6 +268,23 @@ impl VirtualMachine {
         self.constants = constants;
     }
 
+    /// Appends the constants of code compiled on its own to the constants of the VM, pointing its
+    /// bytecode, and the bytecode of the functions in the constants, to their new indexes
+    pub fn link(&mut self, bytecode: &mut [OpCodeMetadata], constants: Vec<Value>) {
+        let old_len = self.constants.len();
+
+        patch_bytecode(bytecode, old_len);
+        for constant in &constants {
+            patch_constant(constant, old_len);
+        }
+        self.constants.extend(constants);
+    }
+
+    /// Sets how many calls deep the programs can go before raising a `RecursionError`
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
     /// Pop's the last value on the stack
     #[must_use]
     pub fn pop_last(&self) -> &Value {
@@ -120,6 +296,40 @@ impl VirtualMachine {
         self.globals.get(&name.into())
     }
 
+    /// Get the value of a global variable by its name, without creating a symbol for it, which
+    /// is cheaper for the names that aren't already symbols
+    #[must_use]
+    pub fn get_global_str(&self, name: &str) -> Option<Value> {
+        self.globals.get_str(name)
+    }
+
+    /// Get the value of a global variable converted to `T`, raising a `TypeError` if it's of
+    /// another type
+    pub fn try_global<T>(&self, name: &str) -> InterpretResult<Option<T>>
+    where
+        Value: TryGet<T>,
+    {
+        self.get_global_str(name)
+            .map(|value| value.get())
+            .transpose()
+    }
+
+    /// The entry of a global variable, to read, define or update it with a single lookup
+    pub fn global_entry<T: Into<Symbol>>(&mut self, name: T) -> env::Entry<'_> {
+        self.globals.entry(name.into())
+    }
+
+    /// The table of the global variables, including the prelude
+    #[must_use]
+    pub fn globals(&self) -> &EnvTable {
+        &self.globals
+    }
+
+    /// Removes a global variable, returning its value, so it can be defined again
+    pub fn remove_global<T: Into<Symbol>>(&mut self, name: T) -> Option<Value> {
+        self.globals.remove(&name.into())
+    }
+
     /// Set the value of a global variable
     pub fn set_global<T: Into<Symbol>>(&mut self, name: T, value: Value) {
         self.globals.insert(name.into(), value);
@@ -127,266 +337,388 @@ impl VirtualMachine {
 
     /// Executes a given set of bytecode instructions
     pub fn run(&mut self, bytecode: BytecodeRef) -> InterpretResult<()> {
-        let bytecode = bytecode;
-        let mut try_stack = vec![];
-
-        let mut ip = 0;
-        let mut frame_locals = 0;
-
-        while ip < bytecode.len() {
-            let op = unsafe {
-                let op = bytecode[ip];
-                LINE = op.line;
-                COLUMN = op.column;
-                op.opcode
-            };
-
-            self.debug_stack(&op);
-
-            let res = match op {
-                OpCode::Try(offset) => {
-                    try_stack.push(offset);
-                    Ok(())
-                }
+        self.run_frame(bytecode, Frame::default())
+    }
 
-                OpCode::EndTry => {
-                    try_stack.pop();
-                    Ok(())
-                }
+    /// Executes bytecode packed with [`encoding::encode`]
+    pub fn run_packed(&mut self, bytes: &[u8]) -> InterpretResult<()> {
+        let Some(bytecode) = encoding::decode(&mut &bytes[..]) else {
+            return raise!(DecodeError, "Malformed bytecode");
+        };
+        self.run(&bytecode)
+    }
 
-                OpCode::Jmp(offset) => {
-                    ip = offset;
-                    continue;
-                }
+    /// Executes a given set of bytecode instructions, starting from (or resuming) `frame`
+    pub(crate) fn run_frame(&mut self, bytecode: BytecodeRef, frame: Frame) -> InterpretResult<()> {
+        self.enter_frame()?;
+
+        let Frame {
+            ip,
+            locals,
+            stack,
+            try_stack,
+            scopes,
+            generator,
+            callee,
+        } = frame;
+
+        let frame_locals = locals.len();
+        for local in locals {
+            self.set_local(self.used_locals, local);
+            self.used_locals += 1;
+        }
 
-                OpCode::Jmf(offset) => {
-                    if !self.pop().to_bool() {
-                        ip = offset;
-                        continue;
-                    }
-                    Ok(())
-                }
+        let stack_base = self.stack.len();
+        for value in stack {
+            self.push(value);
+        }
 
-                OpCode::TCall(arity) => {
-                    self.valid_tail_call(arity, bytecode)?;
-                    ip = 0;
-                    continue;
-                }
+        let frame = CallFrame {
+            body: None,
+            ip,
+            locals: frame_locals,
+            stack_base,
+            try_stack,
+            scopes,
+            generator,
+            callee,
+            caught: None,
+        };
+        let base = self.frames.len();
+        self.run_loop(bytecode, frame, base)
+    }
 
-                _ => self.run_op(op, &mut frame_locals),
-            };
+    /// Counts a new frame, raising a `RecursionError` if there are too many already
+    fn enter_frame(&mut self) -> InterpretResult<()> {
+        if self.depth >= self.max_depth {
+            return Err(self.recursion_error());
+        }
 
-            if let Err(e) = res {
-                if try_stack.is_empty() {
-                    return Err(e);
-                }
+        self.depth += 1;
+        self.stats.max_depth = self.stats.max_depth.max(self.depth);
+        Ok(())
+    }
 
-                let try_ip = try_stack.pop().unwrap();
-                self.push(e.err.into());
-                ip = try_ip;
-            }
+    /// The error raised by a call past the maximum depth
+    pub(crate) fn recursion_error(&self) -> InterpretError {
+        raise_err!(
+            RecursionError,
+            "Maximum recursion depth of {} calls exceeded",
+            self.max_depth
+        )
+    }
 
-            ip += 1;
+    /// Runs `frame` until it returns, along with the functions it calls, which push their frames
+    /// instead of running in a new loop. The frames of the callers wait in `self.frames`, the
+    /// loop stops once it returns to the `base` frame. `code` is the bytecode of the frame
+    /// without a body
+    fn run_loop(
+        &mut self,
+        code: &[OpCodeMetadata],
+        frame: CallFrame,
+        base: usize,
+    ) -> InterpretResult<()> {
+        if self.nesting == 0 {
+            self.host_stack = stack_address();
         }
-
-        self.used_locals -= frame_locals;
-
-        Ok(())
+        self.nesting += 1;
+        let res = self.run_frames(code, frame, base);
+        self.nesting -= 1;
+        res
     }
 
-    #[inline]
-    fn run_op(&mut self, op: OpCode, frame_locals: &mut usize) -> InterpretResult<()> {
-        match op {
-            OpCode::Nop => (),
-
-            // Stack manipulation
-            OpCode::Push(value) => {
-                let value = self.constants[value].clone();
-                self.push(value);
-            }
-            OpCode::Pop => {
-                self.pop();
-            }
+    /// The body of [`Self::run_loop`], which also stops when the running fiber is suspended,
+    /// leaving its frames in `self.frames`
+    fn run_frames(
+        &mut self,
+        code: &[OpCodeMetadata],
+        mut frame: CallFrame,
+        base: usize,
+    ) -> InterpretResult<()> {
+        'frames: loop {
+            // the body is kept alive while the frame runs, even if it's the only reference left
+            let body = frame.body.clone();
+            let bytecode = CallFrame::bytecode(body.as_ref(), code);
+
+            while frame.ip < bytecode.len() {
+                let op = bytecode[frame.ip];
+                self.stats.instructions += 1;
+                self.stats.max_stack = self.stats.max_stack.max(self.stack.len());
+                LINE.with(|line| line.set(op.line));
+                COLUMN.with(|column| column.set(op.column));
+                if self.debugger.is_some() {
+                    self.debug_hook(op.line, op.column, frame.locals);
+                }
+                self.trace_op(op);
+                let op = op.opcode;
 
-            OpCode::Dup => {
-                let value = self.pop();
-                self.push(value.clone());
-                self.push(value);
-            }
+                self.debug_stack(&op);
 
-            OpCode::Swap(a, b) => unsafe {
-                let a = self.stack.get_uninit_mut(a) as *mut _;
-                let b = self.stack.get_uninit_mut(b) as *mut _;
+                let res = match op {
+                    OpCode::Try(offset) => {
+                        frame.try_stack.push((offset, frame.scopes.len()));
+                        Ok(())
+                    }
 
-                ptr::swap(a, b);
-            },
+                    OpCode::EnterScope => {
+                        frame.scopes.push(frame.locals);
+                        Ok(())
+                    }
 
-            OpCode::Rev => {
-                let (a, b) = self.pop_two();
-                self.push(b);
-                self.push(a);
-            }
+                    OpCode::ExitScope => self.exit_scope(&mut frame.scopes, &mut frame.locals),
 
-            OpCode::RevN(n) => {
-                let mut vec = vec![];
-                for _ in 0..n {
-                    vec.push(self.pop());
-                }
-                for elem in vec {
-                    self.push(elem);
-                }
-            }
+                    OpCode::Drop(index) if self.check_scopes => {
+                        let scope_base = frame.scopes.last().copied().unwrap_or(0);
+                        self.checked_drop(index, scope_base, &mut frame.locals)
+                    }
 
-            // function calls
-            OpCode::Call(arity) => self.call(arity)?,
-
-            // mathematical operators
-            OpCode::Add => self.binop(|a, b| a + b)?,
-            OpCode::Sub => self.binop(|a, b| a - b)?,
-            OpCode::Mul => self.binop(|a, b| a * b)?,
-            OpCode::Div => self.binop(|a, b| a / b)?,
-            OpCode::Rem => self.binop(|a, b| a % b)?,
-
-            // bitwise operators
-            OpCode::BitAnd => self.binop(|a, b| a & b)?,
-            OpCode::BitOr => self.binop(|a, b| a | b)?,
-            OpCode::Xor => self.binop(|a, b| a ^ b)?,
-            OpCode::Shl => self.binop(|a, b| a << b)?,
-            OpCode::Shr => self.binop(|a, b| a >> b)?,
-
-            // comparison operators
-            OpCode::Eq => self.binop(|a, b| Ok(a == b))?,
-            OpCode::Less => {
-                let (a, b) = self.pop_two();
-                self.push(a.ord_cmp(&b)?.is_lt().into());
-            }
-            OpCode::LessEq => {
-                let (a, b) = self.pop_two();
-                self.push(a.ord_cmp(&b)?.is_le().into());
-            }
+                    OpCode::EndTry => {
+                        frame.try_stack.pop();
+                        Ok(())
+                    }
 
-            // unary operators
-            OpCode::Not => {
-                let value = self.pop();
-                self.push(!value);
-            }
-            OpCode::Len => {
-                let value = self.pop();
-                self.push(Value::Num(value.len() as f64));
-            }
-            OpCode::Neg => {
-                let value = self.pop();
-                self.try_push(-value)?;
-            }
+                    OpCode::Jmp(offset) => {
+                        let back = offset <= frame.ip;
+                        frame.ip = offset;
+                        if back && self.fiber_turn() {
+                            self.switch_fiber(frame);
+                            return Ok(());
+                        }
+                        continue;
+                    }
 
-            // locals manipulation
-            OpCode::Load(offset) => {
-                let value = self.locals[offset + self.used_locals - *frame_locals].clone();
-                self.push(value);
-            }
-            OpCode::Save(offset) => {
-                let value = self.pop();
+                    OpCode::Jmf(offset) => {
+                        if !self.pop().to_bool() {
+                            frame.ip = offset;
+                            continue;
+                        }
+                        Ok(())
+                    }
 
-                if self.used_locals + 1 >= self.locals.capacity() {
-                    let cap = self.locals.capacity();
-                    self.locals.resize(cap, NIL);
-                }
+                    OpCode::Call(arity) => match self.enter_call(arity) {
+                        Ok(Some((fun, args))) => match self.push_frame(fun, args) {
+                            Ok(callee) => {
+                                frame.ip += 1;
+                                self.frames.push(mem::replace(&mut frame, callee));
+                                if self.fiber_turn() {
+                                    self.switch_fiber(frame);
+                                    return Ok(());
+                                }
+                                continue 'frames;
+                            }
+                            Err(e) => Err(e),
+                        },
+                        Ok(None) if self.fiber_turn() => {
+                            frame.ip += 1;
+                            self.switch_fiber(frame);
+                            return Ok(());
+                        }
+                        res => res.map(|_| ()),
+                    },
+
+                    OpCode::TCall(arity) => match self.valid_tail_call(arity, bytecode) {
+                        Ok(fun) => {
+                            // another closure of the same function may have captured other values
+                            frame.callee = Some(fun);
+                            // the new call reuses the frame, so its locals start over
+                            self.used_locals -= frame.locals;
+                            frame.locals = 0;
+                            frame.scopes.clear();
+                            frame.ip = 0;
+                            if self.fiber_turn() {
+                                self.switch_fiber(frame);
+                                return Ok(());
+                            }
+                            continue;
+                        }
+                        Err(e) => Err(e),
+                    },
+
+                    OpCode::Yield if frame.generator => {
+                        let value = self.pop();
+
+                        let base = self.used_locals - frame.locals;
+                        let locals = self.locals[base..self.used_locals].to_vec();
+                        self.used_locals = base;
+
+                        let mut stack = vec![];
+                        while self.stack.len() > frame.stack_base {
+                            stack.push(self.pop());
+                        }
+                        stack.reverse();
+                        // the value of the `yield` expression, once resumed
+                        stack.push(Value::Nil);
+
+                        // only the frame a generator started with can yield, so it's the base
+                        self.suspended = Some(Frame {
+                            ip: frame.ip + 1,
+                            locals,
+                            stack,
+                            try_stack: frame.try_stack,
+                            scopes: frame.scopes,
+                            generator: frame.generator,
+                            callee: frame.callee,
+                        });
+                        self.push(value);
+                        self.depth -= 1;
+                        return Ok(());
+                    }
 
-                self.locals[offset + (self.used_locals - *frame_locals)] = value;
-                self.used_locals += 1;
-                *frame_locals += 1;
-            }
-            OpCode::Drop(_) => {
-                *frame_locals -= 1;
-                self.used_locals -= 1;
-            }
+                    OpCode::Yield => {
+                        raise!(YieldError, "'yield' can only be used inside functions")
+                    }
 
-            // globals manipulation
-            OpCode::Loag(name) => {
-                let value = match self.get_global(name) {
-                    Some(value) => value,
-                    None => raise!(NameError, "Undefined variable '{}'", name)?,
-                };
-                self.push(value);
-            }
-            OpCode::Savg(name) => {
-                let value = self.pop();
-                if self.globals.get(&name).is_some() {
-                    raise!(NameError, "Tried to reassign global variable '{}'", name)?;
-                }
-                self.set_global(name, value);
-            }
+                    OpCode::Recur => match &frame.callee {
+                        Some(fun) if fun.args.is_empty() => Ok(self.push(Value::Fn(fun.clone()))),
+                        Some(fun) => Ok(self.push(Value::Fn(GcRef::new(fun.unapplied())))),
+                        None => raise!(RecurError, "'recur' can only be used inside functions"),
+                    },
+
+                    OpCode::Loac(index) => {
+                        match frame
+                            .callee
+                            .as_ref()
+                            .and_then(|fun| fun.captures.get(index))
+                        {
+                            Some(value) => Ok(self.push(value.clone())),
+                            None => raise!(
+                                LocalError,
+                                "The function didn't capture a value at {}",
+                                index
+                            ),
+                        }
+                    }
 
-            // list manipulation
-            OpCode::Prep => {
-                let list: List = self.pop().get()?;
-                let value = self.pop();
+                    OpCode::Rethrow => Err(frame.caught.take().unwrap_or_else(|| {
+                        raise_err!(RethrowError, "There's no error to raise again")
+                    })),
 
-                self.push(list.prepend(value).into());
-            }
+                    _ => dispatch::run(self, op, &mut frame.locals),
+                };
 
-            OpCode::New => {
-                todo!()
-            }
+                if let Err(e) = res {
+                    self.catch(&mut frame, code, base, e)?;
+                    continue 'frames;
+                }
 
-            OpCode::Type => {
-                let value = self.pop();
-                self.push(Value::Module(value.type_of()));
+                frame.ip += 1;
             }
 
-            OpCode::Ref(method) => {
-                let ty: GcRef<YexModule> = self.pop().get()?;
-
-                let method = ty.fields.get(&method).ok_or(raise_err!(
-                    FieldError,
-                    "Undefined method '{}' for type '{}'",
-                    method,
-                    ty.name
-                ))?;
+            self.used_locals -= frame.locals;
+            self.depth -= 1;
+
+            let res = if self.check_scopes && !frame.scopes.is_empty() {
+                raise!(
+                    ScopeError,
+                    "{} scopes were entered but never exited",
+                    frame.scopes.len()
+                )
+            } else {
+                Ok(())
+            };
 
-                self.push(method);
+            if self.frames.len() == base {
+                return res;
             }
 
-            OpCode::Tup(len) => {
-                let mut tup = vec![];
-                for _ in 0..len {
-                    tup.push(self.pop());
-                }
-                self.push(tup.into());
+            // returns to the caller, with the value of the call on the stack
+            self.used_locals -= 1;
+            frame = self.frames.pop().unwrap();
+            if let Err(e) = res {
+                // the error is raised by the call the frame was running
+                frame.ip -= 1;
+                self.catch(&mut frame, code, base, e)?;
             }
+        }
+    }
 
-            OpCode::TupGet(index) => {
-                let tup: Tuple = self.pop().get()?;
-                let elem = tup.0.get(index).unwrap(); // this SHOULD be unreachable
-                self.push(elem.clone());
+    /// Jumps to the innermost `try` around the instruction of `frame` that raised `e`, returning
+    /// from the frames that don't have one, but not past the `base` frame of the loop, which
+    /// raises `e` if it doesn't catch it either. The frames it returns from are added to the
+    /// trace of `e`
+    fn catch(
+        &mut self,
+        frame: &mut CallFrame,
+        code: &[OpCodeMetadata],
+        base: usize,
+        mut e: InterpretError,
+    ) -> InterpretResult<()> {
+        // the last function named, which is the next one too when it recurses
+        let mut named = None;
+        while frame.try_stack.is_empty() {
+            self.trace_frame(&mut e, frame, code, &mut named);
+            self.used_locals -= frame.locals;
+            self.depth -= 1;
+            if self.frames.len() == base {
+                return Err(e);
             }
 
-            OpCode::Tag(tag) => {
-                let module: GcRef<YexModule> = self.pop().get()?;
-                let tup: Tuple = self.pop().get()?;
+            self.used_locals -= 1;
+            *frame = self.frames.pop().unwrap();
+            // the error is raised by the call the frame was running
+            frame.ip -= 1;
+        }
 
-                self.push(Value::Tagged(module, tag, tup));
-            }
+        let (try_ip, open) = frame.try_stack.pop().unwrap();
+        self.unwind_scopes(&mut frame.scopes, open, &mut frame.locals);
+        self.push(e.exception(self));
+        frame.caught = Some(e);
+        frame.ip = try_ip + 1;
+        Ok(())
+    }
 
-            OpCode::TagOf => match self.pop() {
-                Value::Tagged(_, tag, _) => self.push(tag.into()),
-                _ => self.push(NIL),
+    /// Adds the function of `frame`, and the instruction it's running, to the trace of `e`
+    fn trace_frame(
+        &self,
+        e: &mut InterpretError,
+        frame: &CallFrame,
+        code: &[OpCodeMetadata],
+        named: &mut Option<(FnBody, String)>,
+    ) {
+        let name = match &frame.callee {
+            None => String::from("<main>"),
+            Some(fun) => match named {
+                Some((body, name)) if GcRef::ptr_eq(body, &fun.body) => name.clone(),
+                _ => {
+                    let name = self.fn_name(fun);
+                    *named = Some((fun.body.clone(), name.clone()));
+                    name
+                }
             },
+        };
 
-            OpCode::TagTup => {
-                let (_, _, tup) = self.pop().get()?;
-                self.push(tup.into());
+        let op = CallFrame::bytecode(frame.body.as_ref(), code).get(frame.ip);
+        let (line, column) = op.map_or((e.line, e.column), |op| (op.line, op.column));
+        if let Some(last) = e.trace.last_mut() {
+            if (&*last.name, last.line, last.column) == (&*name, line, column) {
+                last.repeated += 1;
+                return;
             }
+        }
+        e.trace.push(TraceEntry {
+            name,
+            line,
+            column,
+            repeated: 0,
+        });
+    }
 
-            // these opcodes are handled by the run function, since they can manipulate the ip
-            OpCode::Try(..)
-            | OpCode::EndTry
-            | OpCode::Jmp(..)
-            | OpCode::Jmf(..)
-            | OpCode::TCall(..) => unreachable!(),
-        };
-
-        Ok(())
+    /// The name of the global, or of the field of a global type, bound to `fun`
+    fn fn_name(&self, fun: &Fn) -> String {
+        let same =
+            |value: &Value| matches!(value, Value::Fn(it) if GcRef::ptr_eq(&it.body, &fun.body));
+        for (name, value) in self.globals.iter() {
+            match &value {
+                Value::Module(module) => {
+                    if let Some((field, _)) = module.fields.iter().find(|(_, it)| same(it)) {
+                        return format!("{}.{}", name.as_str(), field.as_str());
+                    }
+                }
+                value if same(value) => return name.as_str().to_string(),
+                _ => (),
+            }
+        }
+        String::from("<fn>")
     }
 
     #[cfg(debug_assertions)]
@@ -400,65 +732,138 @@ impl VirtualMachine {
     pub fn debug_stack(&self, _: &OpCode) {}
 
     #[inline]
-    fn call_args(&mut self, arity: usize, fun: &Fn) -> Option<FnArgs> {
+    fn call_args(&mut self, arity: usize, fun: &Fn) -> InterpretResult<Option<FnArgs>> {
         if fun.is_bytecode() && fun.args.is_empty() {
-            return None;
+            return Ok(None);
         }
 
-        let mut args = stackvec![];
-
-        let mut i = 1;
+        // the arguments are popped from the last to the first
+        let mut args = FnArgs::new();
         for _ in 0..arity {
-            unsafe { args.insert_at(arity - i, self.pop()) };
-            i += 1;
+            args.try_insert(0, self.pop())
+                .map_err(|_| fun.too_many_args())?;
         }
 
-        unsafe { args.set_len(arity) };
-
         for arg in fun.args.iter() {
-            args.push(arg.clone());
+            args.try_push(arg.clone())
+                .map_err(|_| fun.too_many_args())?;
         }
 
-        Some(args)
+        Ok(Some(args))
     }
 
+    /// Pushes `fun` applied to the `arity` arguments on the stack, kept out of [`Self::call`] so
+    /// its arguments don't take room in the frame of every call
+    #[inline(never)]
+    fn partial_apply(&mut self, arity: usize, fun: &Fn) -> InterpretResult<()> {
+        let mut args = FnArgs::new();
+        for _ in 0..arity {
+            args.try_push(self.pop()).map_err(|_| fun.too_many_args())?;
+        }
+
+        self.push(Value::Fn(GcRef::new(fun.apply(&args)?)));
+        Ok(())
+    }
+
+    /// Calls the function on the top of the stack with the `arity` values under it, running it
+    /// until it returns, which leaves its value on the stack
     #[inline]
     pub(crate) fn call(&mut self, arity: usize) -> InterpretResult<()> {
-        let fun: GcRef<Fn> = self.pop().get()?;
+        match self.enter_call(arity)? {
+            Some((fun, args)) => self.call_bytecode(fun, args),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts calling the function on the top of the stack, natives and the rest of the calls that
+    /// don't run bytecode finish right away. A function compiled from yex is returned, with its
+    /// arguments if they aren't on the stack already, for the caller to run its frame
+    #[inline]
+    fn enter_call(&mut self, arity: usize) -> InterpretResult<Option<(GcRef<Fn>, Option<FnArgs>)>> {
+        let fun: GcRef<Fn> = match self.pop() {
+            Value::Sym(name) => return self.call_field(name.0, arity).map(|()| None),
+            fun => fun.get()?,
+        };
 
         if arity < fun.arity {
-            let mut args = stackvec![];
+            return self.partial_apply(arity, &fun).map(|()| None);
+        }
 
-            for _ in 0..arity {
-                args.push(self.pop());
-            }
+        let args = self.call_args(arity, &fun)?;
 
-            self.push(Value::Fn(GcRef::new(fun.apply(&args))));
-            return Ok(());
+        if let (Some(signature), true) = (fun.signature, arity > fun.arity) {
+            signature.check_arity(arity + fun.args.len())?;
         }
 
-        let args = self.call_args(arity, &fun);
-
         if arity > fun.arity {
             raise!(
                 CallError,
-                "Too many arguments passed for function {:?}",
-                fun
+                "Too many arguments passed for function {}, expected {}, found {}",
+                *fun,
+                fun.arity,
+                arity
             )?;
         }
 
         match &*fun.body {
-            FnKind::Bytecode(bytecode) => self.call_bytecode(bytecode, args),
-            FnKind::Native(ptr) => self.call_native(*ptr, args),
+            FnKind::Bytecode(_) => {
+                #[cfg(feature = "jit")]
+                if self.call_jit(&fun)? {
+                    return Ok(None);
+                }
+                Ok(Some((fun, args)))
+            }
+            FnKind::Native(ptr) => self.call_native(*ptr, args, fun.signature).map(|()| None),
+            FnKind::Generator(_) => {
+                let args = args.map(|args| args.into_iter().collect());
+                let generator = Generator::new(fun.clone(), args.unwrap_or_default());
+                let handle = literal::generator::handle(self, generator);
+                self.push(handle);
+                Ok(None)
+            }
         }
     }
 
+    /// Calls a symbol, `:name` is the function `fn x -> x.name`
+    fn call_field(&mut self, name: Symbol, arity: usize) -> InterpretResult<()> {
+        if arity != 1 {
+            raise!(
+                CallError,
+                "Symbol ':{}' expects a single argument, found {}",
+                name,
+                arity
+            )?;
+        }
+
+        let ty: GcRef<YexModule> = self.pop().get()?;
+        self.push(ty.field(name)?);
+        Ok(())
+    }
+
+    /// Stores a local, growing the storage of the locals when it's full
     #[inline]
-    fn call_bytecode(
-        &mut self,
-        bytecode: BytecodeRef,
-        args: Option<FnArgs>,
-    ) -> InterpretResult<()> {
+    pub(crate) fn set_local(&mut self, index: usize, value: Value) {
+        if index >= self.locals.len() {
+            self.locals.resize((index + 1).next_power_of_two(), NIL);
+        }
+        self.locals[index] = value;
+    }
+
+    /// Runs a function compiled from yex in a loop of its own, for the calls made from Rust, like
+    /// the ones of native functions
+    fn call_bytecode(&mut self, fun: GcRef<Fn>, args: Option<FnArgs>) -> InterpretResult<()> {
+        let frame = self.push_frame(fun, args)?;
+        let base = self.frames.len();
+        let res = self.run_loop(&[], frame, base);
+        self.used_locals -= 1;
+        res
+    }
+
+    /// Makes the frame of a call to a function compiled from yex, pushing its arguments if they
+    /// aren't on the stack already
+    #[inline]
+    fn push_frame(&mut self, fun: GcRef<Fn>, args: Option<FnArgs>) -> InterpretResult<CallFrame> {
+        self.enter_frame()?;
         self.used_locals += 1;
 
         args.map(|stack| {
@@ -467,44 +872,83 @@ impl VirtualMachine {
             }
         });
 
-        self.run(bytecode)?;
-        self.used_locals -= 1;
-        Ok(())
+        Ok(CallFrame::new(fun, self.stack.len()))
     }
 
     #[inline]
-    fn call_native(&mut self, fp: NativeFn, args: Option<FnArgs>) -> InterpretResult<()> {
-        let args = args.unwrap_or_else(FnArgs::new).reverse().into();
-        let result = fp(self, args);
+    fn call_native(
+        &mut self,
+        fp: NativeFn,
+        args: Option<FnArgs>,
+        signature: Option<&Signature>,
+    ) -> InterpretResult<()> {
+        let args: Vec<Value> = args.unwrap_or_else(FnArgs::new).reverse().into();
+        if let Some(signature) = signature {
+            signature.check(&args)?;
+        }
+
+        // natives calling back into yex recurse on the host stack, which runs out way before
+        // the frames of yex functions do
+        if self.nesting == 0 {
+            self.host_stack = stack_address();
+        } else if self.host_stack.abs_diff(stack_address()) > HOST_STACK {
+            return raise!(
+                RecursionError,
+                "Maximum recursion depth exceeded in calls through native functions"
+            );
+        }
+
+        // a bug in a native function raises an error instead of taking the host down with it
+        let vm = std::ptr::from_mut(self);
+        self.nesting += 1;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| fp(vm, args)));
+        self.nesting -= 1;
+        let result = result.unwrap_or_else(|err| {
+            let msg = err
+                .downcast_ref::<&str>()
+                .map(ToString::to_string)
+                .or_else(|| err.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown panic"));
+            raise!(NativeError, "Native function panicked: {}", msg)
+        });
         self.try_push(result)
     }
 
     #[inline]
-    fn valid_tail_call(&mut self, arity: usize, frame: BytecodeRef) -> InterpretResult<()> {
+    fn valid_tail_call(
+        &mut self,
+        arity: usize,
+        frame: &[OpCodeMetadata],
+    ) -> InterpretResult<GcRef<Fn>> {
         let fun: GcRef<Fn> = self.pop().get()?;
 
         match &*fun.body {
-            FnKind::Bytecode(_) if fun.arity != arity => {
+            FnKind::Bytecode(_) | FnKind::Generator(_) if fun.arity != arity => {
                 raise!(TailCallError, "")
             }
-            FnKind::Bytecode(bytecode) if bytecode != frame => {
+            FnKind::Bytecode(bytecode) | FnKind::Generator(bytecode) if bytecode != frame => {
                 raise!(TailCallError, "")
             }
             FnKind::Native(_) => {
                 raise!(TailCallError, "")
             }
-            FnKind::Bytecode(_) => Ok(()),
+            FnKind::Bytecode(_) | FnKind::Generator(_) => Ok(fun),
         }
     }
 
+    /// Pushes a value, collecting the cycles of garbage first once enough values were allocated
+    /// since the last collection, so the instances built by `Tag` are counted too
     #[track_caller]
     pub(crate) fn push(&mut self, constant: Value) {
+        if gc::should_collect() {
+            let _ = gc::collect();
+        }
         self.stack.push(constant);
     }
 
     #[track_caller]
     pub(crate) fn pop(&mut self) -> Value {
-        self.stack.pop()
+        self.stack.pop().expect("the stack is empty")
     }
 
     fn binop<T, F>(&mut self, f: F) -> InterpretResult<()>
@@ -517,12 +961,53 @@ impl VirtualMachine {
         Ok(self.push(f(b, a)?.into()))
     }
 
+    /// Applies an operator to the two numbers on the top of the stack, leaving the result in the
+    /// slot of the left one instead of popping and pushing them, returns false (leaving the stack
+    /// untouched) if they aren't numbers or can't be compared
+    #[inline]
+    fn num_op(&mut self, operator: Operator) -> bool {
+        let len = self.stack.len();
+        let [.., a, b] = &self.stack[..] else {
+            return false;
+        };
+        let Some(value) = dispatch::on_nums(operator, a, b) else {
+            return false;
+        };
+
+        // numbers have nothing to drop, so the right one can be forgotten
+        unsafe { self.stack.set_len(len - 1) };
+        *self.stack.last_mut().unwrap() = value;
+        true
+    }
+
+    /// Runs a binary operator, through [`Self::num_op`] if both operands are numbers
+    #[inline]
+    fn arith<T, F>(&mut self, operator: Operator, f: F) -> InterpretResult<()>
+    where
+        T: Into<Value>,
+        F: ops::Fn(Value, Value) -> InterpretResult<T>,
+    {
+        if self.num_op(operator) {
+            return Ok(());
+        }
+        self.binop(f)
+    }
+
     fn pop_two(&mut self) -> (Value, Value) {
         let mut ret = (self.pop(), self.pop());
         swap(&mut ret.0, &mut ret.1);
         ret
     }
 
+    /// The constant at `index`, the ones of the yex prelude are past `PRELUDE_BASE`
+    #[inline]
+    fn constant(&self, index: usize) -> &Value {
+        match self.constants.get(index) {
+            Some(value) => value,
+            None => &self.prelude_constants[index - PRELUDE_BASE],
+        }
+    }
+
     fn try_push(&mut self, constant: InterpretResult<Value>) -> InterpretResult<()> {
         Ok(self.push(constant?))
     }
@@ -530,15 +1015,234 @@ impl VirtualMachine {
 
 impl Default for VirtualMachine {
     fn default() -> Self {
-        const STACK: Stack = StackVec::new();
-
         let prelude = prelude::prelude();
-        Self {
-            stack: STACK,
-            locals: vec![NIL; u16::MAX.into()],
+        let mut vm = Self {
+            stack: Vec::with_capacity(STACK_SIZE),
+            locals: vec![NIL; LOCALS],
             used_locals: 0,
             constants: Vec::new(),
+            prelude_constants: Vec::new(),
             globals: prelude,
-        }
+            fibers: Handles::new(|fiber| !fiber.is_done()),
+            channels: Handles::new(|_| false),
+            mailbox: Channel::new(),
+            pid: literal::process::main_pid(),
+            current_fiber: None,
+            scheduler: literal::fiber::Scheduler::default(),
+            registry: EnvTable::new(),
+            generators: Handles::new(|it| matches!(it.state, GeneratorState::Running)),
+            suspended: None,
+            promises: Handles::new(|it| matches!(it, PromiseState::Pending)),
+            events: EventLoop::default(),
+            debugger: None,
+            trace: None,
+            stats: stats::Stats::default(),
+            allocated: gc::allocations(),
+            output: output::Output::default(),
+            extensions: Box::new([None; 256]),
+            frames: Vec::new(),
+            depth: 0,
+            nesting: 0,
+            max_depth: MAX_DEPTH.load(Ordering::Relaxed),
+            host_stack: 0,
+            check_scopes: cfg!(debug_assertions),
+            rng: replay::time_seed(),
+            started: std::time::Instant::now(),
+            deterministic: None,
+            #[cfg(feature = "jit")]
+            jit: jit::Jit::default(),
+        };
+
+        prelude::load_yex(&mut vm);
+        // the prelude isn't traced, its instructions would only push the program's out
+        vm.default_trace();
+        vm
+    }
+}
+
+#[test]
+fn catch_native_panics() {
+    let fun = Fn::new_native(1, |_, _| panic!("boom"));
+    let mut vm = VirtualMachine::default();
+    vm.set_consts(vec![Value::Nil, Value::Fn(GcRef::new(fun))]);
+
+    let bytecode = [OpCode::Push(0), OpCode::Push(1), OpCode::Call(1)]
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .to_vec();
+    let err = vm.run(&bytecode).unwrap_err();
+
+    assert_eq!(err.err.as_str(), "NativeError");
+    assert!(err.msg.contains("boom"));
+}
+
+#[test]
+fn grow_the_locals() {
+    // a single frame with more locals than there's room for at first
+    let mut ops = vec![];
+    for i in 0..LOCALS * 2 {
+        ops.extend([OpCode::Push(0), OpCode::Save(i)]);
     }
+    ops.extend([OpCode::Load(LOCALS * 2 - 1), OpCode::Pop]);
+
+    let mut vm = VirtualMachine::default();
+    vm.set_consts(vec![Value::Num(1.0)]);
+    let bytecode = ops.into_iter().map(|op| OpCodeMetadata::new(1, 1, op));
+    assert!(vm.run(&bytecode.collect()).is_ok());
+    assert!(vm.locals.len() >= LOCALS * 2);
+}
+
+#[test]
+fn limit_the_recursion_depth() {
+    let body = [OpCode::Loag(Symbol::new("f")), OpCode::Call(0)]
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .to_vec();
+    let mut vm = VirtualMachine::default();
+    vm.set_global("f", Value::Fn(GcRef::new(Fn::new_bt(0, body.clone()))));
+    vm.set_max_depth(50);
+
+    let err = vm.run(&body).unwrap_err();
+    assert_eq!(err.err.as_str(), "RecursionError");
+    assert_eq!(vm.depth, 0);
+    // the calls from the same place are shown once
+    let trace: Vec<_> = err.trace.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        trace,
+        ["in f at 1:1 (repeated 48 more times)", "in <main> at 1:1"]
+    );
+}
+
+#[test]
+fn trace_uncaught_errors() {
+    let at = |line, ops: &[OpCode]| {
+        ops.iter()
+            .map(|op| OpCodeMetadata::new(line, 3, *op))
+            .collect::<Bytecode>()
+    };
+    let mut vm = VirtualMachine::default();
+    let fail = at(3, &[OpCode::Loag(Symbol::new("undefined"))]);
+    let call_fail = at(2, &[OpCode::Loag(Symbol::new("fail")), OpCode::Call(0)]);
+    vm.set_global("fail", Value::Fn(GcRef::new(Fn::new_bt(0, fail))));
+    let lambda = Value::Fn(GcRef::new(Fn::new_bt(0, call_fail)));
+    vm.set_consts(vec![lambda]);
+
+    let err = vm
+        .run(&at(1, &[OpCode::Push(0), OpCode::Call(0)]))
+        .unwrap_err();
+    assert_eq!(err.err.as_str(), "NameError");
+    assert_eq!(
+        err.to_string(),
+        "[3:3] :NameError\n  Undefined variable 'undefined'\n    in fail at 3:3\n    in <fn> at 2:3\n    \
+         in <main> at 1:3"
+    );
+}
+
+#[test]
+fn recurse_without_the_host_stack() {
+    let body = [OpCode::Loag(Symbol::new("f")), OpCode::Call(0)]
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .to_vec();
+
+    // the calls push frames instead of recursing in Rust, so a small stack is enough
+    let small = std::thread::Builder::new().stack_size(1 << 20);
+    let run = move || {
+        let mut vm = VirtualMachine::default();
+        vm.set_global("f", Value::Fn(GcRef::new(Fn::new_bt(0, body.clone()))));
+        vm.set_max_depth(100_000);
+
+        let err = vm.run(&body).unwrap_err();
+        assert_eq!(err.err.as_str(), "RecursionError");
+        assert_eq!(vm.stats().max_depth, 100_000);
+        assert!(vm.frames.is_empty());
+        assert_eq!((vm.depth, vm.used_locals), (0, 0));
+    };
+    small.spawn(run).unwrap().join().unwrap();
+}
+
+#[test]
+fn grow_the_stack_with_the_calls() {
+    // every call leaves a value on the stack for when the next one returns, like `1 + f ()`
+    let body = [
+        OpCode::Push(0),
+        OpCode::Loag(Symbol::new("f")),
+        OpCode::Call(0),
+    ]
+    .map(|op| OpCodeMetadata::new(1, 1, op))
+    .to_vec();
+
+    let mut vm = VirtualMachine::default();
+    vm.set_consts(vec![Value::Int(1)]);
+    vm.set_global("f", Value::Fn(GcRef::new(Fn::new_bt(0, body.clone()))));
+    vm.set_max_depth(STACK_SIZE + 100);
+
+    let err = vm.run(&body).unwrap_err();
+    assert_eq!(err.err.as_str(), "RecursionError");
+    assert!(vm.stats().max_stack > STACK_SIZE);
+    assert!(vm.frames.is_empty());
+}
+
+#[test]
+fn read_globals_by_name() {
+    let mut vm = VirtualMachine::default();
+    vm.set_global("width", Value::Num(640.0));
+
+    assert_eq!(vm.try_global::<f64>("width").unwrap(), Some(640.0));
+    assert_eq!(vm.try_global::<f64>("not defined").unwrap(), None);
+    assert!(vm.try_global::<bool>("width").is_err());
+
+    let frames = vm.global_entry("frames").or_insert(Value::Num(0.0));
+    *frames = Value::Num(1.0);
+    assert_eq!(vm.get_global_str("frames"), Some(Value::Num(1.0)));
+}
+
+#[test]
+fn complete_partially_applied_natives() {
+    let collect: NativeFn = |_, args| Ok(Value::List(args.into_iter().collect()));
+    let nums = |ns: &[f64]| ns.iter().map(|n| Value::Num(*n)).collect::<Vec<_>>();
+    let args = |ns: &[f64]| nums(ns).into_iter().collect::<FnArgs>();
+    let fun = Fn::new_native(4, collect)
+        .apply(&args(&[1.0, 2.0]))
+        .and_then(|fun| fun.apply(&args(&[3.0])))
+        .unwrap();
+
+    assert_eq!(fun.applied_args(), nums(&[1.0, 2.0, 3.0]));
+    assert_eq!(fun.remaining_arity(), 1);
+
+    let mut vm = VirtualMachine::default();
+    vm.set_consts(vec![Value::Num(4.0), Value::Fn(GcRef::new(fun))]);
+    let bytecode = [OpCode::Push(0), OpCode::Push(1), OpCode::Call(1)]
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .to_vec();
+    vm.run(&bytecode).unwrap();
+
+    let Value::List(args) = vm.pop_last().clone() else {
+        panic!("expected a list");
+    };
+    assert_eq!(
+        args.iter().cloned().collect::<Vec<_>>(),
+        nums(&[1.0, 2.0, 3.0, 4.0])
+    );
+}
+
+#[test]
+fn recur_only_inside_functions() {
+    let body = [OpCode::Recur]
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .to_vec();
+    let fun = Fn::new_bt(2, body.clone()).apply(&[Value::Nil].into_iter().collect());
+
+    let mut vm = VirtualMachine::default();
+    vm.set_consts(vec![Value::Nil, Value::Fn(GcRef::new(fun.unwrap()))]);
+    let call = [OpCode::Push(0), OpCode::Push(1), OpCode::Call(1)]
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .to_vec();
+    vm.run(&call).unwrap();
+
+    // the function before it was partially applied
+    let Value::Fn(recur) = vm.pop_last().clone() else {
+        panic!("expected a function");
+    };
+    assert_eq!((recur.arity, recur.args.len()), (2, 0));
+
+    let err = VirtualMachine::default().run(&body).unwrap_err();
+    assert_eq!(err.err.as_str(), "RecurError");
 }
@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+
+use crate::{
+    error::InterpretResult,
+    literal::{
+        fiber::{self, Wait},
+        nil,
+        yexmodule::builtin,
+    },
+    raise, Value, VirtualMachine,
+};
+
+/// A FIFO queue used by fibers to communicate, the channel itself lives in the VM and yex code
+/// only ever sees an immutable handle to it
+pub type Channel = VecDeque<Value>;
+
+/// Where the messages sent to a channel handle, a process id or a registered name go
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Mailbox {
+    /// A channel made by `Channel.new`
+    Channel(usize),
+    /// The mailbox of the process ran by a fiber
+    Process(usize),
+    /// The mailbox of the main program
+    Main,
+}
+
+impl Mailbox {
+    /// The queue of the messages
+    pub(crate) fn queue(self, vm: &mut VirtualMachine) -> &mut Channel {
+        match self {
+            Mailbox::Channel(id) => &mut vm.channels[id],
+            Mailbox::Process(id) => vm.fibers[id].mailbox.get_or_insert_with(Channel::new),
+            Mailbox::Main => &mut vm.mailbox,
+        }
+    }
+}
+
+/// Gets the mailbox of a channel handle, a process id or a registered process name
+pub(crate) fn mailbox(vm: &VirtualMachine, value: &Value) -> InterpretResult<Mailbox> {
+    match value {
+        pid if *pid == vm.pid => Ok(Mailbox::Main),
+        Value::Tagged(_, tag, tup) if tag.as_str() == "Channel.handle" => {
+            match vm.channels.id(tup) {
+                Some(id) => Ok(Mailbox::Channel(id)),
+                None => raise!(ChannelError, "Unknown channel '{}'", value),
+            }
+        }
+        Value::Tagged(_, tag, tup) if tag.as_str() == "Process.pid" => match vm.fibers.id(tup) {
+            Some(id) => Ok(Mailbox::Process(id)),
+            None => raise!(ProcessError, "Unknown process '{}'", value),
+        },
+        Value::Sym(name) => match vm.registry.get(name) {
+            Some(pid) => mailbox(vm, &pid),
+            None => raise!(ProcessError, "No process registered as '{}'", value),
+        },
+        other => raise!(TypeError, "Expected a channel, found '{}'", other),
+    }
+}
+
+pub(crate) fn new(vm: *mut VirtualMachine, _: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+
+    Ok(vm
+        .channels
+        .insert(builtin::channel(), "Channel.handle", Channel::new()))
+}
+
+pub(crate) fn send(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let mailbox = mailbox(vm, &args[0])?;
+
+    if !args[1].is_frozen() {
+        raise!(
+            FrozenError,
+            "Only frozen values can be sent, found '{}'",
+            args[1]
+        )?;
+    }
+
+    mailbox.queue(vm).push_back(args[1].clone());
+    Ok(nil())
+}
+
+/// Receives the oldest message from the channel, waiting for one if it's empty
+pub(crate) fn recv(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    mailbox(vm, &args[0])?;
+
+    fiber::wait(vm, Wait::Recv(args[0].clone()))
+}
+
+/// Receives a message without blocking, returning `nil` if the channel is empty
+pub(crate) fn try_recv(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let mailbox = mailbox(vm, &args[0])?;
+
+    Ok(mailbox.queue(vm).pop_front().unwrap_or_else(nil))
+}
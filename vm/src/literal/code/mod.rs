@@ -0,0 +1,200 @@
+use std::sync::OnceLock;
+
+use crate::{
+    env::EnvTable,
+    error::InterpretResult,
+    gc::GcRef,
+    literal::{fun::Fn, yexmodule::builtin, List, TryGet},
+    raise, signature, Symbol, Tuple, Value, VirtualMachine,
+};
+
+/// Compiles the nodes of an expression into a function of one ignored argument that evaluates
+/// it, returning the function and the constants it uses, or what's wrong with the nodes
+pub type Compile = fn(&Value) -> Result<(Value, Vec<Value>), String>;
+
+static COMPILER: OnceLock<Compile> = OnceLock::new();
+
+/// Sets the compiler used by `compile`, the virtual machine can't compile code by itself
+pub fn set_compiler(compile: Compile) {
+    COMPILER.get_or_init(|| compile);
+}
+
+macro_rules! nodes {
+    ($($kind:ident => [$($field:literal),*],)*) => {
+        /// The kinds of the nodes of quoted code, with the names of their fields, which are the
+        /// ones `yex ast` dumps. The kinds of the nodes of patterns start with `Pattern`
+        pub const NODES: &[(&str, &[&str])] = &[$((stringify!($kind), &[$($field),*])),*];
+
+        /// Adds the constructor of each node with fields, like `Code.Var line column name`, the
+        /// nodes without fields are values added once the type is shared
+        pub(crate) fn constructors(methods: &mut EnvTable) {
+            $({
+                const ARITY: usize = <[&str]>::len(&[$($field),*]);
+                if ARITY > 0 {
+                    methods.insert(
+                        Symbol::from(stringify!($kind)),
+                        Value::Fn(GcRef::new(
+                            Fn::new_native(0, |_, args| Ok(node(stringify!($kind), args)))
+                                .with_signature(signature!(
+                                    concat!("Code.", stringify!($kind)),
+                                    ARITY
+                                )),
+                        )),
+                    );
+                }
+            })*
+        }
+    };
+}
+
+nodes! {
+    If => ["line", "column", "cond", "then", "else"],
+    Let => ["line", "column", "pattern", "value", "body"],
+    Def => ["line", "column", "bind", "body"],
+    Bind => ["line", "column", "name", "value"],
+    Match => ["line", "column", "expr", "arms"],
+    Arm => ["line", "column", "pattern", "guard", "body"],
+    Receive => ["line", "column", "arms"],
+    Yield => ["line", "column", "value"],
+    Recur => ["line", "column"],
+    Lambda => ["line", "column", "args", "body"],
+    App => ["line", "column", "callee", "args", "tail"],
+    TryPipe => ["line", "column", "arg", "callee"],
+    Quote => ["line", "column", "expr"],
+    Unquote => ["line", "column", "value"],
+    Interp => ["line", "column", "pieces", "exprs"],
+    Dbg => ["line", "column", "source", "value"],
+    When => ["line", "column", "flag", "body"],
+    MethodRef => ["line", "column", "type", "method"],
+    With => ["line", "column", "bind", "value", "body"],
+    SetField => ["line", "column", "instance", "field", "value"],
+    Var => ["line", "column", "name"],
+    Lit => ["line", "column", "value"],
+    List => ["line", "column", "items"],
+    Binary => ["line", "column", "op", "left", "right"],
+    Cons => ["line", "column", "head", "tail"],
+    UnOp => ["line", "column", "op", "value"],
+    Try => ["line", "column", "body", "bind", "rescue"],
+    Tuple => ["line", "column", "items"],
+    PatternId => ["name"],
+    PatternLit => ["value"],
+    PatternVariant => ["path", "args"],
+    PatternTuple => ["items"],
+    PatternCons => ["head", "tail"],
+    PatternEmptyList => [],
+    PatternRange => ["start", "end"],
+    PatternPrefix => ["prefix", "rest"],
+    PatternSuffix => ["start", "suffix"],
+    PatternTyped => ["pattern", "type"],
+    PatternFields => ["fields"],
+}
+
+/// Creates a node of quoted code, of one of the kinds in [`NODES`]
+#[must_use]
+pub fn node(kind: &str, fields: Vec<Value>) -> Value {
+    Value::Tagged(
+        builtin::code(),
+        Symbol::new(format!("Code.{kind}")),
+        Tuple::from(fields),
+    )
+}
+
+/// Gets the kind of a node of quoted code and its fields
+#[must_use]
+pub fn kind_of(value: &Value) -> Option<(&str, &[Value])> {
+    match value {
+        Value::Tagged(_, tag, fields) => Some((tag.as_str().strip_prefix("Code.")?, &fields.0)),
+        _ => None,
+    }
+}
+
+/// The node of a value spliced into quoted code by `unquote`, at the place of the `unquote`.
+/// Nodes are spliced as they are, lists and tuples are spliced item by item
+fn to_node(value: &Value, line: &Value, column: &Value) -> InterpretResult<Value> {
+    let all = |items: &mut dyn Iterator<Item = &Value>| {
+        items
+            .map(|item| to_node(item, line, column))
+            .collect::<InterpretResult<Vec<_>>>()
+    };
+
+    Ok(match value {
+        _ if kind_of(value).is_some() => value.clone(),
+        Value::Int(_)
+        | Value::Num(_)
+        | Value::Str(_)
+        | Value::Sym(_)
+        | Value::Bool(_)
+        | Value::Nil => node("Lit", vec![line.clone(), column.clone(), value.clone()]),
+        Value::List(xs) => {
+            let items = all(&mut xs.iter())?.into_iter().collect::<List>();
+            node(
+                "List",
+                vec![line.clone(), column.clone(), Value::List(items)],
+            )
+        }
+        Value::Tuple(t) => {
+            let items = all(&mut t.0.iter())?.into_iter().collect::<List>();
+            node(
+                "Tuple",
+                vec![line.clone(), column.clone(), Value::List(items)],
+            )
+        }
+        other => raise!(TypeError, "'{}' can't be spliced into quoted code", other)?,
+    })
+}
+
+/// Replaces each `Code.Unquote` node holding the index of a value with the node of the value.
+/// The quotes in the code keep their own `unquote`s, which are spliced when they're evaluated
+fn fill(code: &Value, values: &[Value]) -> InterpretResult<Value> {
+    let all = |items: &mut dyn Iterator<Item = &Value>| {
+        items
+            .map(|item| fill(item, values))
+            .collect::<InterpretResult<Vec<_>>>()
+    };
+
+    Ok(match (code, kind_of(code)) {
+        (_, Some(("Unquote", [line, column, Value::Int(idx)]))) => {
+            match usize::try_from(*idx).ok().and_then(|idx| values.get(idx)) {
+                Some(value) => to_node(value, line, column)?,
+                None => code.clone(),
+            }
+        }
+        (_, Some(("Quote", _))) => code.clone(),
+        (Value::Tagged(ty, tag, fields), Some(_)) => {
+            Value::Tagged(ty.clone(), *tag, Tuple::from(all(&mut fields.0.iter())?))
+        }
+        (Value::List(xs), _) => Value::List(all(&mut xs.iter())?.into_iter().collect()),
+        (Value::Tuple(t), _) => Value::Tuple(Tuple::from(all(&mut t.0.iter())?)),
+        _ => code.clone(),
+    })
+}
+
+/// Splices the values of the `unquote`s of a quote into its code, the values are in the order
+/// of the `unquote`s
+pub(crate) fn splice(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let values: List = args[1].get()?;
+    fill(&args[0], &values.to_vec())
+}
+
+/// Compiles quoted code into a function, which evaluates it when called
+pub(crate) fn compile(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    if kind_of(&args[0]).is_none() {
+        return raise!(TypeError, "Expected quoted code, found '{}'", args[0]);
+    }
+
+    let Some(compile) = COMPILER.get() else {
+        return raise!(
+            CompileError,
+            "No compiler is available to compile quoted code"
+        );
+    };
+
+    match compile(&args[0]) {
+        Ok((fun, constants)) => {
+            vm.link(&mut [], constants);
+            Ok(fun)
+        }
+        Err(err) => raise!(CompileError, "{}", err),
+    }
+}
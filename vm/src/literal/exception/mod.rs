@@ -0,0 +1,81 @@
+//! The errors given to the `rescue` block of a `try`, as instances of the `Exception` type
+use std::{cell::Cell, sync::Arc};
+
+use crate::{
+    error::{InterpretError, InterpretResult},
+    gc::GcRef,
+    literal::{sendable::Sendable, yexmodule::builtin, TryGet},
+    raise, raise_err, Symbol, Tuple, Value, VirtualMachine,
+};
+
+/// The fields of an exception, in order
+pub const FIELDS: [&str; 3] = ["kind", "message", "data"];
+
+/// Builds an exception, its data is `nil` when it has none
+fn exception(kind: Symbol, message: &str, data: Value) -> Value {
+    let fields = vec![kind.into(), message.to_string().into(), data];
+    Value::Tagged(
+        builtin::exception(),
+        Symbol::from("Exception.new"),
+        Tuple::from(fields),
+    )
+}
+
+impl InterpretError {
+    /// The error as an exception, with its kind, its message and its data, which is built in
+    /// `vm`
+    #[must_use]
+    pub fn exception(&self, vm: &VirtualMachine) -> Value {
+        let data = self
+            .data
+            .as_ref()
+            .map_or(Value::Nil, |data| data.to_value(vm));
+        exception(self.err, &self.msg, data)
+    }
+}
+
+/// `Exception.new kind message data`, an exception to `throw`
+pub(crate) fn new(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let kind: Symbol = args[0].get()?;
+    let message: String = args[1].get()?;
+    Ok(exception(kind, &message, args[2].clone()))
+}
+
+/// `throw exception` raises an exception, like one built with `Exception.new` or rescued by a
+/// `try`, keeping its data
+pub(crate) fn throw(args: &[Value]) -> InterpretResult<Value> {
+    let fields = match &args[0] {
+        Value::Tagged(ty, _, fields) if GcRef::ptr_eq(ty, &builtin::exception()) => fields,
+        other => return raise!(TypeError, "Expected an exception, found {}", other),
+    };
+
+    let [kind, message, data] = &fields.0[..] else {
+        return raise!(TypeError, "Malformed exception {}", args[0]);
+    };
+    let data = match data {
+        Value::Nil => None,
+        data => Some(Arc::new(Sendable::new(data).map_err(|_| {
+            raise_err!(TypeError, "'{}' can't be thrown with an exception", data)
+        })?)),
+    };
+    Err(InterpretError {
+        err: kind.get()?,
+        msg: message.get()?,
+        line: crate::LINE.with(Cell::get),
+        column: crate::COLUMN.with(Cell::get),
+        trace: vec![],
+        data,
+    })
+}
+
+#[test]
+fn throw_exceptions_with_data() {
+    let vm = VirtualMachine::default();
+    let data = Value::Tuple(Tuple::from(vec![Value::Num(1.0)]));
+    let thrown = exception(Symbol::from("Custom"), "oops", data);
+
+    let err = throw(std::slice::from_ref(&thrown)).unwrap_err();
+    assert_eq!((err.err.as_str(), &*err.msg), ("Custom", "oops"));
+    assert_eq!(err.exception(&vm), thrown);
+    assert!(throw(&[Value::Nil]).is_err_and(|err| err.err.as_str() == "TypeError"));
+}
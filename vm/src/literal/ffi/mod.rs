@@ -3,13 +3,18 @@ pub mod userdata;
 
 use std::fmt::{Debug, Display};
 
-use crate::{gc::GcRef, EnvTable, Symbol, Value};
+use crate::{
+    gc::{GcRef, Trace},
+    EnvTable, Symbol, Value,
+};
 use dlopen::wrapper::{Container, WrapperApi};
 use dlopen_derive::WrapperApi;
 #[derive(WrapperApi)]
 pub struct Api {
     init: fn() -> EnvTable,
 }
+impl Trace for Container<Api> {}
+
 #[derive(Clone)]
 pub struct Ffi {
     module: GcRef<Container<Api>>,
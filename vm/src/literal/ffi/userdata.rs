@@ -1,8 +1,10 @@
 use std::any::Any;
 
-use crate::gc::GcRef;
+use crate::gc::{GcRef, Trace};
 #[derive(Debug, Clone)]
 pub struct UserData(pub GcRef<Box<dyn Any>>);
+impl Trace for Box<dyn Any> {}
+
 impl UserData {
     pub fn new<T>(val: T) -> Self
     where
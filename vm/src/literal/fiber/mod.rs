@@ -0,0 +1,374 @@
+use std::mem;
+
+use crate::{
+    error::{InterpretError, InterpretResult},
+    frame::CallFrame,
+    literal::{
+        channel::{self, Channel, Mailbox},
+        nil, process, promise,
+        yexmodule::builtin,
+    },
+    raise, raise_err, Value, VirtualMachine,
+};
+
+/// How many calls and loops a fiber runs before letting the others run
+const TURN: usize = 100;
+
+/// The state of a spawned fiber
+#[derive(Debug, Clone)]
+pub enum FiberState {
+    /// Spawned, but not started yet
+    Ready,
+    /// Currently running
+    Running,
+    /// Stopped at a call or a loop, to let the others run or to wait for something
+    Suspended(Suspended),
+    /// Finished, with its return value
+    Done(Value),
+    /// Finished by raising an error
+    Failed(InterpretError),
+}
+
+/// The frames of a suspended fiber, with the values they had on the stack and in the locals
+#[derive(Debug, Clone)]
+pub struct Suspended {
+    /// The stack bases of the frames are counted from the start of `stack`
+    frames: Vec<CallFrame>,
+    stack: Vec<Value>,
+    locals: Vec<Value>,
+    /// What the fiber waits for, `None` if it only let the others run
+    wait: Option<Wait>,
+}
+
+/// What a fiber can wait for, the value that ends the wait is the result of the call that
+/// started it
+#[derive(Debug, Clone)]
+pub(crate) enum Wait {
+    /// A message sent to a channel, a process id or a registered name
+    Recv(Value),
+    /// A message in a mailbox accepted by the predicate
+    Receive(Mailbox, Value),
+    /// Another fiber to finish
+    Join(Value),
+    /// A promise to finish
+    Await(Value),
+}
+
+impl Wait {
+    /// Ends the wait if it can, returning the value (or the error) it ends with
+    fn poll(&self, vm: &mut VirtualMachine) -> Option<InterpretResult<Value>> {
+        match self {
+            Wait::Recv(channel) => match channel::mailbox(vm, channel) {
+                Ok(mailbox) => mailbox.queue(vm).pop_front().map(Ok),
+                Err(e) => Some(Err(e)),
+            },
+            Wait::Receive(mailbox, predicate) => process::take(vm, *mailbox, predicate).transpose(),
+            Wait::Join(fiber) => match handle_id(vm, fiber) {
+                Ok(id) => match &vm.fibers[id].state {
+                    FiberState::Done(value) => Some(Ok(value.clone())),
+                    FiberState::Failed(err) => Some(Err(err.clone())),
+                    _ => None,
+                },
+                Err(e) => Some(Err(e)),
+            },
+            Wait::Await(promise) => promise::result(vm, promise),
+        }
+    }
+
+    /// The error raised when nothing can end the wait
+    fn deadlock(&self) -> InterpretError {
+        match self {
+            Wait::Recv(channel) => raise_err!(
+                ChannelError,
+                "Channel '{}' is empty and no fiber can send to it",
+                channel
+            ),
+            Wait::Receive(..) => raise_err!(
+                ProcessError,
+                "No message matches the 'receive' and no process can send one"
+            ),
+            Wait::Join(fiber) => raise_err!(
+                FiberError,
+                "Fiber '{}' can't finish, joining it would deadlock",
+                fiber
+            ),
+            Wait::Await(promise) => raise_err!(PromiseError, "Promise '{}' can't finish", promise),
+        }
+    }
+}
+
+/// What the VM knows about the running fiber
+#[derive(Debug, Default)]
+pub(crate) struct Scheduler {
+    /// The nesting of the loop the running fiber started in, which it can only be suspended
+    /// from, since the loops and the natives inside it run in Rust calls
+    pub level: Option<usize>,
+    /// How many calls and loops the running fiber makes before it lets the others run
+    pub turn: usize,
+    /// What the running fiber waits for, set by the native it called
+    pub waiting: Option<Wait>,
+    /// If the running fiber stopped its loop to be suspended
+    pub switching: bool,
+    /// The fiber the search for the next one to run starts from
+    pub next: usize,
+}
+
+/// A lightweight VM-level thread
+///
+/// The scheduler is cooperative: fibers run while the main program waits for one of them, a
+/// message or a promise, and take turns at their calls and loops, or when they wait themselves.
+#[derive(Debug, Clone)]
+pub struct Fiber {
+    /// The function ran by the fiber, it's called with `nil`
+    pub body: Value,
+    /// The current fiber state
+    pub state: FiberState,
+    /// The mailbox, if the fiber is a process
+    pub mailbox: Option<Channel>,
+}
+
+impl Fiber {
+    /// Creates a new fiber, ready to be started
+    #[must_use]
+    pub fn new(body: Value) -> Self {
+        Self {
+            body,
+            state: FiberState::Ready,
+            mailbox: None,
+        }
+    }
+
+    /// Checks if the fiber finished, either by returning or by raising an error
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, FiberState::Done(_) | FiberState::Failed(_))
+    }
+}
+
+/// Gets the fiber id from a fiber handle or a process id
+fn handle_id(vm: &VirtualMachine, value: &Value) -> InterpretResult<usize> {
+    match value {
+        pid if *pid == vm.pid => raise!(FiberError, "The main process can't be joined"),
+        Value::Tagged(_, tag, tup) if matches!(tag.as_str(), "Fiber.handle" | "Process.pid") => {
+            match vm.fibers.id(tup) {
+                Some(id) => Ok(id),
+                None => raise!(FiberError, "Unknown fiber '{}'", value),
+            }
+        }
+        other => raise!(TypeError, "Expected a fiber handle, found '{}'", other),
+    }
+}
+
+impl VirtualMachine {
+    /// Counts a call or a loop of the running fiber, returning if it has to be suspended, either
+    /// because it waits for something or because its turn is over
+    #[inline]
+    pub(crate) fn fiber_turn(&mut self) -> bool {
+        if self.scheduler.level != Some(self.nesting) {
+            return false;
+        }
+
+        self.scheduler.turn = self.scheduler.turn.saturating_sub(1);
+        self.scheduler.turn == 0 || self.scheduler.waiting.is_some()
+    }
+
+    /// Stops the loop of the running fiber, leaving its frames for [`run`] to suspend
+    pub(crate) fn switch_fiber(&mut self, frame: CallFrame) {
+        self.frames.push(frame);
+        self.scheduler.switching = true;
+    }
+}
+
+/// Runs the fiber `id` until it finishes or is suspended, `resume` is the value that ended its
+/// wait
+fn run(vm: &mut VirtualMachine, id: usize, resume: Option<InterpretResult<Value>>) {
+    let parent = vm.current_fiber.replace(id);
+    let level = vm.scheduler.level.replace(vm.nesting + 1);
+    let turn = mem::replace(&mut vm.scheduler.turn, TURN);
+
+    let stack_len = vm.stack.len();
+    let used_locals = vm.used_locals;
+    let depth = vm.depth;
+    let base = vm.frames.len();
+
+    let res = match mem::replace(&mut vm.fibers[id].state, FiberState::Running) {
+        FiberState::Ready => {
+            // the fiber only runs once, so it doesn't need to keep what the body captured
+            let body = mem::replace(&mut vm.fibers[id].body, nil());
+            vm.push(nil());
+            vm.push(body);
+
+            match vm.enter_call(1) {
+                Ok(Some((fun, args))) => vm
+                    .push_frame(fun, args)
+                    .and_then(|frame| vm.run_loop(&[], frame, base)),
+                res => res.map(|_| ()),
+            }
+        }
+        FiberState::Suspended(suspended) => resume_frames(vm, suspended, resume),
+        _ => unreachable!(),
+    };
+
+    let state = if mem::take(&mut vm.scheduler.switching) {
+        let wait = vm.scheduler.waiting.take();
+        if wait.is_some() {
+            // the value the native returned, the one that ends the wait replaces it
+            vm.pop();
+        }
+
+        let mut frames = vm.frames.split_off(base);
+        for frame in &mut frames {
+            frame.stack_base -= stack_len;
+        }
+
+        FiberState::Suspended(Suspended {
+            frames,
+            stack: vm.stack.split_off(stack_len),
+            locals: vm.locals[used_locals..vm.used_locals].to_vec(),
+            wait,
+        })
+    } else {
+        let state = match res {
+            Ok(()) => FiberState::Done(vm.pop()),
+            Err(e) => FiberState::Failed(e),
+        };
+
+        // a failed call may leave garbage behind
+        vm.stack.truncate(stack_len);
+        state
+    };
+
+    vm.used_locals = used_locals;
+    vm.depth = depth;
+    vm.fibers[id].state = state;
+    vm.scheduler.level = level;
+    vm.scheduler.turn = turn;
+    vm.current_fiber = parent;
+}
+
+/// Puts the frames of a suspended fiber back and runs them, raising the error that ended its
+/// wait, if any
+fn resume_frames(
+    vm: &mut VirtualMachine,
+    suspended: Suspended,
+    resume: Option<InterpretResult<Value>>,
+) -> InterpretResult<()> {
+    let Suspended {
+        mut frames,
+        stack,
+        locals,
+        wait: _,
+    } = suspended;
+
+    let stack_len = vm.stack.len();
+    for value in stack {
+        vm.push(value);
+    }
+
+    for local in locals {
+        vm.set_local(vm.used_locals, local);
+        vm.used_locals += 1;
+    }
+
+    for frame in &mut frames {
+        frame.stack_base += stack_len;
+    }
+
+    vm.depth += frames.len();
+    let mut frame = frames.pop().expect("a suspended fiber has a frame");
+    let base = vm.frames.len();
+    vm.frames.extend(frames);
+
+    match resume {
+        Some(Ok(value)) => vm.push(value),
+        Some(Err(e)) => {
+            // the error is raised by the call the frame was running
+            frame.ip -= 1;
+            vm.catch(&mut frame, &[], base, e)?;
+        }
+        None => (),
+    }
+
+    vm.run_loop(&[], frame, base)
+}
+
+/// Runs a turn of the next fiber that can run, or waits for the next promise to finish if none
+/// can, returns `false` if there's nothing to wait for
+pub(crate) fn run_next(vm: &mut VirtualMachine) -> bool {
+    let resolved = promise::resolve_finished(vm);
+
+    let ids = vm.fibers.iter().map(|(id, _)| id).collect::<Vec<_>>();
+    let start = ids.partition_point(|id| *id < vm.scheduler.next);
+
+    for &id in ids[start..].iter().chain(&ids[..start]) {
+        let resume = match &vm.fibers[id].state {
+            FiberState::Ready | FiberState::Suspended(Suspended { wait: None, .. }) => None,
+            FiberState::Suspended(Suspended {
+                wait: Some(wait), ..
+            }) => match wait.clone().poll(vm) {
+                Some(res) => Some(res),
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        vm.scheduler.next = id + 1;
+        run(vm, id, resume);
+        return true;
+    }
+
+    // the wait may be over if a promise finished since it was polled
+    resolved || promise::wait_next(vm)
+}
+
+/// Waits for `wait` to end, returning the value it ends with
+///
+/// A fiber called from its own loop is suspended, and the native returns a placeholder, which
+/// the value replaces once the fiber is resumed. Otherwise, the other fibers run until the wait
+/// ends, or raise an error if none of them can end it.
+pub(crate) fn wait(vm: &mut VirtualMachine, wait: Wait) -> InterpretResult<Value> {
+    if let Some(res) = wait.poll(vm) {
+        return res;
+    }
+
+    // the native is called right from the loop of the fiber
+    if vm.scheduler.level.map(|level| level + 1) == Some(vm.nesting) {
+        vm.scheduler.waiting = Some(wait);
+        return Ok(nil());
+    }
+
+    loop {
+        if !run_next(vm) {
+            return Err(wait.deadlock());
+        }
+
+        if let Some(res) = wait.poll(vm) {
+            return res;
+        }
+    }
+}
+
+pub(crate) fn spawn(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+
+    let fiber = Fiber::new(args[0].clone());
+    Ok(vm.fibers.insert(builtin::fiber(), "Fiber.handle", fiber))
+}
+
+pub(crate) fn join(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let id = handle_id(vm, &args[0])?;
+
+    if vm.current_fiber == Some(id) {
+        raise!(FiberError, "A fiber can't join itself, it would deadlock")?;
+    }
+
+    wait(vm, Wait::Join(args[0].clone()))
+}
+
+pub(crate) fn is_done(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let id = handle_id(vm, &args[0])?;
+
+    Ok(vm.fibers[id].is_done().into())
+}
@@ -1,6 +1,11 @@
+mod signature;
+
 use crate::{
-    error::InterpretResult, gc::GcRef, stackvec, Bytecode, StackVec, Value, VirtualMachine,
+    error::{InterpretError, InterpretResult},
+    gc::{GcRef, Trace, Traced},
+    raise_err, Bytecode, StackVec, Value, VirtualMachine,
 };
+pub use signature::{Param, Signature};
 pub type NativeFn = fn(*mut VirtualMachine, Vec<Value>) -> InterpretResult<Value>;
 pub type FnBody = GcRef<FnKind>;
 pub type FnArgs = StackVec<Value, 8>;
@@ -12,6 +17,8 @@ pub enum FnKind {
     Native(NativeFn),
     /// A function defined in the source code.
     Bytecode(Bytecode),
+    /// A function defined in the source code that `yield`s, calling it creates a generator.
+    Generator(Bytecode),
 }
 
 #[derive(PartialEq, Clone)]
@@ -23,6 +30,22 @@ pub struct Fn {
     pub body: FnBody,
     /// The function Arguments
     pub args: FnArgs,
+    /// The signature of native functions, checked before calling them
+    pub signature: Option<&'static Signature>,
+    /// The values of the enclosing locals a closure uses, read with `Loac`
+    pub captures: Box<[Value]>,
+}
+
+impl Trace for FnKind {}
+
+impl Trace for Fn {
+    const TRACED: bool = true;
+
+    fn trace(&self, visit: &mut dyn FnMut(Traced)) {
+        for arg in self.args.iter().chain(self.captures.iter()) {
+            arg.trace(visit);
+        }
+    }
 }
 
 impl Fn {
@@ -33,6 +56,8 @@ impl Fn {
             arity,
             body: GcRef::new(FnKind::Bytecode(body)),
             args: FnArgs::new(),
+            signature: None,
+            captures: Box::new([]),
         }
     }
 
@@ -42,6 +67,18 @@ impl Fn {
             arity,
             body: GcRef::new(FnKind::Native(native)),
             args: FnArgs::new(),
+            signature: None,
+            captures: Box::new([]),
+        }
+    }
+
+    /// Sets the signature of a native function, its arity is the number of parameters
+    #[must_use]
+    pub fn with_signature(self, signature: &'static Signature) -> Self {
+        Self {
+            arity: signature.params.len(),
+            signature: Some(signature),
+            ..self
         }
     }
 
@@ -52,20 +89,57 @@ impl Fn {
     }
 
     /// Apply the function to the given arguments
-    #[must_use]
-    pub fn apply(&self, app: &FnArgs) -> Self {
-        let mut args = stackvec![];
-        for arg in app.iter().rev().chain(self.args.iter()) {
-            args.push(arg.clone());
-        }
+    /// # Errors
+    /// Raises a `CallError` if the function ends up applied to more arguments than [`FnArgs`]
+    /// can hold
+    pub fn apply(&self, app: &FnArgs) -> InterpretResult<Self> {
+        let args = FnArgs::try_from_iter(app.iter().rev().chain(self.args.iter()).cloned())
+            .ok_or_else(|| self.too_many_args())?;
 
-        Fn {
+        Ok(Fn {
             arity: self.arity + self.args.len() - args.len(),
             body: self.body.clone(),
             args,
+            signature: self.signature,
+            captures: self.captures.clone(),
+        })
+    }
+
+    /// The error raised when the arguments applied to the function don't fit in [`FnArgs`]
+    pub(crate) fn too_many_args(&self) -> InterpretError {
+        raise_err!(
+            CallError,
+            "Partial application of {} can't hold more than {} arguments",
+            self,
+            FnArgs::new().capacity()
+        )
+    }
+
+    /// The arguments the function was partially applied to, in the order they were given, since
+    /// [`Fn::args`] keeps the most recent ones first
+    #[must_use]
+    pub fn applied_args(&self) -> Vec<Value> {
+        self.args.iter().rev().cloned().collect()
+    }
+
+    /// The function before any argument was applied to it
+    #[must_use]
+    pub fn unapplied(&self) -> Self {
+        Self {
+            arity: self.arity + self.args.len(),
+            body: self.body.clone(),
+            args: FnArgs::new(),
+            signature: self.signature,
+            captures: self.captures.clone(),
         }
     }
 
+    /// How many arguments are still missing to call the function
+    #[must_use]
+    pub fn remaining_arity(&self) -> usize {
+        self.arity
+    }
+
     /// Checks if the function is a native function
     #[must_use]
     pub fn is_native(&self) -> bool {
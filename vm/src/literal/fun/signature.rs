@@ -0,0 +1,199 @@
+use std::fmt;
+
+use crate::{error::InterpretResult, raise, Value};
+
+#[macro_export]
+/// Declares the signature of a native function, as a `&'static Signature`, the parameters can be
+/// given by their types, or by their number if they accept any value
+macro_rules! signature {
+    ($name:expr, [$($param:ident),*]) => {{
+        static SIGNATURE: $crate::literal::fun::Signature = $crate::literal::fun::Signature {
+            name: $name,
+            params: &[$($crate::literal::fun::Param::$param),*],
+        };
+        &SIGNATURE
+    }};
+    ($name:expr, $arity:expr) => {{
+        static SIGNATURE: $crate::literal::fun::Signature = $crate::literal::fun::Signature {
+            name: $name,
+            params: &[$crate::literal::fun::Param::Any; $arity],
+        };
+        &SIGNATURE
+    }};
+}
+
+/// The values a parameter of a native function accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    /// Any value
+    Any,
+    /// Numbers, integers or floats
+    Num,
+    /// Integers
+    Int,
+    /// Strings
+    Str,
+    /// Booleans
+    Bool,
+    /// Symbols
+    Sym,
+    /// Lists
+    List,
+    /// Functions, and symbols, which are called to get a field
+    Fn,
+    /// Tuples
+    Tuple,
+    /// Numbers that are non-negative integers, like the indices of lists
+    Index,
+}
+
+impl Param {
+    fn accepts(self, value: &Value) -> bool {
+        if let (Param::Index, Value::Num(_) | Value::Int(_)) = (self, value) {
+            return is_index(value);
+        }
+
+        matches!(
+            (self, value),
+            (Param::Any, _)
+                | (Param::Num, Value::Num(_) | Value::Int(_))
+                | (Param::Int, Value::Int(_))
+                | (Param::Str, Value::Str(_))
+                | (Param::Bool, Value::Bool(_))
+                | (Param::Sym, Value::Sym(_))
+                | (Param::List, Value::List(_))
+                | (Param::Fn, Value::Fn(_) | Value::Sym(_))
+                | (Param::Tuple, Value::Tuple(_))
+        )
+    }
+}
+
+/// Checks if the number can index a list, floats can if they have no decimals
+fn is_index(n: &Value) -> bool {
+    match n {
+        Value::Int(n) => *n >= 0,
+        Value::Num(n) => *n >= 0.0 && n.is_finite() && n.fract() == 0.0,
+        _ => false,
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Param::Any => write!(f, "_"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+/// The name and the parameters of a native function, checked before calling it so the errors
+/// can tell which function was called wrong
+#[derive(Debug, PartialEq, Eq)]
+pub struct Signature {
+    /// The name the function is called by
+    pub name: &'static str,
+    /// What each parameter accepts
+    pub params: &'static [Param],
+}
+
+impl Signature {
+    /// Checks the number of arguments of a call
+    pub fn check_arity(&self, arity: usize) -> InterpretResult<()> {
+        if arity != self.params.len() {
+            raise!(
+                ArityError,
+                "'{}' takes {} argument(s), found {}",
+                self,
+                self.params.len(),
+                arity
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Checks the type of the arguments of a call
+    pub fn check(&self, args: &[Value]) -> InterpretResult<()> {
+        self.check_arity(args.len())?;
+
+        for (index, (param, arg)) in self.params.iter().zip(args).enumerate() {
+            if let (Param::Index, Value::Num(_) | Value::Int(_)) = (param, arg) {
+                if !is_index(arg) {
+                    raise!(
+                        ValueError,
+                        "Expected a non-negative integer for argument {} of '{}', found '{}'",
+                        index + 1,
+                        self,
+                        arg
+                    )?;
+                }
+            } else if !param.accepts(arg) {
+                raise!(
+                    TypeError,
+                    "Expected '{}' for argument {} of '{}', found '{}'",
+                    param,
+                    index + 1,
+                    self,
+                    arg.type_of().name
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for param in self.params {
+            write!(f, " {param}")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn check_signatures() {
+    static SIGNATURE: Signature = Signature {
+        name: "raise",
+        params: &[Param::Sym, Param::Str],
+    };
+
+    let args = [
+        Value::from(crate::Symbol::from("Error")),
+        Value::from(String::from("oops")),
+    ];
+    assert!(SIGNATURE.check(&args).is_ok());
+
+    let err = SIGNATURE.check(&args[..1]).unwrap_err();
+    assert_eq!(err.err.as_str(), "ArityError");
+    assert_eq!(err.msg, "'raise Sym Str' takes 2 argument(s), found 1");
+
+    let err = SIGNATURE
+        .check(&[args[1].clone(), args[1].clone()])
+        .unwrap_err();
+    assert_eq!(err.err.as_str(), "TypeError");
+    assert_eq!(
+        err.msg,
+        "Expected 'Sym' for argument 1 of 'raise Sym Str', found 'Str'"
+    );
+}
+
+#[test]
+fn check_index_params() {
+    static GET: Signature = Signature {
+        name: "List.get",
+        params: &[Param::Index, Param::List],
+    };
+    let xs = Value::List(crate::List::new());
+    assert!(GET.check(&[Value::Int(0), xs.clone()]).is_ok());
+    assert!(GET.check(&[Value::Num(0.0), xs.clone()]).is_ok());
+
+    let err = GET.check(&[Value::Int(-1), xs.clone()]).unwrap_err();
+    assert_eq!(err.err.as_str(), "ValueError");
+    assert_eq!(
+        err.msg,
+        "Expected a non-negative integer for argument 1 of 'List.get Index List', found '-1'"
+    );
+    let err = GET.check(&[Value::Num(1.5), xs]).unwrap_err();
+    assert!(err.msg.ends_with("found '1.5'"));
+}
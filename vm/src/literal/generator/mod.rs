@@ -0,0 +1,172 @@
+use std::mem;
+
+use crate::{
+    error::InterpretResult,
+    gc::GcRef,
+    literal::{nil, yexmodule::builtin, TryGet},
+    raise, Fn, FnKind, List, Value, VirtualMachine,
+};
+
+/// A bytecode frame, with everything needed to resume its execution
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    /// The instruction to resume at
+    pub ip: usize,
+    /// The frame local variables
+    pub locals: Vec<Value>,
+    /// The values the frame left on the stack
+    pub stack: Vec<Value>,
+    /// The addresses of the enclosing `try` blocks, with the number of scopes open when they
+    /// started
+    pub try_stack: Vec<(usize, usize)>,
+    /// The number of locals the frame had when each of its open scopes was entered
+    pub scopes: Vec<usize>,
+    /// If the frame belongs to a generator, and so is allowed to `yield`
+    pub generator: bool,
+    /// The function running in the frame, which `recur` refers to
+    pub callee: Option<GcRef<Fn>>,
+}
+
+/// The state of a generator
+#[derive(Debug, Clone)]
+pub enum GeneratorState {
+    /// Created, but not started yet, with the arguments it was called with
+    Ready(Vec<Value>),
+    /// Suspended by a `yield`
+    Suspended(Frame),
+    /// Currently running
+    Running,
+    /// Finished, either by returning or by raising an error
+    Done,
+}
+
+/// A suspended call to a function that `yield`s values
+///
+/// Only the generator's own frame can be suspended, so a `yield` always belongs to the innermost
+/// function that contains it.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    /// The generator function
+    pub fun: GcRef<Fn>,
+    /// The current generator state
+    pub state: GeneratorState,
+}
+
+impl Generator {
+    /// Creates a new generator, ready to be started
+    #[must_use]
+    pub fn new(fun: GcRef<Fn>, args: Vec<Value>) -> Self {
+        Self {
+            fun,
+            state: GeneratorState::Ready(args),
+        }
+    }
+}
+
+pub(crate) fn handle(vm: &mut VirtualMachine, generator: Generator) -> Value {
+    vm.generators
+        .insert(builtin::generator(), "Generator.handle", generator)
+}
+
+fn handle_id(vm: &VirtualMachine, value: &Value) -> InterpretResult<usize> {
+    match value {
+        Value::Tagged(_, tag, tup) if tag.as_str() == "Generator.handle" => {
+            match vm.generators.id(tup) {
+                Some(id) => Ok(id),
+                None => raise!(GeneratorError, "Unknown generator '{}'", value),
+            }
+        }
+        other => raise!(TypeError, "Expected a generator, found '{}'", other),
+    }
+}
+
+/// Runs the generator `id` until its next `yield`, returns `None` once it's finished
+fn resume(vm: &mut VirtualMachine, id: usize) -> InterpretResult<Option<Value>> {
+    let fun = vm.generators[id].fun.clone();
+    let FnKind::Generator(bytecode) = &*fun.body else {
+        unreachable!()
+    };
+
+    let frame = match mem::replace(&mut vm.generators[id].state, GeneratorState::Running) {
+        GeneratorState::Ready(args) => Frame {
+            stack: args,
+            generator: true,
+            callee: Some(fun.clone()),
+            ..Frame::default()
+        },
+        GeneratorState::Suspended(frame) => frame,
+        GeneratorState::Running => raise!(GeneratorError, "Generator '{}' is already running", id)?,
+        GeneratorState::Done => {
+            vm.generators[id].state = GeneratorState::Done;
+            return Ok(None);
+        }
+    };
+
+    let stack_len = vm.stack.len();
+
+    vm.used_locals += 1;
+    let res = vm.run_frame(bytecode, frame);
+    vm.used_locals -= 1;
+
+    if let Err(e) = res {
+        // a failed run may leave garbage behind
+        while vm.stack.len() > stack_len {
+            vm.pop();
+        }
+
+        vm.generators[id].state = GeneratorState::Done;
+        return Err(e);
+    }
+
+    let value = vm.pop();
+    if let Some(frame) = vm.suspended.take() {
+        vm.generators[id].state = GeneratorState::Suspended(frame);
+        Ok(Some(value))
+    } else {
+        // the returned value isn't yielded
+        vm.generators[id].state = GeneratorState::Done;
+        Ok(None)
+    }
+}
+
+pub(crate) fn next(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let id = handle_id(vm, &args[0])?;
+
+    Ok(resume(vm, id)?.unwrap_or_else(nil))
+}
+
+pub(crate) fn is_done(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let id = handle_id(vm, &args[0])?;
+
+    Ok(matches!(vm.generators[id].state, GeneratorState::Done).into())
+}
+
+pub(crate) fn take(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let n: usize = args[0].get()?;
+    let id = handle_id(vm, &args[1])?;
+
+    let mut xs = vec![];
+    while xs.len() < n {
+        let Some(x) = resume(vm, id)? else {
+            break;
+        };
+        xs.push(x);
+    }
+
+    Ok(Value::List(xs.into_iter().collect::<List>()))
+}
+
+pub(crate) fn to_list(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let id = handle_id(vm, &args[0])?;
+
+    let mut xs = vec![];
+    while let Some(x) = resume(vm, id)? {
+        xs.push(x);
+    }
+
+    Ok(Value::List(xs.into_iter().collect::<List>()))
+}
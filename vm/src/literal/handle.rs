@@ -0,0 +1,179 @@
+//! The tables of the values yex code only refers to through handles, like fibers and channels
+//!
+//! A handle is a tagged value holding the id of its entry, and the table keeps a clone of it, so
+//! once the table has the last reference to a handle nothing can reach the entry anymore. Those
+//! entries are freed when the table grows, and their ids are given to the new ones.
+use std::ops::{Index, IndexMut};
+
+use crate::{
+    gc::GcRef,
+    literal::{yexmodule::YexModule, TryGet},
+    Symbol, Tuple, Value,
+};
+
+/// How many entries a table holds before looking for the ones it can free, at least
+const MIN_SWEEP: usize = 64;
+
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    /// The handle's tuple, which its clones share
+    handle: Tuple,
+    ty: GcRef<YexModule>,
+    tag: Symbol,
+    value: T,
+}
+
+/// A table of values referred to by handles, with their ids reused once they are freed
+#[derive(Debug, Clone)]
+pub struct Handles<T> {
+    entries: Vec<Option<Entry<T>>>,
+    /// The ids of the freed entries
+    free: Vec<usize>,
+    /// How many entries are in use when the table looks for the ones it can free
+    sweep_at: usize,
+    /// If an entry no handle refers to is still needed, like a fiber that didn't finish
+    keep: fn(&T) -> bool,
+}
+
+impl<T> Handles<T> {
+    /// Creates an empty table, `keep` tells which entries can't be freed even when there's no
+    /// handle left to them
+    #[must_use]
+    pub fn new(keep: fn(&T) -> bool) -> Self {
+        Self {
+            entries: Vec::new(),
+            free: Vec::new(),
+            sweep_at: MIN_SWEEP,
+            keep,
+        }
+    }
+
+    /// Adds `value` to the table, returning the `tag` handle to it
+    pub fn insert(&mut self, ty: GcRef<YexModule>, tag: &str, value: T) -> Value {
+        if self.free.is_empty() && self.entries.len() >= self.sweep_at {
+            self.sweep();
+        }
+
+        let id = self.free.pop().unwrap_or(self.entries.len());
+        let handle = Tuple::from(vec![Value::from(id)]);
+        let entry = Some(Entry {
+            handle,
+            ty,
+            tag: Symbol::from(tag),
+            value,
+        });
+
+        if id == self.entries.len() {
+            self.entries.push(entry);
+        } else {
+            self.entries[id] = entry;
+        }
+
+        self.handle(id)
+    }
+
+    /// Frees the entries that nothing refers to anymore
+    fn sweep(&mut self) {
+        for (id, slot) in self.entries.iter_mut().enumerate() {
+            let unused = slot
+                .as_ref()
+                .is_some_and(|it| it.handle.0.is_unique() && !(self.keep)(&it.value));
+
+            if unused {
+                *slot = None;
+                self.free.push(id);
+            }
+        }
+
+        // the ids are given lowest first
+        self.free.sort_unstable_by(|a, b| b.cmp(a));
+        let used = self.entries.len() - self.free.len();
+        self.sweep_at = (used * 2).max(MIN_SWEEP);
+    }
+
+    /// Gets the id of the entry a handle's tuple refers to, `None` if it isn't one of the
+    /// table's handles
+    #[must_use]
+    pub fn id(&self, handle: &Tuple) -> Option<usize> {
+        let id: usize = handle.0.first()?.get().ok()?;
+
+        match self.entries.get(id)? {
+            Some(it) if GcRef::ptr_eq(&it.handle.0, &handle.0) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// The handle to the entry `id`
+    #[must_use]
+    pub fn handle(&self, id: usize) -> Value {
+        let entry = self.entry(id);
+        Value::Tagged(entry.ty.clone(), entry.tag, entry.handle.clone())
+    }
+
+    /// The ids and values of the entries in use, lowest id first
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(id, slot)| slot.as_ref().map(|it| (id, &it.value)))
+    }
+
+    /// How many entries are in use
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len() - self.free.len()
+    }
+
+    /// Checks if the table has no entries in use
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn entry(&self, id: usize) -> &Entry<T> {
+        self.entries[id].as_ref().expect("the entry was freed")
+    }
+}
+
+impl<T> Index<usize> for Handles<T> {
+    type Output = T;
+
+    fn index(&self, id: usize) -> &T {
+        &self.entry(id).value
+    }
+}
+
+impl<T> IndexMut<usize> for Handles<T> {
+    fn index_mut(&mut self, id: usize) -> &mut T {
+        &mut self.entries[id]
+            .as_mut()
+            .expect("the entry was freed")
+            .value
+    }
+}
+
+#[test]
+fn free_the_entries_without_handles() {
+    use crate::literal::yexmodule::builtin;
+
+    let mut table = Handles::new(|running: &bool| *running);
+    let kept = table.insert(builtin::channel(), "Channel.handle", false);
+    let running = table.insert(builtin::channel(), "Channel.handle", true);
+    drop(running);
+
+    for _ in 0..1000 {
+        let _ = table.insert(builtin::channel(), "Channel.handle", false);
+    }
+
+    assert!(table.len() <= MIN_SWEEP);
+    assert!(table.iter().any(|(_, running)| *running));
+
+    let Value::Tagged(_, _, tup) = &kept else {
+        unreachable!()
+    };
+    assert_eq!(table.id(tup), Some(0));
+
+    // a handle rebuilt by hand doesn't refer to the entry that reused its id
+    let forged = Tuple::from(vec![Value::from(2_usize)]);
+    assert_eq!(table.id(&forged), None);
+}
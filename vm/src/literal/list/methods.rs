@@ -1,9 +1,13 @@
-use std::fmt::Write;
+use std::{fmt::Write, iter, thread};
 
 use crate::{
     error::InterpretResult,
-    literal::{nil, TryGet, Value},
-    VirtualMachine,
+    literal::{
+        nil,
+        sendable::{Sendable, Snapshot},
+        TryGet, Value,
+    },
+    raise_err, VirtualMachine,
 };
 
 use super::List;
@@ -18,20 +22,76 @@ pub fn map(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value>
     let xs: List = args[1].get()?;
     let fun = &args[0];
 
-    let xs: InterpretResult<List> = xs
+    let xs = xs
         .iter()
         .map(|it| {
-            vm.push(it);
+            vm.push(it.clone());
             vm.push(fun.clone());
             vm.call(1)?;
             Ok(vm.pop())
         })
-        .try_fold(List::new(), |xs, x| match x {
-            Ok(x) => Ok(xs.prepend(x)),
-            Err(e) => Err(e),
-        });
+        .collect::<InterpretResult<List>>()?;
 
-    Ok(xs?.rev().into())
+    Ok(xs.into())
+}
+
+/// Same as `map`, but the list is split across native threads, each one running a copy of the
+/// virtual machine, so `fun` can't see any fiber, channel or process of the caller
+pub fn par_map(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let xs: List = args[1].get()?;
+
+    let fun = Sendable::new(&args[0])?;
+    let xs = xs
+        .iter()
+        .map(Sendable::new)
+        .collect::<InterpretResult<Vec<_>>>()?;
+
+    if xs.is_empty() {
+        return Ok(List::new().into());
+    }
+
+    let snapshot = Snapshot::new(vm)?;
+    let threads = thread::available_parallelism().map_or(1, usize::from);
+    let chunk_size = xs.len().div_ceil(threads);
+
+    let map_chunk = |chunk: &[Sendable]| -> InterpretResult<Vec<Sendable>> {
+        let mut vm = snapshot.to_vm();
+        let fun = fun.to_value(&vm);
+
+        chunk
+            .iter()
+            .map(|x| {
+                vm.push(x.to_value(&vm));
+                vm.push(fun.clone());
+                vm.call(1)?;
+                Sendable::new(&vm.pop())
+            })
+            .collect()
+    };
+
+    let chunks = thread::scope(|scope| {
+        let handles = xs
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| map_chunk(chunk)))
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(raise_err!(ThreadError, "A worker thread panicked")))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let mut ys = vec![];
+    for chunk in chunks {
+        ys.extend(chunk?.iter().map(|y| y.to_value(vm)));
+    }
+
+    Ok(Value::List(ys.into_iter().collect()))
 }
 
 pub fn fold(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
@@ -41,8 +101,8 @@ pub fn fold(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value>
     let mut acc = args[1].clone();
     let fun = args[0].clone();
 
-    for it in xs.iter() {
-        vm.push(it);
+    for it in &xs {
+        vm.push(it.clone());
         vm.push(acc);
         vm.push(fun.clone());
 
@@ -60,9 +120,9 @@ pub fn filter(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Valu
     let xs: List = args[1].get()?;
     let fun = &args[0];
 
-    let mut ys = List::new();
+    let mut ys = vec![];
 
-    for x in xs.iter() {
+    for x in &xs {
         vm.push(x.clone());
         vm.push(fun.clone());
 
@@ -70,11 +130,11 @@ pub fn filter(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Valu
 
         let res = vm.pop();
         if res.to_bool() {
-            ys = ys.prepend(x);
+            ys.push(x.clone());
         }
     }
 
-    Ok(ys.rev().into())
+    Ok(ys.into_iter().collect::<List>().into())
 }
 
 pub fn head(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
@@ -114,14 +174,14 @@ pub fn find(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value>
 
     let fun = &args[0];
 
-    for x in xs.iter() {
+    for x in &xs {
         vm.push(x.clone());
         vm.push(fun.clone());
 
         vm.call(1)?;
 
         if vm.pop().to_bool() {
-            return Ok(x);
+            return Ok(x.clone());
         }
     }
 
@@ -131,7 +191,23 @@ pub fn find(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value>
 pub fn len(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
     let xs: List = args[0].get()?;
 
-    Ok((xs.len() as f64).into())
+    Ok(xs.len().into())
+}
+
+/// The numbers from `start` up to, but not including, `end`, built in a loop so long ranges
+/// don't need a recursive helper. They're integers if both ends are
+pub fn range(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    if let (Value::Int(start), Value::Int(end)) = (&args[0], &args[1]) {
+        return Ok((*start..*end).map(Value::Int).collect::<List>().into());
+    }
+
+    let start: f64 = args[0].get()?;
+    let end: f64 = args[1].get()?;
+
+    let xs = iter::successors(Some(start), |n| Some(n + 1.0))
+        .take_while(|n| *n < end)
+        .map(Value::Num);
+    Ok(xs.collect::<List>().into())
 }
 
 pub fn show(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
@@ -139,8 +215,8 @@ pub fn show(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value>
 
     let mut s = String::from('[');
 
-    for x in xs.iter() {
-        write!(s, "{}, ", super::super::show(vm, vec![x])?).unwrap();
+    for x in &xs {
+        write!(s, "{}, ", super::super::show(vm, vec![x.clone()])?).unwrap();
     }
 
     if !xs.is_empty() {
@@ -160,3 +236,31 @@ pub fn to_list(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Valu
 pub fn new(_: *mut VirtualMachine, _: Vec<Value>) -> InterpretResult<Value> {
     Ok(List::new().into())
 }
+
+#[test]
+fn long_lists_in_a_loop() {
+    use crate::{gc::GcRef, Fn};
+
+    let mut vm = VirtualMachine::default();
+    let vm = std::ptr::from_mut(&mut vm);
+    let native = |arity, f| Value::Fn(GcRef::new(Fn::new_native(arity, f)));
+
+    let xs = range(vm, vec![Value::Num(0.0), Value::Num(100_000.0)]).unwrap();
+    let add = native(2, |_, args| args[0].clone() + args[1].clone());
+    let sum = fold(vm, vec![add, Value::Num(0.0), xs.clone()]).unwrap();
+    assert_eq!(sum, Value::Num(4_999_950_000.0));
+
+    let even = native(1, |_, args| {
+        let n: f64 = args[0].get()?;
+        Ok(Value::Bool(n % 2.0 == 0.0))
+    });
+    let evens = filter(vm, vec![even.clone(), xs.clone()]).unwrap();
+    assert_eq!(len(vm, vec![evens]).unwrap(), Value::Int(50_000));
+
+    let ys = map(vm, vec![even, xs.clone()]).unwrap();
+    assert_eq!(len(vm, vec![ys]).unwrap(), Value::Int(100_000));
+    assert_eq!(
+        rev(vm, vec![rev(vm, vec![xs.clone()]).unwrap()]).unwrap(),
+        xs
+    );
+}
@@ -1,17 +1,22 @@
 pub(crate) mod methods;
 
 use crate::{
-    gc::GcRef,
+    gc::{GcRef, Trace, Traced},
     literal::{nil, Value},
 };
 
 type Link = Option<GcRef<Node>>;
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone)]
 /// Yex lists implementation
+///
+/// Comparing, printing and dropping a list walk its nodes in a loop, since recursing into the
+/// next node would overflow the stack with long lists
 pub struct List {
     head: Link,
+    /// the number of elements, kept so `len` doesn't walk the list
+    len: usize,
 }
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone)]
 pub struct Node {
     elem: Value,
     next: Link,
@@ -21,13 +26,13 @@ impl List {
     /// Creates a List
     #[must_use]
     pub const fn new() -> Self {
-        Self { head: None }
+        Self { head: None, len: 0 }
     }
 
     /// Checks if the list is empty
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.head == None
+        self.head.is_none()
     }
 
     /// Prepends a value to the end, returning the list
@@ -37,19 +42,16 @@ impl List {
             elem,
             next: self.head.clone(),
         });
-        Self { head: Some(node) }
+        Self {
+            head: Some(node),
+            len: self.len + 1,
+        }
     }
 
     /// Returns the list tail
     #[must_use]
     pub fn tail(&self) -> Self {
-        let tail = self.head.as_ref().map(|node| node.next.clone());
-        let tail = match tail {
-            Some(v) => v,
-            None => None,
-        };
-
-        Self { head: tail }
+        self.drop(1)
     }
 
     /// Returns the current element
@@ -61,98 +63,107 @@ impl List {
     /// Returns a index into the list
     #[must_use]
     pub fn index(&self, index: usize) -> Value {
-        if index == 0 {
-            self.head().unwrap_or_else(nil)
-        } else {
-            let tail = self.tail();
-            if tail.is_empty() {
-                nil()
-            } else {
-                tail.index(index - 1)
-            }
-        }
+        self.iter().nth(index).cloned().unwrap_or_else(nil)
     }
 
     /// Returns the list length
     #[must_use]
     pub fn len(&self) -> usize {
-        let mut xs = self.head.as_ref();
-        let mut count = 0;
-        while xs != None {
-            xs = xs.unwrap().next.as_ref();
-            count += 1;
-        }
-        count
+        self.len
     }
 
     /// Converts list to Vec
     #[must_use]
     pub fn to_vec(&self) -> Vec<Value> {
-        let mut vec = vec![];
-        let mut head = self.clone();
-        while head.head().is_some() {
-            vec.push(head.head().unwrap().clone());
-            head = head.tail();
-        }
-        vec
+        self.iter().cloned().collect()
     }
 
     /// Iterate over all elements of `self`
     #[must_use]
-    pub fn iter(&self) -> Iter {
+    pub fn iter(&self) -> Iter<'_> {
         Iter {
             next: self.head.as_deref(),
+            len: self.len,
         }
     }
 
     /// Reverses `self` without consuming it
     #[must_use]
     pub fn rev(&self) -> Self {
-        let mut node = self.head.as_ref();
-        let mut list = Self::new();
-        while let Some(elem) = node {
-            list = list.prepend(elem.elem.clone());
-            node = elem.next.as_ref();
-        }
-        list
+        self.iter()
+            .fold(Self::new(), |list, elem| list.prepend(elem.clone()))
     }
 
     /// drop n items from Self
     #[must_use]
     pub fn drop(&self, mut len: usize) -> Self {
-        let mut head = self.head.clone();
-        while len > 0 {
-            head = head.and_then(|node| node.next.clone());
+        let mut list = self.clone();
+        while len > 0 && !list.is_empty() {
+            list.head = list.head.take().and_then(|node| node.next.clone());
+            list.len -= 1;
             len -= 1;
         }
 
-        Self { head }
+        list
     }
 
     /// collect the list into a string, separating elements with `sep`
     #[must_use]
     pub fn join(&self, sep: &str) -> String {
-        let mut head = self.head.clone();
-
         let mut str = String::new();
 
-        while let Some(node) = head {
-            match &node.elem {
-                Value::Str(s) => str.push_str(s),
-                val => str.push_str(&val.to_string()),
-            };
-
-            if node.next.is_some() {
+        for (index, elem) in self.iter().enumerate() {
+            if index > 0 {
                 str.push_str(sep);
             }
 
-            head = node.next.clone();
+            match elem {
+                Value::Str(s) => str.push_str(s),
+                val => str.push_str(&val.to_string()),
+            };
         }
 
         str
     }
 }
 
+impl Trace for Node {
+    const TRACED: bool = true;
+
+    fn trace(&self, visit: &mut dyn FnMut(Traced)) {
+        self.elem.trace(visit);
+        self.next.trace(visit);
+    }
+}
+
+impl Trace for List {
+    fn trace(&self, visit: &mut dyn FnMut(Traced)) {
+        self.head.trace(visit);
+    }
+}
+
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl std::fmt::Debug for List {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Drop for List {
+    fn drop(&mut self) {
+        let mut next = self.head.take();
+        // a node shared with another list is kept alive, and so are the nodes after it
+        while let Some(mut node) = next.filter(GcRef::is_unique) {
+            next = unsafe { node.mut_ref() }.next.take();
+        }
+    }
+}
+
 impl std::fmt::Display for List {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "[")?;
@@ -167,26 +178,82 @@ impl std::fmt::Display for List {
     }
 }
 
+/// Borrows the elements of a list, from the first to the last
 pub struct Iter<'a> {
     next: Option<&'a Node>,
+    len: usize,
 }
 
 impl<'a> Iterator for Iter<'a> {
-    type Item = Value;
+    type Item = &'a Value;
     fn next(&mut self) -> Option<Self::Item> {
         self.next.map(|node| {
             self.next = node.next.as_deref();
-            node.elem.clone()
+            self.len -= 1;
+            &node.elem
         })
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl<'a> IntoIterator for &'a List {
+    type Item = &'a Value;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
+/// Builds a list with the elements in the order they come
 impl FromIterator<Value> for List {
     fn from_iter<T: IntoIterator<Item = Value>>(iter: T) -> Self {
-        let mut list = Self::new();
-        for item in iter {
-            list = list.prepend(item);
-        }
-        list
+        // the list is built from its end, so the elements are buffered first
+        let elems = iter.into_iter().collect::<Vec<_>>();
+        elems
+            .into_iter()
+            .rev()
+            .fold(Self::new(), |list, elem| list.prepend(elem))
     }
 }
+
+/// Appends the elements to the end of the list, which copies its nodes, since they're shared
+impl Extend<Value> for List {
+    fn extend<T: IntoIterator<Item = Value>>(&mut self, iter: T) {
+        let mut elems = self.to_vec();
+        elems.extend(iter);
+        *self = elems.into_iter().collect();
+    }
+}
+
+#[test]
+fn iterate_lists() {
+    let mut xs = (1..=3).map(Value::Int).collect::<List>();
+    assert_eq!(xs.len(), 3);
+    assert_eq!(xs.iter().len(), 3);
+    assert_eq!(xs.to_string(), "[1, 2, 3]");
+
+    xs.extend([Value::Int(4)]);
+    assert_eq!(xs.to_string(), "[1, 2, 3, 4]");
+    assert_eq!(xs.rev().to_string(), "[4, 3, 2, 1]");
+    assert_eq!(xs.tail().len(), 3);
+    assert_eq!(xs.drop(10), List::new());
+    assert_eq!(xs.index(1), Value::Int(2));
+}
+
+#[test]
+fn long_lists_dont_recurse() {
+    let long = || {
+        (0..100_000)
+            .map(|n| Value::Num(f64::from(n)))
+            .collect::<List>()
+    };
+    let xs = long();
+    assert_eq!(xs, long());
+    assert!(format!("{xs:?}").ends_with("Num(99999.0)]"));
+}
@@ -1,26 +1,42 @@
 use std::{
     any::Any,
     cmp::Ordering,
+    hash::{Hash, Hasher},
     mem,
     ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub},
+    sync::OnceLock,
 };
 
 //pub mod file;
+pub mod channel;
+pub mod code;
+pub mod exception;
 pub mod ffi;
+pub mod fiber;
 pub mod fun;
+pub mod generator;
+pub mod handle;
 pub mod list;
+pub mod num;
+pub mod process;
+pub mod promise;
 pub mod result;
+pub mod sendable;
 pub mod str;
 pub mod symbol;
 pub mod tuple;
 pub mod yexmodule;
 
-use crate::{error::InterpretResult, gc::GcRef, raise, VirtualMachine};
+use crate::{
+    error::InterpretResult,
+    gc::{GcRef, Trace, Traced},
+    raise, VirtualMachine,
+};
 
 use fun::Fn;
 use list::List;
 use symbol::Symbol;
-use yexmodule::YexModule;
+use yexmodule::{builtin, YexModule};
 
 use self::{
     ffi::{userdata::UserData, Ffi},
@@ -35,7 +51,9 @@ pub fn show(_: *mut VirtualMachine, x: Vec<Value>) -> InterpretResult<String> {
         Value::List(l) => Ok(l.to_string()),
         Value::Tuple(t) => Ok(t.to_string()),
         tag @ Value::Tagged(..) => Ok(tag.to_string()),
+        Value::Num(n) if n.is_finite() => Ok(num::format(*n, num::float_precision())),
         Value::Num(n) => Ok(n.to_string()),
+        Value::Int(n) => Ok(n.to_string()),
         Value::Bool(b) => Ok(b.to_string()),
         Value::FFI(f) => Ok(f.to_string()),
         Value::UserData(u) => Ok(format!("<userdata({:?})>", u.type_id())),
@@ -50,6 +68,17 @@ pub fn nil() -> Value {
     Value::Nil
 }
 
+/// Reads the value written as the given yex code, looking up the types of its tagged values in
+/// the virtual machine, or returns the syntax error
+pub type Parse = fn(&VirtualMachine, &str) -> Result<Value, String>;
+
+static PARSER: OnceLock<Parse> = OnceLock::new();
+
+/// Sets the parser used by [`Value::parse`], the virtual machine can't parse code by itself
+pub fn set_parser(parse: Parse) {
+    PARSER.get_or_init(|| parse);
+}
+
 impl From<Vec<Value>> for Value {
     fn from(vec: Vec<Value>) -> Self {
         Value::Tuple(Tuple::from(vec))
@@ -74,6 +103,19 @@ impl From<f64> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Int(n)
+    }
+}
+
+/// Lengths and indices, which never get past `i64::MAX`
+impl From<usize> for Value {
+    fn from(n: usize) -> Self {
+        Value::Int(i64::try_from(n).unwrap_or(i64::MAX))
+    }
+}
+
 impl From<String> for Value {
     fn from(s: String) -> Self {
         Value::Str(GcRef::new(s))
@@ -105,10 +147,12 @@ impl From<Fn> for Value {
 }
 
 /// Immediate values that can be consumed
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub enum Value {
     /// float-precision numbers
     Num(f64),
+    /// 64-bit integers
+    Int(i64),
     /// Strings
     Str(GcRef<String>),
     /// erlang-like atoms
@@ -135,7 +179,9 @@ pub enum Value {
 
 impl Clone for Value {
     fn clone(&self) -> Self {
-        use Value::{Bool, Fn, List, Module, Nil, Num, Str, Sym, Tagged, Tuple, UserData, FFI};
+        use Value::{
+            Bool, Fn, Int, List, Module, Nil, Num, Str, Sym, Tagged, Tuple, UserData, FFI,
+        };
 
         match self {
             List(xs) => List(xs.clone()),
@@ -143,6 +189,7 @@ impl Clone for Value {
             Fn(f) => Fn(GcRef::clone(f)),
             Bool(b) => Bool(*b),
             Num(n) => Num(*n),
+            Int(n) => Int(*n),
             Sym(s) => Sym(*s),
             Module(t) => Module(t.clone()),
             Tuple(t) => Tuple(t.clone()),
@@ -154,6 +201,89 @@ impl Clone for Value {
     }
 }
 
+/// Numbers are equal when they have the same value, so `1 == 1.0`, the other values when they
+/// have the same structure
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        use Value::{
+            Bool, Fn, Int, List, Module, Nil, Num, Str, Sym, Tagged, Tuple, UserData, FFI,
+        };
+
+        match (self, other) {
+            (Num(x), Num(y)) => x == y,
+            (Int(x), Int(y)) => x == y,
+            (Int(x), Num(y)) | (Num(y), Int(x)) => num::exact_int(*y) == Some(*x),
+            (Str(x), Str(y)) => x == y,
+            (Sym(x), Sym(y)) => x == y,
+            (Bool(x), Bool(y)) => x == y,
+            (Fn(x), Fn(y)) => x == y,
+            (List(x), List(y)) => x == y,
+            (Module(x), Module(y)) => YexModule::same(x, y),
+            (Tuple(x), Tuple(y)) => x == y,
+            (Tagged(ty, tag, tup), Tagged(other_ty, other_tag, other_tup)) => {
+                YexModule::same(ty, other_ty) && tag == other_tag && tup == other_tup
+            }
+            (UserData(x), UserData(y)) => x == y,
+            (FFI(x), FFI(y)) => x == y,
+            (Nil, Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Values are equal to themselves, except for `NaN`, which never is, as in yex
+impl Eq for Value {}
+
+/// Hashes the structure of a value, so equal values hash the same on every run. Functions, native
+/// resources and modules only hash what their equality is sure to share, so they may collide
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // the floats equal to an integer, like `0.0` and `-0.0`, hash as that integer
+        if let Some(n) = self.as_num().and_then(num::exact_int) {
+            mem::discriminant(&Value::Int(n)).hash(state);
+            return state.write_i64(n);
+        }
+        mem::discriminant(self).hash(state);
+
+        match self {
+            Value::Num(n) => state.write_u64(n.to_bits()),
+            Value::Int(n) => state.write_i64(*n),
+            Value::Str(str) => str.hash(state),
+            Value::Sym(sym) => sym.0.hash(state),
+            Value::Bool(b) => b.hash(state),
+            Value::Fn(f) => f.arity.hash(state),
+            Value::List(xs) => {
+                state.write_usize(xs.len());
+                xs.iter().for_each(|x| x.hash(state));
+            }
+            Value::Module(module) => module.name.hash(state),
+            Value::Tuple(tup) => tup.0.hash(state),
+            Value::Tagged(module, tag, tup) => {
+                module.name.hash(state);
+                tag.hash(state);
+                tup.0.hash(state);
+            }
+            Value::UserData(_) | Value::FFI(_) | Value::Nil => (),
+        }
+    }
+}
+
+impl Trace for Value {
+    fn trace(&self, visit: &mut dyn FnMut(Traced)) {
+        match self {
+            Value::Fn(fun) => fun.trace(visit),
+            Value::List(xs) => xs.trace(visit),
+            Value::Module(module) => module.trace(visit),
+            Value::Tuple(tup) => tup.0.trace(visit),
+            Value::Tagged(module, _, tup) => {
+                module.trace(visit);
+                tup.0.trace(visit);
+            }
+            _ => (),
+        }
+    }
+}
+
 impl Value {
     /// checks if the constant is `nil`
     #[must_use]
@@ -168,6 +298,7 @@ impl Value {
         match self {
             Value::List(xs) => xs.len(),
             Value::Num(_) => mem::size_of::<f64>(),
+            Value::Int(_) => mem::size_of::<i64>(),
             Value::Sym(_) => mem::size_of::<Symbol>(),
             Value::Str(s) => s.len(),
             Value::Fn(f) => mem::size_of_val(&f),
@@ -180,59 +311,214 @@ impl Value {
         }
     }
 
-    /// Compares the left and the right value
+    /// The value as a float, if it's a number
+    #[must_use]
+    pub fn as_num(&self) -> Option<f64> {
+        match self {
+            Self::Num(n) => Some(*n),
+            Self::Int(n) => Some(*n as f64),
+            _ => None,
+        }
+    }
+
+    /// Compares the left and the right value, an integer and a float are compared as floats
     pub fn ord_cmp(&self, rhs: &Self) -> InterpretResult<Ordering> {
-        let (left, right) = match (self, rhs) {
-            (Self::Num(left), Self::Num(right)) => (left, right),
-            (l, r) => raise!(TypeError, "cmp not supported with '{}' and '{}'", l, r)?,
+        let ord = match (self, rhs) {
+            (Self::Int(left), Self::Int(right)) => Some(left.cmp(right)),
+            (left, right) => match (left.as_num(), right.as_num()) {
+                (Some(left), Some(right)) => left.partial_cmp(&right),
+                _ => raise!(
+                    TypeError,
+                    "cmp not supported with '{}' and '{}'",
+                    left,
+                    right
+                )?,
+            },
         };
 
-        match left.partial_cmp(right) {
+        match ord {
             Some(ord) => Ok(ord),
-            None => raise!(TypeError, "Cannot compare '{}' and '{}'", left, right),
+            None => raise!(TypeError, "Cannot compare '{}' and '{}'", self, rhs),
+        }
+    }
+
+    /// Divides the left value by the right one, rounding the result down. Integers give an
+    /// integer, and raise a `ZeroDivisionError` when divided by zero
+    pub fn floor_div(self, rhs: Self) -> InterpretResult<Self> {
+        match (self, rhs) {
+            (Self::Int(x), Self::Int(y)) => {
+                let div = int_div(x, y, "%/%")?;
+                // the quotient is rounded towards zero, one past the floor when the signs differ
+                Ok(Self::Int(if x.wrapping_rem(y) != 0 && (x < 0) != (y < 0) {
+                    div - 1
+                } else {
+                    div
+                }))
+            }
+            (x, y) if x.as_num().is_some() && y.as_num().is_some() => Ok(Self::Num(
+                (x.as_num().unwrap() / y.as_num().unwrap()).floor(),
+            )),
+            (l, r) => raise!(
+                TypeError,
+                "Cannot apply '%/%' operator between '{}' and '{}'",
+                l,
+                r
+            ),
+        }
+    }
+
+    /// The remainder of [`Value::floor_div`], which has the sign of the right value, so
+    /// `-1 %% 3` is `2` while `-1 % 3` is `-1`
+    pub fn floor_mod(self, rhs: Self) -> InterpretResult<Self> {
+        match (self, rhs) {
+            (Self::Int(x), Self::Int(y)) => {
+                let rem = int_rem(x, y, "%%")?;
+                Ok(Self::Int(if rem != 0 && (rem < 0) != (y < 0) {
+                    rem + y
+                } else {
+                    rem
+                }))
+            }
+            (x, y) if x.as_num().is_some() && y.as_num().is_some() => {
+                let (x, y) = (x.as_num().unwrap(), y.as_num().unwrap());
+                Ok(Self::Num(x - y * (x / y).floor()))
+            }
+            (l, r) => raise!(
+                TypeError,
+                "Cannot apply '%%' operator between '{}' and '{}'",
+                l,
+                r
+            ),
+        }
+    }
+
+    /// Shifts the bits of the left value to the right, filling the left with zeros instead of
+    /// copying the sign bit like `>>>` does
+    pub fn logical_shr(self, rhs: Self) -> InterpretResult<Self> {
+        match (self, rhs) {
+            (Self::Int(x), Self::Int(y)) => {
+                let x = x.cast_unsigned().checked_shr(shift(y)).unwrap_or(0);
+                Ok(Self::Int(x.cast_signed()))
+            }
+            (l, r) => raise!(
+                TypeError,
+                "Cannot apply '>>>>' operator between '{}' and '{}'",
+                l,
+                r
+            ),
+        }
+    }
+
+    /// Flips every bit of the value
+    pub fn bit_not(self) -> InterpretResult<Self> {
+        match self {
+            Self::Int(x) => Ok(Self::Int(!x)),
+            _ => raise!(TypeError, "Cannot apply '~~~' operator on '{}'", self),
         }
     }
 
     /// Convert the constant to a boolean
     #[must_use]
     pub fn to_bool(&self) -> bool {
-        use Value::{Bool, Fn, List, Module, Nil, Num, Str, Sym, Tagged, Tuple, UserData, FFI};
+        use Value::{
+            Bool, Fn, Int, List, Module, Nil, Num, Str, Sym, Tagged, Tuple, UserData, FFI,
+        };
 
         match self {
             Bool(b) => *b,
             Str(s) if s.is_empty() => false,
             Num(n) if *n == 0.0 => false,
-            Nil => false,
+            Int(0) | Nil => false,
             List(xs) => !xs.is_empty(),
-            Sym(_) | Str(_) | Num(_) | Fn(_) | FFI(_) | Module(_) | Tuple(_) | Tagged(..)
-            | UserData(_) => true,
+            Sym(_) | Str(_) | Num(_) | Int(_) | Fn(_) | FFI(_) | Module(_) | Tuple(_)
+            | Tagged(..) | UserData(_) => true,
+        }
+    }
+
+    /// Reads a value back from the code its [`Display`](std::fmt::Display) writes, which
+    /// needs the parser given to [`set_parser`]. The types of its tagged values are looked up
+    /// in the globals of `vm`
+    pub fn parse(vm: &VirtualMachine, source: &str) -> InterpretResult<Self> {
+        let Some(parse) = PARSER.get() else {
+            return raise!(CompileError, "No parser is available to read values");
+        };
+
+        match parse(vm, source) {
+            Ok(value) => Ok(value),
+            Err(err) => raise!(ValueError, "Can't read a value from '{}': {}", source, err),
         }
     }
 
     /// returns the type of the value
     #[must_use]
     pub fn type_of(&self) -> GcRef<YexModule> {
-        use Value::{Bool, Fn, List, Module, Nil, Num, Str, Sym, Tagged, Tuple, UserData, FFI};
+        use Value::{
+            Bool, Fn, Int, List, Module, Nil, Num, Str, Sym, Tagged, Tuple, UserData, FFI,
+        };
 
         match self {
             Module(t) | Tagged(t, _, _) => return t.clone(),
             _ => {}
         };
 
-        let ty = match self {
-            List(_) => YexModule::list(),
-            Fn(_) => YexModule::fun(),
-            Num(_) => YexModule::num(),
-            Str(_) => YexModule::str(),
-            Bool(_) => YexModule::bool(),
-            Nil => YexModule::nil(),
-            Sym(_) => YexModule::sym(),
-            Tuple(_) => YexModule::tuple(),
-            FFI(_) => YexModule::ffi(),
-            Module(_) | UserData(_) | Tagged(..) => unreachable!(),
-        };
+        match self {
+            List(_) => builtin::list(),
+            Fn(_) => builtin::fun(),
+            Num(_) => builtin::num(),
+            Int(_) => builtin::int(),
+            Str(_) => builtin::str(),
+            Bool(_) => builtin::bool(),
+            Nil => builtin::nil(),
+            Sym(_) => builtin::sym(),
+            Tuple(_) => builtin::tuple(),
+            FFI(_) => builtin::ffi(),
+            UserData(_) => builtin::userdata(),
+            Module(_) | Tagged(..) => unreachable!(),
+        }
+    }
 
-        GcRef::new(ty)
+    /// Checks if the value is of the type `ty`, types are compared by their names. The values of
+    /// a variant are of the type that declared it, which is the start of their tags
+    #[must_use]
+    pub fn is_a(&self, ty: &YexModule) -> bool {
+        match self {
+            Value::Tagged(_, tag, _) => tag
+                .as_str()
+                .rsplit_once('.')
+                .is_some_and(|(name, _)| name == ty.name.as_str()),
+            Value::Module(it) => it.name == ty.name,
+            Value::List(_) => ty.name.as_str() == "List",
+            Value::Fn(_) => ty.name.as_str() == "Fn",
+            Value::Num(_) => ty.name.as_str() == "Num",
+            // integers are numbers too, so they are accepted wherever floats are
+            Value::Int(_) => matches!(ty.name.as_str(), "Int" | "Num"),
+            Value::Str(_) => ty.name.as_str() == "Str",
+            Value::Bool(_) => ty.name.as_str() == "Bool",
+            Value::Nil => ty.name.as_str() == "Nil",
+            Value::Sym(_) => ty.name.as_str() == "Sym",
+            Value::Tuple(_) => ty.name.as_str() == "Tuple",
+            Value::FFI(_) => ty.name.as_str() == "FFI",
+            Value::UserData(_) => ty.name.as_str() == "UserData",
+        }
+    }
+
+    /// Checks if nothing can mutate the value. Yex values are immutable, so the only ones that
+    /// aren't frozen are the ones holding native resources, which foreign code can change
+    #[must_use]
+    pub fn is_frozen(&self) -> bool {
+        match self {
+            Value::Fn(f) => f.args.iter().all(Value::is_frozen),
+            Value::List(xs) => xs.iter().all(Value::is_frozen),
+            Value::Module(m) => m.fields.iter().all(|(_, value)| value.is_frozen()),
+            Value::Tuple(t) | Value::Tagged(_, _, t) => t.0.iter().all(Value::is_frozen),
+            Value::UserData(_) | Value::FFI(_) => false,
+            Value::Num(_)
+            | Value::Int(_)
+            | Value::Str(_)
+            | Value::Sym(_)
+            | Value::Bool(_)
+            | Value::Nil => true,
+        }
     }
 }
 
@@ -250,44 +536,71 @@ impl From<Value> for bool {
     }
 }
 
+impl Value {
+    /// Writes the value as an argument of a tagged value, between parentheses unless it's a
+    /// single token
+    fn fmt_arg(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Num(n) if n.is_sign_negative() && n.is_finite() => {
+                write!(f, "(")?;
+                num::write(f, *n)?;
+                write!(f, ")")
+            }
+            Value::Int(n) if *n < 0 => write!(f, "({n})"),
+            other => write!(f, "{other}"),
+        }
+    }
+}
+
+/// Values are written as the yex code that evaluates to them, so they can be read back with
+/// [`Value::parse`], except for functions and foreign values, which have no literals
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        use Value::{Bool, Fn, List, Module, Nil, Num, Str, Sym, Tagged, Tuple, UserData, FFI};
-        let tk = match self {
-            Fn(f) => format!("fn({})", f.arity),
-            Nil => "nil".to_string(),
-            List(xs) => format!("{}", *xs),
-            Str(s) => "\"".to_owned() + s + "\"",
-            Sym(s) => format!("{}", s),
-            Num(n) => n.to_string(),
-            Module(t) => format!("type '{}'", t.name),
-            Tuple(t) => format!("{t}"),
-            UserData(u) => format!("<userdata({:?})>", u.type_id()),
-            FFI(f) => f.to_string(),
+        use Value::{
+            Bool, Fn, Int, List, Module, Nil, Num, Str, Sym, Tagged, Tuple, UserData, FFI,
+        };
+        match self {
+            Fn(fun) => write!(f, "fn({})", fun.arity),
+            Nil => write!(f, "nil"),
+            List(xs) => write!(f, "{xs}"),
+            Str(s) => str::write_literal(f, s),
+            Sym(s) => write!(f, "{s}"),
+            Num(n) if n.is_nan() => write!(f, "(0 / 0)"),
+            Num(n) if n.is_infinite() => write!(f, "({} / 0)", n.signum()),
+            Num(n) => num::write(f, *n),
+            Int(n) => write!(f, "{n}"),
+            Module(t) => write!(f, "{}", t.name),
+            Tuple(t) => write!(f, "{t}"),
+            UserData(u) => write!(f, "<userdata({:?})>", u.type_id()),
+            FFI(ffi) => write!(f, "{ffi}"),
+            Tagged(_, tag, value) if value.is_empty() => write!(f, "{tag}"),
             Tagged(_, tag, value) => {
-                write!(f, "({}", tag.as_str())?;
+                write!(f, "({tag}")?;
                 for item in value.0.iter() {
-                    write!(f, " {item}")?;
+                    f.write_str(" ")?;
+                    item.fmt_arg(f)?;
                 }
-                write!(f, ")")?;
-
-                return Ok(());
+                write!(f, ")")
             }
-            Bool(b) => b.to_string(),
-        };
-        write!(f, "{}", tk)
+            Bool(b) => write!(f, "{b}"),
+        }
     }
 }
 
+/// Integers give integers, which overflow as [`num::overflow`] says, except for `/`, which always
+/// gives a float. An integer and a float give a float
 macro_rules! impl_numeric {
-    ($($t:ident $op:tt $fn:ident);+$(;)?) => {
+    ($($t:ident $op:tt $fn:ident |$x:ident, $y:ident| $int:expr);+$(;)?) => {
         $(
             impl $t for Value {
                 type Output = ConstantErr;
 
                 fn $fn(self, rhs: Self) -> Self::Output {
                     match (self, rhs) {
+                        (Self::Int($x), Self::Int($y)) => $int,
                         (Self::Num(x), Self::Num(y)) => Ok(Self::Num(x $op y)),
+                        (Self::Int(x), Self::Num(y)) => Ok(Self::Num(x as f64 $op y)),
+                        (Self::Num(x), Self::Int(y)) => Ok(Self::Num(x $op y as f64)),
                         (Self::Str(x), Self::Str(y)) => Ok(Self::Str(GcRef::new(x.to_string() + &y))),
                         (l, r) => raise!(TypeError, "Cannot apply '{}' operator between '{}' and '{}'", stringify!($t), l, r),
                     }
@@ -298,22 +611,56 @@ macro_rules! impl_numeric {
 }
 
 impl_numeric!(
-    Add + add;
-    Sub - sub;
-    Mul * mul;
-    Div / div;
-    Rem % rem;
+    Add + add |x, y| int_arith(x, y, "+", i64::overflowing_add, i64::saturating_add).map(Self::Int);
+    Sub - sub |x, y| int_arith(x, y, "-", i64::overflowing_sub, i64::saturating_sub).map(Self::Int);
+    Mul * mul |x, y| int_arith(x, y, "*", i64::overflowing_mul, i64::saturating_mul).map(Self::Int);
+    Div / div |x, y| Ok(Self::Num(x as f64 / y as f64));
+    Rem % rem |x, y| int_rem(x, y, "%").map(Self::Int);
 );
 
+/// Applies an integer operator with [`num::int_op`], raising an `OverflowError` if the result
+/// overflows and the mode raises
+fn int_arith(
+    x: i64,
+    y: i64,
+    op: &str,
+    overflowing: fn(i64, i64) -> (i64, bool),
+    saturating: fn(i64, i64) -> i64,
+) -> InterpretResult<i64> {
+    match num::int_op(x, y, overflowing, saturating) {
+        Some(n) => Ok(n),
+        None => raise!(OverflowError, "'{}' of '{}' and '{}' overflows", op, x, y),
+    }
+}
+
+/// Divides two integers, rounding towards zero, raising a `ZeroDivisionError` if `y` is zero
+fn int_div(x: i64, y: i64, op: &str) -> InterpretResult<i64> {
+    if y == 0 {
+        return raise!(ZeroDivisionError, "'{}' can't divide '{}' by zero", op, x);
+    }
+    int_arith(x, y, op, i64::overflowing_div, i64::saturating_div)
+}
+
+/// The remainder of [`int_div`], which has the sign of `x`
+fn int_rem(x: i64, y: i64, op: &str) -> InterpretResult<i64> {
+    if y == 0 {
+        return raise!(ZeroDivisionError, "'{}' can't divide '{}' by zero", op, x);
+    }
+    Ok(x.wrapping_rem(y))
+}
+
+/// The bitwise operators work on the 64-bit two's complement of integers, floats aren't rounded
+/// to one
 macro_rules! impl_bit {
-    ($($t:ident $op:tt $opname:literal $fn:ident);+ $(;)? ) => {
+    ($($t:ident $opname:literal $fn:ident |$x:ident, $y:ident| $body:expr);+ $(;)? ) => {
         $(
             impl $t for Value {
                 type Output = ConstantErr;
 
+                #[allow(clippy::suspicious_arithmetic_impl)]
                 fn $fn(self, rhs: Self) -> Self::Output {
                     match (self, rhs) {
-                        (Self::Num(x), Self::Num(y)) if x.fract() == 0.0 && y.fract() == 0.0 => Ok(Self::Num(((x as u64) $op (y as u64)) as f64)),
+                        (Self::Int($x), Self::Int($y)) => Ok(Self::Int($body)),
                         (Self::Str(x), Self::Str(y)) => Ok(Self::Str(GcRef::new(x.to_string() + &y))),
                         (l, r) => raise!(TypeError, "Cannot apply '{}' operator between '{}' and '{}'", $opname, l, r),
                     }
@@ -323,20 +670,28 @@ macro_rules! impl_bit {
     }
 }
 
+// shifting by 64 bits or more shifts every bit out, instead of overflowing
 impl_bit!(
-    BitAnd & "&&&" bitand;
-    BitOr | "|||" bitor;
-    BitXor ^ "^^^" bitxor;
-    Shl << "<<<" shl;
-    Shr >> ">>>" shr;
+    BitAnd "&&&" bitand |x, y| x & y;
+    BitOr "|||" bitor |x, y| x | y;
+    BitXor "^^^" bitxor |x, y| x ^ y;
+    Shl "<<<" shl |x, y| x.checked_shl(shift(y)).unwrap_or(0);
+    Shr ">>>" shr |x, y| x.checked_shr(shift(y)).unwrap_or(x >> 63);
 );
 
+fn shift(amount: i64) -> u32 {
+    u32::try_from(amount).unwrap_or(u32::MAX)
+}
+
 impl Neg for Value {
     type Output = ConstantErr;
 
     fn neg(self) -> Self::Output {
         match self {
             Self::Num(n) => Ok(Self::Num(-n)),
+            Self::Int(n) => {
+                int_arith(0, n, "-", i64::overflowing_sub, i64::saturating_sub).map(Self::Int)
+            }
             _ => raise!(TypeError, "Cannot apply '-' operator on '{}'", self),
         }
     }
@@ -381,7 +736,6 @@ macro_rules! impl_get {
 }
 
 impl_get!(String: Str (s) => s.to_string());
-impl_get!(f64: Num);
 impl_get!(bool: Bool);
 impl_get!(GcRef<YexModule>: Module);
 impl_get!(GcRef<Fn>: Fn);
@@ -391,18 +745,95 @@ impl_get!(Ffi: FFI);
 impl_get!(UserData: UserData);
 impl_get!(Tuple: Tuple);
 impl_get!((GcRef<YexModule>, Symbol, Tuple): Tagged(m, s, t) => (m.clone(), *s, t.clone()));
-impl_get!(usize: Num(n) => {
-    if n.fract() != 0.0 || n.is_nan() || n.is_infinite() || *n < 0.0 {
-        return crate::raise!(ValueError, "Expected a positive integer, got '{}'", n);
+
+/// Integers are read as floats wherever floats are expected
+impl TryGet<f64> for Value {
+    #[inline]
+    fn get(&self) -> InterpretResult<f64> {
+        match self.as_num() {
+            Some(n) => Ok(n),
+            None => raise!(
+                TypeError,
+                "Unexpected type '{}', expected type was 'Num'",
+                self.type_of().name
+            ),
+        }
     }
+}
 
-    n.round() as usize
-});
+/// Floats are read as integers when they have no decimals, like the ones `floor_int` used to give
+impl TryGet<i64> for Value {
+    #[inline]
+    fn get(&self) -> InterpretResult<i64> {
+        match self {
+            Self::Int(n) => Ok(*n),
+            Self::Num(n) => match num::exact_int(*n) {
+                Some(n) => Ok(n),
+                None => raise!(ValueError, "Expected an integer, got '{}'", self),
+            },
+            _ => raise!(
+                TypeError,
+                "Unexpected type '{}', expected type was 'Int'",
+                self.type_of().name
+            ),
+        }
+    }
+}
 
-impl_get!(isize: Num(n) => {
-    if n.fract() != 0.0 || n.is_nan() || n.is_infinite() {
-        return crate::raise!(ValueError, "Expected an integer, got '{}'", n);
+impl TryGet<usize> for Value {
+    #[inline]
+    fn get(&self) -> InterpretResult<usize> {
+        let n: i64 = self.get()?;
+        match usize::try_from(n) {
+            Ok(n) => Ok(n),
+            Err(_) => raise!(ValueError, "Expected a non-negative integer, got '{}'", n),
+        }
     }
+}
+
+impl TryGet<isize> for Value {
+    #[inline]
+    fn get(&self) -> InterpretResult<isize> {
+        let n: i64 = self.get()?;
+        match isize::try_from(n) {
+            Ok(n) => Ok(n),
+            Err(_) => raise!(ValueError, "Expected an integer, got '{}'", n),
+        }
+    }
+}
 
-    n.round() as isize
-});
+#[test]
+fn hash_equal_values_the_same() {
+    use std::collections::{hash_map::DefaultHasher, HashSet};
+
+    let hash = |value: &Value| {
+        let mut state = DefaultHasher::new();
+        value.hash(&mut state);
+        state.finish()
+    };
+    let list = |xs: Vec<Value>| Value::List(xs.into_iter().collect());
+
+    let a = list(vec![Value::Num(1.0), Value::Str(GcRef::new("a".into()))]);
+    let b = list(vec![Value::Num(1.0), Value::Str(GcRef::new("a".into()))]);
+    assert_eq!(hash(&a), hash(&b));
+    assert_eq!(hash(&Value::Num(0.0)), hash(&Value::Num(-0.0)));
+    assert_ne!(hash(&Value::Num(1.0)), hash(&Value::Bool(true)));
+
+    let set = [a, b, Value::Nil, Value::Nil]
+        .into_iter()
+        .collect::<HashSet<_>>();
+    assert_eq!(set.len(), 2);
+}
+
+#[test]
+fn share_the_built_in_types() {
+    let vm = VirtualMachine::default();
+    let addr = |ty: &GcRef<YexModule>| std::ptr::from_ref::<YexModule>(ty);
+    let num = Value::Num(1.0).type_of();
+
+    assert_eq!(addr(&num), addr(&Value::Num(2.0).type_of()));
+    match vm.get_global("Num") {
+        Some(Value::Module(global)) => assert_eq!(addr(&num), addr(&global)),
+        other => panic!("expected the Num type, got {other:?}"),
+    }
+}
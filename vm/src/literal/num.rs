@@ -0,0 +1,150 @@
+//! How numbers are written, the same way on every platform and locale, and what the integer
+//! operators do when they overflow
+use std::{
+    fmt,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+};
+
+/// The precision of the numbers when none is set, written with as few digits as it takes to read
+/// them back
+const SHORTEST: usize = usize::MAX;
+
+/// The most decimals a number can be written with
+pub const MAX_PRECISION: usize = 100;
+
+static PRECISION: AtomicUsize = AtomicUsize::new(SHORTEST);
+
+/// Writes every number with `precision` decimals from now on, or with the shortest digits that
+/// read back as the same number if it's `None`, the default
+pub fn set_float_precision(precision: Option<usize>) {
+    let precision = precision.map_or(SHORTEST, |it| it.min(MAX_PRECISION));
+    PRECISION.store(precision, Ordering::Relaxed);
+}
+
+/// The decimals the numbers are written with, set with [`set_float_precision`]
+#[must_use]
+pub fn float_precision() -> Option<usize> {
+    Some(PRECISION.load(Ordering::Relaxed)).filter(|it| *it != SHORTEST)
+}
+
+/// What the integer operators do with a result past the 64-bit limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wraps around to the other limit
+    Wrap,
+    /// Stops at the limit of the result's sign
+    Saturate,
+    /// Raises an `OverflowError`
+    Raise,
+}
+
+static OVERFLOW: AtomicU8 = AtomicU8::new(Overflow::Raise as u8);
+
+impl Overflow {
+    /// The mode called `name`, which is `wrap`, `saturate` or `raise`
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wrap" => Some(Self::Wrap),
+            "saturate" => Some(Self::Saturate),
+            "raise" => Some(Self::Raise),
+            _ => None,
+        }
+    }
+}
+
+/// Makes the integer operators overflow as `mode` says from now on, they raise by default
+pub fn set_overflow(mode: Overflow) {
+    OVERFLOW.store(mode as u8, Ordering::Relaxed);
+}
+
+/// What the integer operators do when they overflow, set with [`set_overflow`]
+#[must_use]
+pub fn overflow() -> Overflow {
+    match OVERFLOW.load(Ordering::Relaxed) {
+        0 => Overflow::Wrap,
+        1 => Overflow::Saturate,
+        _ => Overflow::Raise,
+    }
+}
+
+/// Applies an integer operator given its overflowing and saturating versions, as [`overflow`]
+/// says when the result overflows, `None` if it raises then
+#[inline]
+pub(crate) fn int_op(
+    x: i64,
+    y: i64,
+    overflowing: fn(i64, i64) -> (i64, bool),
+    saturating: fn(i64, i64) -> i64,
+) -> Option<i64> {
+    match overflowing(x, y) {
+        (n, false) => Some(n),
+        (n, true) => match overflow() {
+            Overflow::Wrap => Some(n),
+            Overflow::Saturate => Some(saturating(x, y)),
+            Overflow::Raise => None,
+        },
+    }
+}
+
+/// Writes a finite number with the precision set with [`set_float_precision`]
+pub(crate) fn write(f: &mut impl fmt::Write, n: f64) -> fmt::Result {
+    write_with(f, n, float_precision())
+}
+
+/// Writes a finite number with `precision` decimals, or in the shortest form if it's `None`,
+/// which switches to an exponent for the numbers too big or too small to write in full, like
+/// `1e300`, and keeps a decimal on the others with none, like `2.0`, so they don't read back as
+/// integers
+fn write_with(f: &mut impl fmt::Write, n: f64, precision: Option<usize>) -> fmt::Result {
+    match precision {
+        Some(precision) => write!(f, "{n:.precision$}"),
+        None if n != 0.0 && !(1e-7..1e16).contains(&n.abs()) => write!(f, "{n:e}"),
+        None if n.fract() == 0.0 => write!(f, "{n}.0"),
+        None => write!(f, "{n}"),
+    }
+}
+
+/// The integer a float is exactly, if it's one that fits in 64 bits
+#[must_use]
+pub fn exact_int(n: f64) -> Option<i64> {
+    // `i64::MIN` is a float, and the next float past `i64::MAX` is its opposite
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    (n.fract() == 0.0 && (-LIMIT..LIMIT).contains(&n)).then_some(n as i64)
+}
+
+/// The number written with `precision` decimals, or in the shortest form if it's `None`
+#[must_use]
+pub fn format(n: f64, precision: Option<usize>) -> String {
+    let mut out = String::new();
+    // writing to a string can't fail
+    let _ = write_with(&mut out, n, precision.map(|it| it.min(MAX_PRECISION)));
+    out
+}
+
+#[test]
+fn write_numbers() {
+    let cases = [
+        (0.1 + 0.2, "0.30000000000000004"),
+        (100.0, "100.0"),
+        (-2.5, "-2.5"),
+        (1e300, "1e300"),
+        (1.5e-8, "1.5e-8"),
+        (0.0, "0.0"),
+        (-0.0, "-0.0"),
+    ];
+    for (n, written) in cases {
+        assert_eq!(format(n, None), written);
+        assert_eq!(written.parse::<f64>().unwrap().to_bits(), n.to_bits());
+    }
+
+    assert_eq!(format(0.1 + 0.2, Some(2)), "0.30");
+    assert_eq!(format(2.0, Some(0)), "2");
+    assert_eq!(format(1e300, Some(1)).len(), 303);
+
+    assert_eq!(exact_int(-3.0), Some(-3));
+    assert_eq!(exact_int(-9_223_372_036_854_775_808.0), Some(i64::MIN));
+    assert_eq!(exact_int(9_223_372_036_854_775_808.0), None);
+    assert_eq!(exact_int(2.5), None);
+    assert_eq!(exact_int(f64::NAN), None);
+}
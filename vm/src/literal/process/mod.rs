@@ -0,0 +1,96 @@
+use crate::{
+    error::InterpretResult,
+    literal::{
+        channel::{self, Channel, Mailbox},
+        fiber::{self, Fiber, Wait},
+        nil,
+        yexmodule::builtin,
+        TryGet,
+    },
+    raise, Symbol, Tuple, Value, VirtualMachine,
+};
+
+/// The id of the main process, a process spawned by it is the id of its fiber
+pub(crate) fn main_pid() -> Value {
+    let tup = Tuple::from(vec![Value::from(Symbol::from("main"))]);
+    Value::Tagged(builtin::process(), Symbol::from("Process.pid"), tup)
+}
+
+/// The mailbox of the running process, fibers that aren't processes share the main mailbox
+fn this_mailbox(vm: &VirtualMachine) -> Mailbox {
+    match vm.current_fiber {
+        Some(id) if vm.fibers[id].mailbox.is_some() => Mailbox::Process(id),
+        _ => Mailbox::Main,
+    }
+}
+
+pub(crate) fn spawn(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+
+    let mut fiber = Fiber::new(args[0].clone());
+    fiber.mailbox = Some(Channel::new());
+
+    Ok(vm.fibers.insert(builtin::process(), "Process.pid", fiber))
+}
+
+pub(crate) fn this(vm: *mut VirtualMachine, _: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+
+    match this_mailbox(vm) {
+        Mailbox::Process(id) => Ok(vm.fibers.handle(id)),
+        _ => Ok(vm.pid.clone()),
+    }
+}
+
+pub(crate) fn register(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let name: Symbol = args[0].get()?;
+
+    match &args[1] {
+        pid @ Value::Tagged(_, tag, _) if tag.as_str() == "Process.pid" => {
+            channel::mailbox(vm, pid)?;
+            vm.registry.insert(name, pid.clone());
+        }
+        other => raise!(TypeError, "Expected a process id, found '{}'", other)?,
+    }
+
+    Ok(nil())
+}
+
+pub(crate) fn whereis(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let name: Symbol = args[0].get()?;
+
+    Ok(vm.registry.get(&name).unwrap_or_else(nil))
+}
+
+/// Takes the oldest message accepted by `predicate` out of `mailbox`, if there's one
+pub(crate) fn take(
+    vm: &mut VirtualMachine,
+    mailbox: Mailbox,
+    predicate: &Value,
+) -> InterpretResult<Option<Value>> {
+    let mut idx = 0;
+    while let Some(msg) = mailbox.queue(vm).get(idx).cloned() {
+        vm.push(msg.clone());
+        vm.push(predicate.clone());
+        vm.call(1)?;
+
+        if vm.pop().to_bool() {
+            mailbox.queue(vm).remove(idx);
+            return Ok(Some(msg));
+        }
+        idx += 1;
+    }
+
+    Ok(None)
+}
+
+/// Takes the oldest message accepted by `predicate` out of the running process mailbox, waiting
+/// for one while there's none
+pub(crate) fn receive(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let mailbox = this_mailbox(vm);
+
+    fiber::wait(vm, Wait::Receive(mailbox, args[0].clone()))
+}
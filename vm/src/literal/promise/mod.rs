@@ -0,0 +1,222 @@
+use std::{
+    fs,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    error::{InterpretError, InterpretResult},
+    literal::{
+        fiber::{self, Wait},
+        nil,
+        yexmodule::builtin,
+        TryGet,
+    },
+    raise, raise_err, Value, VirtualMachine,
+};
+
+/// The state of a promise
+#[derive(Debug, Clone)]
+pub enum PromiseState {
+    /// The operation is still running
+    Pending,
+    /// Finished, with its result
+    Resolved(Value),
+    /// Finished by raising an error
+    Failed(InterpretError),
+}
+
+/// The result of a finished IO operation, values can't cross threads so they are only built once
+/// the event loop gets the result
+#[derive(Debug)]
+enum Completion {
+    Nil,
+    Str(String),
+}
+
+type Event = (usize, Result<Completion, String>);
+
+/// Receives the results of the IO operations running on the background threads
+#[derive(Debug)]
+pub struct EventLoop {
+    sender: Sender<Event>,
+    receiver: Receiver<Event>,
+}
+
+impl Default for EventLoop {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self { sender, receiver }
+    }
+}
+
+fn handle(vm: &mut VirtualMachine, state: PromiseState) -> Value {
+    vm.promises
+        .insert(builtin::promise(), "Promise.handle", state)
+}
+
+fn handle_id(vm: &VirtualMachine, value: &Value) -> InterpretResult<usize> {
+    match value {
+        Value::Tagged(_, tag, tup) if tag.as_str() == "Promise.handle" => {
+            match vm.promises.id(tup) {
+                Some(id) => Ok(id),
+                None => raise!(PromiseError, "Unknown promise '{}'", value),
+            }
+        }
+        other => raise!(TypeError, "Expected a promise, found '{}'", other),
+    }
+}
+
+impl Completion {
+    fn into_outcome(self) -> Option<String> {
+        match self {
+            Completion::Nil => None,
+            Completion::Str(s) => Some(s),
+        }
+    }
+
+    fn from_outcome(outcome: Option<String>) -> Self {
+        outcome.map_or(Completion::Nil, Completion::Str)
+    }
+}
+
+/// Runs `op` on a background thread, returning a promise of its result
+///
+/// In deterministic mode `op` runs right away (or its recorded outcome is used instead), so the
+/// operations finish in the order they started.
+fn spawn_io<F>(vm: &mut VirtualMachine, op: F) -> InterpretResult<Value>
+where
+    F: FnOnce() -> Result<Completion, String> + Send + 'static,
+{
+    let promise = handle(vm, PromiseState::Pending);
+    let id = handle_id(vm, &promise)?;
+
+    let sender = vm.events.sender.clone();
+    if let Some(deterministic) = &mut vm.deterministic {
+        let outcome = deterministic.io(|| op().map(Completion::into_outcome))?;
+        let _ = sender.send((id, outcome.map(Completion::from_outcome)));
+        return Ok(promise);
+    }
+
+    thread::spawn(move || {
+        // the receiver lives as long as the VM, if it's gone nobody is waiting anymore
+        let _ = sender.send((id, op()));
+    });
+
+    Ok(promise)
+}
+
+fn resolve(vm: &mut VirtualMachine, (id, result): Event) {
+    vm.promises[id] = match result {
+        Ok(Completion::Nil) => PromiseState::Resolved(nil()),
+        Ok(Completion::Str(s)) => PromiseState::Resolved(s.into()),
+        Err(msg) => PromiseState::Failed(raise_err!(IoError, "{}", msg)),
+    };
+}
+
+/// The result of a promise, `None` while it's pending
+pub(crate) fn result(vm: &VirtualMachine, promise: &Value) -> Option<InterpretResult<Value>> {
+    let id = match handle_id(vm, promise) {
+        Ok(id) => id,
+        Err(e) => return Some(Err(e)),
+    };
+
+    match &vm.promises[id] {
+        PromiseState::Resolved(value) => Some(Ok(value.clone())),
+        PromiseState::Failed(err) => Some(Err(err.clone())),
+        PromiseState::Pending => None,
+    }
+}
+
+/// Resolves the promises of the operations that finished, without waiting for the others,
+/// returns `true` if it resolved any
+pub(crate) fn resolve_finished(vm: &mut VirtualMachine) -> bool {
+    let mut resolved = false;
+    while let Ok(event) = vm.events.receiver.try_recv() {
+        resolve(vm, event);
+        resolved = true;
+    }
+    resolved
+}
+
+/// Waits for the next operation to finish and resolves its promise, returns `false` if there's
+/// no pending promise to wait for
+pub(crate) fn wait_next(vm: &mut VirtualMachine) -> bool {
+    if !vm
+        .promises
+        .iter()
+        .any(|(_, it)| matches!(it, PromiseState::Pending))
+    {
+        return false;
+    }
+
+    // every pending promise has a thread that will send its result
+    let event = vm.events.receiver.recv().unwrap();
+    resolve(vm, event);
+    true
+}
+
+/// Waits for a promise, running the pending fibers and resolving the other finished operations
+/// in the meantime
+pub(crate) fn wait(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    handle_id(vm, &args[0])?;
+
+    fiber::wait(vm, Wait::Await(args[0].clone()))
+}
+
+pub(crate) fn is_done(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let id = handle_id(vm, &args[0])?;
+
+    resolve_finished(vm);
+    Ok((!matches!(vm.promises[id], PromiseState::Pending)).into())
+}
+
+pub(crate) fn sleep(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let ms: f64 = args[0].get()?;
+
+    if !ms.is_finite() || ms < 0.0 {
+        raise!(
+            ValueError,
+            "Expected a positive number of milliseconds, found '{}'",
+            ms
+        )?;
+    }
+
+    // the virtual clock moves forward without waiting
+    if let Some(deterministic) = &mut vm.deterministic {
+        deterministic.sleep(ms);
+        return Ok(handle(vm, PromiseState::Resolved(nil())));
+    }
+
+    spawn_io(vm, move || {
+        thread::sleep(Duration::from_secs_f64(ms / 1000.0));
+        Ok(Completion::Nil)
+    })
+}
+
+pub(crate) fn read(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let path: String = args[0].get()?;
+
+    spawn_io(vm, move || {
+        fs::read_to_string(&path)
+            .map(Completion::Str)
+            .map_err(|e| format!("Can't read '{path}': {e}"))
+    })
+}
+
+pub(crate) fn write(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    let path: String = args[0].get()?;
+    let contents: String = args[1].get()?;
+
+    spawn_io(vm, move || {
+        fs::write(&path, contents)
+            .map(|()| Completion::Nil)
+            .map_err(|e| format!("Can't write '{path}': {e}"))
+    })
+}
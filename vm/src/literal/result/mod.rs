@@ -1,15 +1,18 @@
-use crate::{error::InterpretResult, gc::GcRef, Symbol, Tuple, Value, VirtualMachine, YexModule};
+use crate::{
+    error::InterpretResult, gc::GcRef, literal::yexmodule::builtin, Symbol, Tuple, Value,
+    VirtualMachine,
+};
 
 #[must_use]
 pub fn ok(args: Vec<Value>) -> Value {
-    let this: GcRef<YexModule> = GcRef::new(YexModule::default());
+    let this = builtin::result();
     let tup = Tuple(GcRef::new(args.into_boxed_slice()));
     Value::Tagged(this, Symbol::from("Result.ok"), tup)
 }
 
 #[must_use]
 pub fn fail(args: Vec<Value>) -> Value {
-    let this: GcRef<YexModule> = GcRef::new(YexModule::default());
+    let this = builtin::result();
     let tup = Tuple(GcRef::new(args.into_boxed_slice()));
     Value::Tagged(this, Symbol::from("Result.fail"), tup)
 }
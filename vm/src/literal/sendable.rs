@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::InterpretResult,
+    gc::GcRef,
+    literal::fun::{FnArgs, Signature},
+    prelude, raise, EnvTable, Fn, FnKind, List, Symbol, Tuple, Value, VirtualMachine, YexModule,
+};
+
+/// A deep copy of a [`Value`] that doesn't share any reference counted pointer, so it can be sent
+/// to (and shared between) other threads
+///
+/// Tagged values only keep the name of their module, since the nullary variants of a type point
+/// back to the type itself. The module is looked up again when the value is rebuilt.
+#[derive(Debug)]
+pub enum Sendable {
+    /// float-precision numbers
+    Num(f64),
+    /// 64-bit integers
+    Int(i64),
+    /// Strings
+    Str(String),
+    /// erlang-like atoms
+    Sym(Symbol),
+    /// Booleans
+    Bool(bool),
+    /// Functions, with their partially applied arguments, their signature if they are native and
+    /// the values they captured if they are closures
+    Fn(
+        usize,
+        FnKind,
+        Vec<Sendable>,
+        Option<&'static Signature>,
+        Vec<Sendable>,
+    ),
+    /// Yex lists
+    List(Vec<Sendable>),
+    /// Yex user-defined types, with the field names of their variants
+    Module(
+        Symbol,
+        Vec<(Symbol, Sendable)>,
+        HashMap<Symbol, Vec<Symbol>>,
+    ),
+    /// Tuples
+    Tuple(Vec<Sendable>),
+    /// Tagged tuples, with the name of their module
+    Tagged(Symbol, Symbol, Vec<Sendable>),
+    /// null
+    Nil,
+}
+
+fn copy_all<'a>(values: impl Iterator<Item = &'a Value>) -> InterpretResult<Vec<Sendable>> {
+    values.map(Sendable::new).collect()
+}
+
+impl Sendable {
+    /// Deep copies a value, raises if it holds native resources, like FFI libraries
+    pub fn new(value: &Value) -> InterpretResult<Self> {
+        Ok(match value {
+            Value::Num(n) => Self::Num(*n),
+            Value::Int(n) => Self::Int(*n),
+            Value::Str(s) => Self::Str(s.to_string()),
+            Value::Sym(s) => Self::Sym(s.0),
+            Value::Bool(b) => Self::Bool(*b),
+            Value::Fn(f) => Self::Fn(
+                f.arity,
+                (*f.body).clone(),
+                copy_all(f.args.iter())?,
+                f.signature,
+                copy_all(f.captures.iter())?,
+            ),
+            Value::List(xs) => Self::List(xs.iter().map(Self::new).collect::<Result<_, _>>()?),
+            Value::Module(m) => {
+                let fields = m
+                    .fields
+                    .iter()
+                    .map(|(name, value)| Ok((name, Self::new(&value)?)))
+                    .collect::<InterpretResult<_>>()?;
+                Self::Module(m.name, fields, m.variants.clone())
+            }
+            Value::Tuple(t) => Self::Tuple(copy_all(t.0.iter())?),
+            Value::Tagged(m, tag, t) => Self::Tagged(m.name, *tag, copy_all(t.0.iter())?),
+            Value::Nil => Self::Nil,
+            other @ (Value::UserData(_) | Value::FFI(_)) => {
+                raise!(TypeError, "'{}' can't be sent to another thread", other)?
+            }
+        })
+    }
+
+    /// Builds a new value from the copy, modules are looked up in the globals of `vm`
+    #[must_use]
+    pub fn to_value(&self, vm: &VirtualMachine) -> Value {
+        self.build(vm, None)
+    }
+
+    fn build(&self, vm: &VirtualMachine, this: Option<&GcRef<YexModule>>) -> Value {
+        let all = |xs: &[Sendable]| xs.iter().map(|x| x.build(vm, this)).collect::<Vec<_>>();
+
+        match self {
+            Self::Num(n) => Value::Num(*n),
+            Self::Int(n) => Value::Int(*n),
+            Self::Str(s) => s.clone().into(),
+            Self::Sym(s) => Value::Sym((*s).into()),
+            Self::Bool(b) => Value::Bool(*b),
+            Self::Fn(arity, body, args, signature, captures) => {
+                let mut fn_args = FnArgs::new();
+                for arg in all(args) {
+                    fn_args.push(arg);
+                }
+
+                Value::Fn(GcRef::new(Fn {
+                    arity: *arity,
+                    body: GcRef::new(body.clone()),
+                    args: fn_args,
+                    signature: *signature,
+                    captures: all(captures).into(),
+                }))
+            }
+            Self::List(xs) => Value::List(all(xs).into_iter().collect::<List>()),
+            Self::Module(name, fields, variants) => {
+                let mut module = YexModule::new(*name, EnvTable::new());
+                module.variants.clone_from(variants);
+                let mut module = GcRef::new(module);
+
+                // the fields can refer back to the module, so it's patched after being created
+                for (field, value) in fields {
+                    let value = value.build(vm, Some(&module));
+                    unsafe { module.mut_ref().fields.insert(*field, value) };
+                }
+
+                Value::Module(module)
+            }
+            Self::Tuple(xs) => Value::Tuple(Tuple::from(all(xs))),
+            Self::Tagged(name, tag, xs) => {
+                let module = match (this, vm.get_global(*name)) {
+                    (Some(this), _) if this.name == *name => this.clone(),
+                    (_, Some(Value::Module(module))) => module,
+                    _ => GcRef::new(YexModule::new(*name, EnvTable::new())),
+                };
+
+                Value::Tagged(module, *tag, Tuple::from(all(xs)))
+            }
+            Self::Nil => Value::Nil,
+        }
+    }
+}
+
+/// A copy of the constants and the user-defined globals of a virtual machine, used to start new
+/// virtual machines in other threads
+#[derive(Debug)]
+pub struct Snapshot {
+    constants: Vec<Sendable>,
+    globals: Vec<(Symbol, Sendable)>,
+}
+
+impl Snapshot {
+    /// Copies the state of `vm`, globals that can't be sent are left out
+    pub fn new(vm: &VirtualMachine) -> InterpretResult<Self> {
+        let constants = copy_all(vm.constants.iter())?;
+        let mut globals = vm
+            .globals
+            .iter()
+            .filter(|(name, _)| !prelude::is_native(name))
+            .filter_map(|(name, value)| Some((name, Sendable::new(&value).ok()?)))
+            .collect::<Vec<_>>();
+
+        // the types are defined first, so the values tagged by them can find them
+        globals.sort_by_key(|(_, value)| !matches!(value, Sendable::Module(..)));
+
+        Ok(Self { constants, globals })
+    }
+
+    /// Creates a new virtual machine with the copied state
+    #[must_use]
+    pub fn to_vm(&self) -> VirtualMachine {
+        let mut vm = VirtualMachine::default();
+
+        for (name, value) in &self.globals {
+            let value = value.to_value(&vm);
+            vm.set_global(*name, value);
+        }
+
+        let constants = self.constants.iter().map(|it| it.to_value(&vm)).collect();
+        vm.set_consts(constants);
+
+        vm
+    }
+}
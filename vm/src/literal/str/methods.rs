@@ -2,7 +2,7 @@ use crate::{
     error::InterpretResult,
     gc::GcRef,
     literal::{nil, TryGet},
-    raise, List, Value, VirtualMachine,
+    raise, List, Symbol, Value, VirtualMachine,
 };
 
 pub fn get(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
@@ -25,20 +25,40 @@ pub fn split(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value>
         .map(|str| str.to_owned().into())
         .collect();
 
-    Ok(list.rev().into())
+    Ok(list.into())
+}
+
+/// The rest of the string after `prefix`, or `nil` if it doesn't start with it
+pub fn strip_prefix(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let string: String = args[1].get()?;
+    let prefix: String = args[0].get()?;
+
+    Ok(string
+        .strip_prefix(&prefix)
+        .map_or_else(nil, |rest| rest.to_owned().into()))
+}
+
+/// The string before `suffix`, or `nil` if it doesn't end with it
+pub fn strip_suffix(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let string: String = args[1].get()?;
+    let suffix: String = args[0].get()?;
+
+    Ok(string
+        .strip_suffix(&suffix)
+        .map_or_else(nil, |rest| rest.to_owned().into()))
 }
 
 pub fn len(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
     let str: String = args[0].get()?;
 
-    Ok((str.len() as f64).into())
+    Ok(str.len().into())
 }
 
 pub fn chars(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
     let str: String = args[0].get()?;
     let iter = str.chars().map(|c| c.to_string().into());
 
-    Ok(iter.rev().collect::<List>().into())
+    Ok(iter.collect::<List>().into())
 }
 
 pub fn ord(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
@@ -48,7 +68,7 @@ pub fn ord(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
         raise!(ValueError, "Expected a character for 'ord'")?;
     }
 
-    Ok(Value::Num(str.as_bytes()[0].into()))
+    Ok(Value::Int(str.as_bytes()[0].into()))
 }
 
 pub fn chr(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
@@ -65,3 +85,73 @@ pub fn chr(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
 pub fn new(_: *mut VirtualMachine, _: Vec<Value>) -> InterpretResult<Value> {
     Ok(Value::Str(GcRef::new(String::from(""))))
 }
+
+pub fn to_sym(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let str: String = args[0].get()?;
+
+    Ok(Symbol::new(str).into())
+}
+
+/// The words of an identifier, split at `_`, `-`, spaces and where the case changes, so
+/// `userName`, `user_name` and `HTTPServer` are split as `user name` and `http server`
+fn words(str: &str) -> Vec<String> {
+    let chars = str.chars().collect::<Vec<_>>();
+    let mut words = vec![];
+    let mut word = String::new();
+
+    for (idx, &ch) in chars.iter().enumerate() {
+        if ch == '_' || ch == '-' || ch.is_whitespace() {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+            continue;
+        }
+
+        let prev = idx.checked_sub(1).map(|it| chars[it]);
+        let next = chars.get(idx + 1);
+        // a capital starts a word after a lowercase letter, or ends an acronym before one
+        let starts_word = ch.is_uppercase()
+            && prev.is_some_and(|prev| {
+                prev.is_lowercase()
+                    || prev.is_numeric()
+                    || prev.is_uppercase() && next.is_some_and(|next| next.is_lowercase())
+            });
+        if starts_word && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.extend(ch.to_lowercase());
+    }
+
+    words.extend((!word.is_empty()).then_some(word));
+    words
+}
+
+/// The string in `snake_case`
+pub fn snake_case(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let str: String = args[0].get()?;
+
+    Ok(words(&str).join("_").into())
+}
+
+/// The string in `camelCase`
+pub fn camel_case(_: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let str: String = args[0].get()?;
+
+    let mut camel = String::new();
+    for (idx, word) in words(&str).iter().enumerate() {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) if idx > 0 => camel.extend(first.to_uppercase().chain(chars)),
+            _ => camel.push_str(word),
+        }
+    }
+    Ok(camel.into())
+}
+
+#[test]
+fn split_identifiers_in_words() {
+    assert_eq!(words("userName"), ["user", "name"]);
+    assert_eq!(words("user_name"), ["user", "name"]);
+    assert_eq!(words("--user name-"), ["user", "name"]);
+    assert_eq!(words("HTTPServer2Go"), ["http", "server2", "go"]);
+    assert_eq!(words("UserID"), ["user", "id"]);
+    assert!(words("_").is_empty());
+}
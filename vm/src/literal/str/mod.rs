@@ -1 +1,21 @@
+use std::fmt::{self, Write};
+
 pub mod methods;
+
+/// Writes `s` as a string literal that the lexer reads back as the same string, escaping the
+/// quotes, the backslashes and the control characters
+pub(crate) fn write_literal(f: &mut impl Write, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
@@ -1,9 +1,22 @@
 use std::{
-    fmt::{self, Formatter},
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Formatter, Write},
     hash::{Hash, Hasher},
     ops::Deref,
+    sync::{Mutex, PoisonError},
 };
 
+/// The string of every symbol created, by its hash, so each one is only allocated once. A name
+/// whose hash was taken by another one gets the next free hash after it
+static INTERNED: Mutex<BTreeMap<usize, &'static str>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// The hashes of the symbols the thread has already seen, by their names, which are looked up
+    /// without locking `INTERNED`
+    static SEEN: RefCell<HashMap<&'static str, usize>> = RefCell::new(HashMap::new());
+}
+
 /// Symbol struct, contains the symbol string and a pre-hashed value for faster comparison
 #[derive(Clone, Copy, Default)]
 pub struct Symbol {
@@ -41,18 +54,60 @@ impl std::fmt::Debug for Symbol {
 impl Symbol {
     /// Creates a new symbol
     pub fn new<T: Into<String>>(str: T) -> Self {
-        let str = str.into();
+        Self::intern(&str.into())
+    }
 
-        let mut hash: usize = 2_166_136_261;
-        for b in str.bytes() {
-            hash ^= b as usize;
-            hash = hash.wrapping_mul(16_777_619);
-        }
+    /// The symbol with the given name, if it was already created, found without allocating
+    #[must_use]
+    pub fn lookup(name: &str) -> Option<Self> {
+        Self::seen(name).or_else(|| {
+            let interned = INTERNED.lock().unwrap_or_else(PoisonError::into_inner);
+            let (hash, string) = find(&interned, name).ok()?;
+            SEEN.with_borrow_mut(|seen| seen.insert(string, hash));
+            Some(Self { string, hash })
+        })
+    }
+
+    /// The symbol with the given name, if the thread has already seen it
+    fn seen(name: &str) -> Option<Self> {
+        SEEN.with_borrow(|seen| {
+            seen.get_key_value(name)
+                .map(|(string, hash)| (*string, *hash))
+        })
+        .map(|(string, hash)| Self { string, hash })
+    }
 
-        Self {
-            string: Box::leak(str.into_boxed_str()),
-            hash,
+    /// Finds the symbol with the given name, leaking a copy of it only if it's new
+    fn intern(name: &str) -> Self {
+        if let Some(sym) = Self::seen(name) {
+            return sym;
         }
+
+        let mut interned = INTERNED.lock().unwrap_or_else(PoisonError::into_inner);
+        let (hash, string) = find(&interned, name).unwrap_or_else(|hash| {
+            let string: &'static str = Box::leak(name.into());
+            interned.insert(hash, string);
+            (hash, string)
+        });
+        drop(interned);
+
+        SEEN.with_borrow_mut(|seen| seen.insert(string, hash));
+        Self { string, hash }
+    }
+
+    /// Every symbol created so far, sorted by their names
+    #[must_use]
+    pub fn interned() -> Vec<Self> {
+        let interned = INTERNED.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut symbols = interned
+            .iter()
+            .map(|(hash, string)| Self {
+                string,
+                hash: *hash,
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_unstable_by_key(|it| it.string);
+        symbols
     }
 
     /// Returns the intern symbol str
@@ -64,10 +119,36 @@ impl Symbol {
 
 impl<T: AsRef<str>> From<T> for Symbol {
     fn from(str: T) -> Self {
-        Self::new(str.as_ref())
+        Self::intern(str.as_ref())
     }
 }
 
+/// Finds the hash and the string of a name in `interned`, or the hash it gets if it's new, the
+/// first one from the hash of its name that no other name took
+fn find(
+    interned: &BTreeMap<usize, &'static str>,
+    name: &str,
+) -> Result<(usize, &'static str), usize> {
+    let mut hash = hash_str(name);
+    loop {
+        match interned.get(&hash) {
+            Some(string) if *string == name => return Ok((hash, string)),
+            Some(_) => hash = hash.wrapping_add(1),
+            None => return Err(hash),
+        }
+    }
+}
+
+/// The FNV-1a hash of the name of a symbol
+fn hash_str(str: &str) -> usize {
+    let mut hash: usize = 2_166_136_261;
+    for b in str.bytes() {
+        hash ^= b as usize;
+        hash = hash.wrapping_mul(16_777_619);
+    }
+    hash
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct YexSymbol(pub Symbol);
 
@@ -77,9 +158,26 @@ impl From<Symbol> for YexSymbol {
     }
 }
 
+impl YexSymbol {
+    /// Checks if the symbol can be written as `:name`, otherwise its name is quoted, like in
+    /// `:"two words"`
+    fn is_plain(&self) -> bool {
+        let name = self.0.as_str();
+        let body = name.trim_end_matches(['?', '!', '\'']);
+
+        body.starts_with(char::is_alphanumeric)
+            && body.chars().all(|c| c.is_alphanumeric() || c == '_')
+    }
+}
+
 impl fmt::Display for YexSymbol {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, ":{}", self.0)
+        if self.is_plain() {
+            write!(f, ":{}", self.0)
+        } else {
+            f.write_char(':')?;
+            super::str::write_literal(f, self.0.as_str())
+        }
     }
 }
 
@@ -90,3 +188,33 @@ impl Deref for YexSymbol {
         &self.0
     }
 }
+
+#[test]
+fn intern_symbols() {
+    let sym = Symbol::new(String::from("interned"));
+    assert!(std::ptr::eq(sym.as_str(), Symbol::new("interned").as_str()));
+    assert!(Symbol::interned().contains(&sym));
+}
+
+#[test]
+fn look_up_symbols_by_name() {
+    assert_eq!(Symbol::lookup("never created anywhere"), None);
+
+    let sym = Symbol::from("looked up");
+    let found = Symbol::lookup("looked up").unwrap();
+    assert_eq!(found, sym);
+    assert!(std::ptr::eq(found.as_str(), sym.as_str()));
+}
+
+#[test]
+fn intern_names_with_the_same_hash() {
+    // another name took the hash of "taken", so it gets the next one
+    let hash = hash_str("taken");
+    let mut interned = BTreeMap::new();
+    interned.insert(hash, "other");
+    assert_eq!(find(&interned, "taken"), Err(hash + 1));
+
+    interned.insert(hash + 1, "taken");
+    assert_eq!(find(&interned, "taken"), Ok((hash + 1, "taken")));
+    assert_eq!(find(&interned, "new"), Err(hash_str("new")));
+}
@@ -1,6 +1,9 @@
 pub mod methods;
 
-use crate::{gc::GcRef, Value};
+use crate::{
+    gc::{GcRef, Trace, Traced},
+    Value,
+};
 
 #[derive(Debug, PartialEq, Clone)]
 /// A yex tuple
@@ -12,6 +15,16 @@ impl From<Vec<Value>> for Tuple {
     }
 }
 
+impl Trace for Box<[Value]> {
+    const TRACED: bool = true;
+
+    fn trace(&self, visit: &mut dyn FnMut(Traced)) {
+        for value in &**self {
+            value.trace(visit);
+        }
+    }
+}
+
 impl Tuple {
     /// Returns the length of the tuple
     #[must_use]
@@ -28,14 +41,16 @@ impl Tuple {
 
 impl std::fmt::Display for Tuple {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "({})",
-            self.0
-                .iter()
-                .map(std::string::ToString::to_string)
-                .collect::<Vec<_>>()
-                .join(", ")
-        )
+        let items = self
+            .0
+            .iter()
+            .map(std::string::ToString::to_string)
+            .collect::<Vec<_>>();
+
+        // `(x)` is just `x`, the trailing comma makes a tuple of one item
+        match &items[..] {
+            [item] => write!(f, "({item},)"),
+            items => write!(f, "({})", items.join(", ")),
+        }
     }
 }
@@ -1,14 +1,23 @@
-use crate::{env::EnvTable, gc::GcRef, literal, Symbol, Value};
+use std::collections::HashMap;
 
-use super::{fun::Fn, list, str, tuple};
+use crate::{
+    env::EnvTable,
+    error::InterpretResult,
+    gc::{GcRef, Trace, Traced},
+    literal, raise, signature, stackvec, suggest, Symbol, Tuple, Value, VirtualMachine,
+};
 
-#[derive(Debug, PartialEq, Default)]
+use super::{fun::Fn, list, str, tuple, TryGet};
+
+#[derive(Debug, Default)]
 /// A Yex user-defined type.
 pub struct YexModule {
     /// Module name.
     pub name: Symbol,
     /// Module functions.
     pub fields: EnvTable,
+    /// The field names of each variant, by its tag
+    pub variants: HashMap<Symbol, Vec<Symbol>>,
 }
 #[macro_export]
 /// Add Fields/Methods to a `YexModule`
@@ -38,16 +47,113 @@ macro_rules! fields {
         $(
             $methods.insert(
                 $crate::literal::Symbol::from(stringify!($name)),
-                $crate::literal::Value::Fn($crate::gc::GcRef::new($crate::literal::fun::Fn::new_native($arg_count, $func))),
+                $crate::literal::Value::Fn($crate::gc::GcRef::new(
+                    $crate::literal::fun::Fn::new_native($arg_count, $func).with_signature(
+                        $crate::signature!(concat!(stringify!($sname), ".", stringify!($name)), $arg_count),
+                    ),
+                )),
             );
          )*
     };}
 
+impl Trace for YexModule {
+    const TRACED: bool = true;
+    // the compiler adds the nullary variants after the type is shared, which point back to it
+    const CYCLIC: bool = true;
+
+    fn trace(&self, visit: &mut dyn FnMut(Traced)) {
+        self.fields.trace(visit);
+    }
+}
+
 impl YexModule {
     /// Creates a new Yex type.
     #[must_use]
     pub fn new(name: Symbol, fields: EnvTable) -> Self {
-        Self { name, fields }
+        Self {
+            name,
+            fields,
+            variants: HashMap::new(),
+        }
+    }
+
+    /// If two types are the same one, or versions of the type with the same name, like the ones
+    /// before and after reloading the file defining it. Types aren't compared by their fields,
+    /// their variants without fields point back to them
+    #[must_use]
+    pub fn same(this: &GcRef<Self>, other: &GcRef<Self>) -> bool {
+        GcRef::ptr_eq(this, other) || this.name == other.name
+    }
+
+    /// Gets a field of the type, like `Type.name` does
+    pub fn field(&self, name: Symbol) -> InterpretResult<Value> {
+        let Some(field) = self.fields.get(&name) else {
+            let names = self.fields.keys().collect::<Vec<_>>();
+            let close = suggest::closest(name.as_str(), names.iter().map(Symbol::as_str));
+            return raise!(
+                FieldError,
+                "Undefined method '{}' for type '{}'{}",
+                name,
+                self.name,
+                suggest::DidYouMean(close)
+            );
+        };
+
+        Ok(field)
+    }
+
+    fn field_index(&self, tag: Symbol, name: Symbol) -> Option<usize> {
+        self.variants
+            .get(&tag)?
+            .iter()
+            .position(|field| *field == name)
+    }
+
+    /// Checks if the variant `tag` of the type has a field named `name`
+    #[must_use]
+    pub fn has_field(&self, tag: Symbol, name: Symbol) -> bool {
+        self.field_index(tag, name).is_some()
+    }
+
+    /// Gets a field of an instance of the type, like `instance.name` does: the field of its
+    /// variant with that name, or else the method of the type, with the instance applied to it
+    pub fn instance_field(&self, instance: &Value, name: Symbol) -> InterpretResult<Value> {
+        if let Value::Tagged(_, tag, tup) = instance {
+            if let Some(index) = self.field_index(*tag, name) {
+                return Ok(tup.0[index].clone());
+            }
+        }
+
+        Ok(match self.field(name)? {
+            // a method that only takes `self` can't be applied to it and still be called, so
+            // `instance.method ()` calls it with the instance instead of the `()`
+            Value::Fn(method) if method.arity == 1 => {
+                let call = Fn::new_native(3, call_with_self);
+                let args = stackvec![Value::Fn(method), instance.clone()];
+                Value::Fn(GcRef::new(call.apply(&args)?))
+            }
+            Value::Fn(method) if method.arity > 0 => {
+                Value::Fn(GcRef::new(method.apply(&stackvec![instance.clone()])?))
+            }
+            field => field,
+        })
+    }
+
+    /// Copies the fields of an instance of the type, with the one named `name` set to `value`
+    pub fn set_field(
+        &self,
+        tag: Symbol,
+        tup: &Tuple,
+        name: Symbol,
+        value: Value,
+    ) -> InterpretResult<Tuple> {
+        let Some(index) = self.field_index(tag, name) else {
+            return raise!(FieldError, "'{}' has no field named '{}'", tag, name);
+        };
+
+        let mut fields = tup.0.to_vec();
+        fields[index] = value;
+        Ok(fields.into())
     }
 
     /// Creates a new List type.
@@ -57,72 +163,129 @@ impl YexModule {
 
         methods.insert(
             Symbol::from("head"),
-            Value::Fn(GcRef::new(Fn::new_native(1, list::methods::head))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::head)
+                    .with_signature(signature!("List.head", [List])),
+            )),
         );
 
         methods.insert(
             Symbol::from("tail"),
-            Value::Fn(GcRef::new(Fn::new_native(1, list::methods::tail))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::tail)
+                    .with_signature(signature!("List.tail", [List])),
+            )),
         );
 
         methods.insert(
             Symbol::from("map"),
-            Value::Fn(GcRef::new(Fn::new_native(2, list::methods::map))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::map)
+                    .with_signature(signature!("List.map", [Fn, List])),
+            )),
+        );
+
+        methods.insert(
+            Symbol::from("par_map"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::par_map)
+                    .with_signature(signature!("List.par_map", [Fn, List])),
+            )),
         );
 
         methods.insert(
             Symbol::from("filter"),
-            Value::Fn(GcRef::new(Fn::new_native(2, list::methods::filter))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::filter)
+                    .with_signature(signature!("List.filter", [Fn, List])),
+            )),
         );
 
         methods.insert(
             Symbol::from("fold"),
-            Value::Fn(GcRef::new(Fn::new_native(3, list::methods::fold))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::fold)
+                    .with_signature(signature!("List.fold", [Fn, Any, List])),
+            )),
         );
 
         methods.insert(
             Symbol::from("rev"),
-            Value::Fn(GcRef::new(Fn::new_native(1, list::methods::rev))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::rev)
+                    .with_signature(signature!("List.rev", [List])),
+            )),
         );
 
         methods.insert(
             Symbol::from("get"),
-            Value::Fn(GcRef::new(Fn::new_native(2, list::methods::get))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::get)
+                    .with_signature(signature!("List.get", [Index, List])),
+            )),
         );
 
         methods.insert(
             Symbol::new("drop"),
-            Value::Fn(GcRef::new(Fn::new_native(2, list::methods::drop))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::drop)
+                    .with_signature(signature!("List.drop", [Index, List])),
+            )),
         );
 
         methods.insert(
             Symbol::new("join"),
-            Value::Fn(GcRef::new(Fn::new_native(2, list::methods::join))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::join)
+                    .with_signature(signature!("List.join", [Str, List])),
+            )),
         );
 
         methods.insert(
             Symbol::from("find"),
-            Value::Fn(GcRef::new(Fn::new_native(2, list::methods::find))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::find)
+                    .with_signature(signature!("List.find", [Fn, List])),
+            )),
         );
 
         methods.insert(
             Symbol::from("len"),
-            Value::Fn(GcRef::new(Fn::new_native(1, list::methods::len))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::len)
+                    .with_signature(signature!("List.len", [List])),
+            )),
+        );
+
+        methods.insert(
+            Symbol::from("range"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::range)
+                    .with_signature(signature!("List.range", [Num, Num])),
+            )),
         );
 
         methods.insert(
             Symbol::from("new"),
-            Value::Fn(GcRef::new(Fn::new_native(0, list::methods::new))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::new).with_signature(signature!("List.new", [])),
+            )),
         );
 
         methods.insert(
             Symbol::from("show"),
-            Value::Fn(GcRef::new(Fn::new_native(1, list::methods::show))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::show)
+                    .with_signature(signature!("List.show", [List])),
+            )),
         );
 
         methods.insert(
             Symbol::from("toList"),
-            Value::Fn(GcRef::new(Fn::new_native(1, list::methods::to_list))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, list::methods::to_list)
+                    .with_signature(signature!("List.toList", [List])),
+            )),
         );
 
         Self::new(Symbol::from("List"), methods)
@@ -135,17 +298,25 @@ impl YexModule {
 
         methods.insert(
             Symbol::from("get"),
-            Value::Fn(GcRef::new(Fn::new_native(2, tuple::methods::get))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, tuple::methods::get)
+                    .with_signature(signature!("Tuple.get", [Index, Tuple])),
+            )),
         );
 
         methods.insert(
             Symbol::from("new"),
-            Value::Fn(GcRef::new(Fn::new_native(0, tuple::methods::new))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, tuple::methods::new).with_signature(signature!("Tuple.new", [])),
+            )),
         );
 
         methods.insert(
             Symbol::from("show"),
-            Value::Fn(GcRef::new(Fn::new_native(1, tuple::methods::show))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, tuple::methods::show)
+                    .with_signature(signature!("Tuple.show", [Tuple])),
+            )),
         );
 
         Self::new(Symbol::from("Tuple"), methods)
@@ -158,14 +329,43 @@ impl YexModule {
 
         methods.insert(
             Symbol::from("show"),
-            Value::Fn(GcRef::new(Fn::new_native(1, |vm, x| {
-                super::show(vm, x).map(std::convert::Into::into)
-            }))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, |vm, x| super::show(vm, x).map(std::convert::Into::into))
+                    .with_signature(signature!("Num.show", [Num])),
+            )),
+        );
+
+        methods.insert(
+            Symbol::from("format"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, |_, args| {
+                    let digits: usize = args[0].get()?;
+                    let n: f64 = args[1].get()?;
+                    Ok(super::num::format(n, Some(digits)).into())
+                })
+                .with_signature(signature!("Num.format", [Index, Num])),
+            )),
         );
 
         Self::new(Symbol::from("Num"), methods)
     }
 
+    /// Creates a new Int type.
+    #[must_use]
+    pub fn int() -> Self {
+        let mut methods = EnvTable::new();
+
+        methods.insert(
+            Symbol::from("show"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, |vm, x| super::show(vm, x).map(std::convert::Into::into))
+                    .with_signature(signature!("Int.show", [Int])),
+            )),
+        );
+
+        Self::new(Symbol::from("Int"), methods)
+    }
+
     /// Creates a new Sym type.
     #[must_use]
     pub fn sym() -> Self {
@@ -173,9 +373,21 @@ impl YexModule {
 
         methods.insert(
             Symbol::from("show"),
-            Value::Fn(GcRef::new(Fn::new_native(1, |vm, x| {
-                super::show(vm, x).map(std::convert::Into::into)
-            }))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, |vm, x| super::show(vm, x).map(std::convert::Into::into))
+                    .with_signature(signature!("Sym.show", [Sym])),
+            )),
+        );
+
+        methods.insert(
+            Symbol::from("toStr"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, |_, x| {
+                    let sym: Symbol = x[0].get()?;
+                    Ok(sym.as_str().to_string().into())
+                })
+                .with_signature(signature!("Sym.toStr", [Sym])),
+            )),
         );
 
         Self::new(Symbol::from("Sym"), methods)
@@ -188,44 +400,102 @@ impl YexModule {
 
         methods.insert(
             Symbol::new("get"),
-            Value::Fn(GcRef::new(Fn::new_native(2, str::methods::get))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::get)
+                    .with_signature(signature!("Str.get", [Index, Str])),
+            )),
         );
 
         methods.insert(
             Symbol::new("split"),
-            Value::Fn(GcRef::new(Fn::new_native(2, str::methods::split))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::split)
+                    .with_signature(signature!("Str.split", [Str, Str])),
+            )),
+        );
+
+        methods.insert(
+            Symbol::new("stripPrefix"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::strip_prefix)
+                    .with_signature(signature!("Str.stripPrefix", [Str, Str])),
+            )),
+        );
+
+        methods.insert(
+            Symbol::new("stripSuffix"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::strip_suffix)
+                    .with_signature(signature!("Str.stripSuffix", [Str, Str])),
+            )),
         );
 
         methods.insert(
             Symbol::new("toList"),
-            Value::Fn(GcRef::new(Fn::new_native(1, str::methods::chars))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::chars)
+                    .with_signature(signature!("Str.toList", [Str])),
+            )),
         );
 
         methods.insert(
             Symbol::new("len"),
-            Value::Fn(GcRef::new(Fn::new_native(1, str::methods::len))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::len).with_signature(signature!("Str.len", [Str])),
+            )),
         );
 
         methods.insert(
             Symbol::new("new"),
-            Value::Fn(GcRef::new(Fn::new_native(0, str::methods::new))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::new).with_signature(signature!("Str.new", [])),
+            )),
+        );
+
+        methods.insert(
+            Symbol::new("toSym"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::to_sym)
+                    .with_signature(signature!("Str.toSym", [Str])),
+            )),
+        );
+
+        methods.insert(
+            Symbol::new("snakeCase"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::snake_case)
+                    .with_signature(signature!("Str.snakeCase", [Str])),
+            )),
+        );
+
+        methods.insert(
+            Symbol::new("camelCase"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::camel_case)
+                    .with_signature(signature!("Str.camelCase", [Str])),
+            )),
         );
 
         methods.insert(
             Symbol::new("ord"),
-            Value::Fn(GcRef::new(Fn::new_native(1, str::methods::ord))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::ord).with_signature(signature!("Str.ord", [Str])),
+            )),
         );
 
         methods.insert(
             Symbol::new("chr"),
-            Value::Fn(GcRef::new(Fn::new_native(1, str::methods::chr))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, str::methods::chr).with_signature(signature!("Str.chr", [Index])),
+            )),
         );
 
         methods.insert(
             Symbol::from("show"),
-            Value::Fn(GcRef::new(Fn::new_native(1, |vm, x| {
-                super::show(vm, x).map(std::convert::Into::into)
-            }))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, |vm, x| super::show(vm, x).map(std::convert::Into::into))
+                    .with_signature(signature!("Str.show", [Str])),
+            )),
         );
 
         Self::new(Symbol::from("Str"), methods)
@@ -238,9 +508,10 @@ impl YexModule {
 
         methods.insert(
             Symbol::from("show"),
-            Value::Fn(GcRef::new(Fn::new_native(1, |vm, x| {
-                super::show(vm, x).map(std::convert::Into::into)
-            }))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, |vm, x| super::show(vm, x).map(std::convert::Into::into))
+                    .with_signature(signature!("Bool.show", [Bool])),
+            )),
         );
 
         Self::new(Symbol::from("Bool"), methods)
@@ -253,9 +524,10 @@ impl YexModule {
 
         methods.insert(
             Symbol::from("show"),
-            Value::Fn(GcRef::new(Fn::new_native(1, |vm, x| {
-                super::show(vm, x).map(std::convert::Into::into)
-            }))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, |vm, x| super::show(vm, x).map(std::convert::Into::into))
+                    .with_signature(signature!("Fn.show", [Fn])),
+            )),
         );
 
         Self::new(Symbol::from("Fn"), methods)
@@ -270,9 +542,10 @@ impl YexModule {
         }, methods);
         methods.insert(
             Symbol::from("show"),
-            Value::Fn(GcRef::new(Fn::new_native(1, |vm, x| {
-                super::show(vm, x).map(std::convert::Into::into)
-            }))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, |vm, x| super::show(vm, x).map(std::convert::Into::into))
+                    .with_signature(signature!("Result.show", 1)),
+            )),
         );
         Self::new(Symbol::from("Result"), methods)
     }
@@ -286,6 +559,142 @@ impl YexModule {
         }, methods);
         Self::new(Symbol::from("FFI"), methods)
     }
+    /// Creates a new Fiber type
+    #[must_use]
+    pub fn fiber() -> Self {
+        let mut methods = EnvTable::new();
+        fields!(Fiber => {
+            spawn @ literal::fiber::spawn => 1,
+            join  @ literal::fiber::join => 1,
+        }, methods);
+        methods.insert(
+            Symbol::from("done?"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, literal::fiber::is_done)
+                    .with_signature(signature!("Fiber.done?", 1)),
+            )),
+        );
+        Self::new(Symbol::from("Fiber"), methods)
+    }
+    /// Creates a new Channel type
+    #[must_use]
+    pub fn channel() -> Self {
+        let mut methods = EnvTable::new();
+        fields!(Channel => {
+            new  @ literal::channel::new => 1,
+            send @ literal::channel::send => 2,
+            recv @ literal::channel::recv => 1,
+            try_recv @ literal::channel::try_recv => 1,
+        }, methods);
+        Self::new(Symbol::from("Channel"), methods)
+    }
+    /// Creates a new Code type, whose instances are the nodes of the code quoted by `quote`
+    #[must_use]
+    pub fn code() -> Self {
+        let mut methods = EnvTable::new();
+        fields!(Code => {
+            splice  @ literal::code::splice => 2,
+            compile @ literal::code::compile => 1,
+        }, methods);
+        literal::code::constructors(&mut methods);
+
+        let mut module = Self::new(Symbol::from("Code"), methods);
+        for (kind, fields) in literal::code::NODES {
+            let fields = fields.iter().copied().map(Symbol::from).collect();
+            module
+                .variants
+                .insert(Symbol::new(format!("Code.{kind}")), fields);
+        }
+        module
+    }
+    /// Creates a new Process type
+    #[must_use]
+    pub fn process() -> Self {
+        let mut methods = EnvTable::new();
+        fields!(Process => {
+            spawn    @ literal::process::spawn => 1,
+            self     @ literal::process::this => 1,
+            register @ literal::process::register => 2,
+            whereis  @ literal::process::whereis => 1,
+            receive  @ literal::process::receive => 1,
+        }, methods);
+        Self::new(Symbol::from("Process"), methods)
+    }
+    /// Creates a new Vm type, whose `stats` are instances of it
+    #[must_use]
+    pub fn vm() -> Self {
+        let mut methods = EnvTable::new();
+        fields!(Vm => {
+            stats @ crate::stats::stats => 1,
+        }, methods);
+
+        let mut module = Self::new(Symbol::from("Vm"), methods);
+        let fields = crate::stats::Stats::FIELDS.map(Symbol::from).to_vec();
+        module.variants.insert(Symbol::from("Vm.stats"), fields);
+        module
+    }
+    /// Creates a new Exception type, whose instances are the errors rescued by `try`
+    #[must_use]
+    pub fn exception() -> Self {
+        let mut methods = EnvTable::new();
+        methods.insert(
+            Symbol::from("new"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, literal::exception::new)
+                    .with_signature(signature!("Exception.new", [Sym, Str, Any])),
+            )),
+        );
+
+        let mut module = Self::new(Symbol::from("Exception"), methods);
+        let fields = literal::exception::FIELDS.map(Symbol::from).to_vec();
+        module
+            .variants
+            .insert(Symbol::from("Exception.new"), fields);
+        module
+    }
+    /// Creates a new Generator type
+    #[must_use]
+    pub fn generator() -> Self {
+        let mut methods = EnvTable::new();
+        fields!(Generator => {
+            next   @ literal::generator::next => 1,
+            toList @ literal::generator::to_list => 1,
+        }, methods);
+        methods.insert(
+            Symbol::from("take"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, literal::generator::take)
+                    .with_signature(signature!("Generator.take", [Index, Any])),
+            )),
+        );
+        methods.insert(
+            Symbol::from("done?"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, literal::generator::is_done)
+                    .with_signature(signature!("Generator.done?", 1)),
+            )),
+        );
+        Self::new(Symbol::from("Generator"), methods)
+    }
+    /// Creates a new Promise type
+    #[must_use]
+    pub fn promise() -> Self {
+        let mut methods = EnvTable::new();
+        fields!(Promise => {
+            await @ literal::promise::wait => 1,
+            sleep @ literal::promise::sleep => 1,
+            read  @ literal::promise::read => 1,
+            write @ literal::promise::write => 2,
+        }, methods);
+        methods.insert(
+            Symbol::from("done?"),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, literal::promise::is_done)
+                    .with_signature(signature!("Promise.done?", 1)),
+            )),
+        );
+        Self::new(Symbol::from("Promise"), methods)
+    }
     /// Creates a new Nil type.
     #[must_use]
     pub fn nil() -> Self {
@@ -293,11 +702,88 @@ impl YexModule {
 
         methods.insert(
             Symbol::from("show"),
-            Value::Fn(GcRef::new(Fn::new_native(1, |vm, x| {
-                super::show(vm, x).map(std::convert::Into::into)
-            }))),
+            Value::Fn(GcRef::new(
+                Fn::new_native(0, |vm, x| super::show(vm, x).map(std::convert::Into::into))
+                    .with_signature(signature!("Nil.show", 1)),
+            )),
         );
 
         Self::new(Symbol::from("Nil"), methods)
     }
 }
+
+/// Calls a method that only takes `self` with the instance it was bound to, ignoring the `()`
+fn call_with_self(vm: *mut VirtualMachine, args: Vec<Value>) -> InterpretResult<Value> {
+    let vm = unsafe { &mut *vm };
+    vm.push(args[1].clone());
+    vm.push(args[0].clone());
+    vm.call(1)?;
+    Ok(vm.pop())
+}
+
+/// The built-in types, each one created the first time a thread needs it and shared by every
+/// value of the type after that, so `type_of` doesn't build a new type on every call
+pub mod builtin {
+    use super::YexModule;
+    use crate::{env::EnvTable, gc::GcRef, Symbol, Tuple, Value};
+
+    macro_rules! builtins {
+        ($($name:ident, $cell:ident => $make:expr;)*) => {
+            thread_local! {
+                $(static $cell: GcRef<YexModule> = shared($make);)*
+            }
+
+            $(
+                #[doc = concat!("The `", stringify!($name), "` built-in type")]
+                #[must_use]
+                pub fn $name() -> GcRef<YexModule> {
+                    $cell.with(GcRef::clone)
+                }
+            )*
+        };
+    }
+
+    /// Shares a built-in type, adding its variants without fields to it like the compiler does
+    /// for the types defined in yex
+    fn shared(module: YexModule) -> GcRef<YexModule> {
+        let nullary = module
+            .variants
+            .iter()
+            .filter(|(_, fields)| fields.is_empty())
+            .map(|(tag, _)| *tag)
+            .collect::<Vec<_>>();
+
+        let mut module = GcRef::new(module);
+        for tag in nullary {
+            let name = tag.as_str().rsplit('.').next().unwrap_or_default();
+            let value = Value::Tagged(module.clone(), tag, Tuple::from(vec![]));
+            unsafe { module.mut_ref() }
+                .fields
+                .insert(Symbol::from(name), value);
+        }
+        module
+    }
+
+    builtins! {
+        nil, NIL => YexModule::nil();
+        bool, BOOL => YexModule::bool();
+        num, NUM => YexModule::num();
+        int, INT => YexModule::int();
+        str, STR => YexModule::str();
+        list, LIST => YexModule::list();
+        sym, SYM => YexModule::sym();
+        fun, FUN => YexModule::fun();
+        tuple, TUPLE => YexModule::tuple();
+        result, RESULT => YexModule::result();
+        ffi, FFI => YexModule::ffi();
+        code, CODE => YexModule::code();
+        fiber, FIBER => YexModule::fiber();
+        channel, CHANNEL => YexModule::channel();
+        process, PROCESS => YexModule::process();
+        vm, VM => YexModule::vm();
+        exception, EXCEPTION => YexModule::exception();
+        promise, PROMISE => YexModule::promise();
+        generator, GENERATOR => YexModule::generator();
+        userdata, USERDATA => YexModule::new(Symbol::from("UserData"), EnvTable::new());
+    }
+}
@@ -1,7 +1,15 @@
+use std::ptr;
+
 use crate::Symbol;
 
+/// The version of the instruction set, written at the start of the packed bytecode so it's only
+/// run by a virtual machine that understands it. It goes up whenever an opcode is added, removed
+/// or changes its operands or what it does, or when the packed encoding changes
+pub const BYTECODE_VERSION: u32 = 3;
+
 /// `OpCode`s for the virtualMachine
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[repr(u8)]
 pub enum OpCode {
     /// Do nothing
     Nop,
@@ -26,13 +34,14 @@ pub enum OpCode {
     /// The stack layout after running it: [c2, ..., c1]
     Swap(usize, usize),
 
-    /// Read a value from a variable, receives the index of the variable name in the constant table as
-    /// argument
+    /// Read a value from a variable, receives the slot of the variable in the frame as argument,
+    /// raising a `LocalError` if the frame has never saved to that slot (or a later one)
     /// The stack layout before running this opcode: []
     /// The stack layout after running it: [variable-value]
     Load(usize),
 
-    /// Save a value to a variable
+    /// Save a value to a variable, receives the slot of the variable in the frame as argument, the
+    /// frame grows to cover the slot
     /// The stack layout before running this opcode: [value-to-save]
     /// The stack layout after running it: []
     Save(usize),
@@ -47,7 +56,8 @@ pub enum OpCode {
     /// The stack layout after running it: []
     Savg(Symbol),
 
-    /// Drops a variable, receives the index of the variable name in the constant table as argument
+    /// Drops a variable, receives its slot as argument, the frame shrinks if it's the last one
+    /// (and it's an error if it isn't, when checking the scopes)
     /// The stack layout before running this opcode: []
     /// The stack layout after running it: []
     Drop(usize),
@@ -112,6 +122,17 @@ pub enum OpCode {
     /// The stack layout after running it: [result]
     Div,
 
+    /// Divide the two values on the stack top, rounding the result down
+    /// The stack layout before running this opcode: [const1, const2]
+    /// The stack layout after running it: [result]
+    FloorDiv,
+
+    /// Gets the remainder of the floor division of the two values on the stack top, which has the
+    /// sign of the divisor
+    /// The stack layout before running this opcode: [const1, const2]
+    /// The stack layout after running it: [result]
+    Mod,
+
     /// Negates the value on the stack top
     /// The stack layout before running this opcode: [const]
     /// The stack layout after running it: [result]
@@ -132,11 +153,18 @@ pub enum OpCode {
     /// The stack layout after running it: [result]
     Xor,
 
-    /// Apply shift-right operation on the two values on the stack top
+    /// Apply arithmetic shift-right operation on the two values on the stack top, which keeps the
+    /// sign of the value
     /// The stack layout before running this opcode: [const1, const2]
     /// The stack layout after running it: [result]
     Shr,
 
+    /// Apply logical shift-right operation on the two values on the stack top, which fills the
+    /// left with zeros
+    /// The stack layout before running this opcode: [const1, const2]
+    /// The stack layout after running it: [result]
+    UShr,
+
     /// Apply shift-left operation on the two values on the stack top
     /// The stack layout before running this opcode: [const1, const2]
     /// The stack layout after running it: [result]
@@ -152,6 +180,11 @@ pub enum OpCode {
     /// The stack layout after running it: [result]
     BitOr,
 
+    /// Flips every bit of the value on the stack top
+    /// The stack layout before running this opcode: [const]
+    /// The stack layout after running it: [result]
+    BitNot,
+
     /// Check if the two values on the stack tops are equal
     /// The stack layout before running this opcode: [const1, const2]
     /// The stack layout after running it: [result]
@@ -177,6 +210,11 @@ pub enum OpCode {
     /// The stack layout after running it: [type]
     Type,
 
+    /// Checks if the value is of the type, see [`crate::Value::is_a`]
+    /// The stack layout before running this opcode: [instance, type]
+    /// The stack layout after running it: [bool]
+    IsA,
+
     /// Get a reference to a function from a type
     /// The stack layout before running this opcode: [type]
     /// The stack layout after running it: [function-ref]
@@ -218,6 +256,132 @@ pub enum OpCode {
     /// The stack layout before running this opcode: [tagged]
     /// The stack layout after running it: [tuple]
     TagTup,
+
+    /// Suspends the current generator frame, handing the stack top to whoever resumed it. Once
+    /// resumed, pushes `nil` as the value of the `yield` expression
+    /// The stack layout before running this opcode: [value]
+    /// The stack layout after running it: [nil]
+    Yield,
+
+    /// Applies a binary operator to its operands, which are read from the locals and the constants
+    /// of the frame instead of being pushed first, at most the left one may come from the stack
+    /// The stack layout before running this opcode: [] or [left]
+    /// The stack layout after running it: [result]
+    Bin(Operator, Operand, Operand),
+
+    /// Runs the extension installed with [`crate::VirtualMachine::set_extension`] under the id,
+    /// receives the id and the number of values it takes as arguments, raising an
+    /// `ExtensionError` if there's none
+    /// The stack layout before running this opcode: [...args]
+    /// The stack layout after running it: [result]
+    Ext(u8, usize),
+
+    /// Copies an instance of a type, with one of the fields of its variant set to a new value,
+    /// receives the name of the field as argument
+    /// The stack layout before running this opcode: [instance, value]
+    /// The stack layout after running it: [updated-instance]
+    SetField(Symbol),
+
+    /// Raises again the last error caught by a `Try` of the running function, with its message
+    /// The stack layout before running this opcode: []
+    /// The stack layout after running it: []
+    Rethrow,
+
+    /// Opens a scope for the locals saved after it, until the matching `ExitScope`
+    /// The stack layout before running this opcode: []
+    /// The stack layout after running it: []
+    EnterScope,
+
+    /// Closes the innermost scope, dropping every local saved since it was opened, raising a
+    /// `ScopeError` if there's none
+    /// The stack layout before running this opcode: []
+    /// The stack layout after running it: []
+    ExitScope,
+
+    /// Checks if the value is an instance whose variant has a field with the name, receives the
+    /// name of the field as argument
+    /// The stack layout before running this opcode: [value]
+    /// The stack layout after running it: [bool]
+    HasField(Symbol),
+
+    /// Pushes the running function, before any argument was applied to it, so anonymous
+    /// functions can call themselves, raises a `RecurError` outside of functions
+    /// The stack layout before running this opcode: []
+    /// The stack layout after running it: [function]
+    Recur,
+
+    /// Builds a closure from a function and the values of the enclosing locals it uses, receives
+    /// how many values it captures as argument
+    /// The stack layout before running this opcode: [captured 1, ..., captured n, function]
+    /// The stack layout after running it: [closure]
+    Closure(usize),
+
+    /// Pushes a value captured by the running closure, receives its index as argument, raises a
+    /// `LocalError` if the running function didn't capture it
+    /// The stack layout before running this opcode: []
+    /// The stack layout after running it: [value]
+    Loac(usize),
+}
+
+/// Where an operand of a register-style instruction is read from
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Operand {
+    /// Popped from the stack
+    Stack,
+    /// A local of the running frame, by its index
+    Local(u32),
+    /// A value of the constant table, by its index
+    Const(u32),
+}
+
+/// The binary operators of [`OpCode::Bin`]
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Operator {
+    /// Same as [`OpCode::Add`]
+    Add,
+    /// Same as [`OpCode::Sub`]
+    Sub,
+    /// Same as [`OpCode::Mul`]
+    Mul,
+    /// Same as [`OpCode::Div`]
+    Div,
+    /// Same as [`OpCode::Rem`]
+    Rem,
+    /// Same as [`OpCode::Eq`]
+    Eq,
+    /// Same as [`OpCode::Less`]
+    Less,
+    /// Same as [`OpCode::LessEq`]
+    LessEq,
+}
+
+impl TryFrom<OpCode> for Operator {
+    type Error = ();
+
+    fn try_from(op: OpCode) -> Result<Self, Self::Error> {
+        Ok(match op {
+            OpCode::Add => Self::Add,
+            OpCode::Sub => Self::Sub,
+            OpCode::Mul => Self::Mul,
+            OpCode::Div => Self::Div,
+            OpCode::Rem => Self::Rem,
+            OpCode::Eq => Self::Eq,
+            OpCode::Less => Self::Less,
+            OpCode::LessEq => Self::LessEq,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl OpCode {
+    /// The position of the variant in the declaration of [`OpCode`], used by the interpreter loop
+    /// to find the handler of an opcode
+    #[must_use]
+    #[inline]
+    pub fn kind(&self) -> usize {
+        // SAFETY: `OpCode` is `repr(u8)`, so it starts with its discriminant, which is a `u8`
+        usize::from(unsafe { *ptr::from_ref(self).cast::<u8>() })
+    }
 }
 
 /// Stocks the [`crate::OpCode`] with the line and the column of it on the original source code,
@@ -0,0 +1,116 @@
+//! The standard output and error of the programs, which can be redirected, like to capture what a
+//! program prints
+use std::io::{self, Write};
+
+use crate::{error::InterpretResult, raise, Symbol, VirtualMachine};
+
+/// Where the programs write to
+pub(crate) struct Output {
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+        }
+    }
+}
+
+impl VirtualMachine {
+    /// Sends what the programs write to the standard output (with `print`, `println` and
+    /// `write :stdout`) to `out` instead
+    pub fn set_stdout(&mut self, out: impl Write + 'static) {
+        self.output.stdout = Box::new(out);
+    }
+
+    /// Sends what the programs write to the standard error (with `eprint`, `eprintln`, `dbg` and
+    /// `write :stderr`) to `out` instead
+    pub fn set_stderr(&mut self, out: impl Write + 'static) {
+        self.output.stderr = Box::new(out);
+    }
+
+    /// Writes `text` to the handle named `to`, either `stdout` or `stderr`, and flushes it, so
+    /// the text shows up right away even without a newline
+    pub(crate) fn write_to(&mut self, to: Symbol, text: &str) -> InterpretResult<()> {
+        let out = match to.as_str() {
+            "stdout" => &mut self.output.stdout,
+            "stderr" => &mut self.output.stderr,
+            other => raise!(
+                ValueError,
+                "Can't write to '{}', expected :stdout or :stderr",
+                other
+            )?,
+        };
+
+        out.write_all(text.as_bytes())?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct Captured(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn redirect_the_output() {
+    use crate::{OpCode, OpCodeMetadata, Value};
+
+    let (stdout, stderr) = (Captured::default(), Captured::default());
+    let mut vm = VirtualMachine::default();
+    vm.set_stdout(stdout.clone());
+    vm.set_stderr(stderr.clone());
+    vm.set_consts(vec![
+        Value::Int(1),
+        "two".to_string().into(),
+        Symbol::from("stderr").into(),
+        Symbol::from("stdin").into(),
+    ]);
+
+    let call = |name: &str, args: &[usize]| {
+        let mut ops = args
+            .iter()
+            .rev()
+            .map(|&idx| OpCode::Push(idx))
+            .collect::<Vec<_>>();
+        ops.extend([OpCode::Loag(Symbol::new(name)), OpCode::Call(args.len())]);
+        ops.push(OpCode::Pop);
+        ops
+    };
+    let ops = [
+        call("print", &[0]),
+        call("println", &[1]),
+        call("eprint", &[1]),
+        call("eprintln", &[0]),
+        call("write", &[2, 1]),
+    ]
+    .concat();
+    let bytecode = ops
+        .into_iter()
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .collect::<Vec<_>>();
+    vm.run(&bytecode).unwrap();
+
+    assert_eq!(&*stdout.0.borrow(), b"1two\n");
+    assert_eq!(&*stderr.0.borrow(), b"two1\ntwo");
+
+    let bad = call("write", &[3, 1])
+        .into_iter()
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .collect::<Vec<_>>();
+    assert_eq!(vm.run(&bad).unwrap_err().err.as_str(), "ValueError");
+}
@@ -0,0 +1,105 @@
+//! Grouping and counting the items of lists, in a single pass using the hash of the values. The
+//! groups are tuples of the key and what it gathered, in the order their keys were first seen
+use std::collections::{HashMap, HashSet};
+
+use crate::{literal::TryGet, InterpretResult, List, Tuple, Value, VirtualMachine};
+
+fn call(vm: &mut VirtualMachine, fun: &Value, arg: Value) -> InterpretResult<Value> {
+    vm.push(arg);
+    vm.push(fun.clone());
+    vm.call(1)?;
+    Ok(vm.pop())
+}
+
+/// Gathers the values into groups by their keys, keeping their order in each group
+fn gather(pairs: impl IntoIterator<Item = (Value, Value)>) -> Vec<(Value, Vec<Value>)> {
+    let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+    let mut index: HashMap<Value, usize> = HashMap::new();
+    for (key, value) in pairs {
+        if let Some(&idx) = index.get(&key) {
+            groups[idx].1.push(value);
+        } else {
+            index.insert(key.clone(), groups.len());
+            groups.push((key, vec![value]));
+        }
+    }
+    groups
+}
+
+fn pair(key: Value, value: Value) -> Value {
+    Value::Tuple(Tuple::from(vec![key, value]))
+}
+
+pub(super) fn group_by(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
+    let xs: List = args[1].get()?;
+    let pairs = xs
+        .iter()
+        .map(|x| Ok((call(vm, &args[0], x.clone())?, x.clone())))
+        .collect::<InterpretResult<Vec<_>>>()?;
+
+    let groups = gather(pairs)
+        .into_iter()
+        .map(|(key, items)| pair(key, Value::List(items.into_iter().collect())));
+    Ok(Value::List(groups.collect()))
+}
+
+pub(super) fn frequencies(args: &[Value]) -> InterpretResult<Value> {
+    let xs: List = args[0].get()?;
+    let counts = gather(xs.iter().map(|x| (x.clone(), Value::Nil)))
+        .into_iter()
+        .map(|(key, items)| pair(key, Value::from(items.len())));
+    Ok(Value::List(counts.collect()))
+}
+
+pub(super) fn partition(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
+    let xs: List = args[1].get()?;
+    let (mut matching, mut rest) = (Vec::new(), Vec::new());
+    for x in &xs {
+        if call(vm, &args[0], x.clone())?.to_bool() {
+            matching.push(x.clone());
+        } else {
+            rest.push(x.clone());
+        }
+    }
+
+    Ok(pair(
+        Value::List(matching.into_iter().collect()),
+        Value::List(rest.into_iter().collect()),
+    ))
+}
+
+pub(super) fn uniq(args: &[Value]) -> InterpretResult<Value> {
+    let xs: List = args[0].get()?;
+    let mut seen = HashSet::new();
+    let unique = xs.iter().filter(|x| seen.insert(*x)).cloned();
+    Ok(Value::List(unique.collect()))
+}
+
+#[test]
+fn gather_by_key() {
+    let num = |n: f64| Value::Num(n);
+    let groups = gather([
+        (num(1.0), num(10.0)),
+        (num(2.0), num(20.0)),
+        (num(1.0), num(11.0)),
+    ]);
+    assert_eq!(
+        groups,
+        [
+            (num(1.0), vec![num(10.0), num(11.0)]),
+            (num(2.0), vec![num(20.0)])
+        ]
+    );
+
+    let xs = Value::List([1.0, 2.0, 1.0, 0.0, -0.0].map(num).into_iter().collect());
+    let unique = uniq(std::slice::from_ref(&xs)).unwrap();
+    assert_eq!(
+        unique,
+        Value::List([1.0, 2.0, 0.0].map(num).into_iter().collect())
+    );
+
+    let Value::List(counts) = frequencies(&[xs]).unwrap() else {
+        panic!("expected a list of counts");
+    };
+    assert_eq!(counts.index(2), pair(num(0.0), num(2.0)));
+}
@@ -2,41 +2,43 @@ use crate::{
     env::EnvTable,
     error::InterpretError,
     gc::GcRef,
-    literal::{nil, show, TryGet, Value},
-    raise_err, InterpretResult, Symbol, VirtualMachine, YexModule,
+    literal::{
+        channel, code, exception, fiber, nil, num, promise, show, yexmodule::builtin, TryGet, Value,
+    },
+    patch_bytecode, patch_constant, raise, raise_err, signature, Bytecode, Fn, InterpretResult,
+    List, OpCode, Symbol, VirtualMachine, YexModule, PRELUDE_BASE,
 };
-use std::io::{self, Write};
+use std::{
+    cell::{Cell, RefCell},
+    io,
+    sync::{Mutex, PoisonError},
+};
+
+mod aggregate;
+mod nested;
 
 #[macro_export]
 /// Insert a function into a `EnvTable`
 macro_rules! insert_fn {
-    ($table:ident, $name: expr, $fn: expr) => {
-        insert_fn!($table, $name, $fn, 1)
-    };
-
-    ($table:ident, $name: expr, $fn: expr, $arity:expr) => {
+    ($table:ident, $name: expr, $fn: expr, [$($param:ident),*]) => {
         $table.insert(
             $crate::Symbol::new($name),
-            $crate::literal::Value::Fn($crate::gc::GcRef::new($crate::literal::fun::Fn {
-                arity: $arity,
-                body: $crate::gc::GcRef::new($crate::literal::fun::FnKind::Native(|_, it| {
-                    $fn(&*it)
-                })),
-                args: $crate::StackVec::new(),
-            })),
+            $crate::literal::Value::Fn($crate::gc::GcRef::new(
+                $crate::literal::fun::Fn::new_native(0, |_, it| $fn(&*it))
+                    .with_signature($crate::signature!($name, [$($param),*])),
+            )),
         )
     };
 
-    (:vm $table:ident, $name: expr, $fn: expr, $arity:expr) => {
+    (:vm $table:ident, $name: expr, $fn: expr, [$($param:ident),*]) => {
         $table.insert(
             $crate::Symbol::new($name),
-            $crate::literal::Value::Fn($crate::gc::GcRef::new($crate::literal::fun::Fn {
-                arity: $arity,
-                body: $crate::gc::GcRef::new($crate::literal::fun::FnKind::Native(|vm, it| {
+            $crate::literal::Value::Fn($crate::gc::GcRef::new(
+                $crate::literal::fun::Fn::new_native(0, |vm, it| {
                     $fn(unsafe { vm.as_mut().unwrap() }, &*it)
-                })),
-                args: $crate::StackVec::new(),
-            })),
+                })
+                .with_signature($crate::signature!($name, [$($param),*])),
+            )),
         )
     };
 }
@@ -48,52 +50,330 @@ macro_rules! insert {
     };
 }
 
-fn println(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
-    println!("{}", show(vm, args.into())?);
+/// Shows a value, like `print` does, to `to`, with `end` after it
+fn show_to(vm: &mut VirtualMachine, to: &str, value: &Value, end: &str) -> InterpretResult<Value> {
+    let text = show(vm, vec![value.clone()])? + end;
+    vm.write_to(Symbol::from(to), &text)?;
     Ok(nil())
 }
 
 fn print(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
-    print!("{}", show(vm, args.into())?);
-    Ok(nil())
+    show_to(vm, "stdout", &args[0], "")
 }
 
-fn debug_stack(vm: &mut VirtualMachine, _args: &[Value]) -> InterpretResult<Value> {
-    println!("{:#?}", vm.stack);
+fn println(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
+    show_to(vm, "stdout", &args[0], "\n")
+}
+
+fn eprint(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
+    show_to(vm, "stderr", &args[0], "")
+}
+
+fn eprintln(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
+    show_to(vm, "stderr", &args[0], "\n")
+}
+
+/// Writes a string as it is, without a newline or spaces, to `:stdout` or `:stderr`
+fn write(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
+    let to: Symbol = args[0].get()?;
+    let text: String = args[1].get()?;
+    vm.write_to(to, &text)?;
     Ok(nil())
 }
 
-fn input(args: &[Value]) -> InterpretResult<Value> {
-    let prompt: String = args[0].get()?;
-    print!("{}", prompt);
+/// Prints a value after its label, for `dbg` expressions, whose keyword hides this function
+fn dbg(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
+    let label: String = args[1].get()?;
+    vm.write_to(Symbol::from("stderr"), &format!("{label} {}\n", args[0]))?;
+    Ok(args[0].clone())
+}
 
-    io::stdout().flush()?;
+fn debug_stack(vm: &mut VirtualMachine, _args: &[Value]) -> InterpretResult<Value> {
+    let stack = format!("{:#?}\n", vm.stack);
+    vm.write_to(Symbol::from("stdout"), &stack)?;
+    Ok(nil())
+}
 
+fn read_line() -> io::Result<String> {
     let mut input = String::new();
 
     io::stdin().read_line(&mut input)?;
 
     input.pop();
 
+    Ok(input)
+}
+
+fn input(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
+    let prompt: String = args[0].get()?;
+    vm.write_to(Symbol::from("stdout"), &prompt)?;
+
+    // the lines read in deterministic mode are recorded, or taken from the recording
+    let input = match &mut vm.deterministic {
+        Some(deterministic) => {
+            match deterministic.io(|| read_line().map(Some).map_err(|e| e.to_string()))? {
+                Ok(input) => input.unwrap_or_default(),
+                Err(e) => raise!(IoError, "{}", e)?,
+            }
+        }
+        None => read_line()?,
+    };
+
     Ok(Value::Str(GcRef::new(input)))
 }
 
+fn random(vm: &mut VirtualMachine, _: &[Value]) -> InterpretResult<Value> {
+    Ok(Value::Num(vm.random()))
+}
+
+fn clock(vm: &mut VirtualMachine, _: &[Value]) -> InterpretResult<Value> {
+    Ok(Value::Num(vm.clock()))
+}
+
 fn r#type(args: &[Value]) -> InterpretResult<Value> {
     Ok(Value::Module(args[0].type_of()))
 }
 
+fn is_a(args: &[Value]) -> InterpretResult<Value> {
+    let ty: GcRef<YexModule> = args[1].get()?;
+    Ok(args[0].is_a(&ty).into())
+}
+
+fn sym_from_str(args: &[Value]) -> InterpretResult<Value> {
+    let name: String = args[0].get()?;
+    Ok(Symbol::new(name).into())
+}
+
+fn sym_to_str(args: &[Value]) -> InterpretResult<Value> {
+    let sym: Symbol = args[0].get()?;
+    Ok(Value::Str(GcRef::new(sym.as_str().to_string())))
+}
+
+/// The field named by a string or a symbol, of an instance (like `instance.name`) or of a type
+/// (like `Type.name`), so names read as strings reach the fields written as symbols
+fn field(args: &[Value]) -> InterpretResult<Value> {
+    let name = match &args[0] {
+        Value::Str(name) => Symbol::new(name.as_str()),
+        name => name.get()?,
+    };
+
+    match &args[1] {
+        instance @ Value::Tagged(ty, ..) => ty.instance_field(instance, name),
+        Value::Module(ty) => ty.field(name),
+        other => raise!(
+            TypeError,
+            "Expected an instance or a type for 'field', found '{}'",
+            other
+        ),
+    }
+}
+
+fn all_symbols(_: &[Value]) -> InterpretResult<Value> {
+    let symbols = Symbol::interned().into_iter().map(Value::from);
+    Ok(Value::List(symbols.collect()))
+}
+
+fn freeze(args: &[Value]) -> InterpretResult<Value> {
+    if !args[0].is_frozen() {
+        raise!(
+            FrozenError,
+            "'{}' holds a native resource and can't be frozen",
+            args[0]
+        )?;
+    }
+    Ok(args[0].clone())
+}
+
+/// Shows a value like `print` does, which is how it's shown in an interpolated string
+fn to_str(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
+    Ok(Value::Str(GcRef::new(show(vm, vec![args[0].clone()])?)))
+}
+
 fn inspect(args: &[Value]) -> InterpretResult<Value> {
     Ok(Value::Str(GcRef::new(format!("{:#?}", &args[0]))))
 }
 
+/// Reads a number from a string, an integer unless it has decimals or an exponent
 fn num(args: &[Value]) -> InterpretResult<Value> {
     let str: String = args[0].get()?;
 
-    str.parse::<f64>()
-        .map(Value::Num)
+    str.parse::<i64>()
+        .map(Value::Int)
+        .or_else(|_| str.parse::<f64>().map(Value::Num))
         .map_err(|_| raise_err!(TypeError, "Cannot convert '{}' to number", str))
 }
 
+/// The largest number below which every integer is exactly a float
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// Rounds a number to an integer with `round`, raising if it's not finite or too large to fit
+/// in 64 bits
+fn to_int(name: &str, n: &Value, round: fn(f64) -> f64) -> InterpretResult<Value> {
+    let n: f64 = match n {
+        Value::Int(_) => return Ok(n.clone()),
+        n => n.get()?,
+    };
+    if !n.is_finite() {
+        return raise!(ValueError, "'{}' can't convert '{}' to an integer", name, n);
+    }
+
+    match num::exact_int(round(n)) {
+        Some(int) => Ok(Value::Int(int)),
+        None => raise!(
+            ValueError,
+            "'{}' of '{}' is too large for an integer",
+            name,
+            n
+        ),
+    }
+}
+
+fn floor_int(args: &[Value]) -> InterpretResult<Value> {
+    to_int("floor_int", &args[0], f64::floor)
+}
+
+fn ceil_int(args: &[Value]) -> InterpretResult<Value> {
+    to_int("ceil_int", &args[0], f64::ceil)
+}
+
+fn trunc_int(args: &[Value]) -> InterpretResult<Value> {
+    to_int("trunc_int", &args[0], f64::trunc)
+}
+
+fn round_int(args: &[Value]) -> InterpretResult<Value> {
+    to_int("round_int", &args[0], f64::round)
+}
+
+/// Checks if `n`, the result of an operation on `a` and `b`, overflowed: it's infinite when they
+/// aren't, or they're integers and it's too large to be exact
+fn overflows(a: f64, b: f64, n: f64) -> bool {
+    let integers = a.fract() == 0.0 && b.fract() == 0.0;
+    (a.is_finite() && b.is_finite() && !n.is_finite()) || (integers && n.abs() > MAX_SAFE_INTEGER)
+}
+
+/// Applies `op` to the two numbers, raising an `OverflowError` if the result overflows, which
+/// integers do past 64 bits
+fn checked(name: &str, args: &[Value], op: Ops) -> InterpretResult<Value> {
+    if let (Value::Int(a), Value::Int(b)) = (&args[0], &args[1]) {
+        return match (op.checked)(*a, *b) {
+            Some(n) => Ok(Value::Int(n)),
+            None => raise!(OverflowError, "'{}' of '{}' and '{}' overflows", name, a, b),
+        };
+    }
+
+    let (a, b): (f64, f64) = (args[0].get()?, args[1].get()?);
+    let op = op.float;
+    let n = op(a, b);
+    if overflows(a, b, n) {
+        return raise!(OverflowError, "'{}' of '{}' and '{}' overflows", name, a, b);
+    }
+    Ok(Value::Num(n))
+}
+
+/// Applies `op` to the two numbers, clamping the result to the limit of its sign if it overflows:
+/// the limits of 64 bits for integers, and for floats the largest exact integer (or the largest
+/// number, if they aren't integers)
+fn saturating(args: &[Value], op: Ops) -> InterpretResult<Value> {
+    if let (Value::Int(a), Value::Int(b)) = (&args[0], &args[1]) {
+        return Ok(Value::Int((op.saturating)(*a, *b)));
+    }
+
+    let (a, b): (f64, f64) = (args[0].get()?, args[1].get()?);
+    let op = op.float;
+    let n = op(a, b);
+    if !overflows(a, b, n) {
+        return Ok(Value::Num(n));
+    }
+
+    let max = if a.fract() == 0.0 && b.fract() == 0.0 {
+        MAX_SAFE_INTEGER
+    } else {
+        f64::MAX
+    };
+    Ok(Value::Num(max.copysign(n)))
+}
+
+/// An operation on floats and its checked and saturating versions on integers
+struct Ops {
+    float: fn(f64, f64) -> f64,
+    checked: fn(i64, i64) -> Option<i64>,
+    saturating: fn(i64, i64) -> i64,
+}
+
+const ADD: Ops = Ops {
+    float: |a, b| a + b,
+    checked: i64::checked_add,
+    saturating: i64::saturating_add,
+};
+
+const SUB: Ops = Ops {
+    float: |a, b| a - b,
+    checked: i64::checked_sub,
+    saturating: i64::saturating_sub,
+};
+
+const MUL: Ops = Ops {
+    float: |a, b| a * b,
+    checked: i64::checked_mul,
+    saturating: i64::saturating_mul,
+};
+
+fn checked_add(args: &[Value]) -> InterpretResult<Value> {
+    checked("checked_add", args, ADD)
+}
+
+fn checked_sub(args: &[Value]) -> InterpretResult<Value> {
+    checked("checked_sub", args, SUB)
+}
+
+fn checked_mul(args: &[Value]) -> InterpretResult<Value> {
+    checked("checked_mul", args, MUL)
+}
+
+fn saturating_add(args: &[Value]) -> InterpretResult<Value> {
+    saturating(args, ADD)
+}
+
+fn saturating_sub(args: &[Value]) -> InterpretResult<Value> {
+    saturating(args, SUB)
+}
+
+fn saturating_mul(args: &[Value]) -> InterpretResult<Value> {
+    saturating(args, MUL)
+}
+
+fn applied_args(args: &[Value]) -> InterpretResult<Value> {
+    let fun: GcRef<Fn> = args[0].get()?;
+    Ok(Value::List(fun.applied_args().into_iter().collect()))
+}
+
+fn remaining_arity(args: &[Value]) -> InterpretResult<Value> {
+    let fun: GcRef<Fn> = args[0].get()?;
+    Ok(fun.remaining_arity().into())
+}
+
+fn set_float_precision(args: &[Value]) -> InterpretResult<Value> {
+    let precision = match &args[0] {
+        Value::Nil => None,
+        other => Some(other.get()?),
+    };
+    num::set_float_precision(precision);
+    Ok(Value::Nil)
+}
+
+fn set_overflow(args: &[Value]) -> InterpretResult<Value> {
+    let name: Symbol = args[0].get()?;
+    match num::Overflow::from_name(name.as_str()) {
+        Some(mode) => num::set_overflow(mode),
+        None => raise!(
+            ValueError,
+            "Expected :wrap, :saturate or :raise, found ':{}'",
+            name
+        )?,
+    }
+    Ok(Value::Nil)
+}
+
 fn exit(args: &[Value]) -> InterpretResult<Value> {
     let code: isize = args[0].get()?;
 
@@ -107,49 +387,227 @@ fn raise(args: &[Value]) -> InterpretResult<Value> {
     Err(InterpretError {
         err,
         msg,
-        line: unsafe { crate::LINE },
-        column: unsafe { crate::COLUMN },
+        line: crate::LINE.with(Cell::get),
+        column: crate::COLUMN.with(Cell::get),
+        trace: vec![],
+        data: None,
     })
 }
 
+fn assert_eq(args: &[Value]) -> InterpretResult<Value> {
+    let (expected, found) = (&args[0], &args[1]);
+
+    let diff = expected.diff(found);
+    if diff.is_empty() {
+        return Ok(nil());
+    }
+
+    // the parts of lists and instances that differ are listed below the values
+    let mut msg = format!("expected {expected}, found {found}");
+    if !diff.is_whole() {
+        for line in diff.to_string().lines() {
+            msg.push_str("\n  ");
+            msg.push_str(line);
+        }
+    }
+    raise!(AssertionError, "{}", msg)
+}
+
+/// Compiles (or unpacks) the part of the prelude written in yex, see [`crate::set_prelude_loader`]
+pub type Loader = fn(&VirtualMachine) -> Option<(Bytecode, Vec<Value>)>;
+
+static LOADER: Mutex<Option<Loader>> = Mutex::new(None);
+
+thread_local! {
+    /// The yex prelude, already loaded and pointed to the prelude constants, so it's only
+    /// compiled once per thread
+    static LOADED: RefCell<Option<(Bytecode, Vec<Value>)>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn set_loader(loader: Loader) {
+    *LOADER.lock().unwrap_or_else(PoisonError::into_inner) = Some(loader);
+    LOADED.with_borrow_mut(|loaded| *loaded = None);
+}
+
+/// Defines the functions of the yex prelude in `vm`, if there's a loader for it
+pub(crate) fn load_yex(vm: &mut VirtualMachine) {
+    let loaded = LOADED.with_borrow_mut(|loaded| {
+        if loaded.is_none() {
+            let loader = *LOADER.lock().unwrap_or_else(PoisonError::into_inner);
+            *loaded = loader
+                .and_then(|loader| loader(vm))
+                .map(|(mut bytecode, constants)| {
+                    patch_bytecode(&mut bytecode, PRELUDE_BASE);
+                    for constant in &constants {
+                        patch_constant(constant, PRELUDE_BASE);
+                    }
+                    (bytecode, constants)
+                });
+        }
+        loaded.clone()
+    });
+
+    if let Some((bytecode, constants)) = loaded {
+        vm.prelude_constants = constants;
+        vm.run(&bytecode).expect("the yex prelude can't fail");
+        vm.reset();
+    }
+}
+
+thread_local! {
+    /// The native part of the prelude, built the first time a thread starts a virtual machine
+    static NATIVES: EnvTable = natives();
+}
+
+/// Creates a table with the globals defined by the prelude
 pub fn prelude() -> EnvTable {
+    NATIVES.with(|natives| {
+        let mut prelude = EnvTable::with_capacity(64);
+        prelude.extend(natives.iter());
+        prelude
+    })
+}
+
+/// Checks if `name` is one of the globals defined by the native prelude
+pub(crate) fn is_native(name: &Symbol) -> bool {
+    NATIVES.with(|natives| natives.contains_key(name))
+}
+
+/// Checks if `value` is what the prelude defines `name` as, which every virtual machine starts
+/// with
+pub(crate) fn is_prelude(name: &Symbol, value: &Value) -> bool {
+    is_native(name)
+        || LOADED.with_borrow(|loaded| {
+            let Some((bytecode, constants)) = loaded else {
+                return false;
+            };
+            bytecode
+                .windows(2)
+                .any(|ops| match (&ops[0].opcode, &ops[1].opcode) {
+                    (OpCode::Push(idx), OpCode::Savg(saved)) => {
+                        let constant = idx
+                            .checked_sub(PRELUDE_BASE)
+                            .and_then(|it| constants.get(it));
+                        saved == name && constant == Some(value)
+                    }
+                    _ => false,
+                })
+        })
+}
+
+fn natives() -> EnvTable {
     let mut prelude = EnvTable::with_capacity(64);
-    insert_fn!(:vm prelude, "println", println, 1);
-    insert_fn!(:vm prelude, "print", print, 1);
-    insert_fn!(:vm prelude, "print_stack!", debug_stack, 1);
-    insert_fn!(prelude, "input", input);
-    insert_fn!(prelude, "type", r#type);
-    insert_fn!(prelude, "inspect", inspect);
-    insert_fn!(prelude, "num", num);
-    insert_fn!(prelude, "exit", exit);
-    insert_fn!(prelude, "raise", raise, 2);
-
-    insert!(prelude, "Nil", Value::Module(GcRef::new(YexModule::nil())));
+    insert_fn!(:vm prelude, "println", println, [Any]);
+    insert_fn!(:vm prelude, "print", print, [Any]);
+    insert_fn!(:vm prelude, "eprintln", eprintln, [Any]);
+    insert_fn!(:vm prelude, "eprint", eprint, [Any]);
+    insert_fn!(:vm prelude, "write", write, [Sym, Str]);
+    insert_fn!(:vm prelude, "print_stack!", debug_stack, [Any]);
+    insert_fn!(:vm prelude, "dbg", dbg, [Any, Str]);
+    insert_fn!(:vm prelude, "input", input, [Str]);
+    insert_fn!(:vm prelude, "random", random, [Any]);
+    insert_fn!(:vm prelude, "clock", clock, [Any]);
+    insert_fn!(prelude, "type", r#type, [Any]);
+    insert_fn!(prelude, "typeof", r#type, [Any]);
+    insert_fn!(prelude, "is_a", is_a, [Any, Any]);
+    insert_fn!(prelude, "inspect", inspect, [Any]);
+    insert_fn!(:vm prelude, "to_str", to_str, [Any]);
+    insert_fn!(prelude, "freeze", freeze, [Any]);
+    insert_fn!(prelude, "sym_from_str", sym_from_str, [Str]);
+    insert_fn!(prelude, "sym_to_str", sym_to_str, [Sym]);
+    insert_fn!(prelude, "all_symbols", all_symbols, [Any]);
+    insert_fn!(prelude, "field", field, [Any, Any]);
+    insert_fn!(:vm prelude, "group_by", aggregate::group_by, [Fn, List]);
+    insert_fn!(prelude, "frequencies", aggregate::frequencies, [List]);
+    insert_fn!(:vm prelude, "partition", aggregate::partition, [Fn, List]);
+    insert_fn!(prelude, "uniq", aggregate::uniq, [List]);
+    insert_fn!(prelude, "get_in", nested::get_in, [List, Any]);
+    insert_fn!(prelude, "get_in_or", nested::get_in_or, [Any, List, Any]);
+    insert_fn!(prelude, "put_in", nested::put_in, [List, Any, Any]);
+    insert_fn!(:vm prelude, "update_in", nested::update_in, [List, Any, Any]);
+    insert_fn!(prelude, "num", num, [Str]);
+    insert_fn!(prelude, "floor_int", floor_int, [Num]);
+    insert_fn!(prelude, "ceil_int", ceil_int, [Num]);
+    insert_fn!(prelude, "trunc_int", trunc_int, [Num]);
+    insert_fn!(prelude, "round_int", round_int, [Num]);
+    insert_fn!(prelude, "checked_add", checked_add, [Num, Num]);
+    insert_fn!(prelude, "checked_sub", checked_sub, [Num, Num]);
+    insert_fn!(prelude, "checked_mul", checked_mul, [Num, Num]);
+    insert_fn!(prelude, "saturating_add", saturating_add, [Num, Num]);
+    insert_fn!(prelude, "saturating_sub", saturating_sub, [Num, Num]);
+    insert_fn!(prelude, "saturating_mul", saturating_mul, [Num, Num]);
+    insert_fn!(prelude, "applied_args", applied_args, [Fn]);
+    insert_fn!(prelude, "remaining_arity", remaining_arity, [Fn]);
+    insert_fn!(prelude, "set_float_precision", set_float_precision, [Any]);
+    insert_fn!(prelude, "set_overflow", set_overflow, [Sym]);
+    insert_fn!(prelude, "exit", exit, [Num]);
+    insert_fn!(prelude, "raise", raise, [Sym, Str]);
+    insert_fn!(prelude, "throw", exception::throw, [Any]);
+    insert_fn!(prelude, "assert_eq", assert_eq, [Any, Any]);
+    // the command line arguments of scripts, set by the CLI
+    insert!(prelude, "args", Value::List(List::new()));
     insert!(
         prelude,
-        "Bool",
-        Value::Module(GcRef::new(YexModule::bool()))
+        "spawn",
+        Value::Fn(GcRef::new(
+            Fn::new_native(0, fiber::spawn).with_signature(signature!("spawn", [Fn]))
+        ))
     );
-    insert!(prelude, "Num", Value::Module(GcRef::new(YexModule::num())));
-    insert!(prelude, "Str", Value::Module(GcRef::new(YexModule::str())));
     insert!(
         prelude,
-        "List",
-        Value::Module(GcRef::new(YexModule::list()))
+        "join",
+        Value::Fn(GcRef::new(
+            Fn::new_native(0, fiber::join).with_signature(signature!("join", [Any]))
+        ))
     );
-    insert!(prelude, "Sym", Value::Module(GcRef::new(YexModule::sym())));
-    insert!(prelude, "Fn", Value::Module(GcRef::new(YexModule::fun())));
     insert!(
         prelude,
-        "Tuple",
-        Value::Module(GcRef::new(YexModule::tuple()))
+        "compile",
+        Value::Fn(GcRef::new(
+            Fn::new_native(0, code::compile).with_signature(signature!("compile", [Any]))
+        ))
     );
     insert!(
         prelude,
-        "Result",
-        Value::Module(GcRef::new(YexModule::result()))
+        "send",
+        Value::Fn(GcRef::new(
+            Fn::new_native(0, channel::send).with_signature(signature!("send", [Any, Any]))
+        ))
+    );
+    insert!(
+        prelude,
+        "recv",
+        Value::Fn(GcRef::new(
+            Fn::new_native(0, channel::recv).with_signature(signature!("recv", [Any]))
+        ))
+    );
+
+    insert!(prelude, "Nil", Value::Module(builtin::nil()));
+    insert!(prelude, "Bool", Value::Module(builtin::bool()));
+    insert!(prelude, "Num", Value::Module(builtin::num()));
+    insert!(prelude, "Int", Value::Module(builtin::int()));
+    insert!(prelude, "Str", Value::Module(builtin::str()));
+    insert!(prelude, "List", Value::Module(builtin::list()));
+    insert!(prelude, "Sym", Value::Module(builtin::sym()));
+    insert!(prelude, "Fn", Value::Module(builtin::fun()));
+    insert!(prelude, "Tuple", Value::Module(builtin::tuple()));
+    insert!(prelude, "Result", Value::Module(builtin::result()));
+    insert!(prelude, "FFI", Value::Module(builtin::ffi()));
+    insert!(prelude, "Code", Value::Module(builtin::code()));
+    insert!(prelude, "Fiber", Value::Module(builtin::fiber()));
+    insert!(prelude, "Channel", Value::Module(builtin::channel()));
+    insert!(prelude, "Process", Value::Module(builtin::process()));
+    insert!(prelude, "Vm", Value::Module(builtin::vm()));
+    insert!(prelude, "Exception", Value::Module(builtin::exception()));
+    insert!(
+        prelude,
+        "await",
+        Value::Fn(GcRef::new(
+            Fn::new_native(0, promise::wait).with_signature(signature!("await", [Any]))
+        ))
     );
-    insert!(prelude, "FFI", Value::Module(GcRef::new(YexModule::ffi())));
+    insert!(prelude, "Promise", Value::Module(builtin::promise()));
+    insert!(prelude, "Generator", Value::Module(builtin::generator()));
 
     prelude
 }
@@ -0,0 +1,141 @@
+//! Reading and updating the parts of nested values, like the items of a list of instances, by
+//! following a path of indices and field names
+use crate::{literal::TryGet, raise, InterpretResult, List, Symbol, Tuple, Value, VirtualMachine};
+
+/// The name of a field in a path, which can be a symbol or a string, like the ones `field` takes
+fn field_name(key: &Value) -> Option<Symbol> {
+    match key {
+        Value::Sym(name) => Some(name.0),
+        Value::Str(name) => Some(Symbol::new(name.as_str())),
+        _ => None,
+    }
+}
+
+/// The part of `value` at `key`, `None` if `value` doesn't have it, like an index past the end of
+/// a list or a field of another variant
+fn part(value: &Value, key: &Value) -> InterpretResult<Option<Value>> {
+    if !matches!(
+        key,
+        Value::Num(_) | Value::Int(_) | Value::Sym(_) | Value::Str(_)
+    ) {
+        return raise!(
+            TypeError,
+            "Expected an index or a field name in the path, found '{}'",
+            key
+        );
+    }
+
+    Ok(match (value, key) {
+        (Value::List(xs), Value::Num(_) | Value::Int(_)) => {
+            let idx: usize = key.get()?;
+            (idx < xs.len()).then(|| xs.index(idx))
+        }
+        (Value::Tuple(xs), Value::Num(_) | Value::Int(_)) => {
+            let idx: usize = key.get()?;
+            xs.0.get(idx).cloned()
+        }
+        (Value::Tagged(ty, tag, _), key) => match field_name(key) {
+            Some(name) if ty.has_field(*tag, name) => Some(ty.instance_field(value, name)?),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// A copy of `value` with the part at the end of `path` replaced by calling `update` with it
+fn update(
+    value: &Value,
+    path: &[Value],
+    update: &mut dyn FnMut(Value) -> InterpretResult<Value>,
+) -> InterpretResult<Value> {
+    let Some((key, rest)) = path.split_first() else {
+        return update(value.clone());
+    };
+    let Some(inner) = part(value, key)? else {
+        return raise!(ValueError, "'{}' has no part at '{}' to update", value, key);
+    };
+    let new = self::update(&inner, rest, update)?;
+
+    // the part exists, so the key is an index for lists and tuples and a field for instances
+    Ok(match (value, field_name(key)) {
+        (Value::Tagged(ty, tag, tup), Some(name)) => {
+            Value::Tagged(ty.clone(), *tag, ty.set_field(*tag, tup, name, new)?)
+        }
+        (Value::Tuple(xs), _) => {
+            let idx: usize = key.get()?;
+            let mut items = xs.0.to_vec();
+            items[idx] = new;
+            Value::Tuple(Tuple::from(items))
+        }
+        _ => {
+            let (xs, idx): (List, usize) = (value.get()?, key.get()?);
+            let mut items = xs.to_vec();
+            items[idx] = new;
+            Value::List(items.into_iter().collect())
+        }
+    })
+}
+
+/// The part at the end of `path`, `None` if any step of it is missing
+fn get(value: &Value, path: &List) -> InterpretResult<Option<Value>> {
+    let mut value = value.clone();
+    for key in path {
+        match part(&value, key)? {
+            Some(inner) => value = inner,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(value))
+}
+
+pub(super) fn get_in(args: &[Value]) -> InterpretResult<Value> {
+    let path: List = args[0].get()?;
+    Ok(get(&args[1], &path)?.unwrap_or(Value::Nil))
+}
+
+pub(super) fn get_in_or(args: &[Value]) -> InterpretResult<Value> {
+    let path: List = args[1].get()?;
+    Ok(get(&args[2], &path)?.unwrap_or_else(|| args[0].clone()))
+}
+
+pub(super) fn put_in(args: &[Value]) -> InterpretResult<Value> {
+    let path: List = args[0].get()?;
+    let new = args[1].clone();
+    update(&args[2], &path.to_vec(), &mut |_| Ok(new.clone()))
+}
+
+pub(super) fn update_in(vm: &mut VirtualMachine, args: &[Value]) -> InterpretResult<Value> {
+    let path: List = args[0].get()?;
+    let fun = &args[1];
+    update(&args[2], &path.to_vec(), &mut |value| {
+        vm.push(value);
+        vm.push(fun.clone());
+        vm.call(1)?;
+        Ok(vm.pop())
+    })
+}
+
+#[test]
+fn follow_paths() {
+    let pair = |x: f64, y: f64| Value::Tuple(Tuple::from(vec![Value::Num(x), Value::Num(y)]));
+    let nested = Value::List([pair(1.0, 2.0), pair(3.0, 4.0)].into_iter().collect());
+    let path = |keys: &[f64]| keys.iter().map(|it| Value::Num(*it)).collect::<List>();
+
+    assert_eq!(
+        get(&nested, &path(&[1.0, 0.0])).unwrap(),
+        Some(Value::Num(3.0))
+    );
+    assert_eq!(get(&nested, &path(&[2.0, 0.0])).unwrap(), None);
+    assert_eq!(get(&nested, &path(&[1.0, 0.0, 0.0])).unwrap(), None);
+    assert!(get(&nested, &path(&[-1.0])).is_err());
+
+    let updated = update(&nested, &path(&[0.0, 1.0]).to_vec(), &mut |_| {
+        Ok(Value::Nil)
+    })
+    .unwrap();
+    assert_eq!(
+        get(&updated, &path(&[0.0])).unwrap(),
+        Some(Value::Tuple(Tuple::from(vec![Value::Num(1.0), Value::Nil])))
+    );
+    assert!(update(&nested, &path(&[5.0]).to_vec(), &mut Ok).is_err());
+}
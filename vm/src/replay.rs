@@ -0,0 +1,179 @@
+//! Deterministic execution, to replay a run of a program
+//!
+//! In deterministic mode the random numbers come from a seed, the clock only moves when the
+//! program sleeps (which returns right away) and the IO operations run one at a time, in the
+//! order the program starts them. Their results are recorded to a log, which can be given back
+//! to run the program again with the same inputs, without touching the outside world.
+use std::{collections::VecDeque, time::SystemTime};
+
+use crate::{
+    encoding::{read_str, read_varint, write_str, write_varint},
+    error::InterpretResult,
+    raise, VirtualMachine,
+};
+
+/// The result of an IO operation, with the string it read, if it read one
+pub(crate) type Outcome = Result<Option<String>, String>;
+
+enum Log {
+    Record(Vec<Outcome>),
+    Replay(VecDeque<Outcome>),
+}
+
+/// The state of a VM in deterministic mode
+pub struct Deterministic {
+    seed: u64,
+    /// the virtual time, in milliseconds
+    clock: f64,
+    log: Log,
+}
+
+impl Deterministic {
+    /// Runs with random numbers from `seed`, recording the IO operations
+    #[must_use]
+    pub fn record(seed: u64) -> Self {
+        Self {
+            seed,
+            clock: 0.0,
+            log: Log::Record(vec![]),
+        }
+    }
+
+    /// Runs again the program that recorded `log`, returns `None` if the log is malformed
+    #[must_use]
+    pub fn replay(mut log: &[u8]) -> Option<Self> {
+        let seed = read_varint(&mut log)?;
+
+        let mut outcomes = VecDeque::new();
+        while let Some((&tag, rest)) = log.split_first() {
+            log = rest;
+            outcomes.push_back(match tag {
+                0 => Ok(None),
+                1 => Ok(Some(read_str(&mut log)?)),
+                2 => Err(read_str(&mut log)?),
+                _ => return None,
+            });
+        }
+
+        Some(Self {
+            seed,
+            clock: 0.0,
+            log: Log::Replay(outcomes),
+        })
+    }
+
+    /// The log of a recording run, with its seed and the outcomes of its IO operations
+    #[must_use]
+    pub fn log(&self) -> Vec<u8> {
+        let mut out = vec![];
+        write_varint(&mut out, self.seed);
+
+        let outcomes: Box<dyn Iterator<Item = &Outcome>> = match &self.log {
+            Log::Record(outcomes) => Box::new(outcomes.iter()),
+            Log::Replay(outcomes) => Box::new(outcomes.iter()),
+        };
+        for outcome in outcomes {
+            match outcome {
+                Ok(None) => out.push(0),
+                Ok(Some(s)) => {
+                    out.push(1);
+                    write_str(&mut out, s);
+                }
+                Err(e) => {
+                    out.push(2);
+                    write_str(&mut out, e);
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Runs an IO operation and records its outcome, or takes the recorded one when replaying
+    pub(crate) fn io(&mut self, op: impl FnOnce() -> Outcome) -> InterpretResult<Outcome> {
+        match &mut self.log {
+            Log::Record(outcomes) => {
+                let outcome = op();
+                outcomes.push(outcome.clone());
+                Ok(outcome)
+            }
+            Log::Replay(outcomes) => match outcomes.pop_front() {
+                Some(outcome) => Ok(outcome),
+                None => raise!(
+                    ReplayError,
+                    "The program did more IO operations than the recorded run"
+                ),
+            },
+        }
+    }
+
+    /// Moves the virtual clock forward
+    pub(crate) fn sleep(&mut self, ms: f64) {
+        self.clock += ms;
+    }
+}
+
+/// A seed taken from the system clock, for the runs that aren't deterministic
+pub(crate) fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |it| it.as_nanos() as u64)
+}
+
+/// The next number of a splitmix64 generator
+pub(crate) fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl VirtualMachine {
+    /// Runs in deterministic mode from now on, seeding the random numbers
+    pub fn set_deterministic(&mut self, deterministic: Deterministic) {
+        self.rng = deterministic.seed;
+        self.deterministic = Some(deterministic);
+    }
+
+    /// The deterministic state, if the VM is running in deterministic mode
+    #[must_use]
+    pub fn deterministic(&self) -> Option<&Deterministic> {
+        self.deterministic.as_ref()
+    }
+
+    /// A random number in `[0, 1)`
+    pub(crate) fn random(&mut self) -> f64 {
+        // the 53 high bits fill the mantissa of a double
+        (next_random(&mut self.rng) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// The time since the VM started, in milliseconds, which is virtual in deterministic mode
+    pub(crate) fn clock(&self) -> f64 {
+        match &self.deterministic {
+            Some(deterministic) => deterministic.clock,
+            None => self.started.elapsed().as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+#[test]
+fn replay_recorded_outcomes() {
+    let mut recording = Deterministic::record(42);
+    let outcomes = [
+        Ok(Some(String::from("line"))),
+        Ok(None),
+        Err(String::from("no file")),
+    ];
+    for outcome in &outcomes {
+        assert_eq!(recording.io(|| outcome.clone()).unwrap(), *outcome);
+    }
+
+    let mut replay = Deterministic::replay(&recording.log()).unwrap();
+    assert_eq!(replay.seed, 42);
+    for outcome in &outcomes {
+        assert_eq!(replay.io(|| unreachable!()).unwrap(), *outcome);
+    }
+    assert!(replay.io(|| Ok(None)).is_err());
+}
@@ -0,0 +1,166 @@
+//! Block scopes of the locals, opened and closed by the [`crate::OpCode::EnterScope`] and
+//! [`crate::OpCode::ExitScope`] instructions, so the bytecode doesn't need a `Drop` for each
+//! variable
+use crate::{error::InterpretResult, raise, VirtualMachine, NIL};
+
+impl VirtualMachine {
+    /// Enables (or disables) checking that the scopes are balanced and that every `Drop` gives
+    /// back the last local of its scope, instead of ignoring the mistakes, on by default in debug
+    /// builds
+    pub fn set_check_scopes(&mut self, check: bool) {
+        self.check_scopes = check;
+    }
+
+    /// Closes the innermost scope of the frame, dropping the locals declared since it was opened
+    pub(crate) fn exit_scope(
+        &mut self,
+        scopes: &mut Vec<usize>,
+        frame_locals: &mut usize,
+    ) -> InterpretResult<()> {
+        let Some(base) = scopes.pop() else {
+            return raise!(ScopeError, "Tried to exit a scope that was never entered");
+        };
+
+        if self.check_scopes && *frame_locals < base {
+            return raise!(
+                ScopeError,
+                "The scope dropped {} locals of the enclosing scopes",
+                base - *frame_locals
+            );
+        }
+
+        self.release_locals(base, frame_locals);
+        Ok(())
+    }
+
+    /// Closes every scope of the frame past the first `open`, when an error jumps out of them
+    pub(crate) fn unwind_scopes(
+        &mut self,
+        scopes: &mut Vec<usize>,
+        open: usize,
+        frame_locals: &mut usize,
+    ) {
+        if let Some(&base) = scopes.get(open) {
+            self.release_locals(base, frame_locals);
+        }
+        scopes.truncate(open);
+    }
+
+    /// Runs a `Drop` of the local `index`, raising an error instead of ignoring it if the local
+    /// isn't the last one of the innermost scope
+    pub(crate) fn checked_drop(
+        &mut self,
+        index: usize,
+        scope_base: usize,
+        frame_locals: &mut usize,
+    ) -> InterpretResult<()> {
+        if index + 1 != *frame_locals {
+            return raise!(
+                LocalError,
+                "Tried to drop the local {}, but the frame has {} locals",
+                index,
+                *frame_locals
+            );
+        }
+
+        if index < scope_base {
+            return raise!(
+                ScopeError,
+                "Tried to drop the local {}, which belongs to an enclosing scope",
+                index
+            );
+        }
+
+        self.release_locals(index, frame_locals);
+        Ok(())
+    }
+
+    /// Shrinks the frame to its first `keep` locals, letting go of the values of the others
+    fn release_locals(&mut self, keep: usize, frame_locals: &mut usize) {
+        if keep >= *frame_locals {
+            return;
+        }
+
+        let base = self.used_locals - *frame_locals;
+        for slot in base + keep..self.used_locals {
+            self.locals[slot] = NIL;
+        }
+        self.used_locals = base + keep;
+        *frame_locals = keep;
+    }
+}
+
+#[cfg(test)]
+fn run_checked(ops: Vec<crate::OpCode>) -> InterpretResult<crate::Value> {
+    use crate::{OpCodeMetadata, Value};
+
+    let mut vm = VirtualMachine::default();
+    vm.set_check_scopes(true);
+    vm.set_consts(vec![Value::Num(1.0), Value::Num(2.0)]);
+
+    let bytecode = ops
+        .into_iter()
+        .map(|opcode| OpCodeMetadata::new(1, 1, opcode))
+        .collect::<Vec<_>>();
+    vm.run(&bytecode)?;
+    Ok(vm.pop_last().clone())
+}
+
+#[test]
+fn exit_scopes_drop_their_locals() {
+    use crate::{OpCode, Value};
+
+    // the locals of the enclosing scope outlive the inner one
+    let value = run_checked(vec![
+        OpCode::Push(0),
+        OpCode::Save(0),
+        OpCode::EnterScope,
+        OpCode::Push(1),
+        OpCode::Save(1),
+        OpCode::ExitScope,
+        OpCode::Load(0),
+    ]);
+    assert_eq!(value.unwrap(), Value::Num(1.0));
+
+    let loaded = run_checked(vec![
+        OpCode::EnterScope,
+        OpCode::Push(1),
+        OpCode::Save(0),
+        OpCode::ExitScope,
+        OpCode::Load(0),
+    ]);
+    assert_eq!(loaded.unwrap_err().err.as_str(), "LocalError");
+}
+
+#[test]
+fn detect_unbalanced_scopes() {
+    use crate::OpCode;
+
+    let kind = |ops| run_checked(ops).unwrap_err().err.as_str().to_string();
+
+    assert_eq!(kind(vec![OpCode::ExitScope]), "ScopeError");
+    assert_eq!(
+        kind(vec![OpCode::EnterScope, OpCode::Push(0)]),
+        "ScopeError"
+    );
+
+    // dropping a local that isn't the last one of the frame
+    let drop_first = vec![
+        OpCode::Push(0),
+        OpCode::Save(0),
+        OpCode::Push(1),
+        OpCode::Save(1),
+        OpCode::Drop(0),
+    ];
+    assert_eq!(kind(drop_first), "LocalError");
+
+    // or one declared before the scope started
+    let drop_outer = vec![
+        OpCode::Push(0),
+        OpCode::Save(0),
+        OpCode::EnterScope,
+        OpCode::Drop(0),
+        OpCode::ExitScope,
+    ];
+    assert_eq!(kind(drop_outer), "ScopeError");
+}
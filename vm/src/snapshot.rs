@@ -0,0 +1,50 @@
+//! Snapshots of the definitions of a VM, to start many runs from the same loaded program
+use crate::{Symbol, Value, VirtualMachine};
+
+/// The globals (with the modules they hold) and the constants of a VM, the values are shared with
+/// the VM, so taking and restoring snapshots is cheap
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    globals: Vec<(Symbol, Value)>,
+    constants: Vec<Value>,
+}
+
+impl VirtualMachine {
+    /// Takes a snapshot of the definitions, it must be taken between runs, since the running
+    /// frames aren't saved
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            globals: self.globals.iter().collect(),
+            constants: self.constants.clone(),
+        }
+    }
+
+    /// Goes back to the definitions of a snapshot, forgetting the globals defined since then and
+    /// clearing the stack
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.globals = snapshot.globals.into_iter().collect();
+        self.constants = snapshot.constants;
+        self.reset();
+        self.used_locals = 0;
+    }
+}
+
+#[test]
+fn restore_snapshots() {
+    let mut vm = VirtualMachine::default();
+    vm.set_global("x", Value::Num(1.0));
+    vm.constants.push(Value::Num(2.0));
+
+    let snapshot = vm.snapshot();
+    vm.set_global("y", Value::Num(3.0));
+    vm.constants.push(Value::Num(4.0));
+
+    vm.restore(snapshot.clone());
+    assert_eq!(vm.get_global("x"), Some(Value::Num(1.0)));
+    assert_eq!(vm.get_global("y"), None);
+    assert_eq!(vm.constants, [Value::Num(2.0)]);
+
+    // the prelude is part of the snapshot too
+    assert!(vm.get_global("println").is_some());
+}
@@ -21,14 +21,53 @@ impl<T, const S: usize> StackVec<T, S> {
         }
     }
 
+    /// Creates a `StackVec` with the elements of `iter`, or `None` if they don't fit in it
+    pub fn try_from_iter(iter: impl IntoIterator<Item = T>) -> Option<Self> {
+        let mut stackvec = Self::new();
+        for it in iter {
+            stackvec.try_push(it).ok()?;
+        }
+        Some(stackvec)
+    }
+
     #[track_caller]
     #[inline]
     /// Push a new element to the array
+    /// # Panics
+    /// Panics if the array is full, see [`Self::try_push`]
     pub fn push(&mut self, new_value: T) {
         self.array[self.len].write(new_value);
         self.len += 1;
     }
 
+    #[inline]
+    /// Pushes a new element to the array, giving it back if the array is full
+    /// # Errors
+    /// Returns the element if there's no room left for it
+    pub fn try_push(&mut self, new_value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(new_value);
+        }
+
+        self.push(new_value);
+        Ok(())
+    }
+
+    /// Inserts an element at `index`, shifting the elements after it to the right, giving it back
+    /// if the array is full or `index` is past its end
+    /// # Errors
+    /// Returns the element if it can't be inserted
+    pub fn try_insert(&mut self, index: usize, value: T) -> Result<(), T> {
+        if index > self.len || self.is_full() {
+            return Err(value);
+        }
+
+        self.array[index..=self.len].rotate_right(1);
+        self.array[index].write(value);
+        self.len += 1;
+        Ok(())
+    }
+
     #[track_caller]
     #[inline]
     /// Pop's the last element
@@ -47,6 +86,16 @@ impl<T, const S: usize> StackVec<T, S> {
         self.len() == 0
     }
 
+    /// How many elements fit in the `StackVec`
+    pub const fn capacity(&self) -> usize {
+        S
+    }
+
+    /// Checks if there's no room left for more elements
+    pub fn is_full(&self) -> bool {
+        self.len == S
+    }
+
     #[track_caller]
     /// Returns an iterator of mutable references to the elements
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> + DoubleEndedIterator {
@@ -103,7 +152,8 @@ impl<T, const S: usize> StackVec<T, S> {
     /// Inserts an element at a given index
     /// # Safety
     /// This function is unsafe because it does not check if the index is out of bounds, it's up to
-    /// the caller to make sure that the index is valid or to manually resize the array if needed
+    /// the caller to make sure that the index is valid or to manually resize the array if needed,
+    /// see [`Self::try_insert`] for a checked version
     pub unsafe fn insert_at(&mut self, idx: usize, value: T) {
         self.array[idx].write(value);
     }
@@ -258,3 +308,16 @@ impl<T, const S: usize> Deref for StackVec<T, S> {
         unsafe { &*(std::ptr::addr_of!(self.array[0..self.len]) as *const [T]) }
     }
 }
+
+#[test]
+fn checked_pushes_and_inserts() {
+    let mut stackvec: StackVec<u8, 3> = StackVec::try_from_iter([1, 3]).unwrap();
+    assert_eq!(stackvec.try_insert(3, 4), Err(4));
+    assert_eq!(stackvec.try_insert(1, 2), Ok(()));
+    assert_eq!(&*stackvec, &[1, 2, 3]);
+
+    assert!(stackvec.is_full());
+    assert_eq!(stackvec.try_push(4), Err(4));
+    assert_eq!(stackvec.try_insert(0, 0), Err(0));
+    assert!(StackVec::<u8, 3>::try_from_iter(0..4).is_none());
+}
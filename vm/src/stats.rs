@@ -0,0 +1,84 @@
+//! Counters of what a virtual machine did, so scripts and tools can report how a program performs
+//! without a profiler
+use crate::{
+    gc,
+    literal::{yexmodule::builtin, Value},
+    InterpretResult, Symbol, Tuple, VirtualMachine,
+};
+
+/// What a virtual machine did since it started, or since [`VirtualMachine::reset_stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// How many instructions ran
+    pub instructions: u64,
+    /// How many values were allocated, by any virtual machine of the thread
+    pub allocations: u64,
+    /// How many calls deep the programs went, counting the top-level code as one
+    pub max_depth: usize,
+    /// The most values the stack held
+    pub max_stack: usize,
+}
+
+impl Stats {
+    /// The names of the counters, which are the fields of the instances `Vm.stats` returns
+    pub const FIELDS: [&'static str; 4] = ["instructions", "allocations", "max_depth", "max_stack"];
+
+    fn values(self) -> Vec<Value> {
+        let count = |n: u64| Value::Int(i64::try_from(n).unwrap_or(i64::MAX));
+        vec![
+            count(self.instructions),
+            count(self.allocations),
+            Value::from(self.max_depth),
+            Value::from(self.max_stack),
+        ]
+    }
+}
+
+impl VirtualMachine {
+    /// The counters of what the virtual machine did
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        Stats {
+            allocations: gc::allocations() - self.allocated,
+            ..self.stats
+        }
+    }
+
+    /// Starts the counters over from zero
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+        self.allocated = gc::allocations();
+    }
+}
+
+/// `Vm.stats ()`, the counters of the running virtual machine as an instance of the `Vm` type
+pub(crate) fn stats(vm: *mut VirtualMachine, _: Vec<Value>) -> InterpretResult<Value> {
+    let values = unsafe { &*vm }.stats().values();
+    Ok(Value::Tagged(
+        builtin::vm(),
+        Symbol::from("Vm.stats"),
+        Tuple::from(values),
+    ))
+}
+
+#[test]
+fn count_the_instructions() {
+    use crate::{OpCode, OpCodeMetadata};
+
+    let mut vm = VirtualMachine::default();
+    vm.reset_stats();
+    vm.set_consts(vec![Value::Num(1.0)]);
+
+    let bytecode = [OpCode::Push(0), OpCode::Push(0), OpCode::Pop]
+        .map(|op| OpCodeMetadata::new(1, 1, op))
+        .to_vec();
+    vm.run(&bytecode).unwrap();
+
+    let stats = vm.stats();
+    assert_eq!(stats.instructions, 3);
+    assert_eq!(stats.max_depth, 1);
+    assert_eq!(stats.max_stack, 2);
+
+    vm.reset_stats();
+    assert_eq!(vm.stats(), Stats::default());
+}
@@ -0,0 +1,84 @@
+//! Suggestions for misspelled names, for the errors about undefined variables and methods
+use std::fmt;
+
+/// The number of single character edits (insertions, deletions, substitutions and swaps of two
+/// adjacent characters) needed to turn `a` into `b`
+#[must_use]
+pub fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+
+    // the last two rows of the table, the distances between prefixes of `a` and of `b`
+    let mut before = vec![0; b.len() + 1];
+    let mut last = (0..=b.len()).collect::<Vec<_>>();
+
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (last[j] + 1).min(row[j - 1] + 1).min(last[j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut last, row);
+    }
+
+    last[b.len()]
+}
+
+/// The candidates closest to `name`, at most three, skipping the ones too different from it to
+/// be a typo
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    // one edit for every three characters, so short names don't match everything
+    let max = (name.chars().count() / 3).max(1);
+
+    let mut found = candidates
+        .into_iter()
+        .filter(|it| *it != name)
+        .map(|it| (distance(name, it), it))
+        .filter(|(distance, _)| *distance <= max)
+        .collect::<Vec<_>>();
+    found.sort_unstable();
+    found.dedup();
+
+    found.into_iter().take(3).map(|(_, it)| it).collect()
+}
+
+/// Formats the suggestions as the end of an error message, like `, did you mean 'length'?`,
+/// which is empty if there are none
+pub struct DidYouMean<'a>(pub Vec<&'a str>);
+
+impl fmt::Display for DidYouMean<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((last, rest)) = self.0.split_last() else {
+            return Ok(());
+        };
+
+        write!(f, ", did you mean ")?;
+        for (index, name) in rest.iter().enumerate() {
+            let sep = if index + 1 == rest.len() { " or" } else { "," };
+            write!(f, "'{name}'{sep} ")?;
+        }
+        write!(f, "'{last}'?")
+    }
+}
+
+#[test]
+fn suggest_close_names() {
+    assert_eq!(distance("lenght", "length"), 1);
+    assert_eq!(distance("kitten", "sitting"), 3);
+    assert_eq!(distance("", "abc"), 3);
+
+    let names = ["length", "print", "println", "map"];
+    assert_eq!(closest("lenght", names), ["length"]);
+    assert_eq!(closest("prinln", names), ["println", "print"]);
+    assert!(closest("xyz", names).is_empty());
+
+    assert_eq!(DidYouMean(vec![]).to_string(), "");
+    assert_eq!(
+        DidYouMean(vec!["println", "print"]).to_string(),
+        ", did you mean 'println' or 'print'?"
+    );
+}
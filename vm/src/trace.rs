@@ -0,0 +1,160 @@
+//! A record of the last instructions run, with the top of the stack before each of them, to find
+//! out how a program got to an error raised far from its cause
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{OpCode, OpCodeMetadata, Value, VirtualMachine};
+
+/// How many values of the top of the stack each step keeps
+const STACK_TOP: usize = 8;
+
+static TRACE_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets how many instructions the virtual machines created after it record, `0` (the default)
+/// records none
+pub fn set_default_trace_len(len: usize) {
+    TRACE_LEN.store(len, Ordering::Relaxed);
+}
+
+/// An instruction run by the virtual machine
+#[derive(Debug, Clone)]
+pub struct Step {
+    /// Source's code line
+    pub line: usize,
+    /// Source's code column
+    pub column: usize,
+    /// How many calls deep the instruction was, starting at 1 for the top-level code
+    pub depth: usize,
+    /// The instruction
+    pub opcode: OpCode,
+    /// The values on the top of the stack right before it ran, from the bottom to the top
+    pub stack: Vec<Value>,
+}
+
+/// The last instructions run by a virtual machine, the oldest ones are dropped to make room for
+/// the new ones
+#[derive(Debug, Clone)]
+pub struct Trace {
+    len: usize,
+    steps: VecDeque<Step>,
+}
+
+impl Trace {
+    /// Creates a trace of the last `len` instructions
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            steps: VecDeque::with_capacity(len),
+        }
+    }
+
+    /// The recorded instructions, from the oldest to the last one run
+    pub fn steps(&self) -> impl Iterator<Item = &Step> {
+        self.steps.iter()
+    }
+
+    fn record(&mut self, op: OpCodeMetadata, depth: usize, stack: &[Value]) {
+        if self.steps.len() == self.len {
+            self.steps.pop_front();
+        }
+
+        self.steps.push_back(Step {
+            line: op.line,
+            column: op.column,
+            depth,
+            opcode: op.opcode,
+            stack: stack[stack.len().saturating_sub(STACK_TOP)..].to_vec(),
+        });
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = "  ".repeat(self.depth.saturating_sub(1));
+        write!(
+            f,
+            "[{}:{}] {indent}{:?} [",
+            self.line, self.column, self.opcode
+        )?;
+        for (idx, value) in self.stack.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{value}")?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{step}")?;
+        }
+        Ok(())
+    }
+}
+
+impl VirtualMachine {
+    /// Records the last `len` instructions run, with the top of the stack before each of them,
+    /// `0` stops recording and forgets the trace
+    pub fn set_trace(&mut self, len: usize) {
+        self.trace = (len > 0).then(|| Trace::new(len));
+    }
+
+    /// The last instructions run, if they're being recorded, after an error the last of them is
+    /// the one that raised it
+    #[must_use]
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    pub(crate) fn default_trace(&mut self) {
+        self.set_trace(TRACE_LEN.load(Ordering::Relaxed));
+    }
+
+    #[inline]
+    pub(crate) fn trace_op(&mut self, op: OpCodeMetadata) {
+        if let Some(trace) = &mut self.trace {
+            trace.record(op, self.depth, &self.stack);
+        }
+    }
+}
+
+#[test]
+fn record_the_last_instructions() {
+    use crate::{Operand, Operator};
+
+    let mut vm = VirtualMachine::default();
+    vm.set_trace(3);
+    vm.set_consts(vec![Value::Int(1), "a".to_string().into()]);
+
+    let ops = [
+        OpCode::Push(0),
+        OpCode::Push(0),
+        OpCode::Pop,
+        OpCode::Push(1),
+        OpCode::Bin(Operator::Add, Operand::Stack, Operand::Stack),
+    ];
+    let bytecode = ops
+        .into_iter()
+        .enumerate()
+        .map(|(idx, op)| OpCodeMetadata::new(1, idx + 1, op))
+        .collect::<Vec<_>>();
+    vm.run(&bytecode).unwrap_err();
+
+    let trace = vm.trace().unwrap();
+    let columns = trace.steps().map(|step| step.column).collect::<Vec<_>>();
+    assert_eq!(columns, [3, 4, 5]);
+    assert_eq!(
+        trace.steps().last().unwrap().to_string(),
+        "[1:5] Bin(Add, Stack, Stack) [1, \"a\"]"
+    );
+
+    vm.set_trace(0);
+    assert!(vm.trace().is_none());
+}
@@ -0,0 +1,18 @@
+//! Compiles the part of the prelude written in yex ahead of time, with the `precompiled-prelude`
+//! feature, so the interpreter only has to unpack it when it starts
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "precompiled-prelude")]
+    {
+        println!("cargo:rerun-if-changed=../vm/src/prelude/prelude.yex");
+
+        let (bytecode, constants) =
+            front::parse(vm::PRELUDE_SOURCE).expect("the prelude should compile");
+        let bytes = vm::encoding::encode_program(&bytecode, &constants)
+            .expect("the prelude should only have encodable constants");
+
+        let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("prelude.bin");
+        std::fs::write(out, bytes).unwrap();
+    }
+}
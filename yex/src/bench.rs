@@ -0,0 +1,144 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use vm::{Bytecode, Value};
+
+use crate::test::{collect, discover, load, setup, TestCase};
+
+const WARMUP_TIME: Duration = Duration::from_millis(100);
+const DEFAULT_ITERATIONS: usize = 100;
+
+struct Stats {
+    mean: Duration,
+    median: Duration,
+    min: Duration,
+    max: Duration,
+    deviation: Duration,
+    /// How many instructions each iteration ran
+    instructions: u64,
+}
+
+impl Stats {
+    #[allow(clippy::cast_precision_loss)]
+    fn new(mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+
+        let len = samples.len() as f64;
+        let mean = samples.iter().map(Duration::as_secs_f64).sum::<f64>() / len;
+        let variance = samples
+            .iter()
+            .map(|it| (it.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / len;
+
+        Self {
+            mean: Duration::from_secs_f64(mean),
+            median: samples[samples.len() / 2],
+            min: samples[0],
+            max: samples[samples.len() - 1],
+            deviation: Duration::from_secs_f64(variance.sqrt()),
+            instructions: 0,
+        }
+    }
+}
+
+/// Runs a single bench, first warming it up and then timing each one of the `iterations`
+fn run_bench(
+    bytecode: &Bytecode,
+    constants: &[Value],
+    bench: &TestCase,
+    iterations: usize,
+) -> Result<Stats, String> {
+    let mut vm = setup(bytecode, constants, None)?;
+    let call = bench.call();
+
+    let start = Instant::now();
+    while start.elapsed() < WARMUP_TIME {
+        vm.run(&call).map_err(|e| e.to_string())?;
+        vm.reset();
+    }
+
+    vm.reset_stats();
+    let mut samples = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        vm.run(&call).map_err(|e| e.to_string())?;
+        samples.push(start.elapsed());
+        vm.reset();
+    }
+
+    Ok(Stats {
+        instructions: vm.stats().instructions / iterations as u64,
+        ..Stats::new(samples)
+    })
+}
+
+/// Runs the benches of every file in `args` (or the current directory), returning the exit code,
+/// `-n <iterations>` sets how many timed iterations are run for each bench
+pub fn run(args: &[String]) -> i32 {
+    let mut iterations = DEFAULT_ITERATIONS;
+    let mut files = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" | "--iterations" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => iterations = n,
+                _ => {
+                    eprintln!("expected a positive number of iterations after '{arg}'");
+                    return 1;
+                }
+            },
+            path => discover(Path::new(path), &mut files),
+        }
+    }
+
+    if files.is_empty() {
+        discover(Path::new("."), &mut files);
+    }
+
+    let mut failed = 0;
+
+    for file in files {
+        let display = file.display().to_string();
+
+        let (bytecode, constants) = match load(&file) {
+            Ok(res) => res,
+            Err(e) => {
+                eprintln!("{display}: {e}");
+                failed += 1;
+                continue;
+            }
+        };
+
+        let benches = collect(&bytecode, front::BENCH_PREFIX);
+        if benches.is_empty() {
+            continue;
+        }
+
+        println!("\nrunning {} benches from {display}", benches.len());
+
+        for bench in benches {
+            match run_bench(&bytecode, &constants, &bench, iterations) {
+                Ok(stats) => println!(
+                    "bench {} ... {:?}/iter (± {:?}, median {:?}, min {:?}, max {:?}, {} instructions/iter, {iterations} iterations)",
+                    bench.name(),
+                    stats.mean,
+                    stats.deviation,
+                    stats.median,
+                    stats.min,
+                    stats.max,
+                    stats.instructions,
+                ),
+                Err(e) => {
+                    println!("bench {} ... FAILED\n{e}", bench.name());
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    i32::from(failed > 0)
+}
@@ -0,0 +1,218 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::compile;
+
+/// Marks the end of an executable with a program appended to it
+const MAGIC: &[u8; 8] = b"YEXBNDL1";
+/// The trailer is the length of the bundle followed by [`MAGIC`]
+const TRAILER: usize = 16;
+
+/// A program appended to a copy of the interpreter
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Bundle {
+    /// The program packed by `yex compile`, with the files and the packages it imports
+    pub program: Vec<u8>,
+    /// The source of the program, to show where the errors are, left out by `--release`
+    pub source: Option<String>,
+}
+
+fn push_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend((bytes.len() as u64).to_le_bytes());
+    out.extend(bytes);
+}
+
+fn read_bytes(bytes: &mut &[u8]) -> Option<Vec<u8>> {
+    let (len, rest) = bytes.split_first_chunk::<8>()?;
+    let len = usize::try_from(u64::from_le_bytes(*len)).ok()?;
+    if rest.len() < len {
+        return None;
+    }
+
+    let (read, rest) = rest.split_at(len);
+    *bytes = rest;
+    Some(read.to_vec())
+}
+
+impl Bundle {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        push_bytes(&mut out, &self.program);
+        if let Some(source) = &self.source {
+            push_bytes(&mut out, source.as_bytes());
+        }
+
+        out.extend((out.len() as u64).to_le_bytes());
+        out.extend(MAGIC);
+        out
+    }
+
+    fn decode(mut bytes: &[u8]) -> Option<Self> {
+        let program = read_bytes(&mut bytes)?;
+        let source = match bytes {
+            [] => None,
+            _ => Some(String::from_utf8(read_bytes(&mut bytes)?).ok()?),
+        };
+        Some(Self { program, source })
+    }
+
+    /// Reads the bundle appended to the running executable, if there's one
+    pub fn current() -> Option<Self> {
+        let mut exe = File::open(env::current_exe().ok()?).ok()?;
+
+        let mut trailer = [0; TRAILER];
+        exe.seek(SeekFrom::End(-i64::try_from(TRAILER).ok()?))
+            .ok()?;
+        exe.read_exact(&mut trailer).ok()?;
+
+        let (len, magic) = trailer.split_at(8);
+        if magic != MAGIC {
+            return None;
+        }
+
+        let len = usize::try_from(u64::from_le_bytes(len.try_into().ok()?)).ok()?;
+        exe.seek(SeekFrom::End(
+            -i64::try_from(len.checked_add(TRAILER)?).ok()?,
+        ))
+        .ok()?;
+        let mut bytes = vec![0; len];
+        exe.read_exact(&mut bytes).ok()?;
+
+        Self::decode(&bytes)
+    }
+}
+
+/// The interpreter, without a bundle if the running executable has one
+fn interpreter() -> io::Result<Vec<u8>> {
+    without_bundle(fs::read(env::current_exe()?)?)
+}
+
+/// Strips the bundle off an executable, raising an error if its trailer doesn't fit in it
+fn without_bundle(mut exe: Vec<u8>) -> io::Result<Vec<u8>> {
+    if exe.ends_with(MAGIC) {
+        let corrupt = || io::Error::new(io::ErrorKind::InvalidData, "the bundle is corrupt");
+        let start = exe.len().checked_sub(TRAILER).ok_or_else(corrupt)?;
+        let len = u64::from_le_bytes(exe[start..start + 8].try_into().unwrap());
+        let end = usize::try_from(len)
+            .ok()
+            .and_then(|len| start.checked_sub(len))
+            .ok_or_else(corrupt)?;
+        exe.truncate(end);
+    }
+
+    Ok(exe)
+}
+
+fn build(file: &str, output: &Path, release: bool) -> Result<(), String> {
+    let source = fs::read_to_string(file).map_err(|e| format!("error reading {file}: {e}"))?;
+    let program = compile::pack(file, &source)?;
+
+    let bundle = Bundle {
+        program,
+        source: (!release).then_some(source),
+    };
+    let mut exe = interpreter().map_err(|e| format!("can't read the yex executable: {e}"))?;
+    exe.extend(bundle.encode());
+
+    fs::write(output, exe).map_err(|e| format!("can't write {}: {e}", output.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(output, fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("can't make {} executable: {e}", output.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Builds a standalone executable of a program, named after it unless `-o` is given
+///
+/// The program is compiled once, the executable only unpacks it. `--release` leaves its source
+/// out, so the errors only show their line and column.
+pub fn run(args: &[String]) -> i32 {
+    let mut file = None;
+    let mut output = None;
+    let mut release = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--release" => release = true,
+            "-o" | "--output" => output = args.next().map(PathBuf::from),
+            _ if file.is_none() => file = Some(arg.clone()),
+            other => {
+                eprintln!("unexpected argument '{other}'");
+                return 1;
+            }
+        }
+    }
+
+    let Some(file) = file else {
+        eprintln!("usage: yex build [--release] <file> [-o <output>]");
+        return 1;
+    };
+
+    let output =
+        output.unwrap_or_else(|| Path::new(&file).with_extension(env::consts::EXE_EXTENSION));
+    match build(&file, &output, release) {
+        Ok(()) => {
+            println!("built {}", output.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+#[test]
+fn encode_and_decode_bundles() {
+    let dir = crate::temp_dir("bundle");
+    fs::write(dir.join("lib.yex"), "def double x = x * 2").unwrap();
+    let file = dir.join("main.yex");
+    let source = "import \"lib.yex\"\nLib.double 21";
+    let program = compile::pack(file.to_str().unwrap(), source).unwrap();
+
+    for source in [Some(String::from(source)), None] {
+        let bundle = Bundle {
+            program: program.clone(),
+            source,
+        };
+
+        let bytes = bundle.encode();
+        assert!(bytes.ends_with(MAGIC));
+
+        let payload = &bytes[..bytes.len() - TRAILER];
+        assert_eq!(Bundle::decode(payload).as_ref(), Some(&bundle));
+    }
+
+    // the imported files are packed along with the program
+    let mut vm = vm::VirtualMachine::default();
+    let bytecode = vm.load_chunk(&program).unwrap();
+    vm.run(&bytecode).unwrap();
+    assert_eq!(vm.pop_last(), &vm::Value::Int(42));
+}
+
+#[test]
+fn strip_bundles() {
+    let bundle = Bundle {
+        program: vec![1, 2, 3],
+        source: None,
+    };
+    let mut exe = b"interpreter".to_vec();
+    exe.extend(bundle.encode());
+    assert_eq!(without_bundle(exe).unwrap(), b"interpreter");
+
+    // a trailer longer than the executable, or cut short, is an error instead of a panic
+    let mut exe = b"interpreter".to_vec();
+    exe.extend(u64::MAX.to_le_bytes());
+    exe.extend(MAGIC);
+    assert!(without_bundle(exe).is_err());
+    assert!(without_bundle(MAGIC.to_vec()).is_err());
+}
@@ -0,0 +1,70 @@
+use std::{fs, path::Path};
+
+use vm::diagnostic::{Severity, Span};
+
+use crate::{pretty, test::discover};
+
+/// Checks every file in `paths` (or the current directory) without running them, returning the
+/// exit code
+pub fn run(paths: &[String], color: bool) -> i32 {
+    let mut files = vec![];
+    if paths.is_empty() {
+        discover(Path::new("."), &mut files);
+    }
+    for path in paths {
+        discover(Path::new(path), &mut files);
+    }
+
+    let mut errors = 0;
+    for file in &files {
+        let display = file.display();
+
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(e) if pretty::is_json() => {
+                let report = format!("error reading {display}: {e}").into();
+                eprintln!("{}", pretty::json(&report, Some(&display.to_string())));
+                errors += 1;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("error reading {display}: {e}");
+                errors += 1;
+                continue;
+            }
+        };
+
+        let dir = file.parent().unwrap_or(Path::new("."));
+        for diagnostic in front::check_in(source.as_str(), dir) {
+            if diagnostic.severity == Severity::Error {
+                errors += 1;
+            }
+            if pretty::is_json() {
+                eprintln!(
+                    "{}",
+                    pretty::json(&diagnostic.into(), Some(&display.to_string()))
+                );
+                continue;
+            }
+
+            match diagnostic.span {
+                Some(Span { line, column }) => eprintln!("{display}:{line}:{column}"),
+                None => eprintln!("{display}"),
+            }
+            eprintln!("{}\n", pretty::error(&diagnostic.into(), &source, color));
+        }
+    }
+
+    // the summary would break the tools reading the diagnostics as JSON
+    if pretty::is_json() {
+        return i32::from(errors > 0);
+    }
+
+    if errors > 0 {
+        eprintln!("found {errors} errors in {} files", files.len());
+        1
+    } else {
+        println!("checked {} files, no errors found", files.len());
+        0
+    }
+}
@@ -0,0 +1,79 @@
+//! `yex compile`, which packs a program into a `.yexc` file, run later like a source file but
+//! without compiling it again
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The extension of the packed programs, which `yex <file>` runs without compiling them
+pub const EXTENSION: &str = "yexc";
+
+/// Compiles the source of a file, along with the files it imports, and packs it
+pub(crate) fn pack(file: &str, source: &str) -> Result<Vec<u8>, String> {
+    let dir = Path::new(file).parent().unwrap_or(Path::new("."));
+
+    let (bytecode, constants) = front::parse_in(source, dir).map_err(|e| format!("{file}: {e}"))?;
+    vm::encoding::encode_program(&bytecode, &constants).map_err(|e| format!("{file}: {e}"))
+}
+
+/// Compiles a file, along with the files it imports, and packs it into `output`
+fn compile(file: &str, output: &Path) -> Result<(), String> {
+    let source = fs::read_to_string(file).map_err(|e| format!("error reading {file}: {e}"))?;
+    let bytes = pack(file, &source)?;
+    fs::write(output, bytes).map_err(|e| format!("error writing {}: {e}", output.display()))
+}
+
+/// Runs `yex compile <file> [-o <output>]`, returning the exit code
+pub fn run(args: &[String]) -> i32 {
+    let mut file = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = args.next().map(PathBuf::from),
+            _ if file.is_none() => file = Some(arg.clone()),
+            other => {
+                eprintln!("unexpected argument '{other}'");
+                return 1;
+            }
+        }
+    }
+
+    let Some(file) = file else {
+        eprintln!("usage: yex compile <file> [-o <output>]");
+        return 1;
+    };
+
+    let output = output.unwrap_or_else(|| Path::new(&file).with_extension(EXTENSION));
+    match compile(&file, &output) {
+        Ok(()) => {
+            println!("compiled {}", output.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+#[test]
+fn compile_and_load_programs() {
+    let dir = std::env::temp_dir();
+    let (file, output) = (dir.join("yex_compile.yex"), dir.join("yex_compile.yexc"));
+    fs::write(
+        &file,
+        "type Shape = circle r | square side with\n\tdef area self k = k * match self with\n\t| Shape.circle r -> 3 * r * r\n\t| Shape.square s -> s * s\nend\n(Shape.circle 2).area 1 + (Shape.square 3).area 1",
+    )
+    .unwrap();
+    compile(file.to_str().unwrap(), &output).unwrap();
+
+    let mut vm = vm::VirtualMachine::default();
+    let bytecode = vm.load_chunk(&fs::read(&output).unwrap()).unwrap();
+    vm.run(&bytecode).unwrap();
+    assert_eq!(vm.pop_last(), &vm::Value::Num(21.0));
+
+    // only packed programs are loaded
+    assert!(vm.load_chunk(b"def f x = x").is_err());
+}
@@ -0,0 +1,58 @@
+use std::{env, fs, path::PathBuf};
+
+use vm::{Value, VirtualMachine};
+
+use crate::run_patched;
+
+/// Gets the path of a file in the user's home directory
+pub(crate) fn home_file(name: &str) -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(name))
+}
+
+/// Settings of the REPL
+///
+/// `~/.yexrc` is a yex file ran before the REPL starts, its definitions are kept and the settings
+/// are read from the `yex_prompt` and `yex_color` globals, if it defines them
+pub(crate) struct Config {
+    pub prompt: String,
+    pub color: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            prompt: String::from("yex> "),
+            color: true,
+        }
+    }
+}
+
+impl Config {
+    /// Runs `~/.yexrc` on `vm` and reads the settings it defined
+    pub fn load(vm: &mut VirtualMachine) -> Self {
+        let mut config = Self::default();
+
+        let Some(source) = home_file(".yexrc").and_then(|path| fs::read_to_string(path).ok())
+        else {
+            return config;
+        };
+
+        let res = front::parse(source)
+            .map_err(Into::into)
+            .and_then(|(bytecode, constants)| run_patched(vm, bytecode, constants));
+
+        if let Err(e) = res {
+            eprintln!("error loading ~/.yexrc: {e}");
+        }
+
+        if let Some(Value::Str(prompt)) = vm.get_global("yex_prompt") {
+            config.prompt = prompt.to_string();
+        }
+
+        if let Some(Value::Bool(color)) = vm.get_global("yex_color") {
+            config.color = color;
+        }
+
+        config
+    }
+}
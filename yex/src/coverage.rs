@@ -0,0 +1,142 @@
+//! Line and function coverage of the tests, reported in the lcov format by `yex test --coverage`
+use std::{cell::RefCell, collections::BTreeMap, fmt::Write, rc::Rc};
+
+use vm::{
+    debug::{Debugger, Position},
+    FnKind, OpCode, OpCodeMetadata, Value, VirtualMachine,
+};
+
+/// How many times the lines and the functions of a file ran
+#[derive(Default)]
+pub(crate) struct Coverage {
+    /// the hits of every line with code on it
+    lines: BTreeMap<usize, usize>,
+    /// the name and the calls of the functions defined by a top-level `def`, by the position of
+    /// their first instruction
+    functions: BTreeMap<(usize, usize), (String, usize)>,
+    /// how many calls deep the last instruction was, a deeper one starts a call
+    depth: usize,
+}
+
+/// Adds the lines of the bytecode, and of the functions in the constants, to `lines`
+fn code_lines(ops: &[OpCodeMetadata], constants: &[Value], lines: &mut BTreeMap<usize, usize>) {
+    for op in ops {
+        lines.insert(op.line, 0);
+    }
+
+    for constant in constants {
+        match constant {
+            Value::Fn(fun) => match &*fun.body {
+                FnKind::Bytecode(ops) | FnKind::Generator(ops) => code_lines(ops, &[], lines),
+                FnKind::Native(_) => (),
+            },
+            Value::Module(module) => {
+                let fields = module.fields.values().collect::<Vec<_>>();
+                code_lines(&[], &fields, lines);
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Coverage {
+    /// Creates the coverage of a compiled file, where nothing ran yet
+    pub fn new(bytecode: &[OpCodeMetadata], constants: &[Value]) -> Self {
+        let mut lines = BTreeMap::new();
+        code_lines(bytecode, constants, &mut lines);
+
+        // `def f x = ...` pushes the function and saves it to the global right after
+        let mut functions = BTreeMap::new();
+        for ops in bytecode.windows(2) {
+            let (OpCode::Push(idx), OpCode::Savg(name)) = (ops[0].opcode, ops[1].opcode) else {
+                continue;
+            };
+            let Some(Value::Fn(fun)) = constants.get(idx) else {
+                continue;
+            };
+            let (FnKind::Bytecode(body) | FnKind::Generator(body)) = &*fun.body else {
+                continue;
+            };
+
+            let name = name.as_str();
+            let generated =
+                name.starts_with(front::TEST_PREFIX) || name.starts_with(front::BENCH_PREFIX);
+            if let (Some(first), false) = (body.first(), generated) {
+                functions.insert((first.line, first.column), (name.to_string(), 0));
+            }
+        }
+
+        Self {
+            lines,
+            functions,
+            depth: 0,
+        }
+    }
+
+    /// A hook that records what runs on the virtual machine it's set to
+    pub fn hook(this: &Rc<RefCell<Self>>) -> Box<dyn Debugger> {
+        Box::new(Hook(Rc::clone(this)))
+    }
+
+    /// The number of lines that ran and the number of lines with code
+    pub fn lines_hit(&self) -> (usize, usize) {
+        let hit = self.lines.values().filter(|hits| **hits > 0).count();
+        (hit, self.lines.len())
+    }
+
+    /// Writes the lcov record of the file at `path`
+    pub fn write_lcov(&self, path: &str, out: &mut String) {
+        writeln!(out, "TN:\nSF:{path}").unwrap();
+
+        for ((line, _), (name, _)) in &self.functions {
+            writeln!(out, "FN:{line},{name}").unwrap();
+        }
+        for (name, calls) in self.functions.values() {
+            writeln!(out, "FNDA:{calls},{name}").unwrap();
+        }
+        let called = self.functions.values().filter(|(_, calls)| *calls > 0);
+        writeln!(out, "FNF:{}\nFNH:{}", self.functions.len(), called.count()).unwrap();
+
+        for (line, hits) in &self.lines {
+            writeln!(out, "DA:{line},{hits}").unwrap();
+        }
+        let (hit, found) = self.lines_hit();
+        writeln!(out, "LF:{found}\nLH:{hit}\nend_of_record").unwrap();
+    }
+}
+
+struct Hook(Rc<RefCell<Coverage>>);
+
+impl Debugger for Hook {
+    fn before_op(&mut self, _: &VirtualMachine, position: &Position) {
+        let mut coverage = self.0.borrow_mut();
+
+        if let Some(hits) = coverage.lines.get_mut(&position.line) {
+            *hits += 1;
+        }
+
+        if position.depth > coverage.depth {
+            let start = (position.line, position.column);
+            if let Some((_, calls)) = coverage.functions.get_mut(&start) {
+                *calls += 1;
+            }
+        }
+        coverage.depth = position.depth;
+    }
+}
+
+#[test]
+fn record_coverage() {
+    let (bytecode, constants) =
+        front::parse("def f x = x\ndef g x =\n  x + 1\nlet y = f 1").unwrap();
+    let coverage = Rc::new(RefCell::new(Coverage::new(&bytecode, &constants)));
+    crate::test::setup(&bytecode, &constants, Some(Coverage::hook(&coverage))).unwrap();
+
+    let mut lcov = String::new();
+    coverage.borrow().write_lcov("a.yex", &mut lcov);
+
+    assert!(lcov.starts_with("TN:\nSF:a.yex\n"));
+    assert!(lcov.contains("FNDA:0,g\nFNF:2\n"));
+    assert!(lcov.contains("DA:3,0\n"));
+    assert_eq!(coverage.borrow().lines_hit(), (3, 4));
+}
@@ -0,0 +1,472 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    rc::Rc,
+};
+
+use serde_json::{json, Value as Json};
+use vm::{
+    debug::{Debugger, Position},
+    Value, VirtualMachine,
+};
+
+use crate::run_patched;
+
+/// The only thread of a yex program
+const THREAD: u64 = 1;
+/// Variable references of the scopes, the locals of each frame start at `LOCALS`
+const GLOBALS: u64 = 1;
+const STACK: u64 = 2;
+const LOCALS: u64 = 1000;
+
+/// What the program is doing between stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Runs until a breakpoint
+    Continue,
+    /// Stops at the next line, in any function
+    StepIn { line: usize, depth: usize },
+    /// Stops at the next line of the current function, or after it returns
+    Next { line: usize, depth: usize },
+    /// Stops after the current function returns
+    StepOut { depth: usize },
+    /// Stops at the first instruction
+    Entry,
+}
+
+/// A debugging session, speaking the Debug Adapter Protocol with a single client
+struct Session {
+    reader: Box<dyn BufRead>,
+    writer: Box<dyn Write>,
+    seq: u64,
+    program: Option<String>,
+    breakpoints: HashSet<usize>,
+    mode: Mode,
+    /// The positions of the running functions, from the top-level code to the innermost call
+    frames: Vec<Position>,
+    /// The line and depth of the last instruction, so a line is only stopped at once
+    last: (usize, usize),
+    /// If the client asked to stop, the program is finished as soon as possible
+    disconnected: bool,
+}
+
+impl Session {
+    fn new(reader: Box<dyn BufRead>, writer: Box<dyn Write>) -> Self {
+        Self {
+            reader,
+            writer,
+            seq: 0,
+            program: None,
+            breakpoints: HashSet::new(),
+            mode: Mode::Continue,
+            frames: vec![],
+            last: (0, 0),
+            disconnected: false,
+        }
+    }
+
+    /// Reads a message, `None` if the client is gone
+    fn read(&mut self) -> Option<Json> {
+        let mut length = None;
+
+        loop {
+            let mut header = String::new();
+            if self.reader.read_line(&mut header).ok()? == 0 {
+                return None;
+            }
+
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+
+        let mut body = vec![0; length?];
+        self.reader.read_exact(&mut body).ok()?;
+        serde_json::from_slice(&body).ok()
+    }
+
+    fn send(&mut self, mut message: Json) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+
+        let body = message.to_string();
+        // a client that went away can't be told anything else
+        let _ = write!(self.writer, "Content-Length: {}\r\n\r\n{body}", body.len());
+        let _ = self.writer.flush();
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+        });
+        response["body"] = body;
+        self.send(response);
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }));
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        let mut message = json!({ "type": "event", "event": event });
+        message["body"] = body;
+        self.send(message);
+    }
+
+    /// Handles the requests that can be answered at any time, returns `false` for the others
+    fn common(&mut self, request: &Json) -> bool {
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                self.respond(request, json!({ "supportsConfigurationDoneRequest": true }));
+                self.event("initialized", json!({}));
+            }
+            "setBreakpoints" => {
+                let lines = request["arguments"]["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|it| it["line"].as_u64())
+                    .filter_map(|it| usize::try_from(it).ok())
+                    .collect::<Vec<_>>();
+
+                self.breakpoints = lines.iter().copied().collect();
+                let verified = lines
+                    .iter()
+                    .map(|line| json!({ "verified": true, "line": line }))
+                    .collect::<Vec<_>>();
+                self.respond(request, json!({ "breakpoints": verified }));
+            }
+            "threads" => {
+                self.respond(
+                    request,
+                    json!({ "threads": [{ "id": THREAD, "name": "main" }] }),
+                );
+            }
+            "disconnect" | "terminate" => {
+                self.disconnected = true;
+                self.respond(request, json!({}));
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Handles the requests before the program starts, returns once it should run
+    fn configure(&mut self) -> bool {
+        while let Some(request) = self.read() {
+            if self.common(&request) {
+                if self.disconnected {
+                    return false;
+                }
+                continue;
+            }
+
+            match request["command"].as_str().unwrap_or_default() {
+                "launch" => {
+                    let args = &request["arguments"];
+                    self.program = args["program"].as_str().map(String::from);
+                    if args["stopOnEntry"].as_bool() == Some(true) {
+                        self.mode = Mode::Entry;
+                    }
+                    self.respond(&request, json!({}));
+                }
+                "configurationDone" => {
+                    self.respond(&request, json!({}));
+                    return self.program.is_some();
+                }
+                _ => self.fail(&request, "the program isn't running"),
+            }
+        }
+
+        false
+    }
+
+    fn should_stop(&self, position: &Position) -> Option<&'static str> {
+        let moved = (position.line, position.depth) != self.last;
+
+        match self.mode {
+            Mode::Entry => Some("entry"),
+            Mode::StepIn { line, depth } if position.line != line || position.depth != depth => {
+                Some("step")
+            }
+            Mode::Next { line, depth }
+                if position.depth < depth || position.depth == depth && position.line != line =>
+            {
+                Some("step")
+            }
+            Mode::StepOut { depth } if position.depth < depth => Some("step"),
+            _ if moved && self.breakpoints.contains(&position.line) => Some("breakpoint"),
+            _ => None,
+        }
+    }
+
+    fn variables(&self, vm: &VirtualMachine, reference: u64) -> Vec<Json> {
+        let variable = |name: String, value: &Value| json!({ "name": name, "value": value.to_string(), "variablesReference": 0 });
+
+        match reference {
+            GLOBALS => {
+                let prelude = VirtualMachine::default();
+                let mut globals = vm
+                    .globals()
+                    .iter()
+                    .filter(|(name, _)| prelude.get_global(*name).is_none())
+                    .map(|(name, value)| variable(name.to_string(), &value))
+                    .collect::<Vec<_>>();
+                globals.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+                globals
+            }
+            STACK => vm
+                .stack_values()
+                .enumerate()
+                .map(|(idx, value)| variable(format!("[{idx}]"), value))
+                .collect(),
+            frame @ LOCALS.. => self
+                .frames
+                .get(usize::try_from(frame - LOCALS).unwrap_or(usize::MAX))
+                .map(|position| vm.locals_at(position))
+                .unwrap_or_default()
+                .iter()
+                .enumerate()
+                .map(|(idx, value)| variable(format!("${idx}"), value))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Answers the requests of the client while the program is stopped
+    fn stopped(&mut self, vm: &VirtualMachine, position: &Position) {
+        while let Some(request) = self.read() {
+            if self.common(&request) {
+                if self.disconnected {
+                    return;
+                }
+                continue;
+            }
+
+            let (line, depth) = (position.line, position.depth);
+            match request["command"].as_str().unwrap_or_default() {
+                "stackTrace" => {
+                    let source = json!({ "path": self.program });
+                    let frames = self
+                        .frames
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(idx, frame)| {
+                            let name = if idx == 0 { "<main>" } else { "<fn>" };
+                            json!({
+                                "id": idx,
+                                "name": name,
+                                "line": frame.line,
+                                "column": frame.column,
+                                "source": source,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+
+                    let total = frames.len();
+                    self.respond(
+                        &request,
+                        json!({ "stackFrames": frames, "totalFrames": total }),
+                    );
+                }
+                "scopes" => {
+                    let frame = request["arguments"]["frameId"].as_u64().unwrap_or_default();
+                    let scopes = json!([
+                        { "name": "Locals", "variablesReference": LOCALS + frame, "expensive": false },
+                        { "name": "Globals", "variablesReference": GLOBALS, "expensive": false },
+                        { "name": "Stack", "variablesReference": STACK, "expensive": false },
+                    ]);
+                    self.respond(&request, json!({ "scopes": scopes }));
+                }
+                "variables" => {
+                    let reference = request["arguments"]["variablesReference"]
+                        .as_u64()
+                        .unwrap_or_default();
+                    let variables = self.variables(vm, reference);
+                    self.respond(&request, json!({ "variables": variables }));
+                }
+                "continue" => {
+                    self.mode = Mode::Continue;
+                    self.respond(&request, json!({ "allThreadsContinued": true }));
+                    return;
+                }
+                "next" => {
+                    self.mode = Mode::Next { line, depth };
+                    self.respond(&request, json!({}));
+                    return;
+                }
+                "stepIn" => {
+                    self.mode = Mode::StepIn { line, depth };
+                    self.respond(&request, json!({}));
+                    return;
+                }
+                "stepOut" => {
+                    self.mode = Mode::StepOut { depth };
+                    self.respond(&request, json!({}));
+                    return;
+                }
+                _ => self.fail(&request, "unsupported request"),
+            }
+        }
+
+        // the client is gone
+        self.disconnected = true;
+    }
+}
+
+/// The hook given to the virtual machine, shares the session with [`run_session`]
+struct Hook(Rc<RefCell<Session>>);
+
+impl Debugger for Hook {
+    fn before_op(&mut self, vm: &VirtualMachine, position: &Position) {
+        let mut session = self.0.borrow_mut();
+        if session.disconnected {
+            return;
+        }
+
+        session.frames.truncate(position.depth.saturating_sub(1));
+        session.frames.push(position.clone());
+
+        if let Some(reason) = session.should_stop(position) {
+            session.event(
+                "stopped",
+                json!({ "reason": reason, "threadId": THREAD, "allThreadsStopped": true }),
+            );
+            session.stopped(vm, position);
+        }
+
+        session.last = (position.line, position.depth);
+    }
+}
+
+/// Runs a session until the client disconnects or the program finishes
+fn run_session(reader: Box<dyn BufRead>, writer: Box<dyn Write>) -> i32 {
+    let session = Rc::new(RefCell::new(Session::new(reader, writer)));
+    if !session.borrow_mut().configure() {
+        return 0;
+    }
+
+    let program = session.borrow().program.clone().unwrap_or_default();
+    let result = fs::read_to_string(&program)
+        .map_err(|e| format!("error reading {program}: {e}"))
+        .and_then(|source| front::parse(source).map_err(|e| e.to_string()));
+
+    let mut vm = VirtualMachine::default();
+    vm.set_debugger(Some(Box::new(Hook(Rc::clone(&session)))));
+
+    let result = result.and_then(|(bytecode, constants)| {
+        run_patched(&mut vm, bytecode, constants).map_err(|e| e.to_string())
+    });
+    vm.set_debugger(None);
+
+    let mut session = session.borrow_mut();
+    let code = match result {
+        Ok(_) => 0,
+        Err(e) => {
+            session.event(
+                "output",
+                json!({ "category": "stderr", "output": format!("{e}\n") }),
+            );
+            1
+        }
+    };
+
+    session.event("exited", json!({ "exitCode": code }));
+    session.event("terminated", json!({}));
+    code
+}
+
+/// Serves a single debugging session on `127.0.0.1`, at the port given with `--port` (4711 by
+/// default)
+pub fn run(args: &[String]) -> i32 {
+    let port = match args {
+        [] => 4711,
+        [flag, port] if flag == "--port" => {
+            let Ok(port) = port.parse::<u16>() else {
+                eprintln!("invalid port '{port}'");
+                return 1;
+            };
+            port
+        }
+        _ => {
+            eprintln!("usage: yex dap [--port <port>]");
+            return 1;
+        }
+    };
+
+    let accept = || -> io::Result<_> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        eprintln!("debug adapter listening on 127.0.0.1:{port}");
+        let (stream, _) = listener.accept()?;
+        Ok((BufReader::new(stream.try_clone()?), stream))
+    };
+
+    match accept() {
+        Ok((reader, writer)) => run_session(Box::new(reader), Box::new(writer)),
+        Err(e) => {
+            eprintln!("can't start the debug adapter: {e}");
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+fn message(body: &Json) -> String {
+    let body = body.to_string();
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+#[test]
+fn stop_at_breakpoint() {
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let program = std::env::temp_dir().join("yex_dap_breakpoint.yex");
+    fs::write(&program, "let x = 1\nlet y = x + 1\n").unwrap();
+
+    let requests = [
+        json!({ "seq": 1, "command": "initialize", "arguments": {} }),
+        json!({ "seq": 2, "command": "launch", "arguments": { "program": program } }),
+        json!({ "seq": 3, "command": "setBreakpoints", "arguments": { "breakpoints": [{ "line": 2 }] } }),
+        json!({ "seq": 4, "command": "configurationDone" }),
+        json!({ "seq": 5, "command": "variables", "arguments": { "variablesReference": GLOBALS } }),
+        json!({ "seq": 6, "command": "continue" }),
+    ];
+    let input = requests.iter().map(message).collect::<String>();
+
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let reader = Box::new(io::Cursor::new(input.into_bytes()));
+    let code = run_session(reader, Box::new(Shared(Rc::clone(&output))));
+    assert_eq!(code, 0);
+
+    let output = String::from_utf8(output.borrow().clone()).unwrap();
+    assert!(output.contains(r#""reason":"breakpoint""#));
+    assert!(output.contains(r#""name":"x","value":"1""#));
+    assert!(output.contains(r#""event":"terminated""#));
+}
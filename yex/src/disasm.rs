@@ -0,0 +1,139 @@
+use std::{collections::HashMap, fmt::Write, fs};
+
+use vm::{FnKind, OpCode, OpCodeMetadata, Operand, Value};
+
+/// Names the constants that are saved right away to a global, like `def` declarations
+fn names(ops: &[OpCodeMetadata]) -> HashMap<usize, String> {
+    ops.windows(2)
+        .filter_map(|pair| match (pair[0].opcode, pair[1].opcode) {
+            (OpCode::Push(idx), OpCode::Savg(name)) => Some((idx, name.to_string())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Writes the disassembly of a chunk of bytecode, showing the constants it pushes
+fn chunk(out: &mut String, name: &str, ops: &[OpCodeMetadata], constants: &[Value]) {
+    writeln!(out, "== {name} ==").unwrap();
+
+    for (idx, op) in ops.iter().enumerate() {
+        let position = format!("{}:{}", op.line, op.column);
+        let opcode = format!("{:?}", op.opcode);
+        let comment = match op.opcode {
+            OpCode::Push(idx) => constants.get(idx).map_or_else(
+                || String::from("; <invalid constant>"),
+                |it| format!("; {}", describe(it)),
+            ),
+            OpCode::Bin(_, left, right) => {
+                let operands = [left, right]
+                    .iter()
+                    .filter_map(|it| match it {
+                        Operand::Const(idx) => constants.get(*idx as usize).map(describe),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+
+                if operands.is_empty() {
+                    String::new()
+                } else {
+                    format!("; {}", operands.join(", "))
+                }
+            }
+            _ => String::new(),
+        };
+
+        let line = format!("{idx:04}  {position:<8}{opcode:<40}{comment}");
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+
+    out.push('\n');
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Fn(fun) => format!("<fn/{}>", fun.arity),
+        Value::Module(module) => format!("<type {}>", module.name),
+        other => other.to_string(),
+    }
+}
+
+/// Writes the disassembly of a function constant, or of the methods of a type
+fn constant(out: &mut String, name: &str, value: &Value, constants: &[Value]) {
+    match value {
+        Value::Fn(fun) => match &*fun.body {
+            FnKind::Bytecode(ops) => chunk(out, name, ops, constants),
+            FnKind::Generator(ops) => chunk(out, &format!("{name} (generator)"), ops, constants),
+            FnKind::Native(_) => (),
+        },
+        Value::Module(module) => {
+            let mut fields = module.fields.iter().collect::<Vec<_>>();
+            fields.sort_by_key(|(field, _)| field.to_string());
+
+            for (field, value) in fields {
+                constant(out, &format!("{}.{field}", module.name), &value, constants);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Disassembles the top-level code of a program and every function it defines
+pub(crate) fn disassemble(ops: &[OpCodeMetadata], constants: &[Value]) -> String {
+    let mut out = String::new();
+    chunk(&mut out, "<main>", ops, constants);
+
+    writeln!(out, "== constants ==").unwrap();
+    for (idx, value) in constants.iter().enumerate() {
+        writeln!(out, "{idx:04}  {}", describe(value)).unwrap();
+    }
+    out.push('\n');
+
+    let names = names(ops);
+    for (idx, value) in constants.iter().enumerate() {
+        let name = match names.get(&idx) {
+            Some(name) => format!("{name} (constant {idx})"),
+            None => format!("constant {idx}"),
+        };
+        constant(&mut out, &name, value, constants);
+    }
+
+    out
+}
+
+/// Prints the disassembly of every file in `paths`, returning the exit code
+pub fn run(paths: &[String]) -> i32 {
+    if paths.is_empty() {
+        eprintln!("expected a file to disassemble");
+        return 1;
+    }
+
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("error reading {path}: {e}");
+                return 1;
+            }
+        };
+
+        match front::parse(source) {
+            Ok((bytecode, constants)) => print!("{}", disassemble(&bytecode, &constants)),
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                return 1;
+            }
+        }
+    }
+
+    0
+}
+
+#[test]
+fn disassemble_functions() {
+    let (bytecode, constants) = front::parse("def add x y = x + y\nlet two = add 1 1").unwrap();
+    let out = disassemble(&bytecode, &constants);
+
+    assert!(out.starts_with("== <main> ==\n"));
+    assert!(out.contains("== add (constant "));
+    assert!(out.contains("Add"));
+}
@@ -0,0 +1,128 @@
+use std::{fmt::Write, fs, path::Path};
+
+use front::DocItem;
+
+use crate::test::discover;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn markdown(out: &mut String, module: &str, items: &[DocItem]) {
+    writeln!(out, "# {module}\n").unwrap();
+
+    for item in items {
+        writeln!(out, "## {} `{}`\n", item.kind, item.signature).unwrap();
+        if !item.doc.is_empty() {
+            writeln!(out, "{}\n", item.doc).unwrap();
+        }
+
+        for member in &item.members {
+            writeln!(
+                out,
+                "### {} `{}`\n\n{}\n",
+                member.kind, member.signature, member.doc
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn html(out: &mut String, module: &str, items: &[DocItem]) {
+    writeln!(out, "<section>\n<h1>{}</h1>", escape(module)).unwrap();
+
+    let paragraphs = |doc: &str| {
+        doc.split("\n\n").fold(String::new(), |mut out, it| {
+            write!(out, "<p>{}</p>", escape(it)).unwrap();
+            out
+        })
+    };
+
+    for item in items {
+        writeln!(
+            out,
+            "<h2>{} <code>{}</code></h2>{}",
+            item.kind,
+            escape(&item.signature),
+            paragraphs(&item.doc)
+        )
+        .unwrap();
+
+        for member in &item.members {
+            writeln!(
+                out,
+                "<h3>{} <code>{}</code></h3>{}",
+                member.kind,
+                escape(&member.signature),
+                paragraphs(&member.doc)
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(out, "</section>").unwrap();
+}
+
+/// Prints the documentation of every file in `args` (or the current directory) as Markdown, or as
+/// HTML with `--html`, each file is a module named after it
+pub fn run(args: &[String]) -> i32 {
+    let as_html = args.iter().any(|arg| arg == "--html");
+    let paths = args
+        .iter()
+        .filter(|arg| *arg != "--html")
+        .collect::<Vec<_>>();
+
+    let mut files = vec![];
+    if paths.is_empty() {
+        discover(Path::new("."), &mut files);
+    }
+    for path in paths {
+        discover(Path::new(path), &mut files);
+    }
+
+    let mut out = String::new();
+    if as_html {
+        out.push_str("<!DOCTYPE html>\n<html>\n<body>\n");
+    }
+
+    for file in &files {
+        let display = file.display();
+
+        let Ok(source) = fs::read_to_string(file) else {
+            eprintln!("error reading {display}");
+            return 1;
+        };
+
+        let items = match front::docs(&source) {
+            Ok(items) => items,
+            Err(e) => {
+                eprintln!("{display}: {e}");
+                return 1;
+            }
+        };
+
+        if items.is_empty() {
+            continue;
+        }
+
+        let module = file.file_stem().map_or_else(
+            || display.to_string(),
+            |it| it.to_string_lossy().to_string(),
+        );
+
+        if as_html {
+            html(&mut out, &module, &items);
+        } else {
+            markdown(&mut out, &module, &items);
+        }
+    }
+
+    if as_html {
+        out.push_str("</body>\n</html>\n");
+    }
+
+    print!("{out}");
+    0
+}
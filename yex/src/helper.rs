@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use rustyline::{
+    completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator, Context,
+    Helper,
+};
+use vm::{EnvTable, Value};
+
+/// Characters that can be part of a name, `.` is included so `Module.method` is a single word
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '?' | '!' | '\'' | '.')
+}
+
+/// The REPL line editor helper, completes keywords, globals and the fields of modules
+#[derive(Default)]
+pub(crate) struct YexHelper {
+    globals: Vec<String>,
+    modules: HashMap<String, Vec<String>>,
+}
+
+impl YexHelper {
+    /// Updates the known names with the globals of the virtual machine
+    pub fn refresh(&mut self, globals: &EnvTable) {
+        self.globals.clear();
+        self.modules.clear();
+
+        // tests and benches are saved as globals with spaces in their names
+        for (name, value) in globals.iter() {
+            if name.as_str().contains(' ') {
+                continue;
+            }
+
+            if let Value::Module(module) = value {
+                let fields = module
+                    .fields
+                    .iter()
+                    .map(|(field, _)| field.to_string())
+                    .collect();
+                self.modules.insert(name.to_string(), fields);
+            }
+
+            self.globals.push(name.to_string());
+        }
+    }
+
+    fn candidates(&self, word: &str) -> Vec<String> {
+        let mut candidates = match word.rsplit_once('.') {
+            Some((module, field)) => self
+                .modules
+                .get(module)
+                .into_iter()
+                .flatten()
+                .filter(|it| it.starts_with(field))
+                .map(|it| format!("{module}.{it}"))
+                .collect(),
+            None => front::KEYWORDS
+                .iter()
+                .map(|it| (*it).to_string())
+                .chain(self.globals.iter().cloned())
+                .filter(|it| it.starts_with(word))
+                .collect::<Vec<_>>(),
+        };
+
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+impl Completer for YexHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| is_name_char(*c))
+            .last()
+            .map_or(pos, |(i, _)| i);
+
+        Ok((start, self.candidates(&line[start..pos])))
+    }
+}
+
+impl Hinter for YexHelper {
+    type Hint = String;
+}
+
+impl Highlighter for YexHelper {}
+
+impl Validator for YexHelper {}
+
+impl Helper for YexHelper {}
+
+#[test]
+fn complete_globals_and_keywords() {
+    let mut helper = YexHelper::default();
+    helper.refresh(vm::VirtualMachine::default().globals());
+
+    assert!(helper.candidates("pri").contains(&String::from("println")));
+    assert!(helper.candidates("rec").contains(&String::from("receive")));
+}
+
+#[test]
+fn complete_module_fields() {
+    let mut helper = YexHelper::default();
+    helper.refresh(vm::VirtualMachine::default().globals());
+
+    let candidates = helper.candidates("List.ma");
+    assert!(candidates.contains(&String::from("List.map")));
+    assert!(!candidates.contains(&String::from("List.filter")));
+}
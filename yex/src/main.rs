@@ -1,117 +1,728 @@
 #![warn(clippy::pedantic)]
-use rustyline::Editor;
+mod bench;
+mod bundle;
+mod check;
+mod compile;
+mod config;
+mod coverage;
+mod dap;
+mod disasm;
+mod doc;
+mod helper;
+mod pkg;
+mod pretty;
+mod reload;
+mod replay;
+mod test;
+mod watch;
+
+use config::Config;
+use helper::YexHelper;
+use pretty::Report;
+use rustyline::{ColorMode, Editor};
 use std::{
-    env::args,
+    env::{self, args},
     fs::{self, File},
+    io::{stderr, stdin, stdout, IsTerminal, Read},
+    path::Path,
     process::exit,
+    time::Instant,
 };
-use vm::{OpCode, OpCodeMetadata, VirtualMachine};
+use vm::{literal::num, Bytecode, FnKind, OpCodeMetadata, Value, VirtualMachine};
+
+const HISTORY_SIZE: usize = 1000;
+
+const HELP: &str = "\
+:help              show this message
+:quit              exit the REPL
+:load <file>       run a file, keeping its definitions
+:reload            run the loaded files that changed again, updating their definitions
+:reset             forget every definition, except the ones in ~/.yexrc
+:globals           list the defined globals
+:type <expr>       show the type of an expression
+:doc <name>        describe a global, or a module field with `Module.name`
+:time <expr>       evaluate an expression and show how long it took";
+
+/// Prints the AST of a file, as indented JSON or as a single line with `--json`
+fn ast(args: &[String]) -> i32 {
+    let json = args.iter().any(|arg| arg == "--json");
+    let Some(path) = args.iter().find(|arg| *arg != "--json") else {
+        eprintln!("expected a file");
+        return 1;
+    };
+
+    let Ok(source) = fs::read_to_string(path) else {
+        eprintln!("error reading {path}");
+        return EXIT_NO_INPUT;
+    };
+
+    match front::ast_json(source, !json) {
+        Ok(ast) => {
+            println!("{ast}");
+            0
+        }
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            EXIT_SYNTAX
+        }
+    }
+}
+
+/// Exit code for programs that don't compile
+const EXIT_SYNTAX: i32 = 65;
+/// Exit code for files that can't be read
+const EXIT_NO_INPUT: i32 = 66;
+/// Exit code for programs stopped by an uncaught error
+const EXIT_RUNTIME: i32 = 70;
 
-fn eval_file(file: &str) {
-    let file = if let Ok(file) = fs::read_to_string(file) {
+/// Maps the value a program evaluated to into an exit code, numbers are used as the exit code.
+/// The OS only keeps the lowest byte of it, so numbers outside of `0..=255` are an error instead
+#[allow(clippy::cast_possible_truncation)]
+fn exit_code(value: &Value) -> i32 {
+    match value {
+        Value::Int(n) if (0..=255).contains(n) => *n as i32,
+        Value::Num(n) if (0.0..256.0).contains(n) => *n as i32,
+        Value::Int(_) | Value::Num(_) => {
+            eprintln!("exit code {value} is outside of 0..=255");
+            EXIT_RUNTIME
+        }
+        _ => 0,
+    }
+}
+
+/// Runs a file and the packages of its project, see [`eval_program`], or a program packed by
+/// `yex compile`, see [`eval_packed`]
+fn eval_file(path: &str, script_args: &[String], color: bool, replay: &replay::Options) -> i32 {
+    if Path::new(path)
+        .extension()
+        .is_some_and(|it| it == compile::EXTENSION)
+    {
+        return eval_packed(path, script_args, color, replay);
+    }
+
+    let file = if let Ok(file) = fs::read_to_string(path) {
         file
     } else {
-        eprintln!("error reading {}", file);
-        exit(1);
+        eprintln!("error reading {}", path);
+        return EXIT_NO_INPUT;
     };
 
-    let (bt, ct) = match front::parse(file) {
+    let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    eval_program(&file, dir, script_args, color, replay)
+}
+
+/// Runs a program, calling its `main` function (as `main ()`) if it defines
+/// one, and returns the exit code, from the value of `main` or else of the trailing expression of
+/// the program. The script arguments are available in the `args` global, and the files it imports
+/// are relative to `dir`
+fn eval_program(
+    file: &str,
+    dir: &Path,
+    script_args: &[String],
+    color: bool,
+    replay: &replay::Options,
+) -> i32 {
+    let (bt, ct) = match front::parse_in(file, dir) {
         Ok(res) => res,
         Err(e) => {
-            eprintln!("{}", e);
-            exit(1);
+            eprintln!("{}", pretty::error(&e.into(), file, color));
+            return EXIT_SYNTAX;
         }
     };
 
+    let load = |vm: &mut VirtualMachine| run_patched(vm, bt, ct);
+    eval_compiled(load, file, script_args, color, replay)
+}
+
+/// Runs a program packed by `yex compile`, which is already compiled, along with the files it
+/// imports, so it only has to be unpacked
+fn eval_packed(path: &str, script_args: &[String], color: bool, replay: &replay::Options) -> i32 {
+    let Ok(bytes) = fs::read(path) else {
+        eprintln!("error reading {path}");
+        return EXIT_NO_INPUT;
+    };
+
+    eval_chunk(&bytes, "", script_args, color, replay)
+}
+
+/// Runs a packed program, `file` is its source if it's known, to show where the errors are
+fn eval_chunk(
+    bytes: &[u8],
+    file: &str,
+    script_args: &[String],
+    color: bool,
+    replay: &replay::Options,
+) -> i32 {
+    let load = |vm: &mut VirtualMachine| {
+        let bytecode = vm.load_chunk(bytes)?;
+        let res = vm.run(&bytecode).map(|()| vm.pop_last().clone());
+        vm.reset();
+        Ok(res?)
+    };
+    eval_compiled(load, file, script_args, color, replay)
+}
+
+/// Runs a program like [`eval_program`] once it's compiled, `load` runs its top-level code and
+/// returns its value. `file` is the source of the program, to show where the errors are
+fn eval_compiled(
+    load: impl FnOnce(&mut VirtualMachine) -> Result<Value, Report>,
+    file: &str,
+    script_args: &[String],
+    color: bool,
+    replay: &replay::Options,
+) -> i32 {
     let mut vm = VirtualMachine::default();
+    let script_args = script_args.iter().map(|arg| arg.clone().into());
+    vm.set_global("args", Value::List(script_args.collect()));
 
-    vm.set_consts(ct);
-    if let Err(e) = vm.run(&bt) {
-        eprintln!("{}", e);
-        exit(1);
+    if let Err(e) = replay.setup(&mut vm) {
+        eprintln!("{e}");
+        return EXIT_NO_INPUT;
     }
-}
 
-fn patch_bytecode(ops: &mut [OpCodeMetadata], old_len: usize) {
-    for op in ops.iter_mut() {
-        if let OpCode::Push(idx) = &mut op.opcode {
-            *idx += old_len;
+    let res = load(&mut vm).and_then(|value| match vm.get_global("main") {
+        Some(Value::Fn(_)) => {
+            let (bt, ct) = front::parse_expr("main ()")?;
+            run_patched(&mut vm, bt, ct).map(|value| exit_code(&value))
+        }
+        _ => Ok(exit_code(&value)),
+    });
+
+    // the log is written even if the program failed, that's when it's most useful
+    if let Err(e) = replay.finish(&vm) {
+        eprintln!("{e}");
+    }
+
+    match res {
+        Ok(code) => code,
+        Err(e) => {
+            print_trace(&vm);
+            let diagnostic = Report { local: true, ..e };
+            eprintln!("{}", pretty::error(&diagnostic, file, color));
+            EXIT_RUNTIME
         }
     }
 }
 
-fn start(args: impl Iterator<Item = String>) -> i32 {
-    let mut repl = Editor::<()>::new();
+/// Runs a program given in the command line or through stdin, printing the value it evaluates to
+fn eval_source(source: &str, color: bool) -> i32 {
+    let mut vm = VirtualMachine::default();
 
-    let path = format!("{}/.yex_history", std::env::var("HOME").unwrap());
-    if repl.load_history(&path).is_err() {
-        File::create(&path).ok();
-        repl.load_history(&path).ok();
+    match eval_line(&mut vm, source) {
+        Ok(Value::Nil) => 0,
+        Ok(value) => {
+            println!("{value}");
+            0
+        }
+        Err(err) => {
+            print_trace(&vm);
+            eprintln!("{}", pretty::error(&err, source, color));
+            if err.diagnostic.is_syntax_error() {
+                EXIT_SYNTAX
+            } else {
+                EXIT_RUNTIME
+            }
+        }
     }
+}
+
+/// Checks if some of the code, or the functions defined by it, was compiled from `line:column`
+fn compiled_from(ops: &[OpCodeMetadata], constants: &[Value], line: usize, column: usize) -> bool {
+    let from_fn = |value: &Value| match value {
+        Value::Fn(fun) => match &*fun.body {
+            FnKind::Bytecode(ops) | FnKind::Generator(ops) => compiled_from(ops, &[], line, column),
+            FnKind::Native(_) => false,
+        },
+        Value::Module(module) => {
+            let fields = module.fields.values().collect::<Vec<_>>();
+            compiled_from(&[], &fields, line, column)
+        }
+        _ => false,
+    };
+
+    ops.iter().any(|op| op.line == line && op.column == column) || constants.iter().any(from_fn)
+}
+
+/// Runs freshly compiled code on the REPL virtual machine, returning the value it evaluated to
+fn run_patched(
+    vm: &mut VirtualMachine,
+    mut bytecode: Bytecode,
+    constants: Vec<Value>,
+) -> Result<Value, Report> {
+    let new_constants = vm.constants.len();
+    vm.link(&mut bytecode, constants);
+
+    let res = vm.run(&bytecode).map(|()| vm.pop_last().clone());
+    vm.reset();
+
+    res.map_err(|e| {
+        let local = compiled_from(&bytecode, &vm.constants[new_constants..], e.line, e.column);
+        Report { local, ..e.into() }
+    })
+}
+
+/// Compiles and runs a line typed in the REPL
+fn eval_line(vm: &mut VirtualMachine, line: &str) -> Result<Value, Report> {
+    let (bytecode, constants) = front::parse_repl(line)?;
+    run_patched(vm, bytecode, constants)
+}
+
+/// Describes a global (or a field of a global module) for `:doc`
+fn describe(vm: &VirtualMachine, name: &str) -> Result<String, String> {
+    let value = match name.split_once('.') {
+        Some((module, field)) => match vm.get_global(module) {
+            Some(Value::Module(module)) => module.fields.get(&field.into()),
+            _ => None,
+        },
+        None => vm.get_global(name),
+    };
+
+    Ok(
+        match value.ok_or_else(|| format!("'{name}' is not defined"))? {
+            Value::Fn(f) => format!("{name} : function of {} arguments", f.arity),
+            Value::Module(module) => {
+                let mut fields = module
+                    .fields
+                    .iter()
+                    .map(|(field, _)| field.to_string())
+                    .collect::<Vec<_>>();
+                fields.sort();
+                format!("{name} : module\n  {}", fields.join(", "))
+            }
+            value => format!("{name} : {} = {value}", value.type_of().name),
+        },
+    )
+}
 
-    if args.size_hint().0 > 1 {
-        for args in args.skip(1) {
-            eval_file(&args);
+/// Runs a REPL meta-command, returns `false` if the REPL should quit
+fn command(vm: &mut VirtualMachine, loaded: &mut reload::Loaded, line: &str, color: bool) -> bool {
+    let (cmd, arg) = line
+        .split_once(' ')
+        .map_or((line, ""), |(cmd, arg)| (cmd, arg.trim()));
+
+    let res = match cmd {
+        ":help" | ":h" => Ok(String::from(HELP)),
+        ":quit" | ":q" => return false,
+        ":reset" => {
+            *vm = VirtualMachine::default();
+            *loaded = reload::Loaded::default();
+            Config::load(vm);
+            Ok(String::from("the virtual machine was reset"))
+        }
+        ":load" => loaded.load(vm, arg, color),
+        ":reload" => loaded.reload(vm, color),
+        ":globals" => {
+            let prelude = VirtualMachine::default();
+            let mut globals = vm
+                .globals()
+                .iter()
+                .filter(|(name, _)| !prelude.globals().contains_key(name))
+                .map(|(name, value)| format!("{name} = {value}"))
+                .collect::<Vec<_>>();
+            globals.sort();
+            Ok(globals.join("\n"))
+        }
+        ":type" => eval_line(vm, arg)
+            .map(|value| value.type_of().name.to_string())
+            .map_err(|e| pretty::error(&e, arg, color)),
+        ":doc" => describe(vm, arg),
+        ":time" => {
+            let start = Instant::now();
+            eval_line(vm, arg)
+                .map(|value| {
+                    format!(
+                        "{}\ntook {:?}",
+                        pretty::value(&value, color),
+                        start.elapsed()
+                    )
+                })
+                .map_err(|e| pretty::error(&e, arg, color))
         }
-        return 0;
+        _ => Err(format!("unknown command '{cmd}', try :help")),
+    };
+
+    match res {
+        Ok(out) if out.is_empty() => (),
+        Ok(out) => println!("{out}"),
+        Err(err) => eprintln!("{err}"),
     }
 
+    true
+}
+
+/// Runs the REPL, `yex repl --preload <file>` loads the file before the first line
+fn repl(args: &[String], color: bool) -> i32 {
+    let mut loaded = reload::Loaded::default();
     let mut vm = VirtualMachine::default();
+    let config = Config::load(&mut vm);
+    let color = color && config.color && stdout().is_terminal();
+
+    for arg in args.chunks(2) {
+        let [flag, path] = arg else {
+            eprintln!("expected a file after '{}'", arg[0]);
+            return 1;
+        };
+        if flag != "--preload" {
+            eprintln!("unknown flag '{flag}', expected '--preload <file>'");
+            return 1;
+        }
+        if let Err(e) = loaded.load(&mut vm, path, color) {
+            eprintln!("{e}");
+            return 1;
+        }
+    }
+
+    let color_mode = if color {
+        ColorMode::Enabled
+    } else {
+        ColorMode::Disabled
+    };
+    let mut repl = Editor::<YexHelper>::with_config(
+        rustyline::Config::builder()
+            .color_mode(color_mode)
+            .max_history_size(HISTORY_SIZE)
+            .build(),
+    );
+
+    let history = config::home_file(".yex_history");
+    if let Some(path) = &history {
+        if repl.load_history(path).is_err() {
+            File::create(path).ok();
+        }
+    }
+
+    let mut helper = YexHelper::default();
+    helper.refresh(vm.globals());
+    repl.set_helper(Some(helper));
 
     loop {
-        let line = if let Ok(str) = repl.readline("yex> ") {
-            str.trim().to_string()
-        } else {
-            repl.save_history(&path).ok();
+        let Ok(line) = repl.readline(&config.prompt) else {
             return 0;
         };
+        let line = line.trim().to_string();
 
         if line.is_empty() || line.starts_with("//") {
             continue;
         }
 
+        // saved right away, so it isn't lost if the program calls `exit`
         repl.add_history_entry(&line);
+        if let Some(path) = &history {
+            repl.save_history(path).ok();
+        }
 
-        if line.starts_with("def") || line.starts_with("let") || line.starts_with("type") {
-            match front::parse(line) {
-                Ok((mut bt, ct)) => {
-                    patch_bytecode(&mut bt, vm.constants.len());
-                    vm.constants.extend(ct);
-                    vm.run(&bt).unwrap_or_else(|e| println!("{}", e));
-                    println!("{}", vm.pop_last());
-                }
-                Err(err) => {
-                    eprintln!("{}", err);
-                }
+        if line.starts_with(':') {
+            if !command(&mut vm, &mut loaded, &line, color) {
+                return 0;
             }
         } else {
-            match front::parse_expr(line) {
-                Ok((mut bt, ct)) => {
-                    patch_bytecode(&mut bt, vm.constants.len());
-                    vm.constants.extend(ct);
-                    vm.run(&bt).unwrap_or_else(|e| println!("{}", e));
-                    println!("{}", vm.pop_last());
-                }
-                Err(err) => {
-                    eprintln!("{}", err);
-                }
+            match eval_line(&mut vm, &line) {
+                Ok(value) => println!("{}", pretty::value(&value, color)),
+                Err(err) => eprintln!("{}", pretty::error(&err, &line, color)),
             }
         }
-        vm.reset();
+
+        if let Some(helper) = repl.helper_mut() {
+            helper.refresh(vm.globals());
+        }
+    }
+}
+
+/// Runs the program of an executable built with `yex build`
+fn eval_bundle(bundle: &bundle::Bundle, args: &[String]) -> i32 {
+    let color = stderr().is_terminal();
+    let replay = replay::Options::default();
+    let source = bundle.source.as_deref().unwrap_or_default();
+    eval_chunk(&bundle.program, source, args, color, &replay)
+}
+
+/// Takes `--inline-threshold <ops>` out of the command line arguments, setting the threshold
+fn take_inline_threshold(args: &mut Vec<String>) -> Result<(), String> {
+    if let Some(idx) = args.iter().position(|arg| arg == "--inline-threshold") {
+        let Some(threshold) = args.get(idx + 1).and_then(|it| it.parse().ok()) else {
+            return Err(String::from(
+                "expected a number of opcodes after '--inline-threshold'",
+            ));
+        };
+        front::set_inline_threshold(threshold);
+        args.drain(idx..idx + 2);
+    }
+
+    Ok(())
+}
+
+/// Takes every `--define <flag>` out of the command line arguments, defining the flags
+fn take_defines(args: &mut Vec<String>) -> Result<(), String> {
+    while let Some(idx) = args.iter().position(|arg| arg == "--define") {
+        let Some(flag) = args.get(idx + 1) else {
+            return Err(String::from("expected a flag name after '--define'"));
+        };
+        front::define(flag);
+        args.drain(idx..idx + 2);
+    }
+
+    Ok(())
+}
+
+/// Takes `--error-format=<human|json>` out of the command line arguments, choosing how the
+/// diagnostics are written
+fn take_error_format(args: &mut Vec<String>) -> Result<(), String> {
+    if let Some(idx) = args
+        .iter()
+        .position(|arg| arg.starts_with("--error-format="))
+    {
+        match &args[idx]["--error-format=".len()..] {
+            "human" => pretty::set_json(false),
+            "json" => pretty::set_json(true),
+            other => {
+                return Err(format!(
+                    "unknown error format '{other}', expected human or json"
+                ))
+            }
+        }
+        args.remove(idx);
+    }
+
+    Ok(())
+}
+
+/// Takes `--max-depth <calls>` out of the command line arguments, limiting the recursion depth
+fn take_max_depth(args: &mut Vec<String>) -> Result<(), String> {
+    if let Some(idx) = args.iter().position(|arg| arg == "--max-depth") {
+        let Some(depth) = args.get(idx + 1).and_then(|it| it.parse().ok()) else {
+            return Err(String::from(
+                "expected a number of calls after '--max-depth'",
+            ));
+        };
+        vm::set_default_max_depth(depth);
+        args.drain(idx..idx + 2);
+    }
+
+    Ok(())
+}
+
+/// Takes `--overflow <mode>` out of the command line arguments, choosing what the integer
+/// operators do when they overflow
+fn take_overflow(args: &mut Vec<String>) -> Result<(), String> {
+    if let Some(idx) = args.iter().position(|arg| arg == "--overflow") {
+        let Some(mode) = args
+            .get(idx + 1)
+            .and_then(|it| num::Overflow::from_name(it))
+        else {
+            return Err(String::from(
+                "expected 'wrap', 'saturate' or 'raise' after '--overflow'",
+            ));
+        };
+        num::set_overflow(mode);
+        args.drain(idx..idx + 2);
+    }
+
+    Ok(())
+}
+
+/// Takes `--trace <ops>` out of the command line arguments, recording the last instructions run
+/// to print them when the program fails
+fn take_trace(args: &mut Vec<String>) -> Result<(), String> {
+    if let Some(idx) = args.iter().position(|arg| arg == "--trace") {
+        let Some(len) = args.get(idx + 1).and_then(|it| it.parse().ok()) else {
+            return Err(String::from(
+                "expected a number of instructions after '--trace'",
+            ));
+        };
+        vm::trace::set_default_trace_len(len);
+        args.drain(idx..idx + 2);
+    }
+
+    Ok(())
+}
+
+/// Prints the last instructions run before an uncaught error, if they were recorded
+fn print_trace(vm: &VirtualMachine) {
+    if let Some(trace) = vm.trace().filter(|_| !pretty::is_json()) {
+        eprint!("last instructions run:\n{trace}");
+    }
+}
+
+/// Takes the compiler and VM flags out of the command line arguments, and lets `compile` and
+/// `Value::parse` use the compiler
+fn setup(args: &mut Vec<String>) -> Result<(), String> {
+    vm::literal::code::set_compiler(front::compile_fn);
+    vm::literal::set_parser(front::parse_value);
+    take_inline_threshold(args)?;
+    take_max_depth(args)?;
+    take_overflow(args)?;
+    take_trace(args)?;
+    take_error_format(args)?;
+    take_defines(args)
+}
+
+/// Lets the virtual machines load the part of the prelude written in yex, compiled by the build
+/// script with the `precompiled-prelude` feature
+#[cfg(feature = "precompiled-prelude")]
+fn init_prelude() {
+    vm::set_prelude_loader(|vm| {
+        let bytes = include_bytes!(concat!(env!("OUT_DIR"), "/prelude.bin"));
+        vm::encoding::decode_program(bytes, vm).ok()
+    });
+}
+
+/// Lets the virtual machines load the part of the prelude written in yex, compiling it
+#[cfg(not(feature = "precompiled-prelude"))]
+fn init_prelude() {
+    front::init();
+}
+
+fn start(args: impl Iterator<Item = String>) -> i32 {
+    let mut args = args.collect::<Vec<_>>();
+    init_prelude();
+
+    // executables built with `yex build` run their program with all the arguments
+    if let Some(bundle) = bundle::Bundle::current() {
+        return eval_bundle(&bundle, &args[1..]);
+    }
+
+    // https://no-color.org
+    let mut color = env::var_os("NO_COLOR").is_none_or(|it| it.is_empty());
+    if let Some(idx) = args.iter().position(|arg| arg == "--no-color") {
+        args.remove(idx);
+        color = false;
+    }
+
+    let replay = setup(&mut args).and_then(|()| replay::Options::take(&mut args));
+    let Ok(replay) = replay.map_err(|e| eprintln!("{e}")) else {
+        return 1;
+    };
+
+    match args.get(1).map(String::as_str) {
+        Some("test") => return test::run(&args[2..]),
+        Some("bench") => return bench::run(&args[2..]),
+        Some("ast") => return ast(&args[2..]),
+        Some("watch") => return watch::run(&args[2..]),
+        Some("doc") => return doc::run(&args[2..]),
+        Some("dap") => return dap::run(&args[2..]),
+        Some("pkg") => return pkg::run(&args[2..]),
+        Some("build") => return bundle::run(&args[2..]),
+        Some("compile") => return compile::run(&args[2..]),
+        Some("disasm") => return disasm::run(&args[2..]),
+        Some("check") => return check::run(&args[2..], color && stderr().is_terminal()),
+        Some("repl") => return repl(&args[2..], color),
+        Some("-e" | "--eval") => {
+            let Some(source) = args.get(2) else {
+                eprintln!("expected a program after '{}'", args[1]);
+                return 1;
+            };
+            return eval_source(source, color && stderr().is_terminal());
+        }
+        Some("-") => {
+            let mut source = String::new();
+            if let Err(e) = stdin().read_to_string(&mut source) {
+                eprintln!("error reading stdin: {e}");
+                return 1;
+            }
+            return eval_source(&source, color && stderr().is_terminal());
+        }
+        _ => (),
     }
+
+    if args.len() > 1 {
+        return eval_file(
+            &args[1],
+            &args[2..],
+            color && stderr().is_terminal(),
+            &replay,
+        );
+    }
+
+    repl(&[], color)
 }
 
 #[test]
 fn test_yex_file() {
     use std::path::Path;
     if Path::new("../tests.yex").exists() {
-        let args = vec![String::new(), String::from("../tests.yex")].into_iter();
+        // debug builds print the stack at every instruction, which gets slow the deeper it is
+        let args = ["", "--max-depth", "500", "../tests.yex"]
+            .map(String::from)
+            .into_iter();
         assert_eq!(start(args), 0);
     } else {
         eprintln!("can't find ./tests.yex, so not running it.");
     }
 }
 
+#[test]
+fn test_yex_file_tests() {
+    use std::path::Path;
+    if Path::new("../tests.yex").exists() {
+        let args = ["", "--max-depth", "500", "test", "../tests.yex"]
+            .map(String::from)
+            .into_iter();
+        assert_eq!(start(args), 0);
+    } else {
+        eprintln!("can't find ./tests.yex, so not running it.");
+    }
+}
+
+#[test]
+fn test_eval_flag() {
+    let args = ["", "-e", "let x = 20 in x + 1"]
+        .map(String::from)
+        .into_iter();
+    assert_eq!(start(args), 0);
+
+    let args = ["", "--eval", "1 +"].map(String::from).into_iter();
+    assert_eq!(start(args), EXIT_SYNTAX);
+
+    let args = ["", "--eval", "1 + nil"].map(String::from).into_iter();
+    assert_eq!(start(args), EXIT_RUNTIME);
+}
+
+/// A new empty directory for a test, named after it and the process running the tests, so
+/// concurrent runs don't share it
+#[cfg(test)]
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = env::temp_dir().join(format!("yex_{name}_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_repl_lines_share_constants() {
+    let mut vm = VirtualMachine::default();
+    assert!(eval_line(&mut vm, "def f x = x * 3").is_ok());
+    assert!(matches!(eval_line(&mut vm, "f 2 + 10"), Ok(value) if value == Value::Num(16.0)));
+}
+
+#[test]
+fn test_recurse_through_natives() {
+    // the calls from the function passed to `List.map` recurse on the host stack, which raises an
+    // error long before the limit instead of overflowing it
+    let mut vm = VirtualMachine::default();
+    vm.set_max_depth(1_000_000);
+    assert!(eval_line(&mut vm, "def f n = List.map (fn x -> f (x + 1)) [n]").is_ok());
+    let caught = eval_line(&mut vm, "(try f 0 rescue e e.kind) == :RecursionError");
+    assert!(matches!(caught, Ok(Value::Bool(true))));
+}
+
+#[test]
+fn test_exit_codes() {
+    let path = temp_dir("exit_codes").join("main.yex");
+    let run = |source: &str| {
+        fs::write(&path, source).unwrap();
+        start([String::new(), path.display().to_string()].into_iter())
+    };
+
+    assert_eq!(run("def main () = 3"), 3);
+    assert_eq!(run("let x = 1\ndef main () = x + nil"), EXIT_RUNTIME);
+    assert_eq!(run("let x = "), EXIT_SYNTAX);
+    assert_eq!(run("let x = 1"), 0);
+    assert_eq!(run("let x = 1\nx + 1\nx + 2"), 3);
+    assert_eq!(run("def main () = 255"), 255);
+    assert_eq!(run("def main () = 300"), EXIT_RUNTIME);
+    assert_eq!(run("def main () = -1"), EXIT_RUNTIME);
+}
+
 fn main() {
     exit(start(args()));
 }
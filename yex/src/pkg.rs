@@ -0,0 +1,345 @@
+use std::{
+    fmt::{self, Write},
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use front::{is_package_name, MODULES};
+
+/// The file listing the dependencies of a project, which are fetched to `yex_modules`, next to it
+const LOCKFILE: &str = "yex.lock";
+
+/// Where a package comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Source {
+    /// A git repository, locked to a commit
+    Git { url: String, rev: String },
+    /// A directory, copied as it is
+    Path(String),
+}
+
+/// A dependency in the lockfile, one per line, as `name git url rev` or `name path dir`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Package {
+    name: String,
+    source: Source,
+}
+
+impl fmt::Display for Package {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Source::Git { url, rev } => write!(f, "{} git {url} {rev}", self.name),
+            Source::Path(path) => write!(f, "{} path {path}", self.name),
+        }
+    }
+}
+
+impl Package {
+    fn parse(line: &str) -> Option<Self> {
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+        let source = match parts[..] {
+            // git would read a url or a revision starting with `-` as an option
+            [_, "git", url, rev] if !url.starts_with('-') && !rev.starts_with('-') => Source::Git {
+                url: url.to_string(),
+                rev: rev.to_string(),
+            },
+            [_, "path", path] => Source::Path(path.to_string()),
+            _ => return None,
+        };
+
+        let name = parts[0];
+        is_package_name(name).then(|| Self {
+            name: name.to_string(),
+            source,
+        })
+    }
+}
+
+fn read_lockfile(root: &Path) -> Result<Vec<Package>, String> {
+    let Ok(lockfile) = fs::read_to_string(root.join(LOCKFILE)) else {
+        return Ok(vec![]);
+    };
+
+    lockfile
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|line| Package::parse(line).ok_or_else(|| format!("invalid {LOCKFILE} line '{line}'")))
+        .collect()
+}
+
+fn write_lockfile(root: &Path, packages: &[Package]) -> io::Result<()> {
+    let mut lockfile = String::from("# generated by `yex pkg`\n");
+    for package in packages {
+        writeln!(lockfile, "{package}").unwrap();
+    }
+    fs::write(root.join(LOCKFILE), lockfile)
+}
+
+fn git(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| format!("can't run git: {e}"))?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Copies a package, without its hidden files and its own `yex_modules`
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with('.') || name == MODULES {
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to.join(name))?;
+        } else {
+            fs::copy(entry.path(), to.join(name))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the copy of a package from `yex_modules`, the name is checked so that nothing outside
+/// of it is ever removed
+fn remove(root: &Path, name: &str) -> Result<PathBuf, String> {
+    if !is_package_name(name) {
+        return Err(format!("'{name}' isn't a valid package name"));
+    }
+
+    let dest = root.join(MODULES).join(name);
+    let res = match dest.symlink_metadata() {
+        // a link is removed without touching what it points to
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(&dest),
+        Ok(_) => fs::remove_file(&dest),
+        Err(_) => Ok(()),
+    };
+
+    res.map_err(|e| format!("can't remove {}: {e}", dest.display()))?;
+    Ok(dest)
+}
+
+/// Fetches a package into `yex_modules`, replacing the previous copy
+fn fetch(root: &Path, package: &Package) -> Result<(), String> {
+    let dest = remove(root, &package.name)?;
+
+    let dest_str = dest.to_string_lossy();
+    match &package.source {
+        Source::Git { url, rev } => {
+            git(&["clone", "--quiet", "--", url, &dest_str])?;
+            git(&[
+                "-C",
+                &dest_str,
+                "checkout",
+                "--quiet",
+                "--end-of-options",
+                rev,
+            ])?;
+        }
+        Source::Path(path) => {
+            let from = root.join(path);
+            // the copy would go on copying itself
+            let inside = match (from.canonicalize(), root.canonicalize()) {
+                (Ok(from), Ok(root)) => root.join(MODULES).starts_with(from),
+                _ => false,
+            };
+            if inside {
+                return Err(format!("can't copy {path} into its own {MODULES}"));
+            }
+
+            copy_dir(&from, &dest).map_err(|e| format!("can't copy {path}: {e}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_git(source: &str) -> bool {
+    let path = Path::new(source.trim_end_matches('/'));
+
+    // `name.git` or `name/.git`
+    path.extension().is_some_and(|ext| ext == "git")
+        || path.file_name().is_some_and(|name| name == ".git")
+        || ["https://", "http://", "ssh://", "git@", "git://"]
+            .iter()
+            .any(|scheme| source.starts_with(scheme))
+}
+
+/// Adds a dependency to the project in the current directory
+fn add(source: &str) -> Result<Package, String> {
+    let root = Path::new(".");
+
+    // `https://host/user/name.git`, `../name` or `/path/to/name/.git`
+    let name = source
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    if !is_package_name(&name) {
+        return Err(format!("can't find a package name in '{source}'"));
+    }
+    if source.starts_with('-') {
+        return Err(format!("'{source}' isn't a git url or a path"));
+    }
+
+    let mut packages = read_lockfile(root)?;
+    packages.retain(|it| it.name != name);
+
+    let package = if is_git(source) {
+        let dest = remove(root, &name)?;
+        let dest = dest.to_string_lossy();
+        git(&["clone", "--quiet", "--", source, &dest])?;
+        Package {
+            name,
+            source: Source::Git {
+                url: source.to_string(),
+                rev: git(&["-C", &dest, "rev-parse", "HEAD"])?,
+            },
+        }
+    } else {
+        let package = Package {
+            name,
+            source: Source::Path(source.to_string()),
+        };
+        fetch(root, &package)?;
+        package
+    };
+
+    packages.push(package.clone());
+    write_lockfile(root, &packages).map_err(|e| format!("can't write {LOCKFILE}: {e}"))?;
+
+    Ok(package)
+}
+
+/// Fetches every dependency listed in the lockfile of the current directory
+fn install() -> Result<usize, String> {
+    let root = Path::new(".");
+    let packages = read_lockfile(root)?;
+
+    for package in &packages {
+        fetch(root, package)?;
+    }
+
+    Ok(packages.len())
+}
+
+/// Runs a `yex pkg` subcommand, returning the exit code
+pub fn run(args: &[String]) -> i32 {
+    let res = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["add", source] => add(source).map(|it| format!("added {it}")),
+        ["install"] => install().map(|n| format!("installed {n} packages")),
+        ["list"] => read_lockfile(Path::new(".")).map(|packages| {
+            packages
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        }),
+        _ => Err(String::from(
+            "usage: yex pkg add <git-url|path> | yex pkg install | yex pkg list",
+        )),
+    };
+
+    match res {
+        Ok(out) => {
+            if !out.is_empty() {
+                println!("{out}");
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+#[test]
+fn parse_lockfile_lines() {
+    let git = Package::parse("json git https://example.com/json.git 1a2b3c").unwrap();
+    assert_eq!(
+        git.to_string(),
+        "json git https://example.com/json.git 1a2b3c"
+    );
+
+    let path = Package::parse("utils path ../utils").unwrap();
+    assert_eq!(path.source, Source::Path(String::from("../utils")));
+
+    assert!(Package::parse("broken git").is_none());
+    assert!(Package::parse(".. path ../utils").is_none());
+    assert!(Package::parse("a/b path ../utils").is_none());
+    assert!(Package::parse("c:d path ../utils").is_none());
+    assert!(Package::parse("x git --upload-pack=touch y").is_none());
+    assert!(Package::parse("x git https://example.com/x.git --orphan").is_none());
+    assert!(Package::parse("").is_none());
+}
+
+#[test]
+fn read_indented_comments() {
+    let root = crate::temp_dir("pkg_comments");
+    fs::write(
+        root.join(LOCKFILE),
+        "# generated\n  # indented\n\tjson path ../json\n",
+    )
+    .unwrap();
+
+    let packages = read_lockfile(&root).unwrap();
+    assert_eq!(packages.len(), 1);
+    assert_eq!(packages[0].name, "json");
+}
+
+#[test]
+fn refuse_to_copy_a_project_into_itself() {
+    let root = crate::temp_dir("pkg_itself");
+    fs::write(root.join("lib.yex"), "def f x = x").unwrap();
+
+    for path in [".", ".."] {
+        let package = Package {
+            name: String::from("me"),
+            source: Source::Path(path.to_string()),
+        };
+        assert!(fetch(&root, &package).is_err());
+    }
+
+    let dep = root.join("dep");
+    fs::create_dir_all(dep.join(MODULES).join("nested")).unwrap();
+    fs::write(dep.join("lib.yex"), "def g x = x").unwrap();
+    let package = Package {
+        name: String::from("dep"),
+        source: Source::Path(String::from("dep")),
+    };
+    fetch(&root, &package).unwrap();
+    assert!(root.join(MODULES).join("dep").join("lib.yex").is_file());
+    assert!(!root.join(MODULES).join("dep").join(MODULES).exists());
+}
+
+#[test]
+fn only_remove_packages_inside_the_modules() {
+    let root = crate::temp_dir("pkg_remove");
+    let outside = root.join("outside");
+    fs::create_dir_all(root.join(MODULES).join("json")).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+
+    for name in ["", ".", "..", "../outside", "/tmp", "a\\b", "c:d"] {
+        assert!(remove(&root, name).is_err());
+    }
+    assert!(outside.is_dir());
+
+    remove(&root, "json").unwrap();
+    assert!(!root.join(MODULES).join("json").exists());
+    assert!(root.join(MODULES).is_dir());
+}
@@ -0,0 +1,283 @@
+use std::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use front::ParseError;
+use vm::{
+    diagnostic::{Diagnostic, Severity},
+    error::InterpretError,
+    Value,
+};
+
+const RED: &str = "1;31";
+const YELLOW: &str = "33";
+const GREEN: &str = "32";
+const CYAN: &str = "36";
+const MAGENTA: &str = "35";
+const BLUE: &str = "1;34";
+const DIM: &str = "2";
+
+/// Values wider than this are split across lines
+const WIDTH: usize = 60;
+
+/// If the diagnostics are written as JSON, set by `--error-format=json`
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Writes the diagnostics as JSON, one object per line, instead of rendering them for humans
+pub(crate) fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+/// Checks if the diagnostics are written as JSON
+pub(crate) fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+fn paint(text: &str, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Pretty-prints a value, breaking the nested lists, tuples and tagged values that don't fit in
+/// a single line
+pub(crate) fn value(value: &Value, color: bool) -> String {
+    render(value, color, 0)
+}
+
+fn render(value: &Value, color: bool, indent: usize) -> String {
+    let text = value.to_string();
+
+    match value {
+        Value::Int(_) | Value::Num(_) => paint(&text, YELLOW, color),
+        Value::Str(_) => paint(&text, GREEN, color),
+        Value::Sym(_) => paint(&text, CYAN, color),
+        Value::Bool(_) | Value::Nil => paint(&text, MAGENTA, color),
+        Value::List(xs) => {
+            let items = xs.to_vec();
+            let items = container(&items, ", ", color, indent, &text);
+            format!("[{items}]")
+        }
+        Value::Tuple(tup) => {
+            let items = container(&tup.0, ", ", color, indent, &text);
+            format!("({items})")
+        }
+        Value::Tagged(_, tag, tup) => {
+            let tag = paint(tag.as_str(), BLUE, color);
+            if tup.0.is_empty() {
+                return format!("({tag})");
+            }
+
+            let items = container(&tup.0, " ", color, indent, &text);
+            if items.starts_with('\n') {
+                format!("({tag}{items})")
+            } else {
+                format!("({tag} {items})")
+            }
+        }
+        _ => paint(&text, DIM, color),
+    }
+}
+
+/// Renders the items of a container, one per line if the whole value is too wide
+fn container(items: &[Value], sep: &str, color: bool, indent: usize, text: &str) -> String {
+    if indent + text.chars().count() <= WIDTH {
+        return items
+            .iter()
+            .map(|item| render(item, color, indent))
+            .collect::<Vec<_>>()
+            .join(sep);
+    }
+
+    let pad = " ".repeat(indent + 2);
+    let mut out = String::new();
+    for item in items {
+        out.push('\n');
+        out.push_str(&pad);
+        out.push_str(&render(item, color, indent + 2));
+        out.push_str(sep.trim_end());
+    }
+    out.push('\n');
+    out.push_str(&" ".repeat(indent));
+    out
+}
+
+/// A diagnostic about some code the CLI compiled or ran, boxed since it's returned as an error
+/// all the way up to `main`
+pub(crate) struct Report {
+    pub diagnostic: Box<Diagnostic>,
+    /// If the position points to the code being ran, instead of some previous definition
+    pub local: bool,
+}
+
+impl From<Diagnostic> for Report {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Self {
+            diagnostic: Box::new(diagnostic),
+            local: true,
+        }
+    }
+}
+
+impl From<ParseError> for Report {
+    fn from(err: ParseError) -> Self {
+        Diagnostic::from(err).into()
+    }
+}
+
+impl From<InterpretError> for Report {
+    fn from(err: InterpretError) -> Self {
+        Self {
+            diagnostic: Box::new(err.into()),
+            local: false,
+        }
+    }
+}
+
+impl From<String> for Report {
+    fn from(message: String) -> Self {
+        Self {
+            diagnostic: Box::new(message.into()),
+            local: false,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.diagnostic)
+    }
+}
+
+/// Writes a diagnostic as a JSON object in a single line, with the file it was found in if it's
+/// known. The position is left out if it doesn't point to the code being ran
+pub(crate) fn json(report: &Report, file: Option<&str>) -> String {
+    let diagnostic = &report.diagnostic;
+    let span = diagnostic.span.filter(|span| report.local && span.line > 0);
+
+    serde_json::json!({
+        "severity": diagnostic.severity.to_string(),
+        "code": diagnostic.error_code(),
+        "kind": diagnostic.code,
+        "message": diagnostic.message,
+        "file": file,
+        "line": span.map(|span| span.line),
+        "column": span.map(|span| span.column),
+        "notes": diagnostic.notes,
+        "trace": diagnostic.trace.iter().map(|entry| serde_json::json!({
+            "name": entry.name,
+            "line": entry.line,
+            "column": entry.column,
+            "repeated": entry.repeated,
+        })).collect::<Vec<_>>(),
+    })
+    .to_string()
+}
+
+/// Renders a diagnostic, pointing to where it happened in `source` if it's there, or writes it as
+/// JSON with `--error-format=json`
+pub(crate) fn error(report: &Report, source: &str, color: bool) -> String {
+    if is_json() {
+        return json(report, None);
+    }
+
+    let diagnostic = &report.diagnostic;
+    let paint_severity = |text: &str| match diagnostic.severity {
+        Severity::Error => paint(text, RED, color),
+        Severity::Warning => paint(text, YELLOW, color),
+    };
+
+    let span = diagnostic.span.filter(|_| report.local);
+    let line = span.and_then(|span| Some((span, source.lines().nth(span.line.checked_sub(1)?)?)));
+    let Some((span, line)) = line else {
+        return paint_severity(&diagnostic.to_string());
+    };
+
+    let (severity, message) = (diagnostic.severity, &diagnostic.message);
+    let title = match (&diagnostic.code, diagnostic.error_code()) {
+        (Some(kind), Some(code)) => format!("{severity}[{code}]: {kind}: {message}"),
+        (Some(kind), None) => format!("{severity}[{kind}]: {message}"),
+        (None, _) => format!("{severity}: {message}"),
+    };
+
+    let number = span.line.to_string();
+    let gutter = " ".repeat(number.len());
+    let caret = " ".repeat(span.column.saturating_sub(1).min(line.chars().count()));
+
+    let bar = paint("|", BLUE, color);
+    let mut notes = String::new();
+    for note in &diagnostic.notes {
+        write!(notes, "\n{gutter} {} note: {note}", paint("=", BLUE, color)).unwrap();
+    }
+    for entry in &diagnostic.trace {
+        write!(notes, "\n{gutter} {} {entry}", paint("=", BLUE, color)).unwrap();
+    }
+    format!(
+        "{}\n{gutter} {bar}\n{} {line}\n{gutter} {bar} {caret}{}{notes}",
+        paint_severity(&title),
+        paint(&format!("{number} |"), BLUE, color),
+        paint_severity("^"),
+    )
+}
+
+#[test]
+fn pretty_short_values_in_a_line() {
+    let xs = Value::List(vec![Value::Int(1), Value::Nil].into_iter().collect());
+    assert_eq!(value(&xs, false), "[1, nil]");
+}
+
+#[test]
+fn pretty_long_values_across_lines() {
+    let long = Value::Str(vm::gc::GcRef::new("a".repeat(WIDTH)));
+    let xs = Value::List(vec![Value::Int(1), long.clone()].into_iter().collect());
+
+    assert_eq!(value(&xs, false), format!("[\n  1,\n  {long},\n]"));
+}
+
+#[test]
+fn render_error_with_caret() {
+    let report = Report::from(Diagnostic::error("unexpected token").at(1, 3));
+
+    assert_eq!(
+        error(&report, "1 +", false),
+        "error: unexpected token\n  |\n1 | 1 +\n  |   ^"
+    );
+}
+
+#[test]
+fn render_warning_with_notes() {
+    let diagnostic = Diagnostic::warning("unused value")
+        .at(1, 1)
+        .with_code("Unused")
+        .with_note("bind it with let");
+
+    assert_eq!(
+        error(&diagnostic.into(), "1 + 2", false),
+        "warning[Unused]: unused value\n  |\n1 | 1 + 2\n  | ^\n  = note: bind it with let"
+    );
+}
+
+#[test]
+fn write_diagnostics_as_json() {
+    let report = Report::from(
+        Diagnostic::error("Expected a number")
+            .at(2, 5)
+            .with_code("TypeError"),
+    );
+    let json: serde_json::Value = serde_json::from_str(&json(&report, Some("main.yex"))).unwrap();
+
+    assert_eq!(json["code"], "E0003");
+    assert_eq!(json["kind"], "TypeError");
+    assert_eq!(json["file"], "main.yex");
+    assert_eq!(
+        (json["line"].as_u64(), json["column"].as_u64()),
+        (Some(2), Some(5))
+    );
+    assert_eq!(
+        error(&report, "let x =\n  1 + :a", false).lines().next(),
+        Some("error[E0003]: TypeError: Expected a number")
+    );
+}
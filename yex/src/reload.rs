@@ -0,0 +1,134 @@
+//! The files loaded in the REPL, which `:reload` runs again once they change
+use std::{fs, time::SystemTime};
+
+use vm::{OpCode, VirtualMachine};
+
+use crate::{
+    pretty::{self, Report},
+    run_patched,
+};
+
+/// A file loaded with `--preload` or `:load`, and when it was modified before loading it
+struct Source {
+    path: String,
+    modified: Option<SystemTime>,
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|it| it.modified()).ok()
+}
+
+/// The files loaded in the REPL
+#[derive(Default)]
+pub(crate) struct Loaded {
+    files: Vec<Source>,
+}
+
+impl Loaded {
+    /// Runs a file, keeping track of it, the globals of a file that was loaded before are defined
+    /// again instead of raising
+    pub fn load(
+        &mut self,
+        vm: &mut VirtualMachine,
+        path: &str,
+        color: bool,
+    ) -> Result<String, String> {
+        let modified = modified(path);
+        let source = fs::read_to_string(path).map_err(|e| format!("error reading {path}: {e}"))?;
+
+        let known = self.files.iter().position(|file| file.path == path);
+        front::parse(source.clone())
+            .map_err(Report::from)
+            .and_then(|(bytecode, constants)| {
+                for op in bytecode.iter().filter(|_| known.is_some()) {
+                    if let OpCode::Savg(name) = op.opcode {
+                        vm.remove_global(name);
+                    }
+                }
+                run_patched(vm, bytecode, constants)
+            })
+            .map_err(|e| pretty::error(&Report { local: true, ..e }, &source, color))?;
+
+        match known {
+            Some(idx) => self.files[idx].modified = modified,
+            None => self.files.push(Source {
+                path: path.to_string(),
+                modified,
+            }),
+        }
+        Ok(format!("loaded {path}"))
+    }
+
+    /// Loads again the files that changed since they were loaded
+    pub fn reload(&mut self, vm: &mut VirtualMachine, color: bool) -> Result<String, String> {
+        let changed = self
+            .files
+            .iter()
+            .filter(|file| modified(&file.path) != file.modified)
+            .map(|file| file.path.clone())
+            .collect::<Vec<_>>();
+
+        if changed.is_empty() {
+            return Ok(String::from("nothing changed"));
+        }
+
+        let loaded = changed
+            .iter()
+            .map(|path| self.load(vm, path, color))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(loaded.join("\n"))
+    }
+}
+
+#[test]
+fn reload_changed_files() {
+    use std::time::Duration;
+
+    let path = std::env::temp_dir().join("yex_reload.yex");
+    let path = path.to_str().unwrap();
+    let mut vm = VirtualMachine::default();
+    let mut loaded = Loaded::default();
+
+    fs::write(path, "def f x = x + 1").unwrap();
+    loaded.load(&mut vm, path, false).unwrap();
+    assert_eq!(loaded.reload(&mut vm, false).unwrap(), "nothing changed");
+
+    fs::write(path, "def f x = x + 2").unwrap();
+    let later = SystemTime::now() + Duration::from_secs(1);
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(later))
+        .unwrap();
+    loaded.reload(&mut vm, false).unwrap();
+
+    let value = crate::eval_line(&mut vm, "f 1");
+    assert!(matches!(value, Ok(value) if value == vm::Value::Num(3.0)));
+}
+
+#[test]
+fn compare_values_of_reloaded_types() {
+    use std::time::Duration;
+
+    let path = std::env::temp_dir().join(format!("yex_reload_type_{}.yex", std::process::id()));
+    let path = path.to_str().unwrap();
+    let mut vm = VirtualMachine::default();
+    let mut loaded = Loaded::default();
+
+    fs::write(path, "type T = a | b x with\n def id x = x\nend").unwrap();
+    loaded.load(&mut vm, path, false).unwrap();
+    assert!(crate::eval_line(&mut vm, "let old = T.a").is_ok());
+
+    fs::write(path, "type T = a | c x with\n def id x = x\nend").unwrap();
+    let later = SystemTime::now() + Duration::from_secs(1);
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(later))
+        .unwrap();
+    loaded.reload(&mut vm, false).unwrap();
+
+    let same = crate::eval_line(&mut vm, "old == T.a && old != T.c 1 && [old] == [T.a]");
+    fs::remove_file(path).unwrap();
+    assert!(matches!(same, Ok(vm::Value::Bool(true))));
+}
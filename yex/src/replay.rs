@@ -0,0 +1,71 @@
+use std::fs;
+
+use vm::{replay::Deterministic, VirtualMachine};
+
+/// How a program is run in deterministic mode, set with `--seed`, `--record` and `--replay`
+#[derive(Default)]
+pub(crate) struct Options {
+    seed: Option<u64>,
+    record: Option<String>,
+    replay: Option<String>,
+}
+
+impl Options {
+    /// Takes the deterministic mode flags out of the command line arguments
+    pub fn take(args: &mut Vec<String>) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        while let Some(idx) = args
+            .iter()
+            .position(|arg| matches!(arg.as_str(), "--seed" | "--record" | "--replay"))
+        {
+            let Some(value) = args.get(idx + 1).cloned() else {
+                return Err(format!("expected a value after '{}'", args[idx]));
+            };
+
+            match args[idx].as_str() {
+                "--seed" => {
+                    let seed = value.parse().map_err(|_| {
+                        format!("expected a number after '--seed', found '{value}'")
+                    })?;
+                    options.seed = Some(seed);
+                }
+                "--record" => options.record = Some(value),
+                _ => options.replay = Some(value),
+            }
+            args.drain(idx..idx + 2);
+        }
+
+        if options.replay.is_some() && (options.seed.is_some() || options.record.is_some()) {
+            return Err(String::from(
+                "'--replay' takes the seed from the log, it can't be used with '--seed' or '--record'",
+            ));
+        }
+
+        Ok(options)
+    }
+
+    /// Puts the VM in deterministic mode, if any of the flags was given
+    pub fn setup(&self, vm: &mut VirtualMachine) -> Result<(), String> {
+        let deterministic = if let Some(path) = &self.replay {
+            let log = fs::read(path).map_err(|e| format!("error reading {path}: {e}"))?;
+            Deterministic::replay(&log).ok_or(format!("{path} isn't a replay log"))?
+        } else if self.seed.is_some() || self.record.is_some() {
+            Deterministic::record(self.seed.unwrap_or_default())
+        } else {
+            return Ok(());
+        };
+
+        vm.set_deterministic(deterministic);
+        Ok(())
+    }
+
+    /// Writes the log of a recording run
+    pub fn finish(&self, vm: &VirtualMachine) -> Result<(), String> {
+        let (Some(path), Some(deterministic)) = (&self.record, vm.deterministic()) else {
+            return Ok(());
+        };
+
+        fs::write(path, deterministic.log()).map_err(|e| format!("can't write {path}: {e}"))
+    }
+}
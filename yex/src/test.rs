@@ -0,0 +1,223 @@
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use vm::{debug::Debugger, Bytecode, OpCode, OpCodeMetadata, Symbol, Value, VirtualMachine};
+
+use crate::coverage::Coverage;
+
+pub(crate) struct TestCase {
+    pub name: Symbol,
+    pub line: usize,
+    pub column: usize,
+    prefix: &'static str,
+}
+
+impl TestCase {
+    /// The name the case was declared with
+    pub fn name(&self) -> &str {
+        &self.name.as_str()[self.prefix.len()..]
+    }
+
+    /// Bytecode that calls the case's function
+    pub fn call(&self) -> Bytecode {
+        vec![
+            OpCodeMetadata::new(self.line, self.column, OpCode::Loag(self.name)),
+            OpCodeMetadata::new(self.line, self.column, OpCode::Call(0)),
+        ]
+    }
+}
+
+/// Collects all the `.yex` files under `path`, or `path` itself if it's a file
+pub(crate) fn discover(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+
+    let mut entries = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    for entry in entries {
+        let hidden = entry
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+        if hidden || entry.ends_with("target") {
+            continue;
+        }
+
+        if entry.is_dir() {
+            discover(&entry, files);
+        } else if entry.extension().is_some_and(|ext| ext == "yex") {
+            files.push(entry);
+        }
+    }
+}
+
+/// Finds the `test` (or `bench`) declarations by looking for the globals they are saved to
+pub(crate) fn collect(bytecode: &Bytecode, prefix: &'static str) -> Vec<TestCase> {
+    bytecode
+        .iter()
+        .filter_map(|op| match op.opcode {
+            OpCode::Savg(name) if name.as_str().starts_with(prefix) => Some(TestCase {
+                name,
+                line: op.line,
+                column: op.column,
+                prefix,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Creates a fresh virtual machine with the top-level declarations of a file already run, with
+/// the hook set before running them
+pub(crate) fn setup(
+    bytecode: &Bytecode,
+    constants: &[Value],
+    hook: Option<Box<dyn Debugger>>,
+) -> Result<VirtualMachine, String> {
+    let mut vm = VirtualMachine::default();
+    vm.set_debugger(hook);
+    vm.set_consts(constants.to_vec());
+    vm.run(bytecode).map_err(|e| e.to_string())?;
+    vm.reset();
+    Ok(vm)
+}
+
+/// Reads and compiles a file
+pub(crate) fn load(file: &Path) -> Result<(Bytecode, Vec<Value>), String> {
+    let source = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let dir = file.parent().unwrap_or(Path::new("."));
+    front::parse_in(source, dir).map_err(|e| e.to_string())
+}
+
+/// Runs a single test on a fresh virtual machine, so tests can't interfere with each other
+fn run_test(
+    bytecode: &Bytecode,
+    constants: &[Value],
+    test: &TestCase,
+    coverage: Option<&Rc<RefCell<Coverage>>>,
+) -> Result<(), String> {
+    let mut vm = setup(bytecode, constants, coverage.map(Coverage::hook))?;
+    vm.run(&test.call()).map_err(|e| e.to_string())?;
+
+    match vm.pop_last() {
+        Value::Bool(false) => Err(String::from("test evaluated to false")),
+        _ => Ok(()),
+    }
+}
+
+/// Runs the tests of every file in `paths` (or the current directory), returning the exit code,
+/// `--coverage <report>` writes the lines and the functions the tests ran to an lcov report
+pub fn run(paths: &[String]) -> i32 {
+    let mut paths = paths.to_vec();
+    let report = match paths.iter().position(|arg| arg == "--coverage") {
+        Some(idx) if idx + 1 < paths.len() => Some(paths.drain(idx..idx + 2).nth(1).unwrap()),
+        Some(_) => {
+            eprintln!("expected a report path after '--coverage'");
+            return 1;
+        }
+        None => None,
+    };
+    let mut lcov = String::new();
+    let (mut lines_hit, mut lines_found) = (0, 0);
+
+    let mut files = vec![];
+    if paths.is_empty() {
+        discover(Path::new("."), &mut files);
+    }
+    for path in &paths {
+        discover(Path::new(path), &mut files);
+    }
+
+    let mut passed = 0;
+    let mut failures = vec![];
+
+    for file in files {
+        let display = file.display().to_string();
+
+        let (bytecode, constants) = match load(&file) {
+            Ok(res) => res,
+            Err(e) => {
+                failures.push((display, e));
+                continue;
+            }
+        };
+
+        let tests = collect(&bytecode, front::TEST_PREFIX);
+        if tests.is_empty() {
+            continue;
+        }
+
+        println!("\nrunning {} tests from {display}", tests.len());
+
+        let coverage = report
+            .is_some()
+            .then(|| Rc::new(RefCell::new(Coverage::new(&bytecode, &constants))));
+
+        for test in tests {
+            match run_test(&bytecode, &constants, &test, coverage.as_ref()) {
+                Ok(()) => {
+                    println!("test {} ... ok", test.name());
+                    passed += 1;
+                }
+                Err(e) => {
+                    println!("test {} ... FAILED", test.name());
+                    let header =
+                        format!("{} ({display}:{}:{})", test.name(), test.line, test.column);
+                    failures.push((header, e));
+                }
+            }
+        }
+
+        if let Some(coverage) = coverage {
+            let coverage = coverage.borrow();
+            coverage.write_lcov(&display, &mut lcov);
+
+            let (hit, found) = coverage.lines_hit();
+            lines_hit += hit;
+            lines_found += found;
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (header, err) in &failures {
+            println!("\n---- {header} ----\n{err}");
+        }
+    }
+
+    let status = if failures.is_empty() { "ok" } else { "FAILED" };
+    println!(
+        "\ntest result: {status}. {passed} passed; {} failed",
+        failures.len()
+    );
+
+    if let Some(report) = report {
+        if let Err(e) = fs::write(&report, lcov) {
+            eprintln!("error writing {report}: {e}");
+            return 1;
+        }
+
+        let permille = lines_hit * 1000 / lines_found.max(1);
+        println!(
+            "coverage: {}.{}% of lines ({lines_hit}/{lines_found}), written to {report}",
+            permille / 10,
+            permille % 10
+        );
+    }
+
+    i32::from(!failures.is_empty())
+}
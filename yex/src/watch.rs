@@ -0,0 +1,65 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::Path,
+    process::Command,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// How often the file is checked for changes
+const POLL: Duration = Duration::from_millis(250);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|it| it.modified()).ok()
+}
+
+/// Runs (or checks, with `--check`) a file every time it changes, never returns unless the file
+/// can't be found
+pub fn run(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let Some(file) = args.iter().find(|arg| *arg != "--check") else {
+        eprintln!("expected a file to watch");
+        return 1;
+    };
+
+    let Ok(exe) = env::current_exe() else {
+        eprintln!("can't find the yex executable");
+        return 1;
+    };
+
+    let path = Path::new(file);
+    let mut last = None;
+
+    loop {
+        let Some(now) = modified(path) else {
+            eprintln!("can't read {file}");
+            return 1;
+        };
+
+        if last != Some(now) {
+            last = Some(now);
+
+            // clears the screen and moves the cursor to the top
+            print!("\x1b[2J\x1b[H");
+            io::stdout().flush().ok();
+
+            // ran in another process, so `exit` and crashes don't stop the watcher
+            let mut cmd = Command::new(&exe);
+            if check {
+                cmd.arg("check");
+            }
+
+            match cmd.arg(file).status() {
+                Ok(status) => match status.code() {
+                    Some(0) => println!("\n[{file} finished, waiting for changes]"),
+                    Some(code) => println!("\n[{file} exited with {code}, waiting for changes]"),
+                    None => println!("\n[{file} was stopped, waiting for changes]"),
+                },
+                Err(e) => eprintln!("can't run {file}: {e}"),
+            }
+        }
+
+        thread::sleep(POLL);
+    }
+}